@@ -12,6 +12,7 @@ extern crate test;
 
 use rand::{rngs::SmallRng, Rng, SeedableRng};
 use solana_rbpf::{
+    ebpf::SbfVersion,
     memory_region::{AccessType, MemoryMapping, MemoryRegion},
     user_error::UserError,
     vm::Config,
@@ -65,7 +66,8 @@ fn bench_gapped_randomized_access_with_1024_entries(bencher: &mut Bencher) {
         MemoryRegion::new_for_testing(&content[..], 0x100000000, frame_size, false),
     ];
     let config = Config::default();
-    let memory_mapping = MemoryMapping::new::<UserError>(memory_regions, &config).unwrap();
+    let memory_mapping =
+        MemoryMapping::new::<UserError>(memory_regions, &config, SbfVersion::V2).unwrap();
     let mut prng = new_prng!();
     bencher.iter(|| {
         assert!(memory_mapping
@@ -86,7 +88,8 @@ fn bench_randomized_access_with_0001_entry(bencher: &mut Bencher) {
         MemoryRegion::new_readonly(&content[..], 0x100000000),
     ];
     let config = Config::default();
-    let memory_mapping = MemoryMapping::new::<UserError>(memory_regions, &config).unwrap();
+    let memory_mapping =
+        MemoryMapping::new::<UserError>(memory_regions, &config, SbfVersion::V2).unwrap();
     let mut prng = new_prng!();
     bencher.iter(|| {
         let _ = memory_mapping.map::<UserError>(
@@ -102,7 +105,8 @@ fn bench_randomized_mapping_access_with_0004_entries(bencher: &mut Bencher) {
     let mut prng = new_prng!();
     let (memory_regions, end_address) = generate_memory_regions(4, false, Some(&mut prng));
     let config = Config::default();
-    let memory_mapping = MemoryMapping::new::<UserError>(memory_regions, &config).unwrap();
+    let memory_mapping =
+        MemoryMapping::new::<UserError>(memory_regions, &config, SbfVersion::V2).unwrap();
     bencher.iter(|| {
         let _ = memory_mapping.map::<UserError>(
             AccessType::Load,
@@ -117,7 +121,8 @@ fn bench_randomized_mapping_access_with_0016_entries(bencher: &mut Bencher) {
     let mut prng = new_prng!();
     let (memory_regions, end_address) = generate_memory_regions(16, false, Some(&mut prng));
     let config = Config::default();
-    let memory_mapping = MemoryMapping::new::<UserError>(memory_regions, &config).unwrap();
+    let memory_mapping =
+        MemoryMapping::new::<UserError>(memory_regions, &config, SbfVersion::V2).unwrap();
     bencher.iter(|| {
         let _ = memory_mapping.map::<UserError>(
             AccessType::Load,
@@ -132,7 +137,8 @@ fn bench_randomized_mapping_access_with_0064_entries(bencher: &mut Bencher) {
     let mut prng = new_prng!();
     let (memory_regions, end_address) = generate_memory_regions(64, false, Some(&mut prng));
     let config = Config::default();
-    let memory_mapping = MemoryMapping::new::<UserError>(memory_regions, &config).unwrap();
+    let memory_mapping =
+        MemoryMapping::new::<UserError>(memory_regions, &config, SbfVersion::V2).unwrap();
     bencher.iter(|| {
         let _ = memory_mapping.map::<UserError>(
             AccessType::Load,
@@ -147,7 +153,8 @@ fn bench_randomized_mapping_access_with_0256_entries(bencher: &mut Bencher) {
     let mut prng = new_prng!();
     let (memory_regions, end_address) = generate_memory_regions(256, false, Some(&mut prng));
     let config = Config::default();
-    let memory_mapping = MemoryMapping::new::<UserError>(memory_regions, &config).unwrap();
+    let memory_mapping =
+        MemoryMapping::new::<UserError>(memory_regions, &config, SbfVersion::V2).unwrap();
     bencher.iter(|| {
         let _ = memory_mapping.map::<UserError>(
             AccessType::Load,
@@ -162,7 +169,8 @@ fn bench_randomized_mapping_access_with_1024_entries(bencher: &mut Bencher) {
     let mut prng = new_prng!();
     let (memory_regions, end_address) = generate_memory_regions(1024, false, Some(&mut prng));
     let config = Config::default();
-    let memory_mapping = MemoryMapping::new::<UserError>(memory_regions, &config).unwrap();
+    let memory_mapping =
+        MemoryMapping::new::<UserError>(memory_regions, &config, SbfVersion::V2).unwrap();
     bencher.iter(|| {
         let _ = memory_mapping.map::<UserError>(
             AccessType::Load,
@@ -177,7 +185,8 @@ fn bench_randomized_access_with_1024_entries(bencher: &mut Bencher) {
     let mut prng = new_prng!();
     let (memory_regions, end_address) = generate_memory_regions(1024, false, None);
     let config = Config::default();
-    let memory_mapping = MemoryMapping::new::<UserError>(memory_regions, &config).unwrap();
+    let memory_mapping =
+        MemoryMapping::new::<UserError>(memory_regions, &config, SbfVersion::V2).unwrap();
     bencher.iter(|| {
         let _ = memory_mapping.map::<UserError>(
             AccessType::Load,
@@ -192,7 +201,8 @@ fn bench_randomized_mapping_with_1024_entries(bencher: &mut Bencher) {
     let mut prng = new_prng!();
     let (memory_regions, _end_address) = generate_memory_regions(1024, false, Some(&mut prng));
     let config = Config::default();
-    let memory_mapping = MemoryMapping::new::<UserError>(memory_regions, &config).unwrap();
+    let memory_mapping =
+        MemoryMapping::new::<UserError>(memory_regions, &config, SbfVersion::V2).unwrap();
     bencher.iter(|| {
         let _ = memory_mapping.map::<UserError>(AccessType::Load, 0x100000000, 1);
     });
@@ -202,7 +212,8 @@ fn bench_randomized_mapping_with_1024_entries(bencher: &mut Bencher) {
 fn bench_mapping_with_1024_entries(bencher: &mut Bencher) {
     let (memory_regions, _end_address) = generate_memory_regions(1024, false, None);
     let config = Config::default();
-    let memory_mapping = MemoryMapping::new::<UserError>(memory_regions, &config).unwrap();
+    let memory_mapping =
+        MemoryMapping::new::<UserError>(memory_regions, &config, SbfVersion::V2).unwrap();
     bencher.iter(|| {
         assert!(memory_mapping
             .map::<UserError>(AccessType::Load, 0x100000000, 1)
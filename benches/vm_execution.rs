@@ -10,7 +10,7 @@ extern crate solana_rbpf;
 extern crate test;
 
 use solana_rbpf::{
-    ebpf,
+    ebpf::{self, SbfVersion},
     elf::Executable,
     memory_region::MemoryRegion,
     user_error::UserError,
@@ -143,7 +143,8 @@ fn bench_jit_vs_interpreter_address_translation_stack_fixed(bencher: &mut Benche
         bencher,
         ADDRESS_TRANSLATION_STACK_CODE,
         Config {
-            dynamic_stack_frames: false,
+            min_sbf_version: SbfVersion::V1,
+            max_sbf_version: SbfVersion::V1,
             ..Config::default()
         },
         524289,
@@ -158,7 +159,8 @@ fn bench_jit_vs_interpreter_address_translation_stack_dynamic(bencher: &mut Benc
         bencher,
         ADDRESS_TRANSLATION_STACK_CODE,
         Config {
-            dynamic_stack_frames: true,
+            min_sbf_version: SbfVersion::V2,
+            max_sbf_version: SbfVersion::V2,
             ..Config::default()
         },
         524289,
@@ -205,7 +207,8 @@ fn bench_jit_vs_interpreter_call_depth_fixed(bencher: &mut Bencher) {
     call fun
     exit",
         Config {
-            dynamic_stack_frames: false,
+            min_sbf_version: SbfVersion::V1,
+            max_sbf_version: SbfVersion::V1,
             ..Config::default()
         },
         137218,
@@ -236,7 +239,8 @@ fn bench_jit_vs_interpreter_call_depth_dynamic(bencher: &mut Bencher) {
     add r11, 4
     exit",
         Config {
-            dynamic_stack_frames: true,
+            min_sbf_version: SbfVersion::V2,
+            max_sbf_version: SbfVersion::V2,
             ..Config::default()
         },
         176130,
@@ -1,9 +1,14 @@
 use clap::{crate_version, App, Arg};
 use solana_rbpf::{
     assembler::assemble,
-    ebpf,
+    coverage::{Coverage, LineMapping},
+    ebpf::{self, SbfVersion},
     elf::Executable,
+    error::EbpfError,
+    jit_cache::JitCache,
+    jit_disassembler::disassemble_jit_program,
     memory_region::{MemoryMapping, MemoryRegion},
+    profiler::Profile,
     static_analysis::Analysis,
     syscalls::Result,
     user_error::UserError,
@@ -82,7 +87,14 @@ fn main() {
                 .short('u')
                 .long("use")
                 .takes_value(true)
-                .possible_values(&["cfg", "disassembler", "interpreter", "jit"])
+                .possible_values(&[
+                    "cfg",
+                    "disassembler",
+                    "jit-disassembler",
+                    "interpreter",
+                    "threaded",
+                    "jit",
+                ])
                 .required(true),
         )
         .arg(
@@ -94,6 +106,13 @@ fn main() {
                 .value_name("COUNT")
                 .default_value(&std::i64::MAX.to_string()),
         )
+        .arg(
+            Arg::new("jit cache")
+                .about("Directory in which JIT compiled programs are cached")
+                .long("jit-cache")
+                .takes_value(true)
+                .value_name("DIR"),
+        )
         .arg(
             Arg::new("trace")
                 .about("Display trace using tracing instrumentation")
@@ -102,10 +121,16 @@ fn main() {
         )
         .arg(
             Arg::new("profile")
-                .about("Display profile using tracing instrumentation")
+                .about("Write profile.dot, profile.folded and profile.pb using tracing instrumentation")
                 .short('p')
                 .long("prof"),
         )
+        .arg(
+            Arg::new("coverage")
+                .about("Write coverage.lcov and coverage.xml using tracing instrumentation")
+                .short('c')
+                .long("cov"),
+        )
         .arg(
             Arg::new("verify")
                 .about("Run the verifier before execution or disassembly")
@@ -115,11 +140,15 @@ fn main() {
         .get_matches();
 
     let config = Config {
-        enable_instruction_tracing: matches.is_present("trace") || matches.is_present("profile"),
+        enable_instruction_tracing: matches.is_present("trace")
+            || matches.is_present("profile")
+            || matches.is_present("coverage"),
         enable_symbol_and_section_labels: true,
+        enable_debug_info: matches.is_present("coverage"),
+        enable_backtrace: true,
         ..Config::default()
     };
-    let verifier: Option<for<'r> fn(&'r [u8], &Config) -> std::result::Result<_, _>> =
+    let verifier: Option<for<'r> fn(&'r [u8], &Config, SbfVersion) -> std::result::Result<_, _>> =
         if matches.is_present("verify") {
             Some(check)
         } else {
@@ -177,16 +206,35 @@ fn main() {
             .parse::<usize>()
             .unwrap()
     ];
-    if matches.value_of("use") == Some("jit") {
-        Executable::<UserError, TestInstructionMeter>::jit_compile(&mut executable).unwrap();
+    match matches.value_of("use") {
+        Some("jit-disassembler") => {
+            Executable::<UserError, TestInstructionMeter>::jit_compile(&mut executable).unwrap()
+        }
+        Some("jit") => match matches.value_of("jit cache") {
+            Some(directory) => Executable::<UserError, TestInstructionMeter>::jit_compile_cached(
+                &mut executable,
+                &JitCache::new(directory).unwrap(),
+            )
+            .unwrap(),
+            None => {
+                Executable::<UserError, TestInstructionMeter>::jit_compile(&mut executable).unwrap()
+            }
+        },
+        Some("threaded") => {
+            Executable::<UserError, TestInstructionMeter>::threaded_compile(&mut executable)
+                .unwrap()
+        }
+        _ => {}
     }
     let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
     let mut vm = EbpfVm::new(&executable, &mut heap, vec![mem_region]).unwrap();
 
     let analysis = if matches.value_of("use") == Some("cfg")
         || matches.value_of("use") == Some("disassembler")
+        || matches.value_of("use") == Some("jit-disassembler")
         || matches.is_present("trace")
         || matches.is_present("profile")
+        || matches.is_present("coverage")
     {
         Some(Analysis::from_executable(&executable).unwrap())
     } else {
@@ -211,16 +259,27 @@ fn main() {
                 .unwrap();
             return;
         }
+        Some("jit-disassembler") => {
+            let stdout = std::io::stdout();
+            disassemble_jit_program(analysis.as_ref().unwrap(), &mut stdout.lock()).unwrap();
+            return;
+        }
         _ => {}
     }
 
     vm.bind_syscall_context_objects(0).unwrap();
-    let result = if matches.value_of("use").unwrap() == "interpreter" {
-        vm.execute_program_interpreted(&mut instruction_meter)
-    } else {
-        vm.execute_program_jit(&mut instruction_meter)
+    let result = match matches.value_of("use").unwrap() {
+        "interpreter" => vm.execute_program_interpreted(&mut instruction_meter),
+        "threaded" => vm.execute_program_threaded(&mut instruction_meter),
+        _ => vm.execute_program_jit(&mut instruction_meter),
     };
-    println!("Result: {:?}", result);
+    match result {
+        Err(EbpfError::WithBacktrace(error, backtrace)) => {
+            println!("Result: {:?}", Err::<u64, _>(error));
+            println!("Backtrace:\n{}", backtrace);
+        }
+        result => println!("Result: {:?}", result),
+    }
     println!("Instruction Count: {}", vm.get_total_instruction_count());
     if matches.is_present("trace") {
         println!("Trace:\n");
@@ -238,5 +297,57 @@ fn main() {
             .unwrap()
             .visualize_graphically(&mut file, Some(&dynamic_analysis))
             .unwrap();
+        let profile = Profile::new(tracer, analysis.as_ref().unwrap());
+        let mut file = File::create("profile.folded").unwrap();
+        profile.write_collapsed_stacks(&mut file).unwrap();
+        let mut file = File::create("profile.pb").unwrap();
+        profile.write_pprof(&mut file).unwrap();
+        println!("Profile (max call depth {}):\n", profile.max_depth);
+        println!(
+            "{:>12} {:>12} {:>8}  Function",
+            "Inclusive", "Exclusive", "Calls"
+        );
+        let mut functions = profile.functions.values().collect::<Vec<_>>();
+        functions.sort_by(|a, b| b.inclusive_insn_count.cmp(&a.inclusive_insn_count));
+        for function in functions {
+            println!(
+                "{:>12} {:>12} {:>8}  {}",
+                function.inclusive_insn_count,
+                function.exclusive_insn_count,
+                function.call_count,
+                function.name
+            );
+        }
+    }
+    if matches.is_present("coverage") {
+        let analysis = analysis.as_ref().unwrap();
+        let mut coverage = Coverage::default();
+        coverage.add_trace(vm.get_tracer(), analysis);
+        let file_name = Path::new(
+            matches
+                .value_of("elf")
+                .or_else(|| matches.value_of("assembler"))
+                .unwrap(),
+        )
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .to_string();
+        let line_mapping = match executable.get_debug_info() {
+            Some(debug_info) => LineMapping::DebugInfo(debug_info),
+            None => LineMapping::Instructions(&file_name),
+        };
+        let mut file = File::create("coverage.lcov").unwrap();
+        coverage
+            .write_lcov(&mut file, analysis, &line_mapping)
+            .unwrap();
+        let mut file = File::create("coverage.xml").unwrap();
+        coverage
+            .write_cobertura(&mut file, analysis, &line_mapping)
+            .unwrap();
+        let (covered, valid) = coverage.get_instruction_coverage(analysis);
+        println!("Instruction coverage: {}/{}", covered, valid);
+        let (covered, valid) = coverage.get_branch_coverage(analysis);
+        println!("Branch coverage: {}/{}", covered, valid);
     }
 }
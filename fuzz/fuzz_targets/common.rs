@@ -2,7 +2,7 @@ use std::mem::size_of;
 
 use arbitrary::{Arbitrary, Unstructured};
 
-use solana_rbpf::vm::Config;
+use solana_rbpf::{ebpf::SbfVersion, vm::Config};
 
 #[derive(Debug)]
 pub struct ConfigTemplate {
@@ -16,9 +16,14 @@ pub struct ConfigTemplate {
     encrypt_environment_registers: bool,
     disable_deprecated_load_instructions: bool,
     reject_callx_r10: bool,
-    dynamic_stack_frames: bool,
+    enable_sbf_v2: bool,
     enable_sdiv: bool,
     optimize_rodata: bool,
+    optimize_jit: bool,
+    enable_bounds_check_elimination: bool,
+    enable_atomics: bool,
+    enable_jmp32: bool,
+    enable_ebpf_v4: bool,
 }
 
 impl<'a> Arbitrary<'a> for ConfigTemplate {
@@ -38,9 +43,14 @@ impl<'a> Arbitrary<'a> for ConfigTemplate {
             encrypt_environment_registers: bools & (1 << 4) != 0,
             disable_deprecated_load_instructions: bools & (1 << 5) != 0,
             reject_callx_r10: bools & (1 << 6) != 0,
-            dynamic_stack_frames: bools & (1 << 7) != 0,
+            enable_sbf_v2: bools & (1 << 7) != 0,
             enable_sdiv: bools & (1 << 8) != 0,
             optimize_rodata: bools & (1 << 9) != 0,
+            optimize_jit: bools & (1 << 10) != 0,
+            enable_bounds_check_elimination: bools & (1 << 11) != 0,
+            enable_atomics: bools & (1 << 12) != 0,
+            enable_jmp32: bools & (1 << 13) != 0,
+            enable_ebpf_v4: bools & (1 << 14) != 0,
         })
     }
 
@@ -66,25 +76,43 @@ impl From<ConfigTemplate> for Config {
                 encrypt_environment_registers,
                 disable_deprecated_load_instructions,
                 reject_callx_r10,
-                dynamic_stack_frames,
+                enable_sbf_v2,
                 enable_sdiv,
                 optimize_rodata,
-            } => Config {
-                max_call_depth,
-                enable_stack_frame_gaps,
-                instruction_meter_checkpoint_distance,
-                enable_symbol_and_section_labels,
-                disable_unresolved_symbols_at_runtime,
-                noop_instruction_ratio,
-                sanitize_user_provided_values,
-                encrypt_environment_registers,
-                disable_deprecated_load_instructions,
-                reject_callx_r10,
-                dynamic_stack_frames,
-                enable_sdiv,
-                optimize_rodata,
-                ..Default::default()
-            },
+                optimize_jit,
+                enable_bounds_check_elimination,
+                enable_atomics,
+                enable_jmp32,
+                enable_ebpf_v4,
+            } => {
+                let sbf_version = if enable_sbf_v2 {
+                    SbfVersion::V2
+                } else {
+                    SbfVersion::V1
+                };
+                Config {
+                    max_call_depth,
+                    enable_stack_frame_gaps,
+                    instruction_meter_checkpoint_distance,
+                    enable_symbol_and_section_labels,
+                    disable_unresolved_symbols_at_runtime,
+                    noop_instruction_ratio,
+                    sanitize_user_provided_values,
+                    encrypt_environment_registers,
+                    disable_deprecated_load_instructions,
+                    reject_callx_r10,
+                    min_sbf_version: sbf_version,
+                    max_sbf_version: sbf_version,
+                    enable_sdiv,
+                    optimize_rodata,
+                    optimize_jit,
+                    enable_bounds_check_elimination,
+                    enable_atomics,
+                    enable_jmp32,
+                    enable_ebpf_v4,
+                    ..Default::default()
+                }
+            }
         }
     }
 }
@@ -29,7 +29,7 @@ struct DumbFuzzData {
 fuzz_target!(|data: DumbFuzzData| {
     let prog = data.prog;
     let config = data.template.into();
-    if check(&prog, &config).is_err() {
+    if check(&prog, &config, config.max_sbf_version).is_err() {
         // verify please
         return;
     }
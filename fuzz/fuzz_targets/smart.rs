@@ -33,7 +33,7 @@ struct FuzzData {
 fuzz_target!(|data: FuzzData| {
     let prog = make_program(&data.prog, data.arch);
     let config = data.template.into();
-    if check(prog.into_bytes(), &config).is_err() {
+    if check(prog.into_bytes(), &config, config.max_sbf_version).is_err() {
         // verify please
         return;
     }
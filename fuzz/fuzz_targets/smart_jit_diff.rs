@@ -40,7 +40,7 @@ fuzz_target!(|data: FuzzData| {
         .set_imm(data.exit_imm)
         .push();
     let config = data.template.into();
-    if check(prog.into_bytes(), &config).is_err() {
+    if check(prog.into_bytes(), &config, config.max_sbf_version).is_err() {
         // verify please
         return;
     }
@@ -38,7 +38,7 @@ fn dump_insns<E: UserDefinedError, I: InstructionMeter>(executable: &Executable<
 fuzz_target!(|data: FuzzData| {
     let prog = make_program(&data.prog);
     let config = data.template.into();
-    if check(prog.into_bytes(), &config).is_err() {
+    if check(prog.into_bytes(), &config, config.max_sbf_version).is_err() {
         // verify please
         return;
     }
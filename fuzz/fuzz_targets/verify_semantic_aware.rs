@@ -20,5 +20,5 @@ struct FuzzData {
 fuzz_target!(|data: FuzzData| {
     let prog = make_program(&data.prog);
     let config = data.template.into();
-    check(prog.into_bytes(), &config).unwrap();
+    check(prog.into_bytes(), &config, config.max_sbf_version).unwrap();
 });
@@ -0,0 +1,1501 @@
+#![allow(clippy::integer_arithmetic)]
+//! Abstract interpretation verifier
+//!
+//! An optional second stage after [crate::verifier], similar in spirit to the verifier of the
+//! Linux kernel: It follows all paths of the control-flow graph of an [Analysis] and tracks the
+//! type (scalar or pointer into a memory region) and the possible values (tnum and intervals) of
+//! every register. Instructions which read registers that were not written on every path leading
+//! to them, stores to the read-only section and memory accesses which can not hit the region they
+//! point into are reported.
+//!
+//! Values spilled to memory are not tracked, so loads always produce scalars.
+
+use crate::{
+    ebpf,
+    error::UserDefinedError,
+    static_analysis::{Analysis, CfgNode},
+    vm::InstructionMeter,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::Range,
+};
+use thiserror::Error;
+
+/// Size of the virtual address range reserved for each memory region
+const REGION_SIZE: u64 = ebpf::MM_STACK_START - ebpf::MM_PROGRAM_START;
+
+/// How often a basic block is revisited before its bounds are widened to ensure termination
+const WIDENING_THRESHOLD: usize = 8;
+
+/// How often all states are recomputed from their predecessors to recover bounds lost by widening
+const NARROWING_ITERATIONS: usize = 3;
+
+const SIGN_BIT: u64 = 1 << 63;
+
+/// Tristate number: The bits set in `mask` are unknown, all others are given by `value`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tnum {
+    /// Known bits
+    pub value: u64,
+    /// Unknown bits
+    pub mask: u64,
+}
+
+impl Tnum {
+    /// A number with all bits known
+    pub const fn constant(value: u64) -> Self {
+        Self { value, mask: 0 }
+    }
+
+    /// A number with all bits unknown
+    pub const fn unknown() -> Self {
+        Self {
+            value: 0,
+            mask: u64::MAX,
+        }
+    }
+
+    /// The smallest tnum containing all numbers from `min` to `max`
+    pub fn range(min: u64, max: u64) -> Self {
+        let bits = 64 - (min ^ max).leading_zeros();
+        if bits >= 64 {
+            return Self::unknown();
+        }
+        let delta = (1u64 << bits) - 1;
+        Self {
+            value: min & !delta,
+            mask: delta,
+        }
+    }
+
+    /// Are all bits known
+    pub fn is_constant(&self) -> bool {
+        self.mask == 0
+    }
+
+    /// Can the number be `value`
+    pub fn contains(&self, value: u64) -> bool {
+        value & !self.mask == self.value
+    }
+
+    fn wrapping_add(self, other: Self) -> Self {
+        let sum_mask = self.mask.wrapping_add(other.mask);
+        let sum_value = self.value.wrapping_add(other.value);
+        let carries = sum_mask.wrapping_add(sum_value) ^ sum_value;
+        let mask = carries | self.mask | other.mask;
+        Self {
+            value: sum_value & !mask,
+            mask,
+        }
+    }
+
+    fn wrapping_sub(self, other: Self) -> Self {
+        let difference = self.value.wrapping_sub(other.value);
+        let borrows = difference.wrapping_add(self.mask) ^ difference.wrapping_sub(other.mask);
+        let mask = borrows | self.mask | other.mask;
+        Self {
+            value: difference & !mask,
+            mask,
+        }
+    }
+
+    fn wrapping_mul(mut self, mut other: Self) -> Self {
+        let product = self.value.wrapping_mul(other.value);
+        let mut accumulated = Self::constant(0);
+        while self.value != 0 || self.mask != 0 {
+            if self.value & 1 != 0 {
+                accumulated = accumulated.wrapping_add(Self {
+                    value: 0,
+                    mask: other.mask,
+                });
+            } else if self.mask & 1 != 0 {
+                accumulated = accumulated.wrapping_add(Self {
+                    value: 0,
+                    mask: other.value | other.mask,
+                });
+            }
+            self = self.wrapping_shr(1);
+            other = other.wrapping_shl(1);
+        }
+        Self::constant(product).wrapping_add(accumulated)
+    }
+
+    fn and(self, other: Self) -> Self {
+        let value = self.value & other.value;
+        Self {
+            value,
+            mask: (self.value | self.mask) & (other.value | other.mask) & !value,
+        }
+    }
+
+    fn or(self, other: Self) -> Self {
+        let value = self.value | other.value;
+        Self {
+            value,
+            mask: (self.mask | other.mask) & !value,
+        }
+    }
+
+    fn xor(self, other: Self) -> Self {
+        let mask = self.mask | other.mask;
+        Self {
+            value: (self.value ^ other.value) & !mask,
+            mask,
+        }
+    }
+
+    fn wrapping_shl(self, shift: u32) -> Self {
+        Self {
+            value: self.value.wrapping_shl(shift),
+            mask: self.mask.wrapping_shl(shift),
+        }
+    }
+
+    fn wrapping_shr(self, shift: u32) -> Self {
+        Self {
+            value: self.value.wrapping_shr(shift),
+            mask: self.mask.wrapping_shr(shift),
+        }
+    }
+
+    fn wrapping_arithmetic_shr(self, shift: u32) -> Self {
+        Self {
+            value: (self.value as i64).wrapping_shr(shift) as u64,
+            mask: (self.mask as i64).wrapping_shr(shift) as u64,
+        }
+    }
+
+    /// Smallest tnum containing both
+    fn union(self, other: Self) -> Self {
+        let mask = self.mask | other.mask | (self.value ^ other.value);
+        Self {
+            value: self.value & other.value & !mask,
+            mask,
+        }
+    }
+
+    /// Largest tnum contained in both, `None` if they have no number in common
+    fn intersect(self, other: Self) -> Option<Self> {
+        if (self.value ^ other.value) & !self.mask & !other.mask != 0 {
+            return None;
+        }
+        let mask = self.mask & other.mask;
+        Some(Self {
+            value: (self.value | other.value) & !mask,
+            mask,
+        })
+    }
+}
+
+/// The possible values of a register
+///
+/// Tracked as tnum, unsigned and signed interval at the same time, each of them refining the others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValueRange {
+    /// Known and unknown bits
+    pub tnum: Tnum,
+    /// Unsigned lower bound
+    pub umin: u64,
+    /// Unsigned upper bound
+    pub umax: u64,
+    /// Signed lower bound
+    pub smin: i64,
+    /// Signed upper bound
+    pub smax: i64,
+}
+
+impl ValueRange {
+    /// A single known value
+    pub fn constant(value: u64) -> Self {
+        Self {
+            tnum: Tnum::constant(value),
+            umin: value,
+            umax: value,
+            smin: value as i64,
+            smax: value as i64,
+        }
+    }
+
+    /// Any value
+    pub fn unknown() -> Self {
+        Self {
+            tnum: Tnum::unknown(),
+            umin: 0,
+            umax: u64::MAX,
+            smin: i64::MIN,
+            smax: i64::MAX,
+        }
+    }
+
+    /// All values from `min` to `max` (unsigned)
+    pub fn range(min: u64, max: u64) -> Self {
+        Self {
+            umin: min,
+            umax: max,
+            ..Self::from_tnum(Tnum::range(min, max))
+        }
+        .normalize()
+        .unwrap_or_else(Self::unknown)
+    }
+
+    fn from_tnum(tnum: Tnum) -> Self {
+        Self {
+            tnum,
+            ..Self::unknown()
+        }
+        .normalize()
+        .unwrap_or_else(Self::unknown)
+    }
+
+    /// Returns the value if there is only one
+    pub fn as_constant(&self) -> Option<u64> {
+        if self.tnum.is_constant() {
+            Some(self.tnum.value)
+        } else {
+            None
+        }
+    }
+
+    /// Tightens every representation using the others, `None` if no value remains
+    fn normalize(mut self) -> Option<Self> {
+        for _ in 0..2 {
+            self.umin = self.umin.max(self.tnum.value);
+            self.umax = self.umax.min(self.tnum.value | self.tnum.mask);
+            self.smin = self
+                .smin
+                .max((self.tnum.value | (self.tnum.mask & SIGN_BIT)) as i64);
+            self.smax = self
+                .smax
+                .min((self.tnum.value | (self.tnum.mask & !SIGN_BIT)) as i64);
+            // The unsigned interval does not cross the sign boundary
+            if (self.umin as i64) <= (self.umax as i64) {
+                self.smin = self.smin.max(self.umin as i64);
+                self.smax = self.smax.min(self.umax as i64);
+            }
+            // The signed interval does not cross the unsigned wrap around
+            if (self.smin as u64) <= (self.smax as u64) {
+                self.umin = self.umin.max(self.smin as u64);
+                self.umax = self.umax.min(self.smax as u64);
+            }
+            if self.umin > self.umax || self.smin > self.smax {
+                return None;
+            }
+            self.tnum = self.tnum.intersect(Tnum::range(self.umin, self.umax))?;
+        }
+        Some(self)
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        Self {
+            tnum: self.tnum.union(other.tnum),
+            umin: self.umin.min(other.umin),
+            umax: self.umax.max(other.umax),
+            smin: self.smin.min(other.smin),
+            smax: self.smax.max(other.smax),
+        }
+        .normalize()
+        .unwrap_or_else(Self::unknown)
+    }
+
+    /// Moves the bounds which grew since `self` to the extremes
+    fn widen(&self, joined: &Self) -> Self {
+        Self {
+            tnum: joined.tnum,
+            umin: if joined.umin < self.umin {
+                0
+            } else {
+                joined.umin
+            },
+            umax: if joined.umax > self.umax {
+                u64::MAX
+            } else {
+                joined.umax
+            },
+            smin: if joined.smin < self.smin {
+                i64::MIN
+            } else {
+                joined.smin
+            },
+            smax: if joined.smax > self.smax {
+                i64::MAX
+            } else {
+                joined.smax
+            },
+        }
+        .normalize()
+        .unwrap_or_else(Self::unknown)
+    }
+
+    fn intersect(&self, other: &Self) -> Option<Self> {
+        Self {
+            tnum: self.tnum.intersect(other.tnum)?,
+            umin: self.umin.max(other.umin),
+            umax: self.umax.min(other.umax),
+            smin: self.smin.max(other.smin),
+            smax: self.smax.min(other.smax),
+        }
+        .normalize()
+    }
+
+    fn with_bounds(tnum: Tnum, (umin, umax): (u64, u64), (smin, smax): (i64, i64)) -> Self {
+        Self {
+            tnum,
+            umin,
+            umax,
+            smin,
+            smax,
+        }
+        .normalize()
+        .unwrap_or_else(Self::unknown)
+    }
+
+    fn wrapping_add(&self, other: &Self) -> Self {
+        let unsigned = match (
+            self.umin.checked_add(other.umin),
+            self.umax.checked_add(other.umax),
+        ) {
+            (Some(umin), Some(umax)) => (umin, umax),
+            _ => (0, u64::MAX),
+        };
+        let signed = match (
+            self.smin.checked_add(other.smin),
+            self.smax.checked_add(other.smax),
+        ) {
+            (Some(smin), Some(smax)) => (smin, smax),
+            _ => (i64::MIN, i64::MAX),
+        };
+        Self::with_bounds(self.tnum.wrapping_add(other.tnum), unsigned, signed)
+    }
+
+    fn wrapping_sub(&self, other: &Self) -> Self {
+        let unsigned = if self.umin >= other.umax {
+            (self.umin - other.umax, self.umax - other.umin)
+        } else {
+            (0, u64::MAX)
+        };
+        let signed = match (
+            self.smin.checked_sub(other.smax),
+            self.smax.checked_sub(other.smin),
+        ) {
+            (Some(smin), Some(smax)) => (smin, smax),
+            _ => (i64::MIN, i64::MAX),
+        };
+        Self::with_bounds(self.tnum.wrapping_sub(other.tnum), unsigned, signed)
+    }
+
+    fn wrapping_mul(&self, other: &Self) -> Self {
+        let unsigned = if self.umax <= u32::MAX as u64 && other.umax <= u32::MAX as u64 {
+            (self.umin * other.umin, self.umax * other.umax)
+        } else {
+            (0, u64::MAX)
+        };
+        Self::with_bounds(
+            self.tnum.wrapping_mul(other.tnum),
+            unsigned,
+            (i64::MIN, i64::MAX),
+        )
+    }
+
+    fn and(&self, other: &Self) -> Self {
+        Self::with_bounds(
+            self.tnum.and(other.tnum),
+            (0, self.umax.min(other.umax)),
+            (i64::MIN, i64::MAX),
+        )
+    }
+
+    fn or(&self, other: &Self) -> Self {
+        Self::with_bounds(
+            self.tnum.or(other.tnum),
+            (self.umin.max(other.umin), u64::MAX),
+            (i64::MIN, i64::MAX),
+        )
+    }
+
+    fn xor(&self, other: &Self) -> Self {
+        Self::from_tnum(self.tnum.xor(other.tnum))
+    }
+
+    fn wrapping_shl(&self, shift: u32) -> Self {
+        let unsigned = if self.umax.leading_zeros() >= shift {
+            (self.umin << shift, self.umax << shift)
+        } else {
+            (0, u64::MAX)
+        };
+        Self::with_bounds(
+            self.tnum.wrapping_shl(shift),
+            unsigned,
+            (i64::MIN, i64::MAX),
+        )
+    }
+
+    fn wrapping_shr(&self, shift: u32) -> Self {
+        Self::with_bounds(
+            self.tnum.wrapping_shr(shift),
+            (self.umin >> shift, self.umax >> shift),
+            (i64::MIN, i64::MAX),
+        )
+    }
+
+    fn wrapping_arithmetic_shr(&self, shift: u32) -> Self {
+        Self::with_bounds(
+            self.tnum.wrapping_arithmetic_shr(shift),
+            (0, u64::MAX),
+            (self.smin >> shift, self.smax >> shift),
+        )
+    }
+
+    fn unsigned_div(&self, divisor: u64) -> Self {
+        Self::range(self.umin / divisor, self.umax / divisor)
+    }
+
+    fn unsigned_rem(&self, divisor: u64) -> Self {
+        if self.umax < divisor {
+            *self
+        } else {
+            Self::range(0, divisor - 1)
+        }
+    }
+
+    /// Keeps only the lower `bits`, zero extending them
+    fn truncate(&self, bits: u32) -> Self {
+        if bits >= 64 {
+            return *self;
+        }
+        let max = (1u64 << bits) - 1;
+        if self.umax <= max {
+            return *self;
+        }
+        Self::from_tnum(Tnum {
+            value: self.tnum.value & max,
+            mask: self.tnum.mask & max,
+        })
+    }
+
+    /// Sign extends the lower `bits`, expects the value to be truncated to them already
+    fn sign_extend(&self, bits: u32) -> Self {
+        let sign = 1u64 << (bits - 1);
+        if self.umax < sign {
+            return *self;
+        }
+        let upper = !((sign << 1) - 1);
+        let mut tnum = self.tnum;
+        if tnum.mask & sign != 0 {
+            tnum.mask |= upper;
+        } else if tnum.value & sign != 0 {
+            tnum.value |= upper;
+        }
+        let signed = if self.umin >= sign {
+            ((self.umin | upper) as i64, (self.umax | upper) as i64)
+        } else {
+            (-(sign as i64), (sign - 1) as i64)
+        };
+        Self::with_bounds(tnum, (0, u64::MAX), signed)
+    }
+
+    /// Any value loaded from memory by an access of `bits` width
+    fn loaded(bits: u32, sign_extend: bool) -> Self {
+        let value = Self::unknown().truncate(bits);
+        if sign_extend {
+            value.sign_extend(bits)
+        } else {
+            value
+        }
+    }
+}
+
+/// Abstract type of a register
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterType {
+    /// Not written on at least one path
+    Uninitialized,
+    /// A number, or a pointer whose region is unknown
+    Scalar,
+    /// Pointer into the stack
+    StackPointer,
+    /// Pointer into the input region
+    InputPointer,
+    /// Pointer into the heap
+    HeapPointer,
+    /// Pointer into the read-only section of the executable
+    ReadOnlyPointer,
+}
+
+impl RegisterType {
+    /// The pointer type of the region a virtual address belongs to
+    fn from_vm_addr(vm_addr: u64) -> Option<Self> {
+        match vm_addr / REGION_SIZE {
+            1 => Some(Self::ReadOnlyPointer),
+            2 => Some(Self::StackPointer),
+            3 => Some(Self::HeapPointer),
+            4 => Some(Self::InputPointer),
+            _ => None,
+        }
+    }
+
+    /// Virtual address which the values of this pointer type are relative to
+    fn region_start(self) -> u64 {
+        match self {
+            Self::ReadOnlyPointer => ebpf::MM_PROGRAM_START,
+            Self::StackPointer => ebpf::MM_STACK_START,
+            Self::HeapPointer => ebpf::MM_HEAP_START,
+            Self::InputPointer => ebpf::MM_INPUT_START,
+            Self::Uninitialized | Self::Scalar => 0,
+        }
+    }
+
+    fn is_pointer(self) -> bool {
+        !matches!(self, Self::Uninitialized | Self::Scalar)
+    }
+}
+
+impl std::fmt::Display for RegisterType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Uninitialized => "uninitialized register",
+            Self::Scalar => "scalar",
+            Self::StackPointer => "stack pointer",
+            Self::InputPointer => "input pointer",
+            Self::HeapPointer => "heap pointer",
+            Self::ReadOnlyPointer => "read-only pointer",
+        })
+    }
+}
+
+/// Abstract state of a register
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterState {
+    /// Type
+    pub reg_type: RegisterType,
+    /// Possible values, relative to the start of the memory region for pointers
+    pub value: ValueRange,
+}
+
+impl RegisterState {
+    fn uninitialized() -> Self {
+        Self {
+            reg_type: RegisterType::Uninitialized,
+            value: ValueRange::unknown(),
+        }
+    }
+
+    fn scalar(value: ValueRange) -> Self {
+        Self {
+            reg_type: RegisterType::Scalar,
+            value,
+        }
+    }
+
+    fn pointer(reg_type: RegisterType, value: ValueRange) -> Self {
+        Self { reg_type, value }
+    }
+
+    /// A known virtual address, typed by the region it belongs to
+    fn from_vm_addr(vm_addr: u64) -> Self {
+        match RegisterType::from_vm_addr(vm_addr) {
+            Some(reg_type) => Self::pointer(
+                reg_type,
+                ValueRange::constant(vm_addr - reg_type.region_start()),
+            ),
+            None => Self::scalar(ValueRange::constant(vm_addr)),
+        }
+    }
+
+    /// The possible values as plain numbers (virtual addresses for pointers)
+    fn absolute(&self) -> ValueRange {
+        if self.reg_type.is_pointer() {
+            self.value
+                .wrapping_add(&ValueRange::constant(self.reg_type.region_start()))
+        } else {
+            self.value
+        }
+    }
+
+    fn join(&self, other: &Self) -> Self {
+        if self.reg_type == RegisterType::Uninitialized
+            || other.reg_type == RegisterType::Uninitialized
+        {
+            Self::uninitialized()
+        } else if self.reg_type == other.reg_type {
+            Self::pointer(self.reg_type, self.value.join(&other.value))
+        } else {
+            Self::scalar(self.absolute().join(&other.absolute()))
+        }
+    }
+
+    fn widen(&self, joined: &Self) -> Self {
+        if self.reg_type == joined.reg_type {
+            Self::pointer(joined.reg_type, self.value.widen(&joined.value))
+        } else {
+            *joined
+        }
+    }
+}
+
+type RegisterFile = [RegisterState; 11];
+
+/// Problems found by the abstract interpretation
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    /// A register is read on a path where it was not written
+    #[error("r{0} is read before it is written")]
+    UninitializedRegister(u8),
+    /// A memory access of the given length can not hit the region its base register points into
+    #[error("out of bounds access of {1} bytes through a {0}")]
+    OutOfBoundsAccess(RegisterType, u64),
+    /// A store to the read-only section
+    #[error("store to read-only memory")]
+    WriteToReadOnly,
+}
+
+/// A problem found at a specific instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Instruction index
+    pub pc: usize,
+    /// What was found
+    pub kind: DiagnosticKind,
+}
+
+/// Interprets all functions of the analysis abstractly
+///
+/// Returns the diagnostics ordered by instruction index, the program passes if there are none.
+pub fn check<E: UserDefinedError, I: InstructionMeter>(
+    analysis: &Analysis<E, I>,
+) -> Vec<Diagnostic> {
+    AbstractInterpreter::new(analysis).run()
+}
+
+struct AbstractInterpreter<'a, 'b, E: UserDefinedError, I: InstructionMeter> {
+    analysis: &'b Analysis<'a, E, I>,
+    enable_ebpf_v4: bool,
+    /// Offsets of the read-only section relative to MM_PROGRAM_START
+    read_only_section: Range<u64>,
+    stack_len: u64,
+    /// Size of the gaps between the stack frames, if any
+    stack_gap_size: Option<u64>,
+}
+
+impl<'a, 'b, E: UserDefinedError, I: InstructionMeter> AbstractInterpreter<'a, 'b, E, I> {
+    fn new(analysis: &'b Analysis<'a, E, I>) -> Self {
+        let executable = analysis.executable;
+        let config = executable.get_config();
+        let ro_region = executable.get_ro_region();
+        let read_only_start = ro_region.vm_addr - ebpf::MM_PROGRAM_START;
+        let stack_gap_size = if !executable.get_sbf_version().dynamic_stack_frames()
+            && config.enable_stack_frame_gaps
+            && config.stack_frame_size > 0
+        {
+            // The memory region rounds the gap size down to a power of two
+            Some(1u64 << (63 - (config.stack_frame_size as u64).leading_zeros()))
+        } else {
+            None
+        };
+        Self {
+            analysis,
+            enable_ebpf_v4: config.enable_ebpf_v4,
+            read_only_section: read_only_start..read_only_start + ro_region.len,
+            stack_len: config.stack_size() as u64,
+            stack_gap_size,
+        }
+    }
+
+    /// Register states at the start of every function
+    fn entry_states(&self) -> BTreeMap<usize, RegisterFile> {
+        let executable = self.analysis.executable;
+        let config = executable.get_config();
+        let frame_size = config.stack_frame_size as u64;
+        // Offsets of the frame pointer relative to MM_STACK_START
+        let (entry_frame_ptr, callee_frame_ptr) =
+            if executable.get_sbf_version().dynamic_stack_frames() {
+                (
+                    ValueRange::constant(self.stack_len),
+                    ValueRange::range(0, self.stack_len),
+                )
+            } else {
+                let frame_stride = if config.enable_stack_frame_gaps {
+                    frame_size * 2
+                } else {
+                    frame_size
+                };
+                let frame_ptr = |frame_index: usize| {
+                    ValueRange::constant(frame_size + frame_stride * frame_index as u64)
+                };
+                (
+                    frame_ptr(0),
+                    (2..config.max_call_depth).fold(frame_ptr(1), |frame_ptrs, frame_index| {
+                        frame_ptrs.join(&frame_ptr(frame_index))
+                    }),
+                )
+            };
+        let mut entry_state = [RegisterState::uninitialized(); 11];
+        entry_state[1] =
+            RegisterState::pointer(RegisterType::InputPointer, ValueRange::constant(0));
+        entry_state[ebpf::FRAME_PTR_REG] =
+            RegisterState::pointer(RegisterType::StackPointer, entry_frame_ptr);
+        let mut callee_state = [RegisterState::uninitialized(); 11];
+        // Functions returning nothing exit without writing r0
+        for register in callee_state[0..=5].iter_mut() {
+            *register = RegisterState::scalar(ValueRange::unknown());
+        }
+        callee_state[ebpf::FRAME_PTR_REG] =
+            RegisterState::pointer(RegisterType::StackPointer, callee_frame_ptr);
+        let entrypoint = self.analysis.entrypoint;
+        let entrypoint_is_called = self.analysis.instructions.iter().any(|insn| {
+            insn.opc == ebpf::CALL_REG
+                || (insn.opc == ebpf::CALL_IMM
+                    && executable.lookup_bpf_function(insn.imm as u32) == Some(entrypoint))
+        });
+        let mut states = BTreeMap::new();
+        for pc in self.analysis.functions.keys() {
+            states.insert(*pc, callee_state);
+        }
+        states.insert(
+            entrypoint,
+            if entrypoint_is_called {
+                join(&entry_state, &callee_state)
+            } else {
+                entry_state
+            },
+        );
+        states
+    }
+
+    fn run(&self) -> Vec<Diagnostic> {
+        let mut states = self.entry_states();
+        let mut visits = BTreeMap::<usize, usize>::new();
+        let mut worklist = states.keys().cloned().collect::<BTreeSet<usize>>();
+        let mut ignored_diagnostics = Vec::new();
+        while let Some(pc) = worklist.iter().next().cloned() {
+            worklist.remove(&pc);
+            let cfg_node = match self.analysis.cfg_nodes.get(&pc) {
+                Some(cfg_node) => cfg_node,
+                None => continue,
+            };
+            let successors =
+                self.execute_basic_block(cfg_node, states[&pc], &mut ignored_diagnostics);
+            ignored_diagnostics.clear();
+            for (destination, state) in successors {
+                let state = match states.get(&destination) {
+                    Some(old_state) => {
+                        let visit_count = visits.entry(destination).or_insert(0);
+                        *visit_count += 1;
+                        let joined = join(old_state, &state);
+                        let new_state = if *visit_count > WIDENING_THRESHOLD {
+                            widen(old_state, &joined)
+                        } else {
+                            joined
+                        };
+                        if &new_state == old_state {
+                            continue;
+                        }
+                        new_state
+                    }
+                    None => state,
+                };
+                states.insert(destination, state);
+                worklist.insert(destination);
+            }
+        }
+        for _ in 0..NARROWING_ITERATIONS {
+            let mut narrowed_states = self.entry_states();
+            for (pc, state) in states.iter() {
+                if let Some(cfg_node) = self.analysis.cfg_nodes.get(pc) {
+                    for (destination, state) in
+                        self.execute_basic_block(cfg_node, *state, &mut ignored_diagnostics)
+                    {
+                        let state = match narrowed_states.get(&destination) {
+                            Some(other_state) => join(other_state, &state),
+                            None => state,
+                        };
+                        narrowed_states.insert(destination, state);
+                    }
+                }
+            }
+            ignored_diagnostics.clear();
+            states = narrowed_states;
+        }
+        let mut diagnostics = Vec::new();
+        for (pc, state) in states.iter() {
+            if let Some(cfg_node) = self.analysis.cfg_nodes.get(pc) {
+                self.execute_basic_block(cfg_node, *state, &mut diagnostics);
+            }
+        }
+        diagnostics.sort_by_key(|diagnostic| diagnostic.pc);
+        diagnostics.dedup();
+        diagnostics
+    }
+
+    /// Returns the states at the beginning of the successors which can be reached
+    fn execute_basic_block(
+        &self,
+        cfg_node: &CfgNode,
+        mut state: RegisterFile,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Vec<(usize, RegisterFile)> {
+        let instructions = &self.analysis.instructions[cfg_node.instructions.clone()];
+        for insn in instructions.iter() {
+            self.execute_instruction(&mut state, insn, diagnostics);
+        }
+        let last_insn = match instructions.last() {
+            Some(insn) => insn,
+            None => return Vec::new(),
+        };
+        let is_conditional_jump = matches!(
+            last_insn.opc & ebpf::BPF_CLS_MASK,
+            ebpf::BPF_JMP | ebpf::BPF_JMP32
+        ) && !matches!(
+            last_insn.opc & ebpf::BPF_ALU_OP_MASK,
+            ebpf::BPF_JA | ebpf::BPF_CALL | ebpf::BPF_EXIT
+        );
+        cfg_node
+            .destinations
+            .iter()
+            .filter_map(|destination| {
+                if !is_conditional_jump {
+                    return Some((*destination, state));
+                }
+                let target_pc = (last_insn.ptr as isize + last_insn.off as isize + 1) as usize;
+                let taken = if *destination == target_pc {
+                    self.branch(&state, last_insn, true)
+                } else {
+                    None
+                };
+                let not_taken = if *destination == last_insn.ptr + 1 {
+                    self.branch(&state, last_insn, false)
+                } else {
+                    None
+                };
+                match (taken, not_taken) {
+                    (Some(taken), Some(not_taken)) => Some(join(&taken, &not_taken)),
+                    (taken, not_taken) => taken.or(not_taken),
+                }
+                .map(|state| (*destination, state))
+            })
+            .collect()
+    }
+
+    fn read(
+        &self,
+        state: &RegisterFile,
+        reg: u8,
+        pc: usize,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> RegisterState {
+        let register = state[reg as usize];
+        if register.reg_type == RegisterType::Uninitialized {
+            diagnostics.push(Diagnostic {
+                pc,
+                kind: DiagnosticKind::UninitializedRegister(reg),
+            });
+            RegisterState::scalar(ValueRange::unknown())
+        } else {
+            register
+        }
+    }
+
+    fn execute_instruction(
+        &self,
+        state: &mut RegisterFile,
+        insn: &ebpf::Insn,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        // Rejected by the first stage verifier, or adjusts the stack pointer r11
+        if insn.dst as usize > ebpf::FRAME_PTR_REG || insn.src as usize > ebpf::FRAME_PTR_REG {
+            return;
+        }
+        let pc = insn.ptr;
+        let len = match insn.opc & 0x18 {
+            ebpf::BPF_B => 1,
+            ebpf::BPF_H => 2,
+            ebpf::BPF_W => 4,
+            _ => 8,
+        };
+        let check_access =
+            |base: RegisterState, is_store: bool, diagnostics: &mut Vec<Diagnostic>| {
+                if let Some(kind) = self.check_access(&base, insn.off, len, is_store) {
+                    diagnostics.push(Diagnostic { pc, kind });
+                }
+            };
+        match insn.opc & ebpf::BPF_CLS_MASK {
+            ebpf::BPF_LD => {
+                if insn.opc == ebpf::LD_DW_IMM {
+                    state[insn.dst as usize] = RegisterState::from_vm_addr(insn.imm as u64);
+                }
+            }
+            ebpf::BPF_LDX => {
+                let base = self.read(state, insn.src, pc, diagnostics);
+                check_access(base, false, diagnostics);
+                state[insn.dst as usize] = RegisterState::scalar(ValueRange::loaded(
+                    len as u32 * 8,
+                    insn.opc & 0xe0 == ebpf::BPF_MEMSX,
+                ));
+            }
+            ebpf::BPF_ST => {
+                let base = self.read(state, insn.dst, pc, diagnostics);
+                check_access(base, true, diagnostics);
+            }
+            ebpf::BPF_STX => {
+                let base = self.read(state, insn.dst, pc, diagnostics);
+                self.read(state, insn.src, pc, diagnostics);
+                if insn.opc & 0xe0 == ebpf::BPF_ATOMIC {
+                    let operation = ebpf::atomic_operation(insn.imm);
+                    if operation == Some(ebpf::BPF_CMPXCHG) {
+                        self.read(state, 0, pc, diagnostics);
+                    }
+                    check_access(base, true, diagnostics);
+                    let old_value =
+                        RegisterState::scalar(ValueRange::loaded(len as u32 * 8, false));
+                    match operation {
+                        Some(ebpf::BPF_CMPXCHG) => state[0] = old_value,
+                        Some(operation) if operation & ebpf::BPF_FETCH != 0 => {
+                            state[insn.src as usize] = old_value
+                        }
+                        _ => {}
+                    }
+                } else {
+                    check_access(base, true, diagnostics);
+                }
+            }
+            ebpf::BPF_ALU | ebpf::BPF_ALU64 => {
+                state[insn.dst as usize] = self.execute_alu(state, insn, diagnostics);
+            }
+            _ => match insn.opc {
+                ebpf::JA | ebpf::GOTOL => {}
+                ebpf::CALL_IMM | ebpf::CALL_REG => {
+                    if insn.opc == ebpf::CALL_REG && insn.imm >= 0 && insn.imm <= 10 {
+                        self.read(state, insn.imm as u8, pc, diagnostics);
+                    }
+                    // The arguments are clobbered, the callee saved registers are restored
+                    state[0] = RegisterState::scalar(ValueRange::unknown());
+                    for register in state[1..=5].iter_mut() {
+                        *register = RegisterState::uninitialized();
+                    }
+                }
+                ebpf::EXIT => {
+                    self.read(state, 0, pc, diagnostics);
+                }
+                _ => {
+                    self.read(state, insn.dst, pc, diagnostics);
+                    if insn.opc & ebpf::BPF_X != 0 {
+                        self.read(state, insn.src, pc, diagnostics);
+                    }
+                }
+            },
+        }
+    }
+
+    fn execute_alu(
+        &self,
+        state: &RegisterFile,
+        insn: &ebpf::Insn,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> RegisterState {
+        let is_64 = insn.opc & ebpf::BPF_CLS_MASK == ebpf::BPF_ALU64;
+        let operation = insn.opc & ebpf::BPF_ALU_OP_MASK;
+        let dst = if operation == ebpf::BPF_MOV {
+            RegisterState::uninitialized()
+        } else {
+            self.read(state, insn.dst, insn.ptr, diagnostics)
+        };
+        if operation == ebpf::BPF_END {
+            return RegisterState::scalar(byte_swap(insn, &dst.absolute()));
+        }
+        let src = if insn.opc & ebpf::BPF_X != 0 {
+            self.read(state, insn.src, insn.ptr, diagnostics)
+        } else {
+            RegisterState::scalar(ValueRange::constant(insn.imm as u64))
+        };
+        // Sign extending moves and signed modulo are encoded in the offset
+        let is_signed_modulo = self.enable_ebpf_v4 && insn.off == 1;
+        let sign_extend_from = if self.enable_ebpf_v4 && matches!(insn.off, 8 | 16 | 32) {
+            Some(insn.off as u32)
+        } else {
+            None
+        };
+        if !is_64 {
+            let dst = dst.absolute().truncate(32);
+            let src = src.absolute().truncate(32);
+            let result = match operation {
+                ebpf::BPF_MOV => match sign_extend_from {
+                    Some(bits) => src.truncate(bits).sign_extend(bits).truncate(32),
+                    None => src,
+                },
+                ebpf::BPF_ADD | ebpf::BPF_SUB | ebpf::BPF_MUL => {
+                    // These sign extend their result to 64 bits
+                    alu(operation, &dst, &src, 31).truncate(32).sign_extend(32)
+                }
+                ebpf::BPF_ARSH => match src.as_constant() {
+                    Some(shift) => dst
+                        .sign_extend(32)
+                        .wrapping_arithmetic_shr(shift as u32 & 31)
+                        .truncate(32),
+                    None => ValueRange::unknown().truncate(32),
+                },
+                ebpf::BPF_SDIV => ValueRange::unknown(),
+                ebpf::BPF_MOD if is_signed_modulo => ValueRange::unknown().truncate(32),
+                _ => alu(operation, &dst, &src, 31).truncate(32),
+            };
+            return RegisterState::scalar(result);
+        }
+        match operation {
+            ebpf::BPF_MOV => match sign_extend_from {
+                Some(bits) => {
+                    RegisterState::scalar(src.absolute().truncate(bits).sign_extend(bits))
+                }
+                None => src,
+            },
+            ebpf::BPF_ADD if dst.reg_type.is_pointer() && !src.reg_type.is_pointer() => {
+                RegisterState::pointer(dst.reg_type, dst.value.wrapping_add(&src.value))
+            }
+            ebpf::BPF_ADD if src.reg_type.is_pointer() && !dst.reg_type.is_pointer() => {
+                RegisterState::pointer(src.reg_type, src.value.wrapping_add(&dst.value))
+            }
+            ebpf::BPF_SUB if dst.reg_type.is_pointer() && !src.reg_type.is_pointer() => {
+                RegisterState::pointer(dst.reg_type, dst.value.wrapping_sub(&src.value))
+            }
+            ebpf::BPF_SUB if dst.reg_type.is_pointer() && dst.reg_type == src.reg_type => {
+                RegisterState::scalar(dst.value.wrapping_sub(&src.value))
+            }
+            ebpf::BPF_SDIV => RegisterState::scalar(ValueRange::unknown()),
+            ebpf::BPF_MOD if is_signed_modulo => RegisterState::scalar(ValueRange::unknown()),
+            _ => RegisterState::scalar(alu(operation, &dst.absolute(), &src.absolute(), 63)),
+        }
+    }
+
+    /// Returns the refined register states if the branch can be taken (or not taken)
+    fn branch(&self, state: &RegisterFile, insn: &ebpf::Insn, taken: bool) -> Option<RegisterFile> {
+        let is_64 = insn.opc & ebpf::BPF_CLS_MASK == ebpf::BPF_JMP;
+        let operation = insn.opc & ebpf::BPF_ALU_OP_MASK;
+        let is_reg = insn.opc & ebpf::BPF_X != 0;
+        let dst = state[insn.dst as usize];
+        let src = if is_reg {
+            state[insn.src as usize]
+        } else if is_64 {
+            RegisterState::scalar(ValueRange::constant(insn.imm as u64))
+        } else {
+            RegisterState::scalar(ValueRange::constant(insn.imm as u32 as u64))
+        };
+        // Comparisons involving pointers are not refined
+        if dst.reg_type != RegisterType::Scalar || src.reg_type != RegisterType::Scalar {
+            return Some(*state);
+        }
+        let is_signed = matches!(
+            operation,
+            ebpf::BPF_JSGT | ebpf::BPF_JSGE | ebpf::BPF_JSLT | ebpf::BPF_JSLE
+        );
+        // 32 bit comparisons only see the lower half
+        let narrow = |value: &ValueRange| {
+            if is_64 {
+                *value
+            } else if is_signed {
+                value.truncate(32).sign_extend(32)
+            } else {
+                value.truncate(32)
+            }
+        };
+        let is_narrow = |value: &ValueRange| {
+            is_64
+                || if is_signed {
+                    value.smin >= i32::MIN as i64 && value.smax <= i32::MAX as i64
+                } else {
+                    value.umax <= u32::MAX as u64
+                }
+        };
+        let (dst_value, src_value) =
+            refine(operation, taken, narrow(&dst.value), narrow(&src.value))?;
+        let mut state = *state;
+        if is_narrow(&dst.value) {
+            state[insn.dst as usize].value = dst_value;
+        }
+        if is_reg && insn.src != insn.dst && is_narrow(&src.value) {
+            state[insn.src as usize].value = src_value;
+        }
+        Some(state)
+    }
+
+    /// Offsets relative to the start of the region a pointer type can access
+    fn valid_offsets(&self, reg_type: RegisterType) -> Range<u64> {
+        match reg_type {
+            RegisterType::ReadOnlyPointer => self.read_only_section.clone(),
+            RegisterType::StackPointer if self.stack_gap_size.is_some() => 0..self.stack_len * 2,
+            RegisterType::StackPointer => 0..self.stack_len,
+            _ => 0..REGION_SIZE,
+        }
+    }
+
+    fn check_access(
+        &self,
+        base: &RegisterState,
+        off: i16,
+        len: u64,
+        is_store: bool,
+    ) -> Option<DiagnosticKind> {
+        let displacement = ValueRange::constant(off as i64 as u64);
+        let (reg_type, offset) = match base.reg_type {
+            RegisterType::Uninitialized => return None,
+            RegisterType::Scalar => {
+                let vm_addr = base.value.wrapping_add(&displacement);
+                // The region below the read-only section is never mapped
+                if vm_addr.umax < ebpf::MM_PROGRAM_START {
+                    return Some(DiagnosticKind::OutOfBoundsAccess(base.reg_type, len));
+                }
+                let reg_type = RegisterType::from_vm_addr(vm_addr.umin).filter(|reg_type| {
+                    RegisterType::from_vm_addr(vm_addr.umax) == Some(*reg_type)
+                })?;
+                (
+                    reg_type,
+                    vm_addr.wrapping_sub(&ValueRange::constant(reg_type.region_start())),
+                )
+            }
+            reg_type => (reg_type, base.value.wrapping_add(&displacement)),
+        };
+        if is_store && reg_type == RegisterType::ReadOnlyPointer {
+            return Some(DiagnosticKind::WriteToReadOnly);
+        }
+        let valid_offsets = self.valid_offsets(reg_type);
+        let out_of_bounds = match valid_offsets
+            .end
+            .checked_sub(len)
+            .filter(|last| *last >= valid_offsets.start)
+        {
+            Some(last) => {
+                offset.smax < valid_offsets.start as i64
+                    || offset.smin > last as i64
+                    || offset.umax < valid_offsets.start
+                    || offset.umin > last
+            }
+            None => true,
+        };
+        let in_stack_gap = match (self.stack_gap_size, reg_type, offset.as_constant()) {
+            (Some(gap_size), RegisterType::StackPointer, Some(begin))
+                if begin < valid_offsets.end =>
+            {
+                // Mirrors the address translation of gapped memory regions
+                let is_in_gap = (begin / gap_size) & 1 == 1;
+                let gapped_offset = ((begin & !(gap_size - 1)) >> 1) | (begin & (gap_size - 1));
+                is_in_gap || gapped_offset + len > self.stack_len
+            }
+            _ => false,
+        };
+        if out_of_bounds || in_stack_gap {
+            Some(DiagnosticKind::OutOfBoundsAccess(base.reg_type, len))
+        } else {
+            None
+        }
+    }
+}
+
+fn join(a: &RegisterFile, b: &RegisterFile) -> RegisterFile {
+    let mut result = *a;
+    for (register, other) in result.iter_mut().zip(b.iter()) {
+        *register = register.join(other);
+    }
+    result
+}
+
+fn widen(old: &RegisterFile, joined: &RegisterFile) -> RegisterFile {
+    let mut result = *joined;
+    for (register, old) in result.iter_mut().zip(old.iter()) {
+        *register = old.widen(register);
+    }
+    result
+}
+
+/// Evaluates an ALU operation on scalars, `shift_mask` selects the bits of a shift amount used
+fn alu(operation: u8, dst: &ValueRange, src: &ValueRange, shift_mask: u64) -> ValueRange {
+    let constant_src = src.as_constant();
+    match operation {
+        ebpf::BPF_ADD => dst.wrapping_add(src),
+        ebpf::BPF_SUB => dst.wrapping_sub(src),
+        ebpf::BPF_MUL => dst.wrapping_mul(src),
+        ebpf::BPF_OR => dst.or(src),
+        ebpf::BPF_AND => dst.and(src),
+        ebpf::BPF_XOR => dst.xor(src),
+        ebpf::BPF_MOV => *src,
+        ebpf::BPF_NEG => ValueRange::constant(0).wrapping_sub(dst),
+        ebpf::BPF_LSH => match constant_src {
+            Some(shift) => dst.wrapping_shl((shift & shift_mask) as u32),
+            None => ValueRange::unknown(),
+        },
+        ebpf::BPF_RSH => match constant_src {
+            Some(shift) => dst.wrapping_shr((shift & shift_mask) as u32),
+            None => ValueRange::unknown(),
+        },
+        ebpf::BPF_ARSH => match constant_src {
+            Some(shift) => dst.wrapping_arithmetic_shr((shift & shift_mask) as u32),
+            None => ValueRange::unknown(),
+        },
+        ebpf::BPF_DIV => match constant_src.filter(|divisor| *divisor != 0) {
+            Some(divisor) => dst.unsigned_div(divisor),
+            None => ValueRange::unknown(),
+        },
+        ebpf::BPF_MOD => match constant_src.filter(|divisor| *divisor != 0) {
+            Some(divisor) => dst.unsigned_rem(divisor),
+            None => ValueRange::unknown(),
+        },
+        _ => ValueRange::unknown(),
+    }
+}
+
+/// Evaluates LE, BE and BSWAP
+fn byte_swap(insn: &ebpf::Insn, value: &ValueRange) -> ValueRange {
+    let bits = insn.imm.clamp(0, 64) as u32;
+    if insn.opc == ebpf::LE {
+        return value.truncate(bits);
+    }
+    match value.as_constant() {
+        Some(value) => ValueRange::constant(match bits {
+            16 => (value as u16).swap_bytes() as u64,
+            32 => (value as u32).swap_bytes() as u64,
+            _ => value.swap_bytes(),
+        }),
+        None => ValueRange::unknown().truncate(bits),
+    }
+}
+
+/// Narrows the operands of a conditional jump, `None` if the condition can not hold
+fn refine(
+    operation: u8,
+    taken: bool,
+    a: ValueRange,
+    b: ValueRange,
+) -> Option<(ValueRange, ValueRange)> {
+    let swap = |(b, a): (ValueRange, ValueRange)| (a, b);
+    match (operation, taken) {
+        (ebpf::BPF_JEQ, true) | (ebpf::BPF_JNE, false) => {
+            let both = a.intersect(&b)?;
+            Some((both, both))
+        }
+        (ebpf::BPF_JEQ, false) | (ebpf::BPF_JNE, true) => not_equal(a, b),
+        (ebpf::BPF_JGT, true) | (ebpf::BPF_JLE, false) => greater(a, b, 1),
+        (ebpf::BPF_JGE, true) | (ebpf::BPF_JLT, false) => greater(a, b, 0),
+        (ebpf::BPF_JLT, true) | (ebpf::BPF_JGE, false) => greater(b, a, 1).map(swap),
+        (ebpf::BPF_JLE, true) | (ebpf::BPF_JGT, false) => greater(b, a, 0).map(swap),
+        (ebpf::BPF_JSGT, true) | (ebpf::BPF_JSLE, false) => signed_greater(a, b, 1),
+        (ebpf::BPF_JSGE, true) | (ebpf::BPF_JSLT, false) => signed_greater(a, b, 0),
+        (ebpf::BPF_JSLT, true) | (ebpf::BPF_JSGE, false) => signed_greater(b, a, 1).map(swap),
+        (ebpf::BPF_JSLE, true) | (ebpf::BPF_JSGT, false) => signed_greater(b, a, 0).map(swap),
+        (ebpf::BPF_JSET, true) => {
+            if a.tnum.and(b.tnum) == Tnum::constant(0) {
+                return None;
+            }
+            Some((bit_set(a, &b)?, bit_set(b, &a)?))
+        }
+        (ebpf::BPF_JSET, false) => {
+            if a.tnum.and(b.tnum).value != 0 {
+                return None;
+            }
+            Some((bits_clear(a, &b)?, bits_clear(b, &a)?))
+        }
+        _ => Some((a, b)),
+    }
+}
+
+fn not_equal(a: ValueRange, b: ValueRange) -> Option<(ValueRange, ValueRange)> {
+    let exclude = |mut value: ValueRange, excluded: Option<u64>| {
+        if let Some(excluded) = excluded {
+            if value.umin == excluded {
+                value.umin = value.umin.checked_add(1)?;
+            }
+            if value.umax == excluded {
+                value.umax = value.umax.checked_sub(1)?;
+            }
+            if value.smin == excluded as i64 {
+                value.smin = value.smin.checked_add(1)?;
+            }
+            if value.smax == excluded as i64 {
+                value.smax = value.smax.checked_sub(1)?;
+            }
+        }
+        value.normalize()
+    };
+    Some((exclude(a, b.as_constant())?, exclude(b, a.as_constant())?))
+}
+
+/// a >= b + by
+fn greater(mut a: ValueRange, mut b: ValueRange, by: u64) -> Option<(ValueRange, ValueRange)> {
+    a.umin = a.umin.max(b.umin.checked_add(by)?);
+    b.umax = b.umax.min(a.umax.checked_sub(by)?);
+    Some((a.normalize()?, b.normalize()?))
+}
+
+/// a >= b + by, signed
+fn signed_greater(
+    mut a: ValueRange,
+    mut b: ValueRange,
+    by: i64,
+) -> Option<(ValueRange, ValueRange)> {
+    a.smin = a.smin.max(b.smin.checked_add(by)?);
+    b.smax = b.smax.min(a.smax.checked_sub(by)?);
+    Some((a.normalize()?, b.normalize()?))
+}
+
+/// a & b != 0, a single bit in b must be set in a
+fn bit_set(a: ValueRange, b: &ValueRange) -> Option<ValueRange> {
+    match b.as_constant() {
+        Some(bit) if bit.is_power_of_two() => a.intersect(&ValueRange::from_tnum(Tnum {
+            value: bit,
+            mask: !bit,
+        })),
+        _ => Some(a),
+    }
+}
+
+/// a & b == 0, the bits of a constant b must be clear in a
+fn bits_clear(a: ValueRange, b: &ValueRange) -> Option<ValueRange> {
+    match b.as_constant() {
+        Some(bits) => a.intersect(&ValueRange::from_tnum(Tnum {
+            value: 0,
+            mask: !bits,
+        })),
+        None => Some(a),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    fn random_range(rng: &mut SmallRng) -> (ValueRange, Vec<u64>) {
+        let samples = match rng.gen_range(0..4) {
+            0 => vec![rng.gen::<u64>()],
+            1 => {
+                let base = rng.gen::<u8>() as u64;
+                (0..8).map(|_| base + rng.gen_range(0..16)).collect()
+            }
+            2 => {
+                let base = rng.gen::<i16>() as i64 as u64;
+                (0..8)
+                    .map(|_| base.wrapping_add(rng.gen_range(0..1024)))
+                    .collect()
+            }
+            _ => (0..8).map(|_| rng.gen::<u64>()).collect(),
+        };
+        let range = samples
+            .iter()
+            .skip(1)
+            .fold(ValueRange::constant(samples[0]), |range, sample| {
+                range.join(&ValueRange::constant(*sample))
+            });
+        (range, samples)
+    }
+
+    fn contains(range: &ValueRange, value: u64) -> bool {
+        range.tnum.contains(value)
+            && range.umin <= value
+            && value <= range.umax
+            && range.smin <= value as i64
+            && value as i64 <= range.smax
+    }
+
+    #[test]
+    fn test_tnum_range() {
+        assert_eq!(Tnum::range(5, 5), Tnum::constant(5));
+        assert_eq!(Tnum::range(0, 7), Tnum { value: 0, mask: 7 });
+        assert_eq!(Tnum::range(8, 12), Tnum { value: 8, mask: 7 });
+        assert_eq!(Tnum::range(0, u64::MAX), Tnum::unknown());
+    }
+
+    #[test]
+    fn test_alu_soundness() {
+        let mut rng = SmallRng::from_seed([0; 32]);
+        for _ in 0..2_000 {
+            let (a, a_samples) = random_range(&mut rng);
+            let (b, b_samples) = random_range(&mut rng);
+            for operation in [
+                ebpf::BPF_ADD,
+                ebpf::BPF_SUB,
+                ebpf::BPF_MUL,
+                ebpf::BPF_OR,
+                ebpf::BPF_AND,
+                ebpf::BPF_XOR,
+                ebpf::BPF_LSH,
+                ebpf::BPF_RSH,
+                ebpf::BPF_ARSH,
+                ebpf::BPF_DIV,
+                ebpf::BPF_MOD,
+            ] {
+                let result = alu(operation, &a, &b, 63);
+                for x in a_samples.iter() {
+                    for y in b_samples.iter() {
+                        let expected = match operation {
+                            ebpf::BPF_ADD => x.wrapping_add(*y),
+                            ebpf::BPF_SUB => x.wrapping_sub(*y),
+                            ebpf::BPF_MUL => x.wrapping_mul(*y),
+                            ebpf::BPF_OR => x | y,
+                            ebpf::BPF_AND => x & y,
+                            ebpf::BPF_XOR => x ^ y,
+                            ebpf::BPF_LSH => x.wrapping_shl(*y as u32),
+                            ebpf::BPF_RSH => x.wrapping_shr(*y as u32),
+                            ebpf::BPF_ARSH => (*x as i64).wrapping_shr(*y as u32) as u64,
+                            _ if *y == 0 => continue,
+                            ebpf::BPF_DIV => x / y,
+                            _ => x % y,
+                        };
+                        assert!(
+                            contains(&result, expected),
+                            "{:#x} {:#x} {:#x} {:?}",
+                            operation,
+                            x,
+                            y,
+                            result
+                        );
+                    }
+                }
+            }
+            for bits in [8, 16, 32] {
+                let result = a.truncate(bits).sign_extend(bits);
+                for x in a_samples.iter() {
+                    let shift = 64 - bits;
+                    let expected = (((*x << shift) as i64) >> shift) as u64;
+                    assert!(contains(&result, expected));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_refine_soundness() {
+        let mut rng = SmallRng::from_seed([1; 32]);
+        for _ in 0..2_000 {
+            let (a, a_samples) = random_range(&mut rng);
+            let (b, b_samples) = random_range(&mut rng);
+            for operation in [
+                ebpf::BPF_JEQ,
+                ebpf::BPF_JNE,
+                ebpf::BPF_JGT,
+                ebpf::BPF_JGE,
+                ebpf::BPF_JLT,
+                ebpf::BPF_JLE,
+                ebpf::BPF_JSGT,
+                ebpf::BPF_JSGE,
+                ebpf::BPF_JSLT,
+                ebpf::BPF_JSLE,
+                ebpf::BPF_JSET,
+            ] {
+                for x in a_samples.iter() {
+                    for y in b_samples.iter() {
+                        let taken = match operation {
+                            ebpf::BPF_JEQ => x == y,
+                            ebpf::BPF_JNE => x != y,
+                            ebpf::BPF_JGT => x > y,
+                            ebpf::BPF_JGE => x >= y,
+                            ebpf::BPF_JLT => x < y,
+                            ebpf::BPF_JLE => x <= y,
+                            ebpf::BPF_JSGT => (*x as i64) > (*y as i64),
+                            ebpf::BPF_JSGE => (*x as i64) >= (*y as i64),
+                            ebpf::BPF_JSLT => (*x as i64) < (*y as i64),
+                            ebpf::BPF_JSLE => (*x as i64) <= (*y as i64),
+                            _ => x & y != 0,
+                        };
+                        let (a, b) = refine(operation, taken, a, b).unwrap();
+                        assert!(contains(&a, *x) && contains(&b, *y));
+                    }
+                }
+            }
+        }
+    }
+}
@@ -8,8 +8,9 @@
 //! This module translates eBPF assembly language to binary.
 
 use self::InstructionType::{
-    AluBinary, AluUnary, CallImm, CallReg, Endian, JumpConditional, JumpUnconditional, LoadAbs,
-    LoadImm, LoadInd, LoadReg, NoOperand, StoreImm, StoreReg, Syscall,
+    AluBinary, AluSigned, AluUnary, Atomic, CallImm, CallReg, Endian, JumpConditional, JumpLong,
+    JumpUnconditional, LoadAbs, LoadImm, LoadInd, LoadReg, MoveSignExtend, NoOperand, StoreImm,
+    StoreReg, Syscall,
 };
 use crate::{
     asm_parser::{
@@ -30,15 +31,19 @@ use std::{
 #[derive(Clone, Copy, Debug, PartialEq)]
 enum InstructionType {
     AluBinary,
+    AluSigned,
     AluUnary,
+    MoveSignExtend(i64),
     LoadImm,
     LoadAbs,
     LoadInd,
     LoadReg,
     StoreImm,
     StoreReg,
+    Atomic(i64),
     JumpUnconditional,
     JumpConditional,
+    JumpLong,
     Syscall,
     CallImm,
     CallReg,
@@ -71,6 +76,19 @@ fn make_instruction_map() -> HashMap<String, (InstructionType, u8)> {
         ("dw", ebpf::BPF_DW),
     ];
 
+    let atomic_ops = [
+        ("add", ebpf::BPF_ADD),
+        ("or", ebpf::BPF_OR),
+        ("and", ebpf::BPF_AND),
+        ("xor", ebpf::BPF_XOR),
+        ("fetchadd", ebpf::BPF_ADD | ebpf::BPF_FETCH),
+        ("fetchor", ebpf::BPF_OR | ebpf::BPF_FETCH),
+        ("fetchand", ebpf::BPF_AND | ebpf::BPF_FETCH),
+        ("fetchxor", ebpf::BPF_XOR | ebpf::BPF_FETCH),
+        ("xchg", ebpf::BPF_XCHG),
+        ("cmpxchg", ebpf::BPF_CMPXCHG),
+    ];
+
     let jump_conditions = [
         ("jeq", ebpf::BPF_JEQ),
         ("jgt", ebpf::BPF_JGT),
@@ -93,6 +111,7 @@ fn make_instruction_map() -> HashMap<String, (InstructionType, u8)> {
         // Miscellaneous.
         entry("exit", NoOperand, ebpf::EXIT);
         entry("ja", JumpUnconditional, ebpf::JA);
+        entry("gotol", JumpLong, ebpf::GOTOL);
         entry("syscall", Syscall, ebpf::CALL_IMM);
         entry("call", CallImm, ebpf::CALL_IMM);
         entry("callx", CallReg, ebpf::CALL_REG);
@@ -114,6 +133,27 @@ fn make_instruction_map() -> HashMap<String, (InstructionType, u8)> {
         entry("sdiv64", AluBinary, ebpf::BPF_ALU64 | ebpf::BPF_SDIV);
         entry("sdiv32", AluBinary, ebpf::BPF_ALU | ebpf::BPF_SDIV);
 
+        // AluSigned.
+        entry("smod", AluSigned, ebpf::BPF_ALU64 | ebpf::BPF_MOD);
+        entry("smod64", AluSigned, ebpf::BPF_ALU64 | ebpf::BPF_MOD);
+        entry("smod32", AluSigned, ebpf::BPF_ALU | ebpf::BPF_MOD);
+
+        // MoveSignExtend.
+        for &size in &[8, 16, 32] {
+            entry(
+                &format!("movsx{}", size),
+                MoveSignExtend(size),
+                ebpf::MOV64_REG,
+            );
+        }
+        for &size in &[8, 16] {
+            entry(
+                &format!("mov32sx{}", size),
+                MoveSignExtend(size),
+                ebpf::MOV32_REG,
+            );
+        }
+
         // LoadAbs, LoadInd, LoadReg, StoreImm, and StoreReg.
         for &(suffix, size) in &mem_sizes {
             entry(
@@ -131,6 +171,13 @@ fn make_instruction_map() -> HashMap<String, (InstructionType, u8)> {
                 LoadReg,
                 ebpf::BPF_MEM | ebpf::BPF_LDX | size,
             );
+            if size != ebpf::BPF_DW {
+                entry(
+                    &format!("ldxs{}", suffix),
+                    LoadReg,
+                    ebpf::BPF_MEMSX | ebpf::BPF_LDX | size,
+                );
+            }
             entry(
                 &format!("st{}", suffix),
                 StoreImm,
@@ -143,15 +190,35 @@ fn make_instruction_map() -> HashMap<String, (InstructionType, u8)> {
             );
         }
 
+        // Atomic.
+        for &(name, operation) in &atomic_ops {
+            entry(
+                &format!("stxx{}w", name),
+                Atomic(operation as i64),
+                ebpf::ST_W_ATOMIC,
+            );
+            entry(
+                &format!("stxx{}dw", name),
+                Atomic(operation as i64),
+                ebpf::ST_DW_ATOMIC,
+            );
+        }
+
         // JumpConditional.
         for &(name, condition) in &jump_conditions {
             entry(name, JumpConditional, ebpf::BPF_JMP | condition);
+            entry(
+                &format!("{}32", name),
+                JumpConditional,
+                ebpf::BPF_JMP32 | condition,
+            );
         }
 
         // Endian.
         for &size in &[16, 32, 64] {
             entry(&format!("be{}", size), Endian(size), ebpf::BE);
             entry(&format!("le{}", size), Endian(size), ebpf::LE);
+            entry(&format!("bswap{}", size), Endian(size), ebpf::BSWAP);
         }
     }
 
@@ -256,6 +323,7 @@ pub fn assemble<E: UserDefinedError, I: 'static + InstructionMeter>(
         Ok(hash as i32 as i64)
     }
 
+    let sbf_version = config.max_sbf_version;
     let statements = parse(src)?;
     let instruction_map = make_instruction_map();
     let mut insn_ptr = 0;
@@ -291,9 +359,31 @@ pub fn assemble<E: UserDefinedError, I: 'static + InstructionMeter>(
                         (AluBinary, [Register(dst), Register(src)]) => {
                             insn(opc | ebpf::BPF_X, *dst, *src, 0, 0)
                         }
+                        (AluBinary, [Register(dst), Integer(imm)])
+                            if *dst == ebpf::STACK_PTR_REG as i64
+                                && matches!(
+                                    opc | ebpf::BPF_K,
+                                    ebpf::ADD64_IMM | ebpf::SUB64_IMM
+                                )
+                                && !sbf_version.dynamic_stack_frames() =>
+                        {
+                            Err(format!(
+                                "Resizing the stack frame with {} r{} is not supported in {}",
+                                name, dst, sbf_version
+                            ))
+                        }
                         (AluBinary, [Register(dst), Integer(imm)]) => {
                             insn(opc | ebpf::BPF_K, *dst, 0, 0, *imm)
                         }
+                        (AluSigned, [Register(dst), Register(src)]) => {
+                            insn(opc | ebpf::BPF_X, *dst, *src, 1, 0)
+                        }
+                        (AluSigned, [Register(dst), Integer(imm)]) => {
+                            insn(opc | ebpf::BPF_K, *dst, 0, 1, *imm)
+                        }
+                        (MoveSignExtend(size), [Register(dst), Register(src)]) => {
+                            insn(opc, *dst, *src, size, 0)
+                        }
                         (AluUnary, [Register(dst)]) => insn(opc, *dst, 0, 0, 0),
                         (LoadAbs, [Integer(imm)]) => insn(opc, 0, 0, 0, *imm),
                         (LoadInd, [Register(src), Integer(imm)]) => insn(opc, 0, *src, 0, *imm),
@@ -304,6 +394,9 @@ pub fn assemble<E: UserDefinedError, I: 'static + InstructionMeter>(
                         (StoreImm, [Memory(dst, off), Integer(imm)]) => {
                             insn(opc, *dst, 0, *off, *imm)
                         }
+                        (Atomic(operation), [Memory(dst, off), Register(src)]) => {
+                            insn(opc, *dst, *src, *off, operation)
+                        }
                         (NoOperand, []) => insn(opc, 0, 0, 0, 0),
                         (JumpUnconditional, [Integer(off)]) => insn(opc, 0, 0, *off, 0),
                         (JumpConditional, [Register(dst), Register(src), Integer(off)]) => {
@@ -315,6 +408,10 @@ pub fn assemble<E: UserDefinedError, I: 'static + InstructionMeter>(
                         (JumpUnconditional, [Label(label)]) => {
                             insn(opc, 0, 0, resolve_label(insn_ptr, &labels, label)?, 0)
                         }
+                        (JumpLong, [Integer(imm)]) => insn(opc, 0, 0, 0, *imm),
+                        (JumpLong, [Label(label)]) => {
+                            insn(opc, 0, 0, 0, resolve_label(insn_ptr, &labels, label)?)
+                        }
                         (CallImm, [Integer(imm)]) => {
                             let target_pc = (*imm + insn_ptr as i64 + 1) as usize;
                             let label = format!("function_{}", target_pc);
@@ -343,13 +440,20 @@ pub fn assemble<E: UserDefinedError, I: 'static + InstructionMeter>(
                             resolve_label(insn_ptr, &labels, label)?,
                             *imm,
                         ),
-                        (Syscall, [Label(label)]) => insn(
-                            opc,
-                            0,
-                            0,
-                            0,
-                            ebpf::hash_symbol_name(label.as_bytes()) as i32 as i64,
-                        ),
+                        (Syscall, [Label(label)]) => {
+                            let hash = ebpf::hash_symbol_name(label.as_bytes());
+                            // Without static syscalls only registered syscalls can be told
+                            // apart from calls to BPF functions
+                            if !sbf_version.static_syscalls()
+                                && syscall_registry.lookup_syscall(hash).is_none()
+                            {
+                                return Err(format!(
+                                    "Unknown syscall {} is not supported in {}",
+                                    label, sbf_version
+                                ));
+                            }
+                            insn(opc, 0, 0, 0, hash as i32 as i64)
+                        }
                         (CallImm, [Label(label)]) => {
                             let hash = resolve_call(
                                 &config,
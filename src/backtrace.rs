@@ -0,0 +1,86 @@
+#![allow(clippy::integer_arithmetic)]
+//! Symbolized backtraces of runtime errors
+//!
+//! When [Config::enable_backtrace](crate::vm::Config) is set and a program fails with an access
+//! violation, a division by zero or by exceeding the maximum call depth, the
+//! [EbpfVm](crate::vm::EbpfVm) captures the return pointers of all active [CallFrames] at the
+//! point of failure and returns them in
+//! [EbpfError::WithBacktrace](crate::error::EbpfError::WithBacktrace). The interpreter and the JIT
+//! both record them in the [CallFrames] of the VM, so that they produce the same backtrace for the
+//! same failure.
+
+use crate::{
+    call_frames::CallFrames, debug_info::SourceLocation, elf::Executable, error::UserDefinedError,
+    vm::InstructionMeter,
+};
+use rustc_demangle::demangle;
+use std::fmt;
+
+/// One frame of a backtrace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BacktraceFrame {
+    /// Instruction index of the faulting instruction (innermost frame) or of the call instruction
+    pub pc: usize,
+    /// Instruction index of the entry of the surrounding function (if known)
+    pub function: Option<usize>,
+    /// Demangled name of the surrounding function
+    pub name: String,
+    /// Source location of the instruction (if debug info is available)
+    pub location: Option<SourceLocation>,
+}
+
+/// Active frames at the point of failure, the innermost frame first
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Backtrace {
+    /// Frames from the faulting instruction up to the entrypoint
+    pub frames: Vec<BacktraceFrame>,
+}
+
+impl Backtrace {
+    /// Symbolizes the failing instruction `pc` and the return pointers of the active call frames
+    pub fn new<E: UserDefinedError, I: InstructionMeter>(
+        executable: &Executable<E, I>,
+        call_frames: &CallFrames,
+        pc: usize,
+    ) -> Self {
+        let function_symbols = executable.get_function_symbols();
+        let debug_info = executable.get_debug_info();
+        let return_ptrs = call_frames.get_return_ptrs();
+        let frames = std::iter::once(pc)
+            // The return pointer points behind the call instruction
+            .chain(return_ptrs.iter().rev().map(|return_ptr| return_ptr - 1))
+            .map(|pc| {
+                let (function, name) = match function_symbols.range(..=pc).next_back() {
+                    Some((function, (_hash, name))) => {
+                        (Some(*function), demangle(name).to_string())
+                    }
+                    None => (None, "[unknown]".to_string()),
+                };
+                BacktraceFrame {
+                    pc,
+                    function,
+                    name,
+                    location: debug_info
+                        .and_then(|debug_info| debug_info.get_location(pc).cloned()),
+                }
+            })
+            .collect();
+        Self { frames }
+    }
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, frame) in self.frames.iter().enumerate() {
+            write!(f, "#{} pc {} in {}", index, frame.pc, frame.name)?;
+            if let Some(function) = frame.function {
+                write!(f, "+{}", frame.pc - function)?;
+            }
+            if let Some(location) = &frame.location {
+                write!(f, " at {}", location)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
@@ -3,7 +3,7 @@
 
 use crate::{
     aligned_memory::AlignedMemory,
-    ebpf::{ELF_INSN_DUMP_OFFSET, HOST_ALIGN, MM_STACK_START, SCRATCH_REGS},
+    ebpf::{SbfVersion, ELF_INSN_DUMP_OFFSET, HOST_ALIGN, MM_STACK_START, SCRATCH_REGS},
     error::{EbpfError, UserDefinedError},
     memory_region::MemoryRegion,
     vm::Config,
@@ -23,6 +23,7 @@ struct CallFrame {
 #[derive(Clone, Debug)]
 pub struct CallFrames<'a> {
     config: &'a Config,
+    sbf_version: SbfVersion,
     stack: AlignedMemory,
     stack_ptr: u64,
     frame_index: usize,
@@ -31,13 +32,14 @@ pub struct CallFrames<'a> {
 }
 impl<'a> CallFrames<'a> {
     /// New call frame, depth indicates maximum call depth
-    pub fn new(config: &'a Config) -> Self {
+    pub fn new(config: &'a Config, sbf_version: SbfVersion) -> Self {
         let stack_len = config.stack_size();
         let mut stack = AlignedMemory::new(stack_len, HOST_ALIGN);
         stack.resize(stack_len, 0).unwrap();
 
         let mut frames = CallFrames {
             config,
+            sbf_version,
             stack,
             stack_ptr: 0,
             frame_index: 0,
@@ -51,20 +53,25 @@ impl<'a> CallFrames<'a> {
                 config.max_call_depth
             ],
         };
+        frames.reset();
+        frames
+    }
 
-        let frame = &mut frames.frames[0];
-        if config.dynamic_stack_frames {
+    /// Drop all frames except for the root frame, e.g. those left behind by a failed program
+    pub(crate) fn reset(&mut self) {
+        self.frame_index = 0;
+        self.frame_index_max = 0;
+        let frame = &mut self.frames[0];
+        if self.sbf_version.dynamic_stack_frames() {
             // the stack is fully descending, frames start as empty and change
             // size as resize_stack() is invoked anytime r11 is modified
-            frame.frame_ptr = MM_STACK_START + stack_len as u64;
-            frames.stack_ptr = frame.frame_ptr;
+            frame.frame_ptr = MM_STACK_START + self.config.stack_size() as u64;
+            self.stack_ptr = frame.frame_ptr;
         } else {
             // within a frame the stack grows down, but frames are ascending
-            frame.frame_ptr = MM_STACK_START + config.stack_frame_size as u64;
-            frames.stack_ptr = MM_STACK_START;
+            frame.frame_ptr = MM_STACK_START + self.config.stack_frame_size as u64;
+            self.stack_ptr = MM_STACK_START;
         }
-
-        frames
     }
 
     /// Get stack memory region
@@ -72,7 +79,7 @@ impl<'a> CallFrames<'a> {
         MemoryRegion::new_writable_gapped(
             self.stack.as_slice_mut(),
             MM_STACK_START,
-            if !self.config.dynamic_stack_frames && self.config.enable_stack_frame_gaps {
+            if !self.sbf_version.dynamic_stack_frames() && self.config.enable_stack_frame_gaps {
                 self.config.stack_frame_size as u64
             } else {
                 0
@@ -108,6 +115,98 @@ impl<'a> CallFrames<'a> {
         self.frame_index_max
     }
 
+    /// Get the frame pointer, saved registers and return pointer of each active frame
+    pub fn get_frames(&self) -> Vec<(u64, [u64; SCRATCH_REGS], usize)> {
+        self.frames[..=self.frame_index]
+            .iter()
+            .map(|frame| (frame.frame_ptr, frame.saved_reg, frame.return_ptr))
+            .collect()
+    }
+
+    /// Get the return pointer of each frame which called into the next one, the innermost last
+    pub fn get_return_ptrs(&self) -> Vec<usize> {
+        self.frames[..self.frame_index]
+            .iter()
+            .map(|frame| frame.return_ptr)
+            .collect()
+    }
+
+    /// Replace the return pointers of the calling frames, returns false if there are too many frames
+    ///
+    /// Used by the JIT, which keeps its frames on the host stack, to expose them after a failure.
+    pub(crate) fn set_return_ptrs(&mut self, return_ptrs: &[usize]) -> bool {
+        if return_ptrs.len() >= self.frames.len() {
+            return false;
+        }
+        for (frame, return_ptr) in self.frames.iter_mut().zip(return_ptrs) {
+            frame.return_ptr = *return_ptr;
+        }
+        self.frame_index = return_ptrs.len();
+        self.frame_index_max = self.frame_index_max.max(self.frame_index);
+        true
+    }
+
+    /// Replace the active frames and the stack pointer
+    ///
+    /// Returns false if there are too many frames or the pointers do not fit the stack layout.
+    pub fn set_frames(
+        &mut self,
+        frames: &[(u64, [u64; SCRATCH_REGS], usize)],
+        stack_ptr: u64,
+    ) -> bool {
+        if frames.is_empty() || frames.len() > self.frames.len() {
+            return false;
+        }
+        let stack_end = MM_STACK_START + self.stack.len() as u64;
+        let valid = if self.sbf_version.dynamic_stack_frames() {
+            // Frames start at the stack pointer of their caller, which only grows downwards
+            frames[0].0 == stack_end
+                && frames.windows(2).all(|pair| pair[1].0 <= pair[0].0)
+                && frames
+                    .iter()
+                    .map(|frame| frame.0)
+                    .chain(std::iter::once(stack_ptr))
+                    .all(|ptr| (MM_STACK_START..=stack_end).contains(&ptr))
+        } else {
+            let frame_distance = self.config.stack_frame_size as u64
+                * if self.config.enable_stack_frame_gaps {
+                    2
+                } else {
+                    1
+                };
+            frames.iter().enumerate().all(|(index, frame)| {
+                frame.0
+                    == MM_STACK_START
+                        + self.config.stack_frame_size as u64
+                        + index as u64 * frame_distance
+            }) && (MM_STACK_START..=stack_end).contains(&stack_ptr)
+        };
+        valid && self.set_frames_unchecked(frames, stack_ptr)
+    }
+
+    /// Replace the active frames and the stack pointer, returns false if there are too many frames
+    ///
+    /// The pointers are not validated, so that the frames of a suspended execution can be resumed
+    /// even if the program moved its stack pointer out of the stack.
+    pub(crate) fn set_frames_unchecked(
+        &mut self,
+        frames: &[(u64, [u64; SCRATCH_REGS], usize)],
+        stack_ptr: u64,
+    ) -> bool {
+        if frames.is_empty() || frames.len() > self.frames.len() {
+            return false;
+        }
+        for (frame, (frame_ptr, saved_reg, return_ptr)) in self.frames.iter_mut().zip(frames) {
+            frame.frame_ptr = *frame_ptr;
+            frame.saved_reg = *saved_reg;
+            frame.return_ptr = *return_ptr;
+        }
+        self.frame_index = frames.len() - 1;
+        self.frame_index_max = self.frame_index_max.max(self.frame_index);
+        self.stack_ptr = stack_ptr;
+        true
+    }
+
     /// Push a frame
     pub fn push<E: UserDefinedError>(
         &mut self,
@@ -129,7 +228,7 @@ impl<'a> CallFrames<'a> {
 
         let frame = &mut self.frames[self.frame_index];
 
-        if self.config.dynamic_stack_frames {
+        if self.sbf_version.dynamic_stack_frames() {
             frame.frame_ptr = self.stack_ptr;
         } else {
             frame.frame_ptr = frame_ptr
@@ -164,7 +263,7 @@ impl<'a> CallFrames<'a> {
 
     /// Resize the stack
     pub fn resize_stack(&mut self, amount: i64) {
-        debug_assert!(self.config.dynamic_stack_frames);
+        debug_assert!(self.sbf_version.dynamic_stack_frames());
 
         // Let the stack overflow. For legitimate programs, this is a nearly
         // impossible condition to hit since programs are metered and we already
@@ -183,17 +282,18 @@ mod tests {
 
     #[test]
     fn test_frames() {
-        for (enable_stack_frame_gaps, dynamic_stack_frames) in
-            [(false, false), (true, false), (false, true)]
-        {
+        for (enable_stack_frame_gaps, sbf_version) in [
+            (false, SbfVersion::V1),
+            (true, SbfVersion::V1),
+            (false, SbfVersion::V2),
+        ] {
             let config = Config {
                 max_call_depth: 10,
                 stack_frame_size: 8,
                 enable_stack_frame_gaps,
-                dynamic_stack_frames,
                 ..Config::default()
             };
-            let mut frames = CallFrames::new(&config);
+            let mut frames = CallFrames::new(&config, sbf_version);
             let mut frame_ptrs: Vec<u64> = Vec::new();
 
             for i in 0..config.max_call_depth - 1 {
@@ -202,7 +302,7 @@ mod tests {
                 assert_eq!(frames.get_frame_index(), i);
                 frame_ptrs.push(frames.get_frame_pointers()[i]);
 
-                let expected_frame_size = if dynamic_stack_frames {
+                let expected_frame_size = if sbf_version.dynamic_stack_frames() {
                     let frame_size = i as i64 * 8;
                     frames.resize_stack(-frame_size);
 
@@ -248,10 +348,9 @@ mod tests {
     fn test_stack_ptr_overflow() {
         let config = Config {
             enable_stack_frame_gaps: false,
-            dynamic_stack_frames: true,
             ..Config::default()
         };
-        let mut frames = CallFrames::new(&config);
+        let mut frames = CallFrames::new(&config, SbfVersion::V2);
         frames.resize_stack(-(MM_STACK_START as i64 + config.stack_size() as i64));
         assert_eq!(frames.get_stack_ptr(), 0);
 
@@ -264,10 +363,9 @@ mod tests {
     fn test_stack_ptr_underflow() {
         let config = Config {
             enable_stack_frame_gaps: false,
-            dynamic_stack_frames: true,
             ..Config::default()
         };
-        let mut frames = CallFrames::new(&config);
+        let mut frames = CallFrames::new(&config, SbfVersion::V2);
         frames.resize_stack(-(MM_STACK_START as i64 + config.stack_size() as i64));
         assert_eq!(frames.get_stack_ptr(), 0);
 
@@ -276,4 +374,65 @@ mod tests {
         frames.resize_stack(2);
         assert_eq!(frames.get_stack_ptr(), 1);
     }
+
+    #[test]
+    fn test_get_set_frames() {
+        let config = Config {
+            max_call_depth: 3,
+            ..Config::default()
+        };
+        let mut frames = CallFrames::new(&config, SbfVersion::V2);
+        frames.push::<UserError>(&[1, 2, 3, 4], 7).unwrap();
+        let saved = frames.get_frames();
+        let stack_ptr = frames.get_stack_ptr();
+        assert_eq!(saved.len(), 2);
+        assert_eq!(saved[0].1, [1, 2, 3, 4]);
+        assert_eq!(saved[0].2, 7);
+
+        let mut restored = CallFrames::new(&config, SbfVersion::V2);
+        assert!(restored.set_frames(&saved, stack_ptr));
+        assert_eq!(restored.get_frame_index(), 1);
+        assert_eq!(restored.get_stack_ptr(), stack_ptr);
+        assert_eq!(restored.get_frame_pointers(), frames.get_frame_pointers());
+        assert_eq!(
+            restored.pop::<UserError>().unwrap(),
+            frames.pop::<UserError>().unwrap()
+        );
+
+        assert!(!restored.set_frames(&[], 0));
+        assert!(!restored.set_frames(&[saved[0]; 4], 0));
+        // The pointers have to fit the stack
+        assert!(!restored.set_frames(&saved, MM_STACK_START - 1));
+        assert!(!restored.set_frames(
+            &[saved[0], (saved[0].0 + 8, saved[1].1, saved[1].2)],
+            stack_ptr
+        ));
+        let mut fixed = CallFrames::new(&config, SbfVersion::V1);
+        fixed.push::<UserError>(&[0; 4], 7).unwrap();
+        let fixed_saved = fixed.get_frames();
+        let fixed_stack_ptr = fixed.get_stack_ptr();
+        let mut restored = CallFrames::new(&config, SbfVersion::V1);
+        assert!(!restored.set_frames(&[fixed_saved[1]], fixed_stack_ptr));
+        assert!(restored.set_frames(&fixed_saved, fixed_stack_ptr));
+        assert_eq!(restored.get_frame_pointers(), fixed.get_frame_pointers());
+    }
+
+    #[test]
+    fn test_get_set_return_ptrs() {
+        let config = Config {
+            max_call_depth: 3,
+            ..Config::default()
+        };
+        let mut frames = CallFrames::new(&config, SbfVersion::V2);
+        assert!(frames.get_return_ptrs().is_empty());
+        frames.push::<UserError>(&[0; 4], 7).unwrap();
+        frames.push::<UserError>(&[0; 4], 12).unwrap();
+        assert_eq!(frames.get_return_ptrs(), vec![7, 12]);
+
+        let mut restored = CallFrames::new(&config, SbfVersion::V2);
+        assert!(restored.set_return_ptrs(&[7, 12]));
+        assert_eq!(restored.get_frame_index(), 2);
+        assert_eq!(restored.get_return_ptrs(), frames.get_return_ptrs());
+        assert!(!restored.set_return_ptrs(&[7, 12, 19]));
+    }
 }
@@ -0,0 +1,413 @@
+#![allow(clippy::integer_arithmetic)]
+//! Instruction and branch coverage of recorded traces
+//!
+//! Coverage is accumulated from the instruction log of a [Tracer], so either the interpreter or
+//! the JIT has to run with [Config::enable_instruction_tracing](crate::vm::Config). Coverage of
+//! multiple runs can be accumulated into the same [Coverage] or merged afterwards.
+//!
+//! Reports can be generated in the lcov tracefile format and in the Cobertura XML format. By
+//! default every instruction is reported as a line of a pseudo source file, alternatively
+//! [LineMapping::DebugInfo] or [LineMapping::Source] can attribute instructions to source files
+//! and lines.
+
+use crate::{
+    debug_info::DebugInfo,
+    error::UserDefinedError,
+    static_analysis::{Analysis, CfgNode},
+    vm::{InstructionMeter, Tracer},
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Write,
+};
+
+/// Instruction and CFG edge hit counts
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Coverage {
+    /// Number of accumulated runs
+    pub run_count: u64,
+    /// Hit counts indexed by the instruction index
+    pub instruction_hits: BTreeMap<usize, u64>,
+    /// Hit counts indexed by the start of the source and the destination basic block
+    pub edge_hits: BTreeMap<(usize, usize), u64>,
+}
+
+/// How instructions are attributed to lines in reports
+pub enum LineMapping<'a> {
+    /// Every instruction is a line of a pseudo source file with the given name
+    ///
+    /// Line numbers are the instruction indices plus one.
+    Instructions(&'a str),
+    /// Uses the line table of the DWARF debug info
+    ///
+    /// Instructions without a known line are omitted from reports.
+    DebugInfo(&'a DebugInfo),
+    /// Returns the source file and line of an instruction index
+    ///
+    /// Instructions without a source location are omitted from reports.
+    Source(&'a dyn Fn(usize) -> Option<(String, u32)>),
+}
+
+impl<'a> LineMapping<'a> {
+    fn locate(&self, pc: usize) -> Option<(String, u32)> {
+        match self {
+            LineMapping::Instructions(file_name) => Some((file_name.to_string(), pc as u32 + 1)),
+            LineMapping::DebugInfo(debug_info) => debug_info
+                .get_location(pc)
+                .filter(|location| location.line != 0)
+                .map(|location| (location.file.clone(), location.line)),
+            LineMapping::Source(locate) => locate(pc),
+        }
+    }
+}
+
+#[derive(Default)]
+struct LineCoverage {
+    hits: u64,
+    branches: Vec<Option<u64>>,
+}
+
+#[derive(Default)]
+struct FileCoverage {
+    lines: BTreeMap<u32, LineCoverage>,
+    functions: Vec<(String, u32, u64)>,
+}
+
+impl FileCoverage {
+    fn lines_covered(&self) -> usize {
+        self.lines.values().filter(|line| line.hits > 0).count()
+    }
+
+    fn branches_valid(&self) -> usize {
+        self.lines.values().map(|line| line.branches.len()).sum()
+    }
+
+    fn branches_covered(&self) -> usize {
+        self.lines
+            .values()
+            .flat_map(|line| line.branches.iter())
+            .filter(|taken| matches!(taken, Some(taken) if *taken > 0))
+            .count()
+    }
+}
+
+fn rate(covered: usize, valid: usize) -> f64 {
+    if valid == 0 {
+        1.0
+    } else {
+        covered as f64 / valid as f64
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&apos;"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Basic blocks which end in a conditional branch
+fn branching_cfg_nodes<'a, E: UserDefinedError, I: InstructionMeter>(
+    analysis: &'a Analysis<E, I>,
+) -> impl Iterator<Item = (&'a usize, &'a CfgNode)> {
+    analysis
+        .cfg_nodes
+        .iter()
+        .filter(move |(cfg_node_start, cfg_node)| {
+            **cfg_node_start != analysis.super_root && cfg_node.destinations.len() >= 2
+        })
+}
+
+impl Coverage {
+    /// Accumulates the trace of one run
+    pub fn add_trace<E: UserDefinedError, I: InstructionMeter>(
+        &mut self,
+        tracer: &Tracer,
+        analysis: &Analysis<E, I>,
+    ) {
+        self.run_count += 1;
+        for (index, traced_instruction) in tracer.log.iter().enumerate() {
+            let pc = traced_instruction[11] as usize;
+            *self.instruction_hits.entry(pc).or_insert(0) += 1;
+            let next_pc = match tracer.log.get(index + 1) {
+                Some(next_instruction) => next_instruction[11] as usize,
+                None => continue,
+            };
+            if let Some((cfg_node_start, cfg_node)) = analysis.cfg_nodes.range(..=pc).next_back() {
+                if !cfg_node.instructions.is_empty()
+                    && analysis.instructions[cfg_node.instructions.end - 1].ptr == pc
+                    && cfg_node.destinations.contains(&next_pc)
+                {
+                    *self
+                        .edge_hits
+                        .entry((*cfg_node_start, next_pc))
+                        .or_insert(0) += 1;
+                }
+            }
+        }
+    }
+
+    /// Adds the hit counts of another coverage
+    pub fn merge(&mut self, other: &Coverage) {
+        self.run_count += other.run_count;
+        for (pc, hits) in other.instruction_hits.iter() {
+            *self.instruction_hits.entry(*pc).or_insert(0) += hits;
+        }
+        for (edge, hits) in other.edge_hits.iter() {
+            *self.edge_hits.entry(*edge).or_insert(0) += hits;
+        }
+    }
+
+    /// Returns the number of executed and the total number of instructions
+    pub fn get_instruction_coverage<E: UserDefinedError, I: InstructionMeter>(
+        &self,
+        analysis: &Analysis<E, I>,
+    ) -> (usize, usize) {
+        let covered = analysis
+            .instructions
+            .iter()
+            .filter(|insn| self.instruction_hits.contains_key(&insn.ptr))
+            .count();
+        (covered, analysis.instructions.len())
+    }
+
+    /// Returns the number of taken and the total number of conditional branch edges
+    pub fn get_branch_coverage<E: UserDefinedError, I: InstructionMeter>(
+        &self,
+        analysis: &Analysis<E, I>,
+    ) -> (usize, usize) {
+        let mut covered = 0;
+        let mut valid = 0;
+        for (cfg_node_start, cfg_node) in branching_cfg_nodes(analysis) {
+            for destination in cfg_node.destinations.iter() {
+                valid += 1;
+                if self
+                    .edge_hits
+                    .contains_key(&(*cfg_node_start, *destination))
+                {
+                    covered += 1;
+                }
+            }
+        }
+        (covered, valid)
+    }
+
+    fn collect_files<E: UserDefinedError, I: InstructionMeter>(
+        &self,
+        analysis: &Analysis<E, I>,
+        line_mapping: &LineMapping,
+    ) -> BTreeMap<String, FileCoverage> {
+        let mut files = BTreeMap::<String, FileCoverage>::new();
+        for insn in analysis.instructions.iter() {
+            if let Some((file_name, line)) = line_mapping.locate(insn.ptr) {
+                let hits = self.instruction_hits.get(&insn.ptr).cloned().unwrap_or(0);
+                let line_coverage = files
+                    .entry(file_name)
+                    .or_default()
+                    .lines
+                    .entry(line)
+                    .or_default();
+                line_coverage.hits = line_coverage.hits.max(hits);
+            }
+        }
+        for (cfg_node_start, cfg_node) in branching_cfg_nodes(analysis) {
+            if cfg_node.instructions.is_empty() {
+                continue;
+            }
+            let last_pc = analysis.instructions[cfg_node.instructions.end - 1].ptr;
+            let (file_name, line) = match line_mapping.locate(last_pc) {
+                Some(location) => location,
+                None => continue,
+            };
+            let executed = self.instruction_hits.contains_key(&last_pc);
+            let line_coverage = files
+                .entry(file_name)
+                .or_default()
+                .lines
+                .entry(line)
+                .or_default();
+            for destination in cfg_node.destinations.iter() {
+                line_coverage.branches.push(if executed {
+                    Some(
+                        self.edge_hits
+                            .get(&(*cfg_node_start, *destination))
+                            .cloned()
+                            .unwrap_or(0),
+                    )
+                } else {
+                    None
+                });
+            }
+        }
+        let mut seen_functions = BTreeSet::new();
+        for (function, (_hash, name)) in analysis.functions.iter() {
+            if let Some((file_name, line)) = line_mapping.locate(*function) {
+                if !seen_functions.insert((file_name.clone(), name.clone())) {
+                    continue;
+                }
+                let hits = self.instruction_hits.get(function).cloned().unwrap_or(0);
+                files
+                    .entry(file_name)
+                    .or_default()
+                    .functions
+                    .push((name.clone(), line, hits));
+            }
+        }
+        files
+    }
+
+    /// Writes the coverage in the lcov tracefile format
+    pub fn write_lcov<W: Write, E: UserDefinedError, I: InstructionMeter>(
+        &self,
+        output: &mut W,
+        analysis: &Analysis<E, I>,
+        line_mapping: &LineMapping,
+    ) -> std::io::Result<()> {
+        for (file_name, file) in self.collect_files(analysis, line_mapping) {
+            writeln!(output, "TN:")?;
+            writeln!(output, "SF:{}", file_name)?;
+            for (name, line, _hits) in file.functions.iter() {
+                writeln!(output, "FN:{},{}", line, name)?;
+            }
+            for (name, _line, hits) in file.functions.iter() {
+                writeln!(output, "FNDA:{},{}", hits, name)?;
+            }
+            writeln!(output, "FNF:{}", file.functions.len())?;
+            writeln!(
+                output,
+                "FNH:{}",
+                file.functions
+                    .iter()
+                    .filter(|(_name, _line, hits)| *hits > 0)
+                    .count()
+            )?;
+            for (line, line_coverage) in file.lines.iter() {
+                for (branch, taken) in line_coverage.branches.iter().enumerate() {
+                    match taken {
+                        Some(taken) => writeln!(output, "BRDA:{},0,{},{}", line, branch, taken)?,
+                        None => writeln!(output, "BRDA:{},0,{},-", line, branch)?,
+                    }
+                }
+            }
+            writeln!(output, "BRF:{}", file.branches_valid())?;
+            writeln!(output, "BRH:{}", file.branches_covered())?;
+            for (line, line_coverage) in file.lines.iter() {
+                writeln!(output, "DA:{},{}", line, line_coverage.hits)?;
+            }
+            writeln!(output, "LF:{}", file.lines.len())?;
+            writeln!(output, "LH:{}", file.lines_covered())?;
+            writeln!(output, "end_of_record")?;
+        }
+        Ok(())
+    }
+
+    /// Writes the coverage in the Cobertura XML format
+    pub fn write_cobertura<W: Write, E: UserDefinedError, I: InstructionMeter>(
+        &self,
+        output: &mut W,
+        analysis: &Analysis<E, I>,
+        line_mapping: &LineMapping,
+    ) -> std::io::Result<()> {
+        let files = self.collect_files(analysis, line_mapping);
+        let lines_valid = files.values().map(|file| file.lines.len()).sum::<usize>();
+        let lines_covered = files
+            .values()
+            .map(FileCoverage::lines_covered)
+            .sum::<usize>();
+        let branches_valid = files
+            .values()
+            .map(FileCoverage::branches_valid)
+            .sum::<usize>();
+        let branches_covered = files
+            .values()
+            .map(FileCoverage::branches_covered)
+            .sum::<usize>();
+        writeln!(output, "<?xml version=\"1.0\" ?>")?;
+        writeln!(
+            output,
+            "<!DOCTYPE coverage SYSTEM \"http://cobertura.sourceforge.net/xml/coverage-04.dtd\">"
+        )?;
+        writeln!(
+            output,
+            "<coverage line-rate=\"{:.4}\" branch-rate=\"{:.4}\" lines-covered=\"{}\" lines-valid=\"{}\" branches-covered=\"{}\" branches-valid=\"{}\" complexity=\"0\" version=\"0.1\" timestamp=\"0\">",
+            rate(lines_covered, lines_valid),
+            rate(branches_covered, branches_valid),
+            lines_covered,
+            lines_valid,
+            branches_covered,
+            branches_valid,
+        )?;
+        writeln!(output, "  <sources><source>.</source></sources>")?;
+        writeln!(output, "  <packages>")?;
+        writeln!(
+            output,
+            "    <package name=\"program\" line-rate=\"{:.4}\" branch-rate=\"{:.4}\" complexity=\"0\">",
+            rate(lines_covered, lines_valid),
+            rate(branches_covered, branches_valid),
+        )?;
+        writeln!(output, "      <classes>")?;
+        for (file_name, file) in files.iter() {
+            let file_name = escape_xml(file_name);
+            writeln!(
+                output,
+                "        <class name=\"{}\" filename=\"{}\" line-rate=\"{:.4}\" branch-rate=\"{:.4}\" complexity=\"0\">",
+                file_name,
+                file_name,
+                rate(file.lines_covered(), file.lines.len()),
+                rate(file.branches_covered(), file.branches_valid()),
+            )?;
+            writeln!(output, "          <methods>")?;
+            for (name, line, hits) in file.functions.iter() {
+                writeln!(
+                    output,
+                    "            <method name=\"{}\" signature=\"\" line-rate=\"{:.4}\" branch-rate=\"1.0000\"><lines><line number=\"{}\" hits=\"{}\"/></lines></method>",
+                    escape_xml(name),
+                    rate((*hits > 0) as usize, 1),
+                    line,
+                    hits,
+                )?;
+            }
+            writeln!(output, "          </methods>")?;
+            writeln!(output, "          <lines>")?;
+            for (line, line_coverage) in file.lines.iter() {
+                if line_coverage.branches.is_empty() {
+                    writeln!(
+                        output,
+                        "            <line number=\"{}\" hits=\"{}\" branch=\"false\"/>",
+                        line, line_coverage.hits,
+                    )?;
+                } else {
+                    let covered = line_coverage
+                        .branches
+                        .iter()
+                        .filter(|taken| matches!(taken, Some(taken) if *taken > 0))
+                        .count();
+                    let valid = line_coverage.branches.len();
+                    writeln!(
+                        output,
+                        "            <line number=\"{}\" hits=\"{}\" branch=\"true\" condition-coverage=\"{}% ({}/{})\"/>",
+                        line,
+                        line_coverage.hits,
+                        covered * 100 / valid,
+                        covered,
+                        valid,
+                    )?;
+                }
+            }
+            writeln!(output, "          </lines>")?;
+            writeln!(output, "        </class>")?;
+        }
+        writeln!(output, "      </classes>")?;
+        writeln!(output, "    </package>")?;
+        writeln!(output, "  </packages>")?;
+        writeln!(output, "</coverage>")
+    }
+}
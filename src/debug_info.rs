@@ -0,0 +1,384 @@
+#![allow(clippy::integer_arithmetic)]
+//! DWARF debug info for source-level symbolization
+//!
+//! Parses `.debug_line`, `.debug_info` and `.debug_frame` of an ELF so that instruction indices
+//! can be mapped to source locations and (possibly inlined) function names. Addresses in the
+//! debug info are virtual addresses of the ELF file, they are converted to instruction indices
+//! relative to the start of the `.text` section.
+
+use crate::{ebpf, elf::ElfError};
+use gimli::{EndianSlice, LittleEndian, UnwindSection};
+use goblin::elf::Elf;
+use std::{collections::BTreeMap, fmt, mem, ops::Range, path::PathBuf};
+
+type DwarfReader<'a> = EndianSlice<'a, LittleEndian>;
+
+/// A position in a source file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    /// Path of the source file
+    pub file: String,
+    /// Line number, starting at 1 (0 = unknown)
+    pub line: u32,
+    /// Column number, starting at 1 (0 = unknown)
+    pub column: u32,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.file)?;
+        if self.line != 0 {
+            write!(f, ":{}", self.line)?;
+            if self.column != 0 {
+                write!(f, ":{}", self.column)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A function (or an inlined copy of it) active at an instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionFrame {
+    /// Demangled function name
+    pub function: String,
+    /// Location inside of the function
+    pub location: Option<SourceLocation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FunctionRange {
+    range: Range<usize>,
+    depth: usize,
+    name: String,
+    call_location: Option<SourceLocation>,
+}
+
+/// Source-level symbolization of a program
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DebugInfo {
+    /// Line table indexed by the first instruction of each row, None marks the end of a sequence
+    line_rows: BTreeMap<usize, Option<SourceLocation>>,
+    /// Functions and inlined subroutines
+    functions: Vec<FunctionRange>,
+    /// Instruction ranges of the call frame descriptions
+    frames: Vec<Range<usize>>,
+}
+
+struct AddressTranslation {
+    text_section_vaddr: u64,
+    text_section_size: u64,
+}
+
+impl AddressTranslation {
+    fn instruction_index(&self, address: u64) -> Option<usize> {
+        let offset = address.checked_sub(self.text_section_vaddr)?;
+        if offset > self.text_section_size {
+            return None;
+        }
+        Some(offset as usize / ebpf::INSN_SIZE)
+    }
+
+    fn instruction_range(&self, begin: u64, end: u64) -> Option<Range<usize>> {
+        let begin = self.instruction_index(begin)?;
+        let end = self.instruction_index(end)?;
+        if begin < end {
+            Some(begin..end)
+        } else {
+            None
+        }
+    }
+}
+
+fn file_name(
+    dwarf: &gimli::Dwarf<DwarfReader>,
+    unit: &gimli::Unit<DwarfReader>,
+    file_index: u64,
+) -> Result<Option<String>, gimli::Error> {
+    let line_program = match &unit.line_program {
+        Some(line_program) => line_program,
+        None => return Ok(None),
+    };
+    let header = line_program.header();
+    let file = match header.file(file_index) {
+        Some(file) => file,
+        None => return Ok(None),
+    };
+    let mut path = PathBuf::new();
+    if let Some(comp_dir) = &unit.comp_dir {
+        path.push(comp_dir.to_string_lossy().as_ref());
+    }
+    if file.directory_index() != 0 {
+        if let Some(directory) = file.directory(header) {
+            path.push(
+                dwarf
+                    .attr_string(unit, directory)?
+                    .to_string_lossy()
+                    .as_ref(),
+            );
+        }
+    }
+    path.push(
+        dwarf
+            .attr_string(unit, file.path_name())?
+            .to_string_lossy()
+            .as_ref(),
+    );
+    Ok(Some(path.to_string_lossy().to_string()))
+}
+
+fn function_name(
+    dwarf: &gimli::Dwarf<DwarfReader>,
+    unit: &gimli::Unit<DwarfReader>,
+    entry: &gimli::DebuggingInformationEntry<DwarfReader>,
+    recursion_limit: usize,
+) -> Result<Option<String>, gimli::Error> {
+    for attribute_name in [gimli::DW_AT_linkage_name, gimli::DW_AT_MIPS_linkage_name] {
+        if let Some(attribute) = entry.attr_value(attribute_name)? {
+            let name = dwarf.attr_string(unit, attribute)?;
+            return Ok(Some(format!(
+                "{:#}",
+                rustc_demangle::demangle(name.to_string_lossy().as_ref())
+            )));
+        }
+    }
+    if let Some(attribute) = entry.attr_value(gimli::DW_AT_name)? {
+        let name = dwarf.attr_string(unit, attribute)?;
+        return Ok(Some(name.to_string_lossy().to_string()));
+    }
+    if recursion_limit == 0 {
+        return Ok(None);
+    }
+    for attribute_name in [gimli::DW_AT_abstract_origin, gimli::DW_AT_specification] {
+        if let Some(gimli::AttributeValue::UnitRef(offset)) = entry.attr_value(attribute_name)? {
+            let origin = unit.entry(offset)?;
+            return function_name(dwarf, unit, &origin, recursion_limit - 1);
+        }
+    }
+    Ok(None)
+}
+
+impl DebugInfo {
+    /// Parses the debug sections of an ELF
+    ///
+    /// Returns `None` if the ELF carries neither line info nor debug info.
+    pub fn parse(elf: &Elf, elf_bytes: &[u8]) -> Result<Option<Self>, ElfError> {
+        let section_data = |name: &str| -> &[u8] {
+            elf.section_headers
+                .iter()
+                .find(|section_header| elf.shdr_strtab.get_at(section_header.sh_name) == Some(name))
+                .and_then(|section_header| section_header.file_range())
+                .and_then(|range| elf_bytes.get(range))
+                .unwrap_or(&[])
+        };
+        if section_data(".debug_line").is_empty() && section_data(".debug_info").is_empty() {
+            return Ok(None);
+        }
+        let text_section = elf
+            .section_headers
+            .iter()
+            .find(|section_header| elf.shdr_strtab.get_at(section_header.sh_name) == Some(".text"))
+            .ok_or_else(|| ElfError::SectionNotFound(".text".to_string()))?;
+        let address_translation = AddressTranslation {
+            text_section_vaddr: text_section.sh_addr,
+            text_section_size: text_section.sh_size,
+        };
+        let dwarf = gimli::Dwarf::load(|section_id| -> Result<DwarfReader, gimli::Error> {
+            Ok(EndianSlice::new(
+                section_data(section_id.name()),
+                LittleEndian,
+            ))
+        })?;
+        let mut result = Self::default();
+        let mut units = dwarf.units();
+        while let Some(unit_header) = units.next()? {
+            let unit = dwarf.unit(unit_header)?;
+            result.parse_line_program(&dwarf, &unit, &address_translation)?;
+            result.parse_functions(&dwarf, &unit, &address_translation)?;
+        }
+        result.functions.sort_by(|a, b| {
+            (a.range.start, a.depth, a.range.end).cmp(&(b.range.start, b.depth, b.range.end))
+        });
+        let mut debug_frame = gimli::DebugFrame::new(section_data(".debug_frame"), LittleEndian);
+        debug_frame.set_address_size(mem::size_of::<u64>() as u8);
+        let bases = gimli::BaseAddresses::default();
+        let mut entries = debug_frame.entries(&bases);
+        while let Some(entry) = entries.next()? {
+            if let gimli::CieOrFde::Fde(partial) = entry {
+                let fde = partial.parse(gimli::DebugFrame::cie_from_offset)?;
+                if let Some(range) = address_translation
+                    .instruction_range(fde.initial_address(), fde.initial_address() + fde.len())
+                {
+                    result.frames.push(range);
+                }
+            }
+        }
+        result.frames.sort_by_key(|range| range.start);
+        Ok(Some(result))
+    }
+
+    fn parse_line_program(
+        &mut self,
+        dwarf: &gimli::Dwarf<DwarfReader>,
+        unit: &gimli::Unit<DwarfReader>,
+        address_translation: &AddressTranslation,
+    ) -> Result<(), gimli::Error> {
+        let line_program = match &unit.line_program {
+            Some(line_program) => line_program.clone(),
+            None => return Ok(()),
+        };
+        let mut file_names = BTreeMap::<u64, String>::new();
+        let mut rows = line_program.rows();
+        while let Some((_header, row)) = rows.next_row()? {
+            let pc = match address_translation.instruction_index(row.address()) {
+                Some(pc) => pc,
+                None => continue,
+            };
+            if row.end_sequence() {
+                self.line_rows.entry(pc).or_insert(None);
+                continue;
+            }
+            let file = match file_names.entry(row.file_index()) {
+                std::collections::btree_map::Entry::Occupied(entry) => entry.get().clone(),
+                std::collections::btree_map::Entry::Vacant(entry) => entry
+                    .insert(file_name(dwarf, unit, row.file_index())?.unwrap_or_default())
+                    .clone(),
+            };
+            let location = SourceLocation {
+                file,
+                line: row.line().map(|line| line.get() as u32).unwrap_or(0),
+                column: match row.column() {
+                    gimli::ColumnType::LeftEdge => 0,
+                    gimli::ColumnType::Column(column) => column.get() as u32,
+                },
+            };
+            self.line_rows.insert(pc, Some(location));
+        }
+        Ok(())
+    }
+
+    fn parse_functions(
+        &mut self,
+        dwarf: &gimli::Dwarf<DwarfReader>,
+        unit: &gimli::Unit<DwarfReader>,
+        address_translation: &AddressTranslation,
+    ) -> Result<(), gimli::Error> {
+        let mut depth = 0isize;
+        let mut entries = unit.entries();
+        while let Some((delta_depth, entry)) = entries.next_dfs()? {
+            depth += delta_depth;
+            if entry.tag() != gimli::DW_TAG_subprogram
+                && entry.tag() != gimli::DW_TAG_inlined_subroutine
+            {
+                continue;
+            }
+            let name =
+                function_name(dwarf, unit, entry, 16)?.unwrap_or_else(|| "[unknown]".to_string());
+            let call_location = if entry.tag() == gimli::DW_TAG_inlined_subroutine {
+                let file = match entry.attr_value(gimli::DW_AT_call_file)? {
+                    Some(gimli::AttributeValue::FileIndex(file_index)) => {
+                        file_name(dwarf, unit, file_index)?
+                    }
+                    _ => None,
+                };
+                let line = entry
+                    .attr_value(gimli::DW_AT_call_line)?
+                    .and_then(|value| value.udata_value())
+                    .unwrap_or(0) as u32;
+                let column = entry
+                    .attr_value(gimli::DW_AT_call_column)?
+                    .and_then(|value| value.udata_value())
+                    .unwrap_or(0) as u32;
+                file.map(|file| SourceLocation { file, line, column })
+            } else {
+                None
+            };
+            let mut ranges = dwarf.die_ranges(unit, entry)?;
+            while let Some(range) = ranges.next()? {
+                if let Some(range) = address_translation.instruction_range(range.begin, range.end) {
+                    self.functions.push(FunctionRange {
+                        range,
+                        depth: depth.max(0) as usize,
+                        name: name.clone(),
+                        call_location: call_location.clone(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the source location of an instruction
+    pub fn get_location(&self, pc: usize) -> Option<&SourceLocation> {
+        self.line_rows
+            .range(..=pc)
+            .next_back()
+            .and_then(|(_pc, location)| location.as_ref())
+    }
+
+    /// Returns the functions active at an instruction, the innermost (inlined) one first
+    ///
+    /// The location of every frame but the first is the call site of the frame before it.
+    pub fn get_function_frames(&self, pc: usize) -> Vec<FunctionFrame> {
+        let mut functions = self
+            .functions
+            .iter()
+            .filter(|function| function.range.contains(&pc))
+            .collect::<Vec<_>>();
+        functions.sort_by_key(|function| std::cmp::Reverse(function.depth));
+        let mut location = self.get_location(pc).cloned();
+        let mut result = Vec::with_capacity(functions.len());
+        for function in functions {
+            result.push(FunctionFrame {
+                function: function.name.clone(),
+                location: location.take(),
+            });
+            location = function.call_location.clone();
+        }
+        result
+    }
+
+    /// Returns the instruction range of the call frame description containing an instruction
+    pub fn get_frame_range(&self, pc: usize) -> Option<Range<usize>> {
+        self.frames
+            .iter()
+            .find(|range| range.contains(&pc))
+            .cloned()
+    }
+
+    /// Describes an instruction as "function at file:line:column", including inlined functions
+    pub fn symbolize(&self, pc: usize) -> Option<String> {
+        let frames = self.get_function_frames(pc);
+        if frames.is_empty() {
+            return self.get_location(pc).map(|location| location.to_string());
+        }
+        Some(
+            frames
+                .iter()
+                .map(|frame| match &frame.location {
+                    Some(location) => format!("{} at {}", frame.function, location),
+                    None => frame.function.clone(),
+                })
+                .collect::<Vec<_>>()
+                .join(" inlined into "),
+        )
+    }
+
+    /// Calculate the total memory size
+    pub fn mem_size(&self) -> usize {
+        let location_size = |location: &SourceLocation| {
+            mem::size_of::<SourceLocation>().saturating_add(location.file.capacity())
+        };
+        mem::size_of::<Self>()
+            .saturating_add(self.line_rows.values().fold(0, |size, location| {
+                size.saturating_add(mem::size_of::<usize>())
+                    .saturating_add(location.as_ref().map_or(0, location_size))
+            }))
+            .saturating_add(self.functions.iter().fold(0, |size, function| {
+                size.saturating_add(mem::size_of::<FunctionRange>())
+                    .saturating_add(function.name.capacity())
+                    .saturating_add(function.call_location.as_ref().map_or(0, location_size))
+            }))
+            .saturating_add(self.frames.capacity() * mem::size_of::<Range<usize>>())
+    }
+}
@@ -0,0 +1,382 @@
+#![allow(clippy::integer_arithmetic)]
+//! Interactive step debugger built on top of the interpreter
+
+use crate::{
+    ebpf,
+    error::{EbpfError, UserDefinedError},
+    interpreter::Interpreter,
+    memory_region::AccessType,
+    snapshot::{Snapshot, SnapshotError},
+    vm::{EbpfVm, InstructionMeter},
+};
+use std::collections::BTreeSet;
+
+/// Kind of memory access which triggers a watchpoint
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WatchpointKind {
+    /// Triggers on loads only
+    Read,
+    /// Triggers on stores only
+    Write,
+    /// Triggers on loads and stores
+    Access,
+}
+
+impl WatchpointKind {
+    fn matches(&self, access_type: AccessType) -> bool {
+        match self {
+            Self::Read => access_type == AccessType::Load,
+            Self::Write => access_type == AccessType::Store,
+            Self::Access => true,
+        }
+    }
+}
+
+/// A watched range of VM memory
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Watchpoint {
+    /// Start of the watched range in the VM address space
+    pub vm_addr: u64,
+    /// Length of the watched range in bytes
+    pub len: u64,
+    /// Which accesses trigger the watchpoint
+    pub kind: WatchpointKind,
+}
+
+impl Watchpoint {
+    fn overlaps(&self, vm_addr: u64, len: u64) -> bool {
+        vm_addr < self.vm_addr.saturating_add(self.len)
+            && self.vm_addr < vm_addr.saturating_add(len)
+    }
+}
+
+/// Reason why the debugger handed control back to the caller
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StopReason {
+    /// A single step (or step over / step out) completed
+    Step,
+    /// Execution arrived at a breakpoint, the instruction has not been executed yet
+    Breakpoint(usize),
+    /// The previous instruction accessed a watched range (pc, access type, vm_addr, len)
+    Watchpoint(usize, AccessType, u64, u64),
+    /// The program exited with the given return value
+    Exited(u64),
+}
+
+/// Debugger which wraps an interpreter and pauses it at breakpoints and watchpoints
+///
+/// Watchpoints are only checked against the memory accesses of load and store
+/// instructions, accesses performed by syscalls are not observed.
+///
+/// # Examples
+///
+/// ```
+/// use solana_rbpf::{assembler::assemble, debugger::{Debugger, StopReason}, user_error::UserError, vm::{Config, EbpfVm, SyscallRegistry, TestInstructionMeter}};
+///
+/// let executable = assemble::<UserError, TestInstructionMeter>(
+///     "
+///     mov r0, 1
+///     add r0, 2
+///     exit",
+///     None,
+///     Config::default(),
+///     SyscallRegistry::default(),
+/// ).unwrap();
+/// let mut vm = EbpfVm::<UserError, TestInstructionMeter>::new(&executable, &mut [], Vec::new()).unwrap();
+/// let mut instruction_meter = TestInstructionMeter { remaining: 3 };
+/// let mut debugger = Debugger::new(&mut vm, &mut instruction_meter).unwrap();
+/// debugger.set_breakpoint(1);
+/// assert_eq!(debugger.resume().unwrap(), StopReason::Breakpoint(1));
+/// assert_eq!(debugger.get_registers()[0], 1);
+/// debugger.set_register(0, 5);
+/// assert_eq!(debugger.resume().unwrap(), StopReason::Exited(7));
+/// ```
+pub struct Debugger<'a, 'b, E: UserDefinedError, I: InstructionMeter> {
+    interpreter: Interpreter<'a, 'b, E, I>,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: Vec<Watchpoint>,
+    terminated: bool,
+}
+
+impl<'a, 'b, E: UserDefinedError, I: InstructionMeter> Debugger<'a, 'b, E, I> {
+    /// Creates a new debugger, paused at the entrypoint
+    pub fn new(
+        vm: &'a mut EbpfVm<'b, E, I>,
+        instruction_meter: &'a mut I,
+    ) -> Result<Self, EbpfError<E>> {
+        Ok(Self {
+            interpreter: Interpreter::new(vm, instruction_meter)?,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            terminated: false,
+        })
+    }
+
+    /// Sets a breakpoint at the given instruction index
+    pub fn set_breakpoint(&mut self, pc: usize) -> bool {
+        self.breakpoints.insert(pc)
+    }
+
+    /// Sets a breakpoint at the first instruction of the named function
+    ///
+    /// Returns the instruction index of the function or `None` if there is no such symbol.
+    /// Requires `Config::enable_symbol_and_section_labels` for the names to be available.
+    pub fn set_breakpoint_at_symbol(&mut self, name: &str) -> Option<usize> {
+        let pc = self
+            .interpreter
+            .vm
+            .executable
+            .get_function_symbols()
+            .into_iter()
+            .find(|(_pc, (_hash, symbol))| symbol == name)
+            .map(|(pc, _)| pc)?;
+        self.breakpoints.insert(pc);
+        Some(pc)
+    }
+
+    /// Removes the breakpoint at the given instruction index
+    pub fn remove_breakpoint(&mut self, pc: usize) -> bool {
+        self.breakpoints.remove(&pc)
+    }
+
+    /// Returns the instruction indices of all breakpoints
+    pub fn get_breakpoints(&self) -> &BTreeSet<usize> {
+        &self.breakpoints
+    }
+
+    /// Watches a range of VM memory
+    pub fn set_watchpoint(&mut self, vm_addr: u64, len: u64, kind: WatchpointKind) {
+        self.watchpoints.push(Watchpoint { vm_addr, len, kind });
+    }
+
+    /// Removes all watchpoints which start at the given VM address
+    pub fn remove_watchpoint(&mut self, vm_addr: u64) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints
+            .retain(|watchpoint| watchpoint.vm_addr != vm_addr);
+        self.watchpoints.len() != count
+    }
+
+    /// Returns all watchpoints
+    pub fn get_watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Returns true once the program exited or failed
+    pub fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+
+    /// Returns the instruction index of the next instruction to be executed
+    pub fn get_pc(&self) -> usize {
+        self.interpreter.pc
+    }
+
+    /// Moves the program counter to another instruction index
+    ///
+    /// Returns false if the index lies outside of the program.
+    pub fn set_pc(&mut self, pc: usize) -> bool {
+        if pc < self.interpreter.vm.get_program().len() / ebpf::INSN_SIZE {
+            self.interpreter.pc = pc;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the virtual address of the first instruction in the text section
+    pub fn get_program_vm_addr(&self) -> u64 {
+        self.interpreter.vm.program_vm_addr
+    }
+
+    /// Returns the registers r0 to r10
+    pub fn get_registers(&self) -> &[u64; 11] {
+        &self.interpreter.reg
+    }
+
+    /// Overwrites a register, returns false if the register does not exist
+    pub fn set_register(&mut self, reg: usize, value: u64) -> bool {
+        if let Some(slot) = self.interpreter.reg.get_mut(reg) {
+            *slot = value;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the current call frame index, 0 is the root frame
+    pub fn get_frame_index(&self) -> usize {
+        self.interpreter.vm.stack.get_frame_index()
+    }
+
+    /// Reads VM memory through the memory mapping
+    pub fn read_memory(&self, vm_addr: u64, len: u64) -> Result<Vec<u8>, EbpfError<E>> {
+        let host_addr =
+            self.interpreter
+                .vm
+                .memory_mapping
+                .map::<E>(AccessType::Load, vm_addr, len)?;
+        Ok(unsafe { std::slice::from_raw_parts(host_addr as *const u8, len as usize) }.to_vec())
+    }
+
+    /// Writes VM memory through the memory mapping, read-only regions are rejected
+    pub fn write_memory(&mut self, vm_addr: u64, data: &[u8]) -> Result<(), EbpfError<E>> {
+        let host_addr = self.interpreter.vm.memory_mapping.map::<E>(
+            AccessType::Store,
+            vm_addr,
+            data.len() as u64,
+        )?;
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr(), host_addr as *mut u8, data.len());
+        }
+        Ok(())
+    }
+
+    /// Captures the current execution state
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot::capture(&self.interpreter)
+    }
+
+    /// Rewinds (or fast forwards) to a previously captured execution state
+    ///
+    /// The instruction meter is not rewound.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), SnapshotError> {
+        snapshot.restore(&mut self.interpreter)?;
+        self.terminated = false;
+        Ok(())
+    }
+
+    /// Executes exactly one instruction
+    pub fn step(&mut self) -> Result<StopReason, EbpfError<E>> {
+        let reason = self.step_instruction()?;
+        if reason == StopReason::Step && self.breakpoints.contains(&self.interpreter.pc) {
+            return Ok(StopReason::Breakpoint(self.interpreter.pc));
+        }
+        Ok(reason)
+    }
+
+    /// Executes one instruction, BPF to BPF calls are run until they return
+    pub fn step_over(&mut self) -> Result<StopReason, EbpfError<E>> {
+        let frame_index = self.get_frame_index();
+        loop {
+            let reason = self.step()?;
+            if reason != StopReason::Step || self.get_frame_index() <= frame_index {
+                return Ok(reason);
+            }
+        }
+    }
+
+    /// Runs until the current function returns to its caller
+    ///
+    /// In the root frame this runs until the program exits.
+    pub fn step_out(&mut self) -> Result<StopReason, EbpfError<E>> {
+        let frame_index = self.get_frame_index();
+        loop {
+            let reason = self.step()?;
+            if reason != StopReason::Step || self.get_frame_index() < frame_index {
+                return Ok(reason);
+            }
+        }
+    }
+
+    /// Runs until a breakpoint or watchpoint is hit or the program exits
+    pub fn resume(&mut self) -> Result<StopReason, EbpfError<E>> {
+        loop {
+            let reason = self.step()?;
+            if reason != StopReason::Step {
+                return Ok(reason);
+            }
+        }
+    }
+
+    fn step_instruction(&mut self) -> Result<StopReason, EbpfError<E>> {
+        if self.terminated {
+            return Err(EbpfError::ExitRootCallFrame);
+        }
+        let pc = self.interpreter.pc;
+        let access = self.get_memory_access(pc);
+        match self.interpreter.step() {
+            Ok(None) => {}
+            Ok(Some(result)) => {
+                self.terminate();
+                return Ok(StopReason::Exited(result));
+            }
+            Err(error) => {
+                self.terminate();
+                return Err(error);
+            }
+        }
+        if let Some((access_type, vm_addr, len)) = access {
+            if self.watchpoints.iter().any(|watchpoint| {
+                watchpoint.kind.matches(access_type) && watchpoint.overlaps(vm_addr, len)
+            }) {
+                return Ok(StopReason::Watchpoint(pc, access_type, vm_addr, len));
+            }
+        }
+        Ok(StopReason::Step)
+    }
+
+    fn terminate(&mut self) {
+        self.terminated = true;
+        let interpreter = &mut self.interpreter;
+        if interpreter
+            .vm
+            .executable
+            .get_config()
+            .enable_instruction_meter
+        {
+            interpreter
+                .instruction_meter
+                .consume(interpreter.due_insn_count);
+            interpreter.due_insn_count = 0;
+            interpreter.vm.total_insn_count =
+                interpreter.initial_insn_count - interpreter.instruction_meter.get_remaining();
+        }
+    }
+
+    /// Decodes the memory access the instruction at `pc` is about to perform
+    fn get_memory_access(&self, pc: usize) -> Option<(AccessType, u64, u64)> {
+        let program = self.interpreter.vm.program;
+        if (pc + 1) * ebpf::INSN_SIZE > program.len() {
+            return None;
+        }
+        let insn = ebpf::get_insn_unchecked(program, pc);
+        let reg = &self.interpreter.reg;
+        let len = match insn.opc & 0x18 {
+            ebpf::BPF_B => 1,
+            ebpf::BPF_H => 2,
+            ebpf::BPF_W => 4,
+            _ => 8,
+        };
+        let reg_plus_off = |reg: u64| (reg as i64).wrapping_add(insn.off as i64) as u64;
+        let access = match insn.opc {
+            ebpf::LD_ABS_B | ebpf::LD_ABS_H | ebpf::LD_ABS_W | ebpf::LD_ABS_DW => (
+                AccessType::Load,
+                ebpf::MM_INPUT_START.wrapping_add(insn.imm as u32 as u64),
+            ),
+            ebpf::LD_IND_B | ebpf::LD_IND_H | ebpf::LD_IND_W | ebpf::LD_IND_DW => (
+                AccessType::Load,
+                ebpf::MM_INPUT_START
+                    .wrapping_add(reg[insn.src as usize])
+                    .wrapping_add(insn.imm as u32 as u64),
+            ),
+            ebpf::LD_B_REG
+            | ebpf::LD_H_REG
+            | ebpf::LD_W_REG
+            | ebpf::LD_DW_REG
+            | ebpf::LD_SB_REG
+            | ebpf::LD_SH_REG
+            | ebpf::LD_SW_REG => (AccessType::Load, reg_plus_off(reg[insn.src as usize])),
+            ebpf::ST_B_IMM
+            | ebpf::ST_H_IMM
+            | ebpf::ST_W_IMM
+            | ebpf::ST_DW_IMM
+            | ebpf::ST_B_REG
+            | ebpf::ST_H_REG
+            | ebpf::ST_W_REG
+            | ebpf::ST_DW_REG => (AccessType::Store, reg_plus_off(reg[insn.dst as usize])),
+            _ => return None,
+        };
+        Some((access.0, access.1, len))
+    }
+}
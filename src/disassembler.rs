@@ -88,6 +88,39 @@ fn st_reg_str(name: &str, insn: &ebpf::Insn) -> String {
     )
 }
 
+#[inline]
+fn atomic_str(insn: &ebpf::Insn) -> String {
+    let operation = match ebpf::atomic_operation(insn.imm) {
+        Some(ebpf::BPF_XCHG) => "xchg",
+        Some(ebpf::BPF_CMPXCHG) => "cmpxchg",
+        Some(operation) => match (
+            operation & !ebpf::BPF_FETCH,
+            operation & ebpf::BPF_FETCH != 0,
+        ) {
+            (ebpf::BPF_ADD, false) => "add",
+            (ebpf::BPF_OR, false) => "or",
+            (ebpf::BPF_AND, false) => "and",
+            (_, false) => "xor",
+            (ebpf::BPF_ADD, true) => "fetchadd",
+            (ebpf::BPF_OR, true) => "fetchor",
+            (ebpf::BPF_AND, true) => "fetchand",
+            (_, true) => "fetchxor",
+        },
+        None => {
+            return format!(
+                "unknown opcode={:#x} atomic operation={:#x}",
+                insn.opc, insn.imm
+            )
+        }
+    };
+    let size = if insn.opc == ebpf::ST_W_ATOMIC {
+        "w"
+    } else {
+        "dw"
+    };
+    st_reg_str(&format!("stxx{}{}", operation, size), insn)
+}
+
 #[inline]
 fn ldabs_str(name: &str, insn: &ebpf::Insn) -> String {
     format!("{} {}", name, insn.imm)
@@ -133,6 +166,7 @@ fn jmp_reg_str<E: UserDefinedError, I: InstructionMeter>(
 /// Disassemble an eBPF instruction
 #[rustfmt::skip]
 pub fn disassemble_instruction<E: UserDefinedError, I: InstructionMeter>(insn: &ebpf::Insn, analysis: &Analysis<E, I>) -> String {
+    let ebpf_v4 = analysis.executable.get_config().enable_ebpf_v4;
     let name;
     let desc;
     match insn.opc {
@@ -153,6 +187,9 @@ pub fn disassemble_instruction<E: UserDefinedError, I: InstructionMeter>(insn: &
         ebpf::LD_H_REG   => { name = "ldxh";  desc = ld_reg_str(name, insn); },
         ebpf::LD_W_REG   => { name = "ldxw";  desc = ld_reg_str(name, insn); },
         ebpf::LD_DW_REG  => { name = "ldxdw"; desc = ld_reg_str(name, insn); },
+        ebpf::LD_SB_REG  => { name = "ldxsb"; desc = ld_reg_str(name, insn); },
+        ebpf::LD_SH_REG  => { name = "ldxsh"; desc = ld_reg_str(name, insn); },
+        ebpf::LD_SW_REG  => { name = "ldxsw"; desc = ld_reg_str(name, insn); },
 
         // BPF_ST class
         ebpf::ST_B_IMM   => { name = "stb";  desc = ld_st_imm_str(name, insn); },
@@ -165,8 +202,8 @@ pub fn disassemble_instruction<E: UserDefinedError, I: InstructionMeter>(insn: &
         ebpf::ST_H_REG   => { name = "stxh";      desc = st_reg_str(name, insn); },
         ebpf::ST_W_REG   => { name = "stxw";      desc = st_reg_str(name, insn); },
         ebpf::ST_DW_REG  => { name = "stxdw";     desc = st_reg_str(name, insn); },
-        ebpf::ST_W_XADD  => { name = "stxxaddw";  desc = st_reg_str(name, insn); },
-        ebpf::ST_DW_XADD => { name = "stxxadddw"; desc = st_reg_str(name, insn); },
+        ebpf::ST_W_ATOMIC  => { desc = atomic_str(insn); },
+        ebpf::ST_DW_ATOMIC => { desc = atomic_str(insn); },
 
         // BPF_ALU class
         ebpf::ADD32_IMM  => { name = "add32";  desc = alu_imm_str(name, insn);  },
@@ -188,16 +225,21 @@ pub fn disassemble_instruction<E: UserDefinedError, I: InstructionMeter>(insn: &
         ebpf::RSH32_IMM  => { name = "rsh32";  desc = alu_imm_str(name, insn);  },
         ebpf::RSH32_REG  => { name = "rsh32";  desc = alu_reg_str(name, insn);  },
         ebpf::NEG32      => { name = "neg32";  desc = format!("{} r{}", name, insn.dst); },
+        ebpf::MOD32_IMM  if ebpf_v4 && insn.off == 1 => { name = "smod32"; desc = alu_imm_str(name, insn); },
+        ebpf::MOD32_REG  if ebpf_v4 && insn.off == 1 => { name = "smod32"; desc = alu_reg_str(name, insn); },
         ebpf::MOD32_IMM  => { name = "mod32";  desc = alu_imm_str(name, insn);  },
         ebpf::MOD32_REG  => { name = "mod32";  desc = alu_reg_str(name, insn);  },
         ebpf::XOR32_IMM  => { name = "xor32";  desc = alu_imm_str(name, insn);  },
         ebpf::XOR32_REG  => { name = "xor32";  desc = alu_reg_str(name, insn);  },
         ebpf::MOV32_IMM  => { name = "mov32";  desc = alu_imm_str(name, insn);  },
+        ebpf::MOV32_REG  if ebpf_v4 && insn.off == 8  => { name = "mov32sx8";  desc = alu_reg_str(name, insn); },
+        ebpf::MOV32_REG  if ebpf_v4 && insn.off == 16 => { name = "mov32sx16"; desc = alu_reg_str(name, insn); },
         ebpf::MOV32_REG  => { name = "mov32";  desc = alu_reg_str(name, insn);  },
         ebpf::ARSH32_IMM => { name = "arsh32"; desc = alu_imm_str(name, insn);  },
         ebpf::ARSH32_REG => { name = "arsh32"; desc = alu_reg_str(name, insn);  },
         ebpf::LE         => { name = "le";     desc = byteswap_str(name, insn); },
         ebpf::BE         => { name = "be";     desc = byteswap_str(name, insn); },
+        ebpf::BSWAP      => { name = "bswap";  desc = byteswap_str(name, insn); },
 
         // BPF_ALU64 class
         ebpf::ADD64_IMM  => { name = "add64";  desc = alu_imm_str(name, insn); },
@@ -219,11 +261,16 @@ pub fn disassemble_instruction<E: UserDefinedError, I: InstructionMeter>(insn: &
         ebpf::RSH64_IMM  => { name = "rsh64";  desc = alu_imm_str(name, insn); },
         ebpf::RSH64_REG  => { name = "rsh64";  desc = alu_reg_str(name, insn); },
         ebpf::NEG64      => { name = "neg64";  desc = format!("{} r{}", name, insn.dst); },
+        ebpf::MOD64_IMM  if ebpf_v4 && insn.off == 1 => { name = "smod64"; desc = alu_imm_str(name, insn); },
+        ebpf::MOD64_REG  if ebpf_v4 && insn.off == 1 => { name = "smod64"; desc = alu_reg_str(name, insn); },
         ebpf::MOD64_IMM  => { name = "mod64";  desc = alu_imm_str(name, insn); },
         ebpf::MOD64_REG  => { name = "mod64";  desc = alu_reg_str(name, insn); },
         ebpf::XOR64_IMM  => { name = "xor64";  desc = alu_imm_str(name, insn); },
         ebpf::XOR64_REG  => { name = "xor64";  desc = alu_reg_str(name, insn); },
         ebpf::MOV64_IMM  => { name = "mov64";  desc = alu_imm_str(name, insn); },
+        ebpf::MOV64_REG  if ebpf_v4 && insn.off == 8  => { name = "movsx8";  desc = alu_reg_str(name, insn); },
+        ebpf::MOV64_REG  if ebpf_v4 && insn.off == 16 => { name = "movsx16"; desc = alu_reg_str(name, insn); },
+        ebpf::MOV64_REG  if ebpf_v4 && insn.off == 32 => { name = "movsx32"; desc = alu_reg_str(name, insn); },
         ebpf::MOV64_REG  => { name = "mov64";  desc = alu_reg_str(name, insn); },
         ebpf::ARSH64_IMM => { name = "arsh64"; desc = alu_imm_str(name, insn); },
         ebpf::ARSH64_REG => { name = "arsh64"; desc = alu_reg_str(name, insn); },
@@ -260,6 +307,9 @@ pub fn disassemble_instruction<E: UserDefinedError, I: InstructionMeter>(insn: &
             desc = if let Some(syscall_name) = analysis.executable.get_syscall_symbols().get(&(insn.imm as u32)) {
                 name = "syscall";
                 format!("{} {}", name, syscall_name)
+            } else if analysis.executable.get_sbf_version().static_syscalls() && insn.src == 0 {
+                name = "syscall";
+                format!("{} {:#x}", name, insn.imm as u32)
             } else {
                 name = "call";
                 if let Some(target_pc) = analysis
@@ -274,6 +324,35 @@ pub fn disassemble_instruction<E: UserDefinedError, I: InstructionMeter>(insn: &
         ebpf::CALL_REG   => { name = "callx"; desc = format!("{} r{}", name, insn.imm); },
         ebpf::EXIT       => { name = "exit"; desc = name.to_string(); },
 
+        // BPF_JMP32 class
+        ebpf::JEQ32_IMM  => { name = "jeq32";  desc = jmp_imm_str(name, insn, analysis); },
+        ebpf::JEQ32_REG  => { name = "jeq32";  desc = jmp_reg_str(name, insn, analysis); },
+        ebpf::JGT32_IMM  => { name = "jgt32";  desc = jmp_imm_str(name, insn, analysis); },
+        ebpf::JGT32_REG  => { name = "jgt32";  desc = jmp_reg_str(name, insn, analysis); },
+        ebpf::JGE32_IMM  => { name = "jge32";  desc = jmp_imm_str(name, insn, analysis); },
+        ebpf::JGE32_REG  => { name = "jge32";  desc = jmp_reg_str(name, insn, analysis); },
+        ebpf::JLT32_IMM  => { name = "jlt32";  desc = jmp_imm_str(name, insn, analysis); },
+        ebpf::JLT32_REG  => { name = "jlt32";  desc = jmp_reg_str(name, insn, analysis); },
+        ebpf::JLE32_IMM  => { name = "jle32";  desc = jmp_imm_str(name, insn, analysis); },
+        ebpf::JLE32_REG  => { name = "jle32";  desc = jmp_reg_str(name, insn, analysis); },
+        ebpf::JSET32_IMM => { name = "jset32"; desc = jmp_imm_str(name, insn, analysis); },
+        ebpf::JSET32_REG => { name = "jset32"; desc = jmp_reg_str(name, insn, analysis); },
+        ebpf::JNE32_IMM  => { name = "jne32";  desc = jmp_imm_str(name, insn, analysis); },
+        ebpf::JNE32_REG  => { name = "jne32";  desc = jmp_reg_str(name, insn, analysis); },
+        ebpf::JSGT32_IMM => { name = "jsgt32"; desc = jmp_imm_str(name, insn, analysis); },
+        ebpf::JSGT32_REG => { name = "jsgt32"; desc = jmp_reg_str(name, insn, analysis); },
+        ebpf::JSGE32_IMM => { name = "jsge32"; desc = jmp_imm_str(name, insn, analysis); },
+        ebpf::JSGE32_REG => { name = "jsge32"; desc = jmp_reg_str(name, insn, analysis); },
+        ebpf::JSLT32_IMM => { name = "jslt32"; desc = jmp_imm_str(name, insn, analysis); },
+        ebpf::JSLT32_REG => { name = "jslt32"; desc = jmp_reg_str(name, insn, analysis); },
+        ebpf::JSLE32_IMM => { name = "jsle32"; desc = jmp_imm_str(name, insn, analysis); },
+        ebpf::JSLE32_REG => { name = "jsle32"; desc = jmp_reg_str(name, insn, analysis); },
+        ebpf::GOTOL      => {
+            name = "gotol";
+            let target_pc = (insn.ptr as isize + insn.imm as isize + 1) as usize;
+            desc = format!("{} {}", name, resolve_label(analysis, target_pc));
+        },
+
         _                => { name = "unknown"; desc = format!("{} opcode={:#x}", name, insn.opc); },
     };
     desc
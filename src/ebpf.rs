@@ -73,7 +73,8 @@ pub const BPF_STX: u8 = 0x03;
 pub const BPF_ALU: u8 = 0x04;
 /// BPF operation class: jump.
 pub const BPF_JMP: u8 = 0x05;
-// [ class 6 unused, reserved for future use ]
+/// BPF operation class: jump comparing the lower 32 bits of the operands.
+pub const BPF_JMP32: u8 = 0x06;
 /// BPF operation class: 64 bits arithmetic operation.
 pub const BPF_ALU64: u8 = 0x07;
 
@@ -103,12 +104,15 @@ pub const BPF_ABS: u8 = 0x20;
 pub const BPF_IND: u8 = 0x40;
 /// BPF mode modifier: load from / store to memory.
 pub const BPF_MEM: u8 = 0x60;
-// [ 0x80 reserved ]
+/// BPF mode modifier: sign extending load from memory.
+pub const BPF_MEMSX: u8 = 0x80;
 // [ 0xa0 reserved ]
-/// BPF mode modifier: exclusive add.
-pub const BPF_XADD: u8 = 0xc0;
+/// BPF mode modifier: atomic operation, selected by the immediate.
+pub const BPF_ATOMIC: u8 = 0xc0;
+/// BPF mode modifier: exclusive add (legacy name of `BPF_ATOMIC`).
+pub const BPF_XADD: u8 = BPF_ATOMIC;
 
-// For arithmetic (BPF_ALU/BPF_ALU64) and jump (BPF_JMP) instructions:
+// For arithmetic (BPF_ALU/BPF_ALU64) and jump (BPF_JMP/BPF_JMP32) instructions:
 // +----------------+--------+--------+
 // |     4 bits     |1 b.|   3 bits   |
 // | operation code | src| insn class |
@@ -153,7 +157,16 @@ pub const BPF_END: u8 = 0xd0;
 /// BPF ALU/ALU64 operation code: signed division.
 pub const BPF_SDIV: u8 = 0xe0;
 
-// Operation codes -- BPF_JMP class:
+// Operation codes -- BPF_ATOMIC mode, stored in the immediate:
+// BPF_ADD, BPF_OR, BPF_AND and BPF_XOR, optionally combined with BPF_FETCH, or one of:
+/// BPF atomic operation modifier: load the previous value from memory into `src`.
+pub const BPF_FETCH: u8 = 0x01;
+/// BPF atomic operation code: exchange.
+pub const BPF_XCHG: u8 = 0xe0 | BPF_FETCH;
+/// BPF atomic operation code: compare and exchange with `r0`.
+pub const BPF_CMPXCHG: u8 = 0xf0 | BPF_FETCH;
+
+// Operation codes -- BPF_JMP class (the conditional ones are also used by BPF_JMP32):
 /// BPF JMP operation code: jump.
 pub const BPF_JA: u8 = 0x00;
 /// BPF JMP operation code: jump if equal.
@@ -214,6 +227,12 @@ pub const LD_H_REG: u8 = BPF_LDX | BPF_MEM | BPF_H;
 pub const LD_W_REG: u8 = BPF_LDX | BPF_MEM | BPF_W;
 /// BPF opcode: `ldxdw dst, [src + off]` /// `dst = (src + off) as u64`.
 pub const LD_DW_REG: u8 = BPF_LDX | BPF_MEM | BPF_DW;
+/// BPF opcode: `ldxsb dst, [src + off]` /// `dst = (src + off) as i8 as i64`.
+pub const LD_SB_REG: u8 = BPF_LDX | BPF_MEMSX | BPF_B;
+/// BPF opcode: `ldxsh dst, [src + off]` /// `dst = (src + off) as i16 as i64`.
+pub const LD_SH_REG: u8 = BPF_LDX | BPF_MEMSX | BPF_H;
+/// BPF opcode: `ldxsw dst, [src + off]` /// `dst = (src + off) as i32 as i64`.
+pub const LD_SW_REG: u8 = BPF_LDX | BPF_MEMSX | BPF_W;
 /// BPF opcode: `stb [dst + off], imm` /// `(dst + offset) as u8 = imm`.
 pub const ST_B_IMM: u8 = BPF_ST | BPF_MEM | BPF_B;
 /// BPF opcode: `sth [dst + off], imm` /// `(dst + offset) as u16 = imm`.
@@ -231,10 +250,14 @@ pub const ST_W_REG: u8 = BPF_STX | BPF_MEM | BPF_W;
 /// BPF opcode: `stxdw [dst + off], src` /// `(dst + offset) as u64 = src`.
 pub const ST_DW_REG: u8 = BPF_STX | BPF_MEM | BPF_DW;
 
+/// BPF opcode: `stxx<op>w [dst + off], src` /// atomic `(dst + offset) as u32 op= src`.
+pub const ST_W_ATOMIC: u8 = BPF_STX | BPF_ATOMIC | BPF_W;
+/// BPF opcode: `stxx<op>dw [dst + off], src` /// atomic `(dst + offset) as u64 op= src`.
+pub const ST_DW_ATOMIC: u8 = BPF_STX | BPF_ATOMIC | BPF_DW;
 /// BPF opcode: `stxxaddw [dst + off], src`.
-pub const ST_W_XADD: u8 = BPF_STX | BPF_XADD | BPF_W;
+pub const ST_W_XADD: u8 = ST_W_ATOMIC;
 /// BPF opcode: `stxxadddw [dst + off], src`.
-pub const ST_DW_XADD: u8 = BPF_STX | BPF_XADD | BPF_DW;
+pub const ST_DW_XADD: u8 = ST_DW_ATOMIC;
 
 /// BPF opcode: `add32 dst, imm` /// `dst += imm`.
 pub const ADD32_IMM: u8 = BPF_ALU | BPF_K | BPF_ADD;
@@ -271,8 +294,12 @@ pub const RSH32_REG: u8 = BPF_ALU | BPF_X | BPF_RSH;
 /// BPF opcode: `neg32 dst` /// `dst = -dst`.
 pub const NEG32: u8 = BPF_ALU | BPF_NEG;
 /// BPF opcode: `mod32 dst, imm` /// `dst %= imm`.
+///
+/// With `off == 1` this is `smod32 dst, imm` /// `dst s%= imm` (eBPF v4).
 pub const MOD32_IMM: u8 = BPF_ALU | BPF_K | BPF_MOD;
 /// BPF opcode: `mod32 dst, src` /// `dst %= src`.
+///
+/// With `off == 1` this is `smod32 dst, src` /// `dst s%= src` (eBPF v4).
 pub const MOD32_REG: u8 = BPF_ALU | BPF_X | BPF_MOD;
 /// BPF opcode: `xor32 dst, imm` /// `dst ^= imm`.
 pub const XOR32_IMM: u8 = BPF_ALU | BPF_K | BPF_XOR;
@@ -281,6 +308,8 @@ pub const XOR32_REG: u8 = BPF_ALU | BPF_X | BPF_XOR;
 /// BPF opcode: `mov32 dst, imm` /// `dst = imm`.
 pub const MOV32_IMM: u8 = BPF_ALU | BPF_K | BPF_MOV;
 /// BPF opcode: `mov32 dst, src` /// `dst = src`.
+///
+/// With `off` in {8, 16} this is `mov32sx<off> dst, src` /// `dst = src as i<off> as u32` (eBPF v4).
 pub const MOV32_REG: u8 = BPF_ALU | BPF_X | BPF_MOV;
 /// BPF opcode: `arsh32 dst, imm` /// `dst >>= imm (arithmetic)`.
 ///
@@ -299,6 +328,8 @@ pub const SDIV32_REG: u8 = BPF_ALU | BPF_X | BPF_SDIV;
 pub const LE: u8 = BPF_ALU | BPF_K | BPF_END;
 /// BPF opcode: `be dst` /// `dst = htobe<imm>(dst), with imm in {16, 32, 64}`.
 pub const BE: u8 = BPF_ALU | BPF_X | BPF_END;
+/// BPF opcode: `bswap dst` /// `dst = bswap<imm>(dst), with imm in {16, 32, 64}`.
+pub const BSWAP: u8 = BPF_ALU64 | BPF_K | BPF_END;
 
 /// BPF opcode: `add64 dst, imm` /// `dst += imm`.
 pub const ADD64_IMM: u8 = BPF_ALU64 | BPF_K | BPF_ADD;
@@ -335,8 +366,12 @@ pub const RSH64_REG: u8 = BPF_ALU64 | BPF_X | BPF_RSH;
 /// BPF opcode: `neg64 dst, imm` /// `dst = -dst`.
 pub const NEG64: u8 = BPF_ALU64 | BPF_NEG;
 /// BPF opcode: `mod64 dst, imm` /// `dst %= imm`.
+///
+/// With `off == 1` this is `smod64 dst, imm` /// `dst s%= imm` (eBPF v4).
 pub const MOD64_IMM: u8 = BPF_ALU64 | BPF_K | BPF_MOD;
 /// BPF opcode: `mod64 dst, src` /// `dst %= src`.
+///
+/// With `off == 1` this is `smod64 dst, src` /// `dst s%= src` (eBPF v4).
 pub const MOD64_REG: u8 = BPF_ALU64 | BPF_X | BPF_MOD;
 /// BPF opcode: `xor64 dst, imm` /// `dst ^= imm`.
 pub const XOR64_IMM: u8 = BPF_ALU64 | BPF_K | BPF_XOR;
@@ -345,6 +380,8 @@ pub const XOR64_REG: u8 = BPF_ALU64 | BPF_X | BPF_XOR;
 /// BPF opcode: `mov64 dst, imm` /// `dst = imm`.
 pub const MOV64_IMM: u8 = BPF_ALU64 | BPF_K | BPF_MOV;
 /// BPF opcode: `mov64 dst, src` /// `dst = src`.
+///
+/// With `off` in {8, 16, 32} this is `movsx<off> dst, src` /// `dst = src as i<off> as i64` (eBPF v4).
 pub const MOV64_REG: u8 = BPF_ALU64 | BPF_X | BPF_MOV;
 /// BPF opcode: `arsh64 dst, imm` /// `dst >>= imm (arithmetic)`.
 ///
@@ -406,6 +443,53 @@ pub const JSLE_IMM: u8 = BPF_JMP | BPF_K | BPF_JSLE;
 /// BPF opcode: `jsle dst, src, +off` /// `PC += off if dst <= src (signed)`.
 pub const JSLE_REG: u8 = BPF_JMP | BPF_X | BPF_JSLE;
 
+/// BPF opcode: `jeq32 dst, imm, +off` /// `PC += off if (u32)dst == (u32)imm`.
+pub const JEQ32_IMM: u8 = BPF_JMP32 | BPF_K | BPF_JEQ;
+/// BPF opcode: `jeq32 dst, src, +off` /// `PC += off if (u32)dst == (u32)src`.
+pub const JEQ32_REG: u8 = BPF_JMP32 | BPF_X | BPF_JEQ;
+/// BPF opcode: `jgt32 dst, imm, +off` /// `PC += off if (u32)dst > (u32)imm`.
+pub const JGT32_IMM: u8 = BPF_JMP32 | BPF_K | BPF_JGT;
+/// BPF opcode: `jgt32 dst, src, +off` /// `PC += off if (u32)dst > (u32)src`.
+pub const JGT32_REG: u8 = BPF_JMP32 | BPF_X | BPF_JGT;
+/// BPF opcode: `jge32 dst, imm, +off` /// `PC += off if (u32)dst >= (u32)imm`.
+pub const JGE32_IMM: u8 = BPF_JMP32 | BPF_K | BPF_JGE;
+/// BPF opcode: `jge32 dst, src, +off` /// `PC += off if (u32)dst >= (u32)src`.
+pub const JGE32_REG: u8 = BPF_JMP32 | BPF_X | BPF_JGE;
+/// BPF opcode: `jlt32 dst, imm, +off` /// `PC += off if (u32)dst < (u32)imm`.
+pub const JLT32_IMM: u8 = BPF_JMP32 | BPF_K | BPF_JLT;
+/// BPF opcode: `jlt32 dst, src, +off` /// `PC += off if (u32)dst < (u32)src`.
+pub const JLT32_REG: u8 = BPF_JMP32 | BPF_X | BPF_JLT;
+/// BPF opcode: `jle32 dst, imm, +off` /// `PC += off if (u32)dst <= (u32)imm`.
+pub const JLE32_IMM: u8 = BPF_JMP32 | BPF_K | BPF_JLE;
+/// BPF opcode: `jle32 dst, src, +off` /// `PC += off if (u32)dst <= (u32)src`.
+pub const JLE32_REG: u8 = BPF_JMP32 | BPF_X | BPF_JLE;
+/// BPF opcode: `jset32 dst, imm, +off` /// `PC += off if (u32)dst & (u32)imm`.
+pub const JSET32_IMM: u8 = BPF_JMP32 | BPF_K | BPF_JSET;
+/// BPF opcode: `jset32 dst, src, +off` /// `PC += off if (u32)dst & (u32)src`.
+pub const JSET32_REG: u8 = BPF_JMP32 | BPF_X | BPF_JSET;
+/// BPF opcode: `jne32 dst, imm, +off` /// `PC += off if (u32)dst != (u32)imm`.
+pub const JNE32_IMM: u8 = BPF_JMP32 | BPF_K | BPF_JNE;
+/// BPF opcode: `jne32 dst, src, +off` /// `PC += off if (u32)dst != (u32)src`.
+pub const JNE32_REG: u8 = BPF_JMP32 | BPF_X | BPF_JNE;
+/// BPF opcode: `jsgt32 dst, imm, +off` /// `PC += off if (i32)dst > (i32)imm`.
+pub const JSGT32_IMM: u8 = BPF_JMP32 | BPF_K | BPF_JSGT;
+/// BPF opcode: `jsgt32 dst, src, +off` /// `PC += off if (i32)dst > (i32)src`.
+pub const JSGT32_REG: u8 = BPF_JMP32 | BPF_X | BPF_JSGT;
+/// BPF opcode: `jsge32 dst, imm, +off` /// `PC += off if (i32)dst >= (i32)imm`.
+pub const JSGE32_IMM: u8 = BPF_JMP32 | BPF_K | BPF_JSGE;
+/// BPF opcode: `jsge32 dst, src, +off` /// `PC += off if (i32)dst >= (i32)src`.
+pub const JSGE32_REG: u8 = BPF_JMP32 | BPF_X | BPF_JSGE;
+/// BPF opcode: `jslt32 dst, imm, +off` /// `PC += off if (i32)dst < (i32)imm`.
+pub const JSLT32_IMM: u8 = BPF_JMP32 | BPF_K | BPF_JSLT;
+/// BPF opcode: `jslt32 dst, src, +off` /// `PC += off if (i32)dst < (i32)src`.
+pub const JSLT32_REG: u8 = BPF_JMP32 | BPF_X | BPF_JSLT;
+/// BPF opcode: `jsle32 dst, imm, +off` /// `PC += off if (i32)dst <= (i32)imm`.
+pub const JSLE32_IMM: u8 = BPF_JMP32 | BPF_K | BPF_JSLE;
+/// BPF opcode: `jsle32 dst, src, +off` /// `PC += off if (i32)dst <= (i32)src`.
+pub const JSLE32_REG: u8 = BPF_JMP32 | BPF_X | BPF_JSLE;
+/// BPF opcode: `gotol +imm` /// `PC += imm`.
+pub const GOTOL: u8 = BPF_JMP32 | BPF_JA;
+
 /// BPF opcode: `call imm` /// syscall function call to syscall with key `imm`.
 pub const CALL_IMM: u8 = BPF_JMP | BPF_CALL;
 /// BPF opcode: tail call.
@@ -419,6 +503,61 @@ pub const BPF_CLS_MASK: u8 = 0x07;
 /// Mask to extract the arithmetic operation code from an instruction operation code.
 pub const BPF_ALU_OP_MASK: u8 = 0xf0;
 
+/// Version of the SBF instruction set and ELF format
+///
+/// The version of an ELF is derived from its `e_flags`, executables created from raw text bytes
+/// use the highest version enabled in the `Config`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SbfVersion {
+    /// Fixed size stack frames, relocated syscalls and sections at their file offsets
+    V1,
+    /// Dynamic stack frames, static syscalls and sections at their virtual addresses
+    V2,
+}
+
+impl SbfVersion {
+    /// Derives the version from the `e_flags` of an ELF header, `None` if they are unknown
+    pub fn from_e_flags(e_flags: u32) -> Option<Self> {
+        match e_flags {
+            0 => Some(SbfVersion::V1),
+            EF_SBF_V2 => Some(SbfVersion::V2),
+            _ => None,
+        }
+    }
+
+    /// The `e_flags` of an ELF of this version
+    pub fn e_flags(self) -> u32 {
+        match self {
+            SbfVersion::V1 => 0,
+            SbfVersion::V2 => EF_SBF_V2,
+        }
+    }
+
+    /// Stack frames are resized by `add r11, imm` and `sub r11, imm` instead of having a fixed size
+    pub fn dynamic_stack_frames(self) -> bool {
+        self >= SbfVersion::V2
+    }
+
+    /// Syscalls are encoded as `call imm` with `src = 0` instead of being relocated
+    pub fn static_syscalls(self) -> bool {
+        self >= SbfVersion::V2
+    }
+
+    /// Sections are placed at their virtual addresses (`sh_addr`) instead of their file offsets
+    pub fn enable_elf_vaddr(self) -> bool {
+        self >= SbfVersion::V2
+    }
+}
+
+impl fmt::Display for SbfVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SbfVersion::V1 => write!(f, "SBFv1"),
+            SbfVersion::V2 => write!(f, "SBFv2"),
+        }
+    }
+}
+
 /// An eBPF instruction.
 ///
 /// See <https://www.kernel.org/doc/Documentation/networking/filter.txt> for the Linux kernel
@@ -509,6 +648,23 @@ impl Insn {
     }
 }
 
+/// Returns the operation encoded in the immediate of a `BPF_ATOMIC` instruction.
+///
+/// Returns `None` if the immediate does not encode a supported atomic operation.
+pub fn atomic_operation(imm: i64) -> Option<u8> {
+    let operation = imm as u8;
+    if operation as i64 != imm {
+        return None;
+    }
+    match operation {
+        BPF_XCHG | BPF_CMPXCHG => Some(operation),
+        _ => match operation & !BPF_FETCH {
+            BPF_ADD | BPF_OR | BPF_AND | BPF_XOR => Some(operation),
+            _ => None,
+        },
+    }
+}
+
 /// Get the instruction at `idx` of an eBPF program. `idx` is the index (number) of the
 /// instruction (not a byte offset). The first instruction has index 0.
 ///
@@ -11,10 +11,14 @@ extern crate scroll;
 
 use crate::{
     aligned_memory::AlignedMemory,
-    ebpf::{self, EF_SBF_V2, INSN_SIZE},
+    debug_info::DebugInfo,
+    ebpf::{self, SbfVersion, INSN_SIZE},
     error::{EbpfError, UserDefinedError},
     jit::JitProgram,
+    jit_cache::JitCache,
     memory_region::MemoryRegion,
+    threaded_interpreter::ThreadedProgram,
+    tiering::{ExecutionTier, Tiering},
     vm::{Config, InstructionMeter, SyscallRegistry},
 };
 use byteorder::{ByteOrder, LittleEndian};
@@ -100,9 +104,20 @@ pub enum ElfError {
     /// Offset or value is out of bounds
     #[error("Offset or value is out of bounds")]
     ValueOutOfBounds,
-    /// Dynamic stack frames detected but not enabled
-    #[error("Dynamic stack frames detected but not enabled")]
-    DynamicStackFramesDisabled,
+    /// SBF version of the ELF is not enabled in the config
+    #[error("Unsupported SBF version: {0}")]
+    UnsupportedSbfVersion(SbfVersion),
+    /// The e_flags of the ELF do not correspond to any SBF version
+    #[error("Unknown SBF version e_flags: {0:#x}")]
+    UnknownSbfVersion(u32),
+    /// Failed to parse DWARF debug info
+    #[error("Failed to parse debug info: {0}")]
+    FailedToParseDebugInfo(String),
+}
+impl From<gimli::Error> for ElfError {
+    fn from(error: gimli::Error) -> Self {
+        ElfError::FailedToParseDebugInfo(error.to_string())
+    }
 }
 impl From<GoblinError> for ElfError {
     fn from(error: GoblinError) -> Self {
@@ -269,6 +284,8 @@ pub(crate) enum Section {
 pub struct Executable<E: UserDefinedError, I: InstructionMeter> {
     /// Configuration settings
     config: Config,
+    /// SBF version of the ELF, or the highest enabled one for raw text bytes
+    sbf_version: SbfVersion,
     /// Loaded and executable elf
     elf_bytes: AlignedMemory,
     /// Read-only section
@@ -281,8 +298,21 @@ pub struct Executable<E: UserDefinedError, I: InstructionMeter> {
     syscall_symbols: BTreeMap<u32, String>,
     /// Syscall resolution map
     syscall_registry: SyscallRegistry,
+    /// Source-level symbolization (if enabled and present in the ELF)
+    debug_info: Option<DebugInfo>,
     /// Compiled program and argument
     compiled_program: Option<JitProgram<E, I>>,
+    /// Pre-decoded program of the threaded-code interpreter
+    threaded_program: Option<ThreadedProgram<E, I>>,
+    /// Invocation counter and background compilation of the tiered execution
+    tiering: Tiering<E, I>,
+}
+
+impl<E: UserDefinedError, I: InstructionMeter> Drop for Executable<E, I> {
+    fn drop(&mut self) {
+        // The background compilation still reads from the executable
+        self.wait_for_background_compilation();
+    }
 }
 
 impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
@@ -291,6 +321,11 @@ impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
         &self.config
     }
 
+    /// Get the SBF version
+    pub fn get_sbf_version(&self) -> SbfVersion {
+        self.sbf_version
+    }
+
     /// Get the .text section virtual address and bytes
     pub fn get_text_bytes(&self) -> (u64, &[u8]) {
         let (ro_offset, ro_section) = match &self.ro_section {
@@ -339,23 +374,88 @@ impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
         self.bpf_functions.get(&hash).map(|(pc, _name)| *pc)
     }
 
+    /// Get the BPF functions by their hash
+    pub(crate) fn get_bpf_functions(&self) -> &BTreeMap<u32, (usize, String)> {
+        &self.bpf_functions
+    }
+
     /// Get the syscall registry
     pub fn get_syscall_registry(&self) -> &SyscallRegistry {
         &self.syscall_registry
     }
 
-    /// Get the JIT compiled program
+    /// Get the JIT compiled program, compiled either explicitly or by the tiered execution
     pub fn get_compiled_program(&self) -> Option<&JitProgram<E, I>> {
-        self.compiled_program.as_ref()
+        self.compiled_program
+            .as_ref()
+            .or_else(|| self.tiering.get_compiled_program())
     }
 
     /// JIT compile the executable
     pub fn jit_compile(executable: &mut Pin<Box<Self>>) -> Result<(), EbpfError<E>> {
         // TODO: Turn back to `executable: &mut self` once Self::report_unresolved_symbol() is gone
+        executable.wait_for_background_compilation();
         executable.compiled_program = Some(JitProgram::<E, I>::new(executable)?);
         Ok(())
     }
 
+    /// JIT compile the executable, reusing the machine code stored in the given cache if possible
+    ///
+    /// Entries which are missing or fail the integrity check are compiled from scratch and
+    /// (re)placed in the cache. Failing to write the cache is not an error.
+    pub fn jit_compile_cached(
+        executable: &mut Pin<Box<Self>>,
+        cache: &JitCache,
+    ) -> Result<(), EbpfError<E>> {
+        executable.wait_for_background_compilation();
+        let compiled_program = match cache.load(executable) {
+            Ok(Some(compiled_program)) => compiled_program,
+            Ok(None) | Err(EbpfError::InvalidJitCacheEntry(_)) => {
+                let compiled_program = JitProgram::<E, I>::new(executable)?;
+                let _ = cache.store(executable, &compiled_program);
+                compiled_program
+            }
+            Err(err) => return Err(err),
+        };
+        executable.compiled_program = Some(compiled_program);
+        Ok(())
+    }
+
+    /// Get the pre-decoded program of the threaded-code interpreter
+    pub fn get_threaded_program(&self) -> Option<&ThreadedProgram<E, I>> {
+        self.threaded_program.as_ref()
+    }
+
+    /// Pre-decode the executable for the threaded-code interpreter
+    pub fn threaded_compile(executable: &mut Pin<Box<Self>>) -> Result<(), EbpfError<E>> {
+        executable.wait_for_background_compilation();
+        executable.threaded_program = Some(ThreadedProgram::<E, I>::new(executable));
+        Ok(())
+    }
+
+    /// Get the number of `EbpfVm::execute_program()` calls so far
+    pub fn get_invocation_count(&self) -> usize {
+        self.tiering.get_invocation_count()
+    }
+
+    /// Get the backend `EbpfVm::execute_program()` currently uses
+    pub fn get_execution_tier(&self) -> ExecutionTier {
+        if self.get_compiled_program().is_some() {
+            ExecutionTier::Jit
+        } else {
+            ExecutionTier::Interpreter
+        }
+    }
+
+    /// Block until the JIT compilation started by the tiered execution (if any) is finished
+    pub fn wait_for_background_compilation(&self) {
+        self.tiering.wait_for_background_compilation();
+    }
+
+    pub(crate) fn get_tiering(&self) -> &Tiering<E, I> {
+        &self.tiering
+    }
+
     /// Report information on a symbol that failed to be resolved
     pub fn report_unresolved_symbol(&self, insn_offset: usize) -> Result<u64, EbpfError<E>> {
         let file_offset = insn_offset
@@ -407,6 +507,24 @@ impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
         &self.syscall_symbols
     }
 
+    /// Get the DWARF debug info (if enabled and not stripped)
+    pub fn get_debug_info(&self) -> Option<&DebugInfo> {
+        self.debug_info.as_ref()
+    }
+
+    /// Describes an error, including the source location of the faulting instruction if known
+    pub fn describe_error(&self, error: &EbpfError<E>) -> String {
+        let location = error.get_instruction_index().and_then(|pc| {
+            self.debug_info
+                .as_ref()
+                .and_then(|debug_info| debug_info.symbolize(pc))
+        });
+        match location {
+            Some(location) => format!("{} ({})", error, location),
+            None => error.to_string(),
+        }
+    }
+
     /// Create from raw text section bytes (list of instructions)
     pub fn new_from_text_bytes(
         config: Config,
@@ -416,8 +534,10 @@ impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
     ) -> Self {
         let elf_bytes = AlignedMemory::new_with_data(text_bytes, ebpf::HOST_ALIGN);
         let enable_symbol_and_section_labels = config.enable_symbol_and_section_labels;
+        let sbf_version = config.max_sbf_version;
         Self {
             config,
+            sbf_version,
             elf_bytes,
             ro_section: Section::Borrowed(0, 0..text_bytes.len()),
             text_section_info: SectionInfo {
@@ -432,20 +552,23 @@ impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
             bpf_functions,
             syscall_symbols: BTreeMap::default(),
             syscall_registry,
+            debug_info: None,
             compiled_program: None,
+            threaded_program: None,
+            tiering: Tiering::new(),
         }
     }
 
     /// Fully loads an ELF, including validation and relocation
     pub fn load(
-        mut config: Config,
+        config: Config,
         bytes: &[u8],
         syscall_registry: SyscallRegistry,
     ) -> Result<Self, ElfError> {
         let elf = Elf::parse(bytes)?;
         let mut elf_bytes = AlignedMemory::new_with_data(bytes, ebpf::HOST_ALIGN);
 
-        Self::validate(&mut config, &elf, elf_bytes.as_slice())?;
+        let sbf_version = Self::validate(&config, &elf, elf_bytes.as_slice())?;
 
         // calculate the text section info
         let text_section = Self::get_section(&elf, ".text")?;
@@ -478,6 +601,7 @@ impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
         let mut syscall_symbols = BTreeMap::default();
         Self::relocate(
             &config,
+            sbf_version,
             &mut bpf_functions,
             &mut syscall_symbols,
             &syscall_registry,
@@ -505,21 +629,36 @@ impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
 
         let ro_section = Self::parse_ro_sections(
             &config,
+            sbf_version,
             elf.section_headers
                 .iter()
                 .map(|s| (elf.shdr_strtab.get_at(s.sh_name), s)),
             elf_bytes.as_slice(),
         )?;
 
+        let debug_info = if config.enable_debug_info {
+            // Debug info is optional, so a malformed one must not prevent loading the program
+            DebugInfo::parse(&elf, bytes).unwrap_or_else(|err| {
+                log::warn!("Ignoring debug info: {}", err);
+                None
+            })
+        } else {
+            None
+        };
+
         Ok(Self {
             config,
+            sbf_version,
             elf_bytes,
             ro_section,
             text_section_info,
             bpf_functions,
             syscall_symbols,
             syscall_registry,
+            debug_info,
             compiled_program: None,
+            threaded_program: None,
+            tiering: Tiering::new(),
         })
     }
 
@@ -554,8 +693,12 @@ impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
                 .saturating_add(name.capacity())))))
             // syscall registry
             .saturating_add(self.syscall_registry.mem_size())
+            // debug info
+            .saturating_add(self.debug_info.as_ref().map_or(0, |debug_info| debug_info.mem_size()))
             // compiled programs
-            .saturating_add(self.compiled_program.as_ref().map_or(0, |program| program.mem_size()));
+            .saturating_add(self.compiled_program.as_ref().map_or(0, |program| program.mem_size()))
+            .saturating_add(self.threaded_program.as_ref().map_or(0, |program| program.mem_size()))
+            .saturating_add(self.tiering.get_compiled_program().map_or(0, |program| program.mem_size()));
 
         total as usize
     }
@@ -565,6 +708,7 @@ impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
     /// Fix-ups relative calls
     pub fn fixup_relative_calls(
         config: &Config,
+        sbf_version: SbfVersion,
         bpf_functions: &mut BTreeMap<u32, (usize, String)>,
         syscall_registry: &SyscallRegistry,
         elf_bytes: &mut [u8],
@@ -577,7 +721,7 @@ impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
             let mut insn = ebpf::get_insn(elf_bytes, i);
             if insn.opc == ebpf::CALL_IMM
                 && insn.imm != -1
-                && !(config.static_syscalls && insn.src == 0)
+                && !(sbf_version.static_syscalls() && insn.src == 0)
             {
                 let target_pc = (i as isize)
                     .saturating_add(1)
@@ -611,8 +755,8 @@ impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
         Ok(())
     }
 
-    /// Validates the ELF
-    pub fn validate(config: &mut Config, elf: &Elf, elf_bytes: &[u8]) -> Result<(), ElfError> {
+    /// Validates the ELF and returns its SBF version
+    pub fn validate(config: &Config, elf: &Elf, elf_bytes: &[u8]) -> Result<SbfVersion, ElfError> {
         if elf.header.e_ident[EI_CLASS] != ELFCLASS64 {
             return Err(ElfError::WrongClass);
         }
@@ -629,17 +773,13 @@ impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
             return Err(ElfError::WrongType);
         }
 
-        if elf.header.e_flags == EF_SBF_V2 {
-            if !config.dynamic_stack_frames {
-                return Err(ElfError::DynamicStackFramesDisabled);
-            }
-        } else {
-            config.dynamic_stack_frames = false;
-            config.enable_elf_vaddr = false;
-            config.static_syscalls = false;
+        let sbf_version = SbfVersion::from_e_flags(elf.header.e_flags)
+            .ok_or(ElfError::UnknownSbfVersion(elf.header.e_flags))?;
+        if sbf_version < config.min_sbf_version || sbf_version > config.max_sbf_version {
+            return Err(ElfError::UnsupportedSbfVersion(sbf_version));
         }
 
-        if config.enable_elf_vaddr {
+        if sbf_version.enable_elf_vaddr() {
             // This is needed to avoid an overflow error in header.vm_range() as
             // used by relocate(). See https://github.com/m4b/goblin/pull/306.
             //
@@ -701,7 +841,7 @@ impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
             return Err(ElfError::EntrypointOutOfBounds);
         }
 
-        Ok(())
+        Ok(sbf_version)
     }
 
     pub(crate) fn parse_ro_sections<
@@ -709,6 +849,7 @@ impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
         S: IntoIterator<Item = (Option<&'a str>, &'a SectionHeader)>,
     >(
         config: &Config,
+        sbf_version: SbfVersion,
         sections: S,
         elf_bytes: &[u8],
     ) -> Result<Section, ElfError> {
@@ -719,7 +860,7 @@ impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
         // the aggregated section length, not including gaps between sections
         let mut ro_fill_length = 0usize;
         let mut invalid_offsets = false;
-        // when config.enable_elf_vaddr=true, we allow section_addr != sh_offset
+        // when sbf_version.enable_elf_vaddr(), we allow section_addr != sh_offset
         // if section_addr - sh_offset is constant across all sections. That is,
         // we allow sections to be translated by a fixed virtual offset.
         let mut addr_file_offset = None;
@@ -748,7 +889,8 @@ impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
             n_ro_sections = n_ro_sections.saturating_add(1);
 
             let section_addr = section_header.sh_addr;
-            let vaddr = if config.enable_elf_vaddr && section_addr >= ebpf::MM_PROGRAM_START {
+            let vaddr = if sbf_version.enable_elf_vaddr() && section_addr >= ebpf::MM_PROGRAM_START
+            {
                 section_addr
             } else {
                 section_addr.saturating_add(ebpf::MM_PROGRAM_START)
@@ -756,16 +898,16 @@ impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
 
             // sh_offset handling:
             //
-            // If config.enable_elf_vaddr=true, we allow section_addr >
+            // If sbf_version.enable_elf_vaddr(), we allow section_addr >
             // sh_offset, if section_addr - sh_offset is constant across all
             // sections. That is, we allow the linker to align rodata to a
             // positive base address (MM_PROGRAM_START) as long as the mapping
             // to sh_offset(s) stays linear.
             //
-            // If config.enable_elf_vaddr=false, section_addr must match
+            // If !sbf_version.enable_elf_vaddr(), section_addr must match
             // sh_offset for backwards compatibility
             if !invalid_offsets {
-                if config.enable_elf_vaddr {
+                if sbf_version.enable_elf_vaddr() {
                     if section_addr < section_header.sh_offset {
                         invalid_offsets = true;
                     } else {
@@ -812,7 +954,7 @@ impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
             // Read only sections are grouped together with no intermixed non-ro
             // sections. We can borrow.
 
-            // When config.enable_elf_vaddr=true, section addresses and their
+            // When sbf_version.enable_elf_vaddr(), section addresses and their
             // corresponding buffer offsets can be translated by a constant
             // amount. Subtract the constant to get buffer positions.
             let buf_offset_start =
@@ -889,6 +1031,7 @@ impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
     /// Relocates the ELF in-place
     fn relocate(
         config: &Config,
+        sbf_version: SbfVersion,
         bpf_functions: &mut BTreeMap<u32, (usize, String)>,
         syscall_symbols: &mut BTreeMap<u32, String>,
         syscall_registry: &SyscallRegistry,
@@ -901,6 +1044,7 @@ impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
         // Fixup all program counter relative call instructions
         Self::fixup_relative_calls(
             config,
+            sbf_version,
             bpf_functions,
             syscall_registry,
             elf_bytes
@@ -914,10 +1058,10 @@ impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
         for relocation in &elf.dynrels {
             let mut r_offset = relocation.r_offset as usize;
 
-            // When config.enable_elf_vaddr=true, we allow section.sh_addr !=
+            // When sbf_version.enable_elf_vaddr(), we allow section.sh_addr !=
             // section.sh_offset so we need to bring r_offset to the correct
             // byte offset.
-            if config.enable_elf_vaddr {
+            if sbf_version.enable_elf_vaddr() {
                 match program_header {
                     Some(header) if header.vm_range().contains(&r_offset) => {}
                     _ => {
@@ -1053,7 +1197,7 @@ impl<E: UserDefinedError, I: InstructionMeter> Executable<E, I> {
                             refd_addr.checked_shr(32).unwrap_or_default() as u32,
                         );
                     } else {
-                        let refd_addr = if elf.header.e_flags == EF_SBF_V2 {
+                        let refd_addr = if sbf_version != SbfVersion::V1 {
                             // We're relocating an address inside a data section (eg .rodata). The
                             // address is encoded as a simple u64.
 
@@ -1255,34 +1399,30 @@ mod test {
             .expect("failed to read elf file");
         let mut parsed_elf = Elf::parse(&bytes).unwrap();
         let elf_bytes = bytes.to_vec();
-        let mut config = Config::default();
+        let config = Config::default();
 
-        ElfExecutable::validate(&mut config, &parsed_elf, &elf_bytes).expect("validation failed");
+        ElfExecutable::validate(&config, &parsed_elf, &elf_bytes).expect("validation failed");
         parsed_elf.header.e_ident[EI_CLASS] = ELFCLASS32;
-        ElfExecutable::validate(&mut config, &parsed_elf, &elf_bytes)
-            .expect_err("allowed bad class");
+        ElfExecutable::validate(&config, &parsed_elf, &elf_bytes).expect_err("allowed bad class");
         parsed_elf.header.e_ident[EI_CLASS] = ELFCLASS64;
-        ElfExecutable::validate(&mut config, &parsed_elf, &elf_bytes).expect("validation failed");
+        ElfExecutable::validate(&config, &parsed_elf, &elf_bytes).expect("validation failed");
         parsed_elf.header.e_ident[EI_DATA] = ELFDATA2MSB;
-        ElfExecutable::validate(&mut config, &parsed_elf, &elf_bytes)
-            .expect_err("allowed big endian");
+        ElfExecutable::validate(&config, &parsed_elf, &elf_bytes).expect_err("allowed big endian");
         parsed_elf.header.e_ident[EI_DATA] = ELFDATA2LSB;
-        ElfExecutable::validate(&mut config, &parsed_elf, &elf_bytes).expect("validation failed");
+        ElfExecutable::validate(&config, &parsed_elf, &elf_bytes).expect("validation failed");
         parsed_elf.header.e_ident[EI_OSABI] = 1;
-        ElfExecutable::validate(&mut config, &parsed_elf, &elf_bytes)
-            .expect_err("allowed wrong abi");
+        ElfExecutable::validate(&config, &parsed_elf, &elf_bytes).expect_err("allowed wrong abi");
         parsed_elf.header.e_ident[EI_OSABI] = ELFOSABI_NONE;
-        ElfExecutable::validate(&mut config, &parsed_elf, &elf_bytes).expect("validation failed");
+        ElfExecutable::validate(&config, &parsed_elf, &elf_bytes).expect("validation failed");
         parsed_elf.header.e_machine = EM_QDSP6;
-        ElfExecutable::validate(&mut config, &parsed_elf, &elf_bytes)
+        ElfExecutable::validate(&config, &parsed_elf, &elf_bytes)
             .expect_err("allowed wrong machine");
         parsed_elf.header.e_machine = EM_BPF;
-        ElfExecutable::validate(&mut config, &parsed_elf, &elf_bytes).expect("validation failed");
+        ElfExecutable::validate(&config, &parsed_elf, &elf_bytes).expect("validation failed");
         parsed_elf.header.e_type = ET_REL;
-        ElfExecutable::validate(&mut config, &parsed_elf, &elf_bytes)
-            .expect_err("allowed wrong type");
+        ElfExecutable::validate(&config, &parsed_elf, &elf_bytes).expect_err("allowed wrong type");
         parsed_elf.header.e_type = ET_DYN;
-        ElfExecutable::validate(&mut config, &parsed_elf, &elf_bytes).expect("validation failed");
+        ElfExecutable::validate(&config, &parsed_elf, &elf_bytes).expect("validation failed");
     }
 
     #[test]
@@ -1385,6 +1525,7 @@ mod test {
 
         ElfExecutable::fixup_relative_calls(
             &config,
+            SbfVersion::V2,
             &mut bpf_functions,
             &syscall_registry,
             &mut prog,
@@ -1407,6 +1548,7 @@ mod test {
         prog.splice(44.., vec![0xfa, 0xff, 0xff, 0xff]);
         ElfExecutable::fixup_relative_calls(
             &config,
+            SbfVersion::V2,
             &mut bpf_functions,
             &syscall_registry,
             &mut prog,
@@ -1446,6 +1588,7 @@ mod test {
 
         ElfExecutable::fixup_relative_calls(
             &config,
+            SbfVersion::V2,
             &mut bpf_functions,
             &syscall_registry,
             &mut prog,
@@ -1468,6 +1611,7 @@ mod test {
         prog.splice(4..8, vec![0x04, 0x00, 0x00, 0x00]);
         ElfExecutable::fixup_relative_calls(
             &config,
+            SbfVersion::V2,
             &mut bpf_functions,
             &syscall_registry,
             &mut prog,
@@ -1507,6 +1651,7 @@ mod test {
 
         ElfExecutable::fixup_relative_calls(
             &config,
+            SbfVersion::V2,
             &mut bpf_functions,
             &syscall_registry,
             &mut prog,
@@ -1546,6 +1691,7 @@ mod test {
 
         ElfExecutable::fixup_relative_calls(
             &config,
+            SbfVersion::V2,
             &mut bpf_functions,
             &syscall_registry,
             &mut prog,
@@ -1646,6 +1792,7 @@ mod test {
         assert!(matches!(
             ElfExecutable::parse_ro_sections(
                 &config,
+                SbfVersion::V2,
                 [(Some(".text"), &s1), (Some(".dynamic"), &s2), (Some(".rodata"), &s3)],
                 &elf_bytes,
             ),
@@ -1670,6 +1817,7 @@ mod test {
         assert!(matches!(
             ElfExecutable::parse_ro_sections(
                 &config,
+                SbfVersion::V2,
                 [(Some(".text"), &s1), (Some(".rodata"), &s2)],
                 &elf_bytes,
             ),
@@ -1694,6 +1842,7 @@ mod test {
         assert_eq!(
             ElfExecutable::parse_ro_sections(
                 &config,
+                SbfVersion::V2,
                 [(Some(".text"), &s1), (Some(".rodata"), &s2)],
                 &elf_bytes,
             ),
@@ -1720,6 +1869,7 @@ mod test {
         assert_eq!(
             ElfExecutable::parse_ro_sections(
                 &config,
+                SbfVersion::V2,
                 [(Some(".text"), &s1), (Some(".rodata"), &s2)],
                 &elf_bytes,
             ),
@@ -1744,6 +1894,7 @@ mod test {
         assert_eq!(
             ElfExecutable::parse_ro_sections(
                 &config,
+                SbfVersion::V2,
                 [(Some(".text"), &s1), (Some(".rodata"), &s2)],
                 &elf_bytes,
             ),
@@ -1763,6 +1914,7 @@ mod test {
 
         let ro_section = ElfExecutable::parse_ro_sections(
             &config,
+            SbfVersion::V2,
             [
                 (Some(".text"), &s1),
                 (Some(".dynamic"), &s2),
@@ -1807,6 +1959,7 @@ mod test {
 
         let ro_section = ElfExecutable::parse_ro_sections(
             &config,
+            SbfVersion::V2,
             [
                 (Some(".text"), &s1),
                 (Some(".dynamic"), &s2),
@@ -1850,6 +2003,7 @@ mod test {
 
         let ro_section = ElfExecutable::parse_ro_sections(
             &config,
+            SbfVersion::V2,
             [
                 (Some(".text"), &s1),
                 (Some(".dynamic"), &s2),
@@ -1912,6 +2066,7 @@ mod test {
         assert!(matches!(
             ElfExecutable::parse_ro_sections(
                 &config,
+                SbfVersion::V2,
                 [(Some(".text"), &s1), (Some(".rodata"), &s2)],
                 &elf_bytes,
             ),
@@ -1932,6 +2087,7 @@ mod test {
         assert_eq!(
             ElfExecutable::parse_ro_sections(
                 &config,
+                SbfVersion::V2,
                 [
                     (Some(".dynsym"), &s1),
                     (Some(".text"), &s2),
@@ -1955,6 +2111,7 @@ mod test {
 
         let ro_section = ElfExecutable::parse_ro_sections(
             &config,
+            SbfVersion::V2,
             [
                 (Some(".text"), &s1),
                 (Some(".rodata"), &s2),
@@ -1991,6 +2148,7 @@ mod test {
 
         let ro_section = ElfExecutable::parse_ro_sections(
             &config,
+            SbfVersion::V2,
             [
                 (Some(".dynamic"), &s1),
                 (Some(".text"), &s2),
@@ -2056,18 +2214,60 @@ mod test {
     fn test_static_syscall_disabled() {
         let elf_bytes =
             std::fs::read("tests/elfs/syscall_static_unknown.so").expect("failed to read elf file");
+        let mut parsed_elf = Elf::parse(&elf_bytes).unwrap();
+        parsed_elf.header.e_flags = SbfVersion::V1.e_flags();
+        let mut elf_bytes = elf_bytes.clone();
+        elf_bytes.pwrite(parsed_elf.header, 0).unwrap();
 
-        // when config.static_syscalls=false, all CALL_IMMs are treated as relative
+        // SBFv1 has no static syscalls, all CALL_IMMs are treated as relative
         // calls for backwards compatibility
-        ElfExecutable::load(
-            Config {
-                static_syscalls: false,
-                ..Config::default()
-            },
-            &elf_bytes,
-            syscall_registry(),
-        )
-        .expect("validation failed");
+        ElfExecutable::load(Config::default(), &elf_bytes, syscall_registry())
+            .expect("validation failed");
+    }
+
+    #[test]
+    fn test_sbf_version() {
+        let v1_elf_bytes = std::fs::read("tests/elfs/noop.so").expect("failed to read elf file");
+        let v2_elf_bytes =
+            std::fs::read("tests/elfs/syscall_static.so").expect("failed to read elf file");
+
+        let executable =
+            ElfExecutable::load(Config::default(), &v1_elf_bytes, syscall_registry()).unwrap();
+        assert_eq!(executable.get_sbf_version(), SbfVersion::V1);
+        let executable =
+            ElfExecutable::load(Config::default(), &v2_elf_bytes, syscall_registry()).unwrap();
+        assert_eq!(executable.get_sbf_version(), SbfVersion::V2);
+
+        let only_v1 = Config {
+            min_sbf_version: SbfVersion::V1,
+            max_sbf_version: SbfVersion::V1,
+            ..Config::default()
+        };
+        assert!(ElfExecutable::load(only_v1, &v1_elf_bytes, syscall_registry()).is_ok());
+        assert_eq!(
+            ElfExecutable::load(only_v1, &v2_elf_bytes, syscall_registry()),
+            Err(ElfError::UnsupportedSbfVersion(SbfVersion::V2))
+        );
+
+        let only_v2 = Config {
+            min_sbf_version: SbfVersion::V2,
+            max_sbf_version: SbfVersion::V2,
+            ..Config::default()
+        };
+        assert_eq!(
+            ElfExecutable::load(only_v2, &v1_elf_bytes, syscall_registry()),
+            Err(ElfError::UnsupportedSbfVersion(SbfVersion::V1))
+        );
+        assert!(ElfExecutable::load(only_v2, &v2_elf_bytes, syscall_registry()).is_ok());
+
+        let mut parsed_elf = Elf::parse(&v1_elf_bytes).unwrap();
+        parsed_elf.header.e_flags = 0x40;
+        let mut unknown_elf_bytes = v1_elf_bytes.clone();
+        unknown_elf_bytes.pwrite(parsed_elf.header, 0).unwrap();
+        assert_eq!(
+            ElfExecutable::load(Config::default(), &unknown_elf_bytes, syscall_registry()),
+            Err(ElfError::UnknownSbfVersion(0x40))
+        );
     }
 
     #[test]
@@ -17,7 +17,9 @@
 //! <https://www.kernel.org/doc/Documentation/networking/filter.txt>, or for a shorter version of
 //! the list of the operation codes: <https://github.com/iovisor/bpf-docs/blob/master/eBPF.md>
 
-use crate::{elf::ElfError, memory_region::AccessType, verifier::VerifierError};
+use crate::{
+    backtrace::Backtrace, ebpf, elf::ElfError, memory_region::AccessType, verifier::VerifierError,
+};
 
 /// User defined errors must implement this trait
 pub trait UserDefinedError: 'static + std::error::Error {}
@@ -66,6 +68,12 @@ pub enum EbpfError<E: UserDefinedError> {
     /// Program has not been JIT-compiled
     #[error("program has not been JIT-compiled")]
     JitNotCompiled,
+    /// Program has not been pre-decoded for the threaded-code interpreter
+    #[error("program has not been pre-decoded for the threaded-code interpreter")]
+    ThreadedProgramNotCompiled,
+    /// JIT cache entry is corrupted or does not fit the executable
+    #[error("invalid JIT cache entry: {0}")]
+    InvalidJitCacheEntry(String),
     /// Invalid virtual address
     #[error("invalid virtual address {0:x?}")]
     InvalidVirtualAddress(u64),
@@ -95,4 +103,28 @@ pub enum EbpfError<E: UserDefinedError> {
     /// ELF error
     #[error("Verifier error: {0}")]
     VerifierError(#[from] VerifierError),
+    /// Error of a failing program together with the active call frames at the point of failure
+    #[error("{}\nBacktrace:\n{}", .0.as_ref(), .1)]
+    WithBacktrace(Box<EbpfError<E>>, Box<Backtrace>),
+}
+
+impl<E: UserDefinedError> EbpfError<E> {
+    /// Returns the index of the faulting instruction (without `ELF_INSN_DUMP_OFFSET`), if any
+    pub fn get_instruction_index(&self) -> Option<usize> {
+        let pc = match self {
+            EbpfError::CallDepthExceeded(pc, _)
+            | EbpfError::DivideByZero(pc)
+            | EbpfError::DivideOverflow(pc)
+            | EbpfError::ExecutionOverrun(pc)
+            | EbpfError::CallOutsideTextSegment(pc, _)
+            | EbpfError::ExceededMaxInstructions(pc, _)
+            | EbpfError::AccessViolation(pc, _, _, _, _)
+            | EbpfError::StackAccessViolation(pc, _, _, _, _)
+            | EbpfError::InvalidInstruction(pc)
+            | EbpfError::UnsupportedInstruction(pc) => *pc,
+            EbpfError::WithBacktrace(error, _) => return error.get_instruction_index(),
+            _ => return None,
+        };
+        pc.checked_sub(ebpf::ELF_INSN_DUMP_OFFSET)
+    }
 }
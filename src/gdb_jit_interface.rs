@@ -0,0 +1,362 @@
+#![allow(clippy::integer_arithmetic)]
+//! GDB JIT interface for JIT-compiled programs
+//!
+//! Only built with the `gdb-jit-interface` feature, because the interface consists of the
+//! unmangled symbols `__jit_debug_register_code` and `__jit_debug_descriptor`, which must
+//! exist only once in a process. Linking two versions of this crate or another JIT which
+//! implements the interface (e.g. wasmtime or LLVM) would otherwise fail with duplicate symbols.
+
+use crate::jit_symbols::RUNTIME_SYMBOL;
+use goblin::{
+    container::{Container, Ctx, Endian},
+    elf::{
+        header::{Header, EM_X86_64, ET_REL},
+        section_header::{
+            SectionHeader, SHF_ALLOC, SHF_EXECINSTR, SHT_NOBITS, SHT_STRTAB, SHT_SYMTAB,
+        },
+        sym::{Sym, STB_GLOBAL, STB_LOCAL, STT_FUNC},
+    },
+};
+use scroll::Pwrite;
+use std::{ops::Range, ptr, sync::Mutex};
+
+/// Builds a relocatable ELF object which describes the machine code at `text_section_address`
+///
+/// The `.text` section occupies no space in the object, the symbols are relative to it.
+pub(crate) fn build_debug_object(
+    text_section_address: u64,
+    text_section_length: usize,
+    symbols: &[(String, Range<usize>)],
+) -> Vec<u8> {
+    const SECTION_NAMES: [&str; 5] = ["", ".text", ".symtab", ".strtab", ".shstrtab"];
+    let ctx = Ctx::new(Container::Big, Endian::Little);
+    let mut section_name_table = Vec::new();
+    let mut section_names = Vec::new();
+    for name in SECTION_NAMES.iter() {
+        section_names.push(section_name_table.len());
+        section_name_table.extend_from_slice(name.as_bytes());
+        section_name_table.push(0);
+    }
+    let mut string_table = vec![0];
+    let mut symbol_table = vec![Sym::default()];
+    for (name, range) in symbols {
+        symbol_table.push(Sym {
+            st_name: string_table.len(),
+            st_info: (if name == RUNTIME_SYMBOL {
+                STB_LOCAL
+            } else {
+                STB_GLOBAL
+            }) << 4
+                | STT_FUNC,
+            st_other: 0,
+            st_shndx: 1,
+            st_value: range.start as u64,
+            st_size: range.len() as u64,
+        });
+        string_table.extend_from_slice(name.as_bytes());
+        string_table.push(0);
+    }
+    // Local symbols have to precede the global ones
+    symbol_table[1..].sort_by_key(|symbol| symbol.st_bind() != STB_LOCAL);
+    let first_global_symbol = symbol_table
+        .iter()
+        .skip(1)
+        .position(|symbol| symbol.st_bind() != STB_LOCAL)
+        .map_or(symbol_table.len(), |index| index + 1);
+
+    let header_size = Header::size(ctx);
+    let section_header_size = SectionHeader::size(ctx);
+    let symbol_size = Sym::size(ctx.container);
+    let symbol_table_offset = header_size + SECTION_NAMES.len() * section_header_size;
+    let string_table_offset = symbol_table_offset + symbol_table.len() * symbol_size;
+    let section_name_table_offset = string_table_offset + string_table.len();
+    let mut object = vec![0; section_name_table_offset + section_name_table.len()];
+
+    let mut header = Header::new(ctx);
+    header.e_type = ET_REL;
+    header.e_machine = EM_X86_64;
+    header.e_shoff = header_size as u64;
+    header.e_shnum = SECTION_NAMES.len() as u16;
+    header.e_shstrndx = 4;
+    object.pwrite_with(header, 0, ctx.le).unwrap();
+    let section_headers = [
+        SectionHeader::default(),
+        SectionHeader {
+            sh_name: section_names[1],
+            sh_type: SHT_NOBITS,
+            sh_flags: (SHF_ALLOC | SHF_EXECINSTR) as u64,
+            sh_addr: text_section_address,
+            sh_offset: header_size as u64,
+            sh_size: text_section_length as u64,
+            sh_addralign: 16,
+            ..SectionHeader::default()
+        },
+        SectionHeader {
+            sh_name: section_names[2],
+            sh_type: SHT_SYMTAB,
+            sh_offset: symbol_table_offset as u64,
+            sh_size: (symbol_table.len() * symbol_size) as u64,
+            sh_link: 3,
+            sh_info: first_global_symbol as u32,
+            sh_addralign: 8,
+            sh_entsize: symbol_size as u64,
+            ..SectionHeader::default()
+        },
+        SectionHeader {
+            sh_name: section_names[3],
+            sh_type: SHT_STRTAB,
+            sh_offset: string_table_offset as u64,
+            sh_size: string_table.len() as u64,
+            sh_addralign: 1,
+            ..SectionHeader::default()
+        },
+        SectionHeader {
+            sh_name: section_names[4],
+            sh_type: SHT_STRTAB,
+            sh_offset: section_name_table_offset as u64,
+            sh_size: section_name_table.len() as u64,
+            sh_addralign: 1,
+            ..SectionHeader::default()
+        },
+    ];
+    for (index, section_header) in section_headers.iter().cloned().enumerate() {
+        object
+            .pwrite_with(
+                section_header,
+                header_size + index * section_header_size,
+                ctx,
+            )
+            .unwrap();
+    }
+    for (index, symbol) in symbol_table.into_iter().enumerate() {
+        object
+            .pwrite_with(symbol, symbol_table_offset + index * symbol_size, ctx)
+            .unwrap();
+    }
+    object[string_table_offset..section_name_table_offset].copy_from_slice(&string_table);
+    object[section_name_table_offset..].copy_from_slice(&section_name_table);
+    object
+}
+
+// The GDB JIT interface, see https://sourceware.org/gdb/onlinedocs/gdb/JIT-Interface.html
+
+const JIT_REGISTER_FN: u32 = 1;
+const JIT_UNREGISTER_FN: u32 = 2;
+
+#[repr(C)]
+struct JitCodeEntry {
+    next_entry: *mut JitCodeEntry,
+    prev_entry: *mut JitCodeEntry,
+    symfile_addr: *const u8,
+    symfile_size: u64,
+}
+
+#[repr(C)]
+struct JitDescriptor {
+    version: u32,
+    action_flag: u32,
+    relevant_entry: *mut JitCodeEntry,
+    first_entry: *mut JitCodeEntry,
+}
+
+/// The debugger sets a breakpoint in this function to get notified of changes
+#[no_mangle]
+#[inline(never)]
+extern "C" fn __jit_debug_register_code() {
+    // Keeps the function from being optimized away or merged with others
+    std::hint::black_box(());
+}
+
+/// The debugger reads the list of registered objects from here
+#[no_mangle]
+static mut __jit_debug_descriptor: JitDescriptor = JitDescriptor {
+    version: 1,
+    action_flag: 0,
+    relevant_entry: ptr::null_mut(),
+    first_entry: ptr::null_mut(),
+};
+
+/// Serializes the modifications of `__jit_debug_descriptor`
+static JIT_DEBUG_DESCRIPTOR_LOCK: Mutex<()> = Mutex::new(());
+
+/// An object registered with the GDB JIT interface, unregistered when dropped
+pub(crate) struct GdbJitRegistration {
+    entry: Box<JitCodeEntry>,
+    _object: Vec<u8>,
+}
+
+// The entry is only accessed while holding JIT_DEBUG_DESCRIPTOR_LOCK
+unsafe impl Send for GdbJitRegistration {}
+unsafe impl Sync for GdbJitRegistration {}
+
+impl GdbJitRegistration {
+    /// Registers an ELF object with the GDB JIT interface
+    pub(crate) fn new(object: Vec<u8>) -> Self {
+        let mut entry = Box::new(JitCodeEntry {
+            next_entry: ptr::null_mut(),
+            prev_entry: ptr::null_mut(),
+            symfile_addr: object.as_ptr(),
+            symfile_size: object.len() as u64,
+        });
+        let _guard = JIT_DEBUG_DESCRIPTOR_LOCK.lock().unwrap();
+        unsafe {
+            let descriptor = ptr::addr_of_mut!(__jit_debug_descriptor);
+            entry.next_entry = (*descriptor).first_entry;
+            if !entry.next_entry.is_null() {
+                (*entry.next_entry).prev_entry = &mut *entry;
+            }
+            (*descriptor).first_entry = &mut *entry;
+            (*descriptor).relevant_entry = &mut *entry;
+            (*descriptor).action_flag = JIT_REGISTER_FN;
+            __jit_debug_register_code();
+        }
+        Self {
+            entry,
+            _object: object,
+        }
+    }
+}
+
+impl Drop for GdbJitRegistration {
+    fn drop(&mut self) {
+        let _guard = JIT_DEBUG_DESCRIPTOR_LOCK.lock().unwrap();
+        unsafe {
+            let descriptor = ptr::addr_of_mut!(__jit_debug_descriptor);
+            let entry: *mut JitCodeEntry = &mut *self.entry;
+            if (*entry).prev_entry.is_null() {
+                (*descriptor).first_entry = (*entry).next_entry;
+            } else {
+                (*(*entry).prev_entry).next_entry = (*entry).next_entry;
+            }
+            if !(*entry).next_entry.is_null() {
+                (*(*entry).next_entry).prev_entry = (*entry).prev_entry;
+            }
+            (*descriptor).relevant_entry = entry;
+            (*descriptor).action_flag = JIT_UNREGISTER_FN;
+            __jit_debug_register_code();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use goblin::elf::Elf;
+
+    fn registered_entries() -> Vec<*const u8> {
+        registered_objects()
+            .into_iter()
+            .map(|object| object.as_ptr())
+            .collect()
+    }
+
+    fn registered_objects() -> Vec<&'static [u8]> {
+        let _guard = JIT_DEBUG_DESCRIPTOR_LOCK.lock().unwrap();
+        let mut objects = Vec::new();
+        unsafe {
+            let mut entry = (*ptr::addr_of!(__jit_debug_descriptor)).first_entry;
+            while !entry.is_null() {
+                objects.push(std::slice::from_raw_parts(
+                    (*entry).symfile_addr,
+                    (*entry).symfile_size as usize,
+                ));
+                entry = (*entry).next_entry;
+            }
+        }
+        objects
+    }
+
+    #[test]
+    fn test_debug_object() {
+        let symbols = vec![
+            (RUNTIME_SYMBOL.to_string(), 0..0x40),
+            ("entrypoint".to_string(), 0x40..0x80),
+            ("function_foo".to_string(), 0x80..0x90),
+            (RUNTIME_SYMBOL.to_string(), 0x90..0xa0),
+        ];
+        let object = build_debug_object(0x7f00_0000_1000, 0xa0, &symbols);
+        let elf = Elf::parse(&object).unwrap();
+        assert_eq!(elf.header.e_type, ET_REL);
+        assert_eq!(elf.header.e_machine, EM_X86_64);
+        let text = &elf.section_headers[1];
+        assert_eq!(elf.shdr_strtab.get_at(text.sh_name), Some(".text"));
+        assert_eq!(text.sh_type, SHT_NOBITS);
+        assert_eq!(text.sh_addr, 0x7f00_0000_1000);
+        assert_eq!(text.sh_size, 0xa0);
+        let mut parsed_symbols = elf
+            .syms
+            .iter()
+            .skip(1)
+            .map(|symbol| {
+                assert_eq!(symbol.st_type(), STT_FUNC);
+                assert_eq!(symbol.st_shndx, 1);
+                (
+                    elf.strtab.get_at(symbol.st_name).unwrap().to_string(),
+                    symbol.st_value as usize..(symbol.st_value + symbol.st_size) as usize,
+                )
+            })
+            .collect::<Vec<_>>();
+        parsed_symbols.sort_by_key(|(_name, range)| range.start);
+        assert_eq!(parsed_symbols, symbols);
+        assert_eq!(elf.syms.iter().nth(3).unwrap().st_bind(), STB_GLOBAL);
+    }
+
+    #[test]
+    fn test_gdb_jit_registration() {
+        let first = GdbJitRegistration::new(vec![1]);
+        let second = GdbJitRegistration::new(vec![2]);
+        let third = GdbJitRegistration::new(vec![3]);
+        let addresses = [&first, &second, &third].map(|registration| {
+            assert!(registered_entries().contains(&registration.entry.symfile_addr));
+            registration.entry.symfile_addr
+        });
+        drop(second);
+        let entries = registered_entries();
+        assert!(entries.contains(&addresses[0]));
+        assert!(!entries.contains(&addresses[1]));
+        assert!(entries.contains(&addresses[2]));
+        drop(third);
+        drop(first);
+        let entries = registered_entries();
+        assert!(addresses.iter().all(|address| !entries.contains(address)));
+    }
+
+    #[test]
+    #[cfg(all(target_arch = "x86_64", not(target_os = "windows")))]
+    fn test_compiled_program_registration() {
+        use crate::{
+            assembler::assemble,
+            elf::Executable,
+            user_error::UserError,
+            vm::{Config, SyscallRegistry, TestInstructionMeter},
+        };
+        let config = Config {
+            enable_gdb_jit_interface: true,
+            ..Config::default()
+        };
+        let mut executable = assemble::<UserError, TestInstructionMeter>(
+            "
+            mov64 r0, 1
+            exit",
+            None,
+            config,
+            SyscallRegistry::default(),
+        )
+        .unwrap();
+        Executable::jit_compile(&mut executable).unwrap();
+        let text_section_address = executable
+            .get_compiled_program()
+            .unwrap()
+            .machine_code()
+            .as_ptr() as u64;
+
+        let is_registered = || {
+            registered_objects().into_iter().any(|object| {
+                Elf::parse(object).unwrap().section_headers[1].sh_addr == text_section_address
+            })
+        };
+        assert!(is_registered());
+        drop(executable);
+        assert!(!is_registered());
+    }
+}
@@ -0,0 +1,417 @@
+#![allow(clippy::integer_arithmetic)]
+//! GDB remote serial protocol stub on top of the debugger
+//!
+//! The register file is exposed as r0 to r10 followed by the pc, each as
+//! 64 bit little endian values. The pc is reported as the virtual address of
+//! the instruction (text section start + instruction index * 8), which is
+//! also the address breakpoints are set at.
+
+use crate::{
+    debugger::{Debugger, StopReason, WatchpointKind},
+    ebpf,
+    error::{EbpfError, UserDefinedError},
+    memory_region::AccessType,
+    vm::InstructionMeter,
+};
+use std::{
+    io::{Read, Write},
+    net::{Ipv4Addr, TcpListener},
+};
+
+/// Number of registers in the GDB register file (r0 to r10 and pc)
+const NUMBER_OF_REGISTERS: usize = 12;
+/// Index of the pc in the GDB register file
+const PC_REGISTER: usize = 11;
+/// SIGILL, reported when the program terminated on an invalid instruction
+const SIGILL: u8 = 4;
+/// SIGTRAP, reported on breakpoints, watchpoints and single steps
+const SIGTRAP: u8 = 5;
+/// SIGABRT, reported when the program terminated with any other error
+const SIGABRT: u8 = 6;
+/// SIGFPE, reported when the program terminated on a division error
+const SIGFPE: u8 = 8;
+/// SIGSEGV, reported when the program terminated on a memory or control flow violation
+const SIGSEGV: u8 = 11;
+/// SIGXCPU, reported when the program ran out of instructions
+const SIGXCPU: u8 = 24;
+
+/// GDB remote serial protocol stub
+pub struct GdbStub<'a, 'b, E: UserDefinedError, I: InstructionMeter> {
+    debugger: Debugger<'a, 'b, E, I>,
+    no_ack_mode: bool,
+    detached: bool,
+}
+
+impl<'a, 'b, E: UserDefinedError, I: InstructionMeter> GdbStub<'a, 'b, E, I> {
+    /// Creates a new stub which controls the given debugger
+    pub fn new(debugger: Debugger<'a, 'b, E, I>) -> Self {
+        Self {
+            debugger,
+            no_ack_mode: false,
+            detached: false,
+        }
+    }
+
+    /// Returns the underlying debugger
+    pub fn get_debugger(&mut self) -> &mut Debugger<'a, 'b, E, I> {
+        &mut self.debugger
+    }
+
+    /// Waits for one debugger to connect on the loopback interface and serves it
+    pub fn listen(&mut self, port: u16) -> std::io::Result<()> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        let (mut stream, _address) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        self.serve(&mut stream)
+    }
+
+    /// Serves packets from a connection until the debugger detaches or kills the program
+    pub fn serve<S: Read + Write>(&mut self, stream: &mut S) -> std::io::Result<()> {
+        while let Some(packet) = self.receive_packet(stream)? {
+            match self.handle_packet(&packet) {
+                Some(response) => self.send_packet(stream, &response)?,
+                None => break,
+            }
+            if self.detached {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles a single packet (without framing) and returns the response
+    ///
+    /// Returns `None` if the debugger killed the program.
+    pub fn handle_packet(&mut self, packet: &str) -> Option<String> {
+        let command_len = packet.chars().next().map_or(0, char::len_utf8);
+        let (command, arguments) = packet.split_at(command_len);
+        let response = match command {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => {
+                let mut response = String::new();
+                for reg in 0..NUMBER_OF_REGISTERS {
+                    response.push_str(&encode_hex(&self.get_register(reg).to_le_bytes()));
+                }
+                response
+            }
+            "G" => match decode_hex(arguments) {
+                Some(bytes) if bytes.len() == NUMBER_OF_REGISTERS * 8 => {
+                    let values = bytes.chunks(8).map(read_u64_le).collect::<Vec<_>>();
+                    // Nothing is written if the pc is invalid
+                    if self.set_pc(values[PC_REGISTER]) {
+                        for (reg, value) in values[0..PC_REGISTER].iter().enumerate() {
+                            self.debugger.set_register(reg, *value);
+                        }
+                        "OK".to_string()
+                    } else {
+                        error_response()
+                    }
+                }
+                _ => error_response(),
+            },
+            "p" => match usize::from_str_radix(arguments, 16) {
+                Ok(reg) if reg < NUMBER_OF_REGISTERS => {
+                    encode_hex(&self.get_register(reg).to_le_bytes())
+                }
+                _ => error_response(),
+            },
+            "P" => {
+                let parsed = arguments.split_once('=').and_then(|(reg, value)| {
+                    let reg = usize::from_str_radix(reg, 16).ok()?;
+                    let value = decode_hex(value)?;
+                    (reg < NUMBER_OF_REGISTERS && value.len() == 8)
+                        .then(|| (reg, read_u64_le(&value)))
+                });
+                match parsed {
+                    Some((reg, value)) if self.set_register(reg, value) => "OK".to_string(),
+                    _ => error_response(),
+                }
+            }
+            "m" => match parse_address_and_length(arguments) {
+                Some((vm_addr, len)) => match self.debugger.read_memory(vm_addr, len) {
+                    Ok(data) => encode_hex(&data),
+                    Err(_) => error_response(),
+                },
+                None => error_response(),
+            },
+            "M" => {
+                let parsed = arguments.split_once(':').and_then(|(range, data)| {
+                    let (vm_addr, len) = parse_address_and_length(range)?;
+                    let data = decode_hex(data)?;
+                    if data.len() as u64 == len {
+                        Some((vm_addr, data))
+                    } else {
+                        None
+                    }
+                });
+                match parsed {
+                    Some((vm_addr, data)) => match self.debugger.write_memory(vm_addr, &data) {
+                        Ok(()) => "OK".to_string(),
+                        Err(_) => error_response(),
+                    },
+                    None => error_response(),
+                }
+            }
+            "c" => {
+                if !self.set_pc_from_argument(arguments) {
+                    return Some(error_response());
+                }
+                let result = self.debugger.resume();
+                self.stop_reply(result)
+            }
+            "s" => {
+                if !self.set_pc_from_argument(arguments) {
+                    return Some(error_response());
+                }
+                let result = self.debugger.step();
+                self.stop_reply(result)
+            }
+            "Z" | "z" => self
+                .handle_breakpoint_packet(command == "Z", arguments)
+                .unwrap_or_else(error_response),
+            "q" => match arguments {
+                _ if arguments.starts_with("Supported") => {
+                    "PacketSize=4000;QStartNoAckMode+;qXfer:features:read+".to_string()
+                }
+                _ if arguments.starts_with("Xfer:features:read:") => {
+                    read_target_description(&arguments["Xfer:features:read:".len()..])
+                        .unwrap_or_else(error_response)
+                }
+                "Attached" => "1".to_string(),
+                "C" => "QC1".to_string(),
+                "fThreadInfo" => "m1".to_string(),
+                "sThreadInfo" => "l".to_string(),
+                _ => String::new(),
+            },
+            "Q" if arguments == "StartNoAckMode" => {
+                self.no_ack_mode = true;
+                "OK".to_string()
+            }
+            "H" | "T" => "OK".to_string(),
+            "D" => {
+                self.detached = true;
+                "OK".to_string()
+            }
+            "k" => return None,
+            _ => String::new(),
+        };
+        Some(response)
+    }
+
+    fn get_register(&self, reg: usize) -> u64 {
+        if reg == PC_REGISTER {
+            self.debugger.get_program_vm_addr() + (self.debugger.get_pc() * ebpf::INSN_SIZE) as u64
+        } else {
+            self.debugger.get_registers()[reg]
+        }
+    }
+
+    fn set_register(&mut self, reg: usize, value: u64) -> bool {
+        if reg == PC_REGISTER {
+            self.set_pc(value)
+        } else {
+            self.debugger.set_register(reg, value)
+        }
+    }
+
+    fn set_pc(&mut self, vm_addr: u64) -> bool {
+        match self.address_to_pc(vm_addr) {
+            Some(pc) => self.debugger.set_pc(pc),
+            None => false,
+        }
+    }
+
+    fn set_pc_from_argument(&mut self, argument: &str) -> bool {
+        argument.is_empty()
+            || u64::from_str_radix(argument, 16)
+                .map(|vm_addr| self.set_pc(vm_addr))
+                .unwrap_or(false)
+    }
+
+    fn address_to_pc(&self, vm_addr: u64) -> Option<usize> {
+        let offset = vm_addr.checked_sub(self.debugger.get_program_vm_addr())?;
+        (offset % ebpf::INSN_SIZE as u64 == 0).then(|| (offset / ebpf::INSN_SIZE as u64) as usize)
+    }
+
+    fn handle_breakpoint_packet(&mut self, insert: bool, arguments: &str) -> Option<String> {
+        let mut fields = arguments.split(',');
+        let kind = fields.next()?;
+        let vm_addr = u64::from_str_radix(fields.next()?, 16).ok()?;
+        let len = u64::from_str_radix(fields.next()?, 16).ok()?;
+        let watchpoint_kind = match kind {
+            "0" => {
+                let pc = self.address_to_pc(vm_addr)?;
+                if insert {
+                    self.debugger.set_breakpoint(pc);
+                } else {
+                    self.debugger.remove_breakpoint(pc);
+                }
+                return Some("OK".to_string());
+            }
+            "2" => WatchpointKind::Write,
+            "3" => WatchpointKind::Read,
+            "4" => WatchpointKind::Access,
+            _ => return Some(String::new()),
+        };
+        if insert {
+            self.debugger.set_watchpoint(vm_addr, len, watchpoint_kind);
+        } else {
+            self.debugger.remove_watchpoint(vm_addr);
+        }
+        Some("OK".to_string())
+    }
+
+    fn stop_reply(&mut self, result: Result<StopReason, EbpfError<E>>) -> String {
+        match result {
+            Ok(StopReason::Exited(result)) => format!("W{:02x}", result as u8),
+            Ok(StopReason::Watchpoint(_pc, access_type, vm_addr, _len)) => {
+                let kind = match access_type {
+                    AccessType::Load => "rwatch",
+                    AccessType::Store => "watch",
+                };
+                format!("T{:02x}{}:{:x};", SIGTRAP, kind, vm_addr)
+            }
+            Ok(_) => format!("S{:02x}", SIGTRAP),
+            Err(error) => format!("X{:02x}", error_signal(&error)),
+        }
+    }
+
+    fn receive_packet<S: Read + Write>(
+        &mut self,
+        stream: &mut S,
+    ) -> std::io::Result<Option<String>> {
+        let mut byte = [0u8; 1];
+        loop {
+            // Skip acknowledgements and interrupt requests until the start of a packet
+            loop {
+                if stream.read(&mut byte)? == 0 {
+                    return Ok(None);
+                }
+                if byte[0] == b'$' {
+                    break;
+                }
+            }
+            let mut packet = Vec::new();
+            loop {
+                if stream.read(&mut byte)? == 0 {
+                    return Ok(None);
+                }
+                if byte[0] == b'#' {
+                    break;
+                }
+                packet.push(byte[0]);
+            }
+            let mut checksum = [0u8; 2];
+            stream.read_exact(&mut checksum)?;
+            let valid = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok())
+                == Some(compute_checksum(&packet));
+            if !self.no_ack_mode {
+                stream.write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if valid {
+                return Ok(Some(String::from_utf8_lossy(&packet).into_owned()));
+            }
+        }
+    }
+
+    fn send_packet<S: Write>(&self, stream: &mut S, response: &str) -> std::io::Result<()> {
+        write!(
+            stream,
+            "${}#{:02x}",
+            response,
+            compute_checksum(response.as_bytes())
+        )?;
+        stream.flush()
+    }
+}
+
+/// Signal which describes why the program terminated with an error
+fn error_signal<E: UserDefinedError>(error: &EbpfError<E>) -> u8 {
+    match error {
+        EbpfError::DivideByZero(_) | EbpfError::DivideOverflow(_) => SIGFPE,
+        EbpfError::InvalidInstruction(_) | EbpfError::UnsupportedInstruction(_) => SIGILL,
+        EbpfError::ExceededMaxInstructions(_, _) => SIGXCPU,
+        EbpfError::AccessViolation(..)
+        | EbpfError::StackAccessViolation(..)
+        | EbpfError::InvalidVirtualAddress(_)
+        | EbpfError::CallDepthExceeded(_, _)
+        | EbpfError::CallOutsideTextSegment(_, _)
+        | EbpfError::ExecutionOverrun(_)
+        | EbpfError::ExhausedTextSegment(_) => SIGSEGV,
+        _ => SIGABRT,
+    }
+}
+
+/// Target description announcing the register file, so that gdb does not guess the architecture
+fn target_description() -> String {
+    let mut description = String::from(
+        "<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+        <target version=\"1.0\"><architecture>bpf</architecture>\
+        <feature name=\"org.gnu.gdb.bpf.core\">",
+    );
+    for reg in 0..ebpf::FRAME_PTR_REG {
+        description.push_str(&format!(
+            "<reg name=\"r{}\" bitsize=\"64\" type=\"int64\"/>",
+            reg
+        ));
+    }
+    description.push_str(
+        "<reg name=\"r10\" bitsize=\"64\" type=\"data_ptr\"/>\
+        <reg name=\"pc\" bitsize=\"64\" type=\"code_ptr\"/></feature></target>",
+    );
+    description
+}
+
+/// Handles `qXfer:features:read:annex:offset,length`
+fn read_target_description(arguments: &str) -> Option<String> {
+    let (annex, range) = arguments.split_once(':')?;
+    if annex != "target.xml" {
+        return None;
+    }
+    let (offset, len) = parse_address_and_length(range)?;
+    let description = target_description();
+    let start = (offset as usize).min(description.len());
+    let end = start.saturating_add(len as usize).min(description.len());
+    let marker = if end < description.len() { 'm' } else { 'l' };
+    Some(format!("{}{}", marker, &description[start..end]))
+}
+
+fn compute_checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn error_response() -> String {
+    "E01".to_string()
+}
+
+fn encode_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(data: &str) -> Option<Vec<u8>> {
+    data.as_bytes()
+        .chunks(2)
+        .map(|digits| {
+            if digits.len() != 2 {
+                return None;
+            }
+            u8::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()
+        })
+        .collect()
+}
+
+fn read_u64_le(data: &[u8]) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(data);
+    u64::from_le_bytes(bytes)
+}
+
+fn parse_address_and_length(arguments: &str) -> Option<(u64, u64)> {
+    let (vm_addr, len) = arguments.split_once(',')?;
+    Some((
+        u64::from_str_radix(vm_addr, 16).ok()?,
+        u64::from_str_radix(len, 16).ok()?,
+    ))
+}
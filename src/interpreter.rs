@@ -56,14 +56,102 @@ macro_rules! translate_memory_access {
     };
 }
 
+/// Records the value in memory before and after a store, if the store journal is enabled
+macro_rules! journal_store {
+    ($self:ident, $config:ident, $vm_addr:ident, $host_ptr:ident, $value:expr, $T:ty) => {
+        if $config.enable_store_journal {
+            $self.vm.tracer.journal_store(
+                $vm_addr,
+                std::mem::size_of::<$T>() as u8,
+                unsafe { std::ptr::read_unaligned($host_ptr) } as u64,
+                $value as $T as u64,
+            );
+        }
+    };
+}
+
+/// Records a load or store, if memory tracing is enabled
+macro_rules! trace_memory_access {
+    ($self:ident, $config:ident, $pc:ident, $access_type:expr, $vm_addr:ident, $value:expr, $T:ty) => {
+        if $config.enable_memory_tracing {
+            $self.vm.tracer.trace_memory_access(
+                $pc as u64,
+                $access_type as u64,
+                $vm_addr,
+                std::mem::size_of::<$T>() as u64,
+                $value as $T as u64,
+            );
+        }
+    };
+}
+
+/// Performs an atomic read-modify-write of the memory addressed by `dst + off`
+macro_rules! atomic_memory_access {
+    ($self:ident, $config:ident, $pc:ident, $dst:expr, $src:expr, $off:expr, $imm:expr, $T:ty) => {
+        let operation = match ebpf::atomic_operation($imm) {
+            Some(operation) => operation,
+            None => {
+                return Err(EbpfError::UnsupportedInstruction(
+                    $pc + ebpf::ELF_INSN_DUMP_OFFSET,
+                ))
+            }
+        };
+        let vm_addr = ($self.reg[$dst] as i64).wrapping_add($off as i64) as u64;
+        let host_ptr = translate_memory_access!($self, vm_addr, AccessType::Store, $pc, $T);
+        let old_value = unsafe { *host_ptr };
+        let operand = $self.reg[$src] as $T;
+        let new_value = match operation {
+            ebpf::BPF_XCHG => operand,
+            ebpf::BPF_CMPXCHG if old_value == $self.reg[0] as $T => operand,
+            ebpf::BPF_CMPXCHG => old_value,
+            _ => match operation & !ebpf::BPF_FETCH {
+                ebpf::BPF_ADD => old_value.wrapping_add(operand),
+                ebpf::BPF_OR => old_value | operand,
+                ebpf::BPF_AND => old_value & operand,
+                _ => old_value ^ operand,
+            },
+        };
+        if operation & ebpf::BPF_FETCH != 0 {
+            trace_memory_access!(
+                $self,
+                $config,
+                $pc,
+                AccessType::Load,
+                vm_addr,
+                old_value,
+                $T
+            );
+        }
+        journal_store!($self, $config, vm_addr, host_ptr, new_value, $T);
+        unsafe { *host_ptr = new_value };
+        trace_memory_access!(
+            $self,
+            $config,
+            $pc,
+            AccessType::Store,
+            vm_addr,
+            new_value,
+            $T
+        );
+        if operation == ebpf::BPF_CMPXCHG {
+            $self.reg[0] = old_value as u64;
+        } else if operation & ebpf::BPF_FETCH != 0 {
+            $self.reg[$src] = old_value as u64;
+        }
+    };
+}
+
 /// State of an interpreter
 pub struct Interpreter<'a, 'b, E: UserDefinedError, I: InstructionMeter> {
-    vm: &'a mut EbpfVm<'b, E, I>,
+    pub(crate) vm: &'a mut EbpfVm<'b, E, I>,
 
-    instruction_meter: &'a mut I,
+    pub(crate) instruction_meter: &'a mut I,
     pub(crate) initial_insn_count: u64,
-    remaining_insn_count: u64,
+    pub(crate) remaining_insn_count: u64,
     pub(crate) due_insn_count: u64,
+    /// Set once the instruction meter check after an instruction fails,
+    /// as opposed to a syscall returning `ExceededMaxInstructions`
+    pub(crate) exceeded_instruction_meter: bool,
 
     /// General purpose self.registers
     pub reg: [u64; 11],
@@ -82,6 +170,7 @@ impl<'a, 'b, E: UserDefinedError, I: InstructionMeter> Interpreter<'a, 'b, E, I>
         } else {
             0
         };
+        vm.stack.reset();
         // R1 points to beginning of input memory, R10 to the stack of the first frame
         let reg: [u64; 11] = [
             0,
@@ -103,12 +192,17 @@ impl<'a, 'b, E: UserDefinedError, I: InstructionMeter> Interpreter<'a, 'b, E, I>
             initial_insn_count,
             remaining_insn_count: initial_insn_count,
             due_insn_count: 0,
+            exceeded_instruction_meter: false,
             reg,
             pc,
         })
     }
 
-    fn check_pc(&self, current_pc: usize, target_pc: usize) -> Result<usize, EbpfError<E>> {
+    pub(crate) fn check_pc(
+        &self,
+        current_pc: usize,
+        target_pc: usize,
+    ) -> Result<usize, EbpfError<E>> {
         let offset =
             target_pc
                 .checked_mul(ebpf::INSN_SIZE)
@@ -131,6 +225,7 @@ impl<'a, 'b, E: UserDefinedError, I: InstructionMeter> Interpreter<'a, 'b, E, I>
     #[rustfmt::skip]
     pub fn step(&mut self) -> Result<Option<u64>, EbpfError<E>> {
         let config = &self.vm.executable.get_config();
+        let sbf_version = self.vm.executable.get_sbf_version();
 
         let mut instruction_width = 1;
         self.due_insn_count += 1;
@@ -150,10 +245,12 @@ impl<'a, 'b, E: UserDefinedError, I: InstructionMeter> Interpreter<'a, 'b, E, I>
             state[0..11].copy_from_slice(&self.reg);
             state[11] = pc as u64;
             self.vm.tracer.trace(state);
+        } else if config.enable_store_journal {
+            self.vm.tracer.count_instruction();
         }
 
         match insn.opc {
-            _ if dst == STACK_PTR_REG && config.dynamic_stack_frames => {
+            _ if dst == STACK_PTR_REG && sbf_version.dynamic_stack_frames() => {
                 match insn.opc {
                     ebpf::SUB64_IMM => self.vm.stack.resize_stack(-insn.imm),
                     ebpf::ADD64_IMM => self.vm.stack.resize_stack(insn.imm),
@@ -171,41 +268,49 @@ impl<'a, 'b, E: UserDefinedError, I: InstructionMeter> Interpreter<'a, 'b, E, I>
                 let vm_addr = ebpf::MM_INPUT_START.wrapping_add(insn.imm as u32 as u64);
                 let host_ptr = translate_memory_access!(self, vm_addr, AccessType::Load, pc, u8);
                 self.reg[0] = unsafe { *host_ptr as u64 };
+                trace_memory_access!(self, config, pc, AccessType::Load, vm_addr, self.reg[0], u8);
             },
             ebpf::LD_ABS_H   =>  {
                 let vm_addr = ebpf::MM_INPUT_START.wrapping_add(insn.imm as u32 as u64);
                 let host_ptr = translate_memory_access!(self, vm_addr, AccessType::Load, pc, u16);
                 self.reg[0] = unsafe { *host_ptr as u64 };
+                trace_memory_access!(self, config, pc, AccessType::Load, vm_addr, self.reg[0], u16);
             },
             ebpf::LD_ABS_W   => {
                 let vm_addr = ebpf::MM_INPUT_START.wrapping_add(insn.imm as u32 as u64);
                 let host_ptr = translate_memory_access!(self, vm_addr, AccessType::Load, pc, u32);
                 self.reg[0] = unsafe { *host_ptr as u64 };
+                trace_memory_access!(self, config, pc, AccessType::Load, vm_addr, self.reg[0], u32);
             },
             ebpf::LD_ABS_DW  => {
                 let vm_addr = ebpf::MM_INPUT_START.wrapping_add(insn.imm as u32 as u64);
                 let host_ptr = translate_memory_access!(self, vm_addr, AccessType::Load, pc, u64);
                 self.reg[0] = unsafe { *host_ptr as u64 };
+                trace_memory_access!(self, config, pc, AccessType::Load, vm_addr, self.reg[0], u64);
             },
             ebpf::LD_IND_B   => {
                 let vm_addr = ebpf::MM_INPUT_START.wrapping_add(self.reg[src]).wrapping_add(insn.imm as u32 as u64);
                 let host_ptr = translate_memory_access!(self, vm_addr, AccessType::Load, pc, u8);
                 self.reg[0] = unsafe { *host_ptr as u64 };
+                trace_memory_access!(self, config, pc, AccessType::Load, vm_addr, self.reg[0], u8);
             },
             ebpf::LD_IND_H   => {
                 let vm_addr = ebpf::MM_INPUT_START.wrapping_add(self.reg[src]).wrapping_add(insn.imm as u32 as u64);
                 let host_ptr = translate_memory_access!(self, vm_addr, AccessType::Load, pc, u16);
                 self.reg[0] = unsafe { *host_ptr as u64 };
+                trace_memory_access!(self, config, pc, AccessType::Load, vm_addr, self.reg[0], u16);
             },
             ebpf::LD_IND_W   => {
                 let vm_addr = ebpf::MM_INPUT_START.wrapping_add(self.reg[src]).wrapping_add(insn.imm as u32 as u64);
                 let host_ptr = translate_memory_access!(self, vm_addr, AccessType::Load, pc, u32);
                 self.reg[0] = unsafe { *host_ptr as u64 };
+                trace_memory_access!(self, config, pc, AccessType::Load, vm_addr, self.reg[0], u32);
             },
             ebpf::LD_IND_DW  => {
                 let vm_addr = ebpf::MM_INPUT_START.wrapping_add(self.reg[src]).wrapping_add(insn.imm as u32 as u64);
                 let host_ptr = translate_memory_access!(self, vm_addr, AccessType::Load, pc, u64);
                 self.reg[0] = unsafe { *host_ptr as u64 };
+                trace_memory_access!(self, config, pc, AccessType::Load, vm_addr, self.reg[0], u64);
             },
 
             ebpf::LD_DW_IMM  => {
@@ -220,66 +325,106 @@ impl<'a, 'b, E: UserDefinedError, I: InstructionMeter> Interpreter<'a, 'b, E, I>
                 let vm_addr = (self.reg[src] as i64).wrapping_add(insn.off as i64) as u64;
                 let host_ptr = translate_memory_access!(self, vm_addr, AccessType::Load, pc, u8);
                 self.reg[dst] = unsafe { *host_ptr as u64 };
+                trace_memory_access!(self, config, pc, AccessType::Load, vm_addr, self.reg[dst], u8);
             },
             ebpf::LD_H_REG   => {
                 let vm_addr = (self.reg[src] as i64).wrapping_add(insn.off as i64) as u64;
                 let host_ptr = translate_memory_access!(self, vm_addr, AccessType::Load, pc, u16);
                 self.reg[dst] = unsafe { *host_ptr as u64 };
+                trace_memory_access!(self, config, pc, AccessType::Load, vm_addr, self.reg[dst], u16);
             },
             ebpf::LD_W_REG   => {
                 let vm_addr = (self.reg[src] as i64).wrapping_add(insn.off as i64) as u64;
                 let host_ptr = translate_memory_access!(self, vm_addr, AccessType::Load, pc, u32);
                 self.reg[dst] = unsafe { *host_ptr as u64 };
+                trace_memory_access!(self, config, pc, AccessType::Load, vm_addr, self.reg[dst], u32);
             },
             ebpf::LD_DW_REG  => {
                 let vm_addr = (self.reg[src] as i64).wrapping_add(insn.off as i64) as u64;
                 let host_ptr = translate_memory_access!(self, vm_addr, AccessType::Load, pc, u64);
                 self.reg[dst] = unsafe { *host_ptr as u64 };
+                trace_memory_access!(self, config, pc, AccessType::Load, vm_addr, self.reg[dst], u64);
+            },
+            ebpf::LD_SB_REG  => {
+                let vm_addr = (self.reg[src] as i64).wrapping_add(insn.off as i64) as u64;
+                let host_ptr = translate_memory_access!(self, vm_addr, AccessType::Load, pc, i8);
+                self.reg[dst] = unsafe { *host_ptr as i64 as u64 };
+                trace_memory_access!(self, config, pc, AccessType::Load, vm_addr, self.reg[dst], i8);
+            },
+            ebpf::LD_SH_REG  => {
+                let vm_addr = (self.reg[src] as i64).wrapping_add(insn.off as i64) as u64;
+                let host_ptr = translate_memory_access!(self, vm_addr, AccessType::Load, pc, i16);
+                self.reg[dst] = unsafe { *host_ptr as i64 as u64 };
+                trace_memory_access!(self, config, pc, AccessType::Load, vm_addr, self.reg[dst], i16);
+            },
+            ebpf::LD_SW_REG  => {
+                let vm_addr = (self.reg[src] as i64).wrapping_add(insn.off as i64) as u64;
+                let host_ptr = translate_memory_access!(self, vm_addr, AccessType::Load, pc, i32);
+                self.reg[dst] = unsafe { *host_ptr as i64 as u64 };
+                trace_memory_access!(self, config, pc, AccessType::Load, vm_addr, self.reg[dst], i32);
             },
 
             // BPF_ST class
             ebpf::ST_B_IMM   => {
                 let vm_addr = (self.reg[dst] as i64).wrapping_add( insn.off as i64) as u64;
                 let host_ptr = translate_memory_access!(self, vm_addr, AccessType::Store, pc, u8);
+                journal_store!(self, config, vm_addr, host_ptr, insn.imm, u8);
                 unsafe { *host_ptr = insn.imm as u8 };
+                trace_memory_access!(self, config, pc, AccessType::Store, vm_addr, insn.imm, u8);
             },
             ebpf::ST_H_IMM   => {
                 let vm_addr = (self.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
                 let host_ptr = translate_memory_access!(self, vm_addr, AccessType::Store, pc, u16);
+                journal_store!(self, config, vm_addr, host_ptr, insn.imm, u16);
                 unsafe { *host_ptr = insn.imm as u16 };
+                trace_memory_access!(self, config, pc, AccessType::Store, vm_addr, insn.imm, u16);
             },
             ebpf::ST_W_IMM   => {
                 let vm_addr = (self.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
                 let host_ptr = translate_memory_access!(self, vm_addr, AccessType::Store, pc, u32);
+                journal_store!(self, config, vm_addr, host_ptr, insn.imm, u32);
                 unsafe { *host_ptr = insn.imm as u32 };
+                trace_memory_access!(self, config, pc, AccessType::Store, vm_addr, insn.imm, u32);
             },
             ebpf::ST_DW_IMM  => {
                 let vm_addr = (self.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
                 let host_ptr = translate_memory_access!(self, vm_addr, AccessType::Store, pc, u64);
+                journal_store!(self, config, vm_addr, host_ptr, insn.imm, u64);
                 unsafe { *host_ptr = insn.imm as u64 };
+                trace_memory_access!(self, config, pc, AccessType::Store, vm_addr, insn.imm, u64);
             },
 
             // BPF_STX class
             ebpf::ST_B_REG   => {
                 let vm_addr = (self.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
                 let host_ptr = translate_memory_access!(self, vm_addr, AccessType::Store, pc, u8);
+                journal_store!(self, config, vm_addr, host_ptr, self.reg[src], u8);
                 unsafe { *host_ptr = self.reg[src] as u8 };
+                trace_memory_access!(self, config, pc, AccessType::Store, vm_addr, self.reg[src], u8);
             },
             ebpf::ST_H_REG   => {
                 let vm_addr = (self.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
                 let host_ptr = translate_memory_access!(self, vm_addr, AccessType::Store, pc, u16);
+                journal_store!(self, config, vm_addr, host_ptr, self.reg[src], u16);
                 unsafe { *host_ptr = self.reg[src] as u16 };
+                trace_memory_access!(self, config, pc, AccessType::Store, vm_addr, self.reg[src], u16);
             },
             ebpf::ST_W_REG   => {
                 let vm_addr = (self.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
                 let host_ptr = translate_memory_access!(self, vm_addr, AccessType::Store, pc, u32);
+                journal_store!(self, config, vm_addr, host_ptr, self.reg[src], u32);
                 unsafe { *host_ptr = self.reg[src] as u32 };
+                trace_memory_access!(self, config, pc, AccessType::Store, vm_addr, self.reg[src], u32);
             },
             ebpf::ST_DW_REG  => {
                 let vm_addr = (self.reg[dst] as i64).wrapping_add(insn.off as i64) as u64;
                 let host_ptr = translate_memory_access!(self, vm_addr, AccessType::Store, pc, u64);
+                journal_store!(self, config, vm_addr, host_ptr, self.reg[src], u64);
                 unsafe { *host_ptr = self.reg[src] as u64 };
+                trace_memory_access!(self, config, pc, AccessType::Store, vm_addr, self.reg[src], u64);
             },
+            ebpf::ST_W_ATOMIC  => { atomic_memory_access!(self, config, pc, dst, src, insn.off, insn.imm, u32); },
+            ebpf::ST_DW_ATOMIC => { atomic_memory_access!(self, config, pc, dst, src, insn.off, insn.imm, u64); },
 
             // BPF_ALU class
             ebpf::ADD32_IMM  => self.reg[dst] = (self.reg[dst] as i32).wrapping_add(insn.imm as i32)      as u64,
@@ -319,6 +464,21 @@ impl<'a, 'b, E: UserDefinedError, I: InstructionMeter> Interpreter<'a, 'b, E, I>
             ebpf::RSH32_IMM  => self.reg[dst] = (self.reg[dst] as u32).wrapping_shr(insn.imm as u32)      as u64,
             ebpf::RSH32_REG  => self.reg[dst] = (self.reg[dst] as u32).wrapping_shr(self.reg[src] as u32) as u64,
             ebpf::NEG32      => self.reg[dst] = (self.reg[dst] as i32).wrapping_neg()                     as u64 & (u32::MAX as u64),
+            ebpf::MOD32_IMM  if config.enable_ebpf_v4 && insn.off == 1 => {
+                if self.reg[dst] as i32 == i32::MIN && insn.imm == -1 {
+                    return Err(EbpfError::DivideOverflow(pc + ebpf::ELF_INSN_DUMP_OFFSET));
+                }
+                                self.reg[dst] = (self.reg[dst] as i32             % insn.imm as i32)      as u32 as u64;
+            },
+            ebpf::MOD32_REG  if config.enable_ebpf_v4 && insn.off == 1 => {
+                if self.reg[src] as i32 == 0 {
+                    return Err(EbpfError::DivideByZero(pc + ebpf::ELF_INSN_DUMP_OFFSET));
+                }
+                if self.reg[dst] as i32 == i32::MIN && self.reg[src] as i32 == -1 {
+                    return Err(EbpfError::DivideOverflow(pc + ebpf::ELF_INSN_DUMP_OFFSET));
+                }
+                                self.reg[dst] = (self.reg[dst] as i32             % self.reg[src] as i32) as u32 as u64;
+            },
             ebpf::MOD32_IMM  => self.reg[dst] = (self.reg[dst] as u32             % insn.imm as u32)      as u64,
             ebpf::MOD32_REG  => {
                 if self.reg[src] as u32 == 0 {
@@ -329,6 +489,8 @@ impl<'a, 'b, E: UserDefinedError, I: InstructionMeter> Interpreter<'a, 'b, E, I>
             ebpf::XOR32_IMM  => self.reg[dst] = (self.reg[dst] as u32             ^ insn.imm as u32)      as u64,
             ebpf::XOR32_REG  => self.reg[dst] = (self.reg[dst] as u32             ^ self.reg[src] as u32) as u64,
             ebpf::MOV32_IMM  => self.reg[dst] = insn.imm as u32 as u64,
+            ebpf::MOV32_REG  if config.enable_ebpf_v4 && insn.off == 8  => self.reg[dst] = (self.reg[src] as i8  as i32) as u32 as u64,
+            ebpf::MOV32_REG  if config.enable_ebpf_v4 && insn.off == 16 => self.reg[dst] = (self.reg[src] as i16 as i32) as u32 as u64,
             ebpf::MOV32_REG  => self.reg[dst] = (self.reg[src] as u32) as u64,
             ebpf::ARSH32_IMM => self.reg[dst] = (self.reg[dst] as i32).wrapping_shr(insn.imm as u32)      as u64 & (u32::MAX as u64),
             ebpf::ARSH32_REG => self.reg[dst] = (self.reg[dst] as i32).wrapping_shr(self.reg[src] as u32) as u64 & (u32::MAX as u64),
@@ -352,6 +514,16 @@ impl<'a, 'b, E: UserDefinedError, I: InstructionMeter> Interpreter<'a, 'b, E, I>
                     }
                 };
             },
+            ebpf::BSWAP      => {
+                self.reg[dst] = match insn.imm {
+                    16 => (self.reg[dst] as u16).swap_bytes() as u64,
+                    32 => (self.reg[dst] as u32).swap_bytes() as u64,
+                    64 =>  self.reg[dst].swap_bytes(),
+                    _  => {
+                        return Err(EbpfError::InvalidInstruction(pc + ebpf::ELF_INSN_DUMP_OFFSET));
+                    }
+                };
+            },
 
             // BPF_ALU64 class
             ebpf::ADD64_IMM  => self.reg[dst] =  self.reg[dst].wrapping_add(insn.imm as u64),
@@ -391,6 +563,21 @@ impl<'a, 'b, E: UserDefinedError, I: InstructionMeter> Interpreter<'a, 'b, E, I>
             ebpf::RSH64_IMM  => self.reg[dst] =  self.reg[dst].wrapping_shr(insn.imm as u32),
             ebpf::RSH64_REG  => self.reg[dst] =  self.reg[dst].wrapping_shr(self.reg[src] as u32),
             ebpf::NEG64      => self.reg[dst] = (self.reg[dst] as i64).wrapping_neg() as u64,
+            ebpf::MOD64_IMM  if config.enable_ebpf_v4 && insn.off == 1 => {
+                if self.reg[dst] as i64 == i64::MIN && insn.imm == -1 {
+                    return Err(EbpfError::DivideOverflow(pc + ebpf::ELF_INSN_DUMP_OFFSET));
+                }
+                                self.reg[dst] = (self.reg[dst] as i64 % insn.imm)                          as u64;
+            },
+            ebpf::MOD64_REG  if config.enable_ebpf_v4 && insn.off == 1 => {
+                if self.reg[src] == 0 {
+                    return Err(EbpfError::DivideByZero(pc + ebpf::ELF_INSN_DUMP_OFFSET));
+                }
+                if self.reg[dst] as i64 == i64::MIN && self.reg[src] as i64 == -1 {
+                    return Err(EbpfError::DivideOverflow(pc + ebpf::ELF_INSN_DUMP_OFFSET));
+                }
+                                self.reg[dst] = (self.reg[dst] as i64 % self.reg[src] as i64)             as u64;
+            },
             ebpf::MOD64_IMM  => self.reg[dst] %= insn.imm as u64,
             ebpf::MOD64_REG  => {
                 if self.reg[src] == 0 {
@@ -401,6 +588,9 @@ impl<'a, 'b, E: UserDefinedError, I: InstructionMeter> Interpreter<'a, 'b, E, I>
             ebpf::XOR64_IMM  => self.reg[dst] ^= insn.imm as u64,
             ebpf::XOR64_REG  => self.reg[dst] ^= self.reg[src],
             ebpf::MOV64_IMM  => self.reg[dst] =  insn.imm as u64,
+            ebpf::MOV64_REG  if config.enable_ebpf_v4 && insn.off == 8  => self.reg[dst] =  self.reg[src] as i8  as i64 as u64,
+            ebpf::MOV64_REG  if config.enable_ebpf_v4 && insn.off == 16 => self.reg[dst] =  self.reg[src] as i16 as i64 as u64,
+            ebpf::MOV64_REG  if config.enable_ebpf_v4 && insn.off == 32 => self.reg[dst] =  self.reg[src] as i32 as i64 as u64,
             ebpf::MOV64_REG  => self.reg[dst] =  self.reg[src],
             ebpf::ARSH64_IMM => self.reg[dst] = (self.reg[dst] as i64).wrapping_shr(insn.imm as u32)      as u64,
             ebpf::ARSH64_REG => self.reg[dst] = (self.reg[dst] as i64).wrapping_shr(self.reg[src] as u32) as u64,
@@ -444,7 +634,7 @@ impl<'a, 'b, E: UserDefinedError, I: InstructionMeter> Interpreter<'a, 'b, E, I>
             // changed after the program has been verified.
             ebpf::CALL_IMM   => {
                 let mut resolved = false;
-                let (syscalls, calls) = if config.static_syscalls {
+                let (syscalls, calls) = if sbf_version.static_syscalls() {
                     (insn.src == 0, insn.src != 0)
                 } else {
                     (true, true)
@@ -511,10 +701,37 @@ impl<'a, 'b, E: UserDefinedError, I: InstructionMeter> Interpreter<'a, 'b, E, I>
                     }
                 }
             }
+
+            // BPF_JMP32 class
+            ebpf::JEQ32_IMM  => if (self.reg[dst] as u32) == insn.imm      as u32     { self.pc = (self.pc as isize + insn.off as isize) as usize; },
+            ebpf::JEQ32_REG  => if (self.reg[dst] as u32) == self.reg[src] as u32     { self.pc = (self.pc as isize + insn.off as isize) as usize; },
+            ebpf::JGT32_IMM  => if (self.reg[dst] as u32) >  insn.imm      as u32     { self.pc = (self.pc as isize + insn.off as isize) as usize; },
+            ebpf::JGT32_REG  => if (self.reg[dst] as u32) >  self.reg[src] as u32     { self.pc = (self.pc as isize + insn.off as isize) as usize; },
+            ebpf::JGE32_IMM  => if (self.reg[dst] as u32) >= insn.imm      as u32     { self.pc = (self.pc as isize + insn.off as isize) as usize; },
+            ebpf::JGE32_REG  => if (self.reg[dst] as u32) >= self.reg[src] as u32     { self.pc = (self.pc as isize + insn.off as isize) as usize; },
+            ebpf::JLT32_IMM  => if (self.reg[dst] as u32) <  insn.imm      as u32     { self.pc = (self.pc as isize + insn.off as isize) as usize; },
+            ebpf::JLT32_REG  => if (self.reg[dst] as u32) <  self.reg[src] as u32     { self.pc = (self.pc as isize + insn.off as isize) as usize; },
+            ebpf::JLE32_IMM  => if (self.reg[dst] as u32) <= insn.imm      as u32     { self.pc = (self.pc as isize + insn.off as isize) as usize; },
+            ebpf::JLE32_REG  => if (self.reg[dst] as u32) <= self.reg[src] as u32     { self.pc = (self.pc as isize + insn.off as isize) as usize; },
+            ebpf::JSET32_IMM => if (self.reg[dst] as u32) &  insn.imm      as u32 != 0 { self.pc = (self.pc as isize + insn.off as isize) as usize; },
+            ebpf::JSET32_REG => if (self.reg[dst] as u32) &  self.reg[src] as u32 != 0 { self.pc = (self.pc as isize + insn.off as isize) as usize; },
+            ebpf::JNE32_IMM  => if (self.reg[dst] as u32) != insn.imm      as u32     { self.pc = (self.pc as isize + insn.off as isize) as usize; },
+            ebpf::JNE32_REG  => if (self.reg[dst] as u32) != self.reg[src] as u32     { self.pc = (self.pc as isize + insn.off as isize) as usize; },
+            ebpf::JSGT32_IMM => if (self.reg[dst] as i32) >  insn.imm      as i32     { self.pc = (self.pc as isize + insn.off as isize) as usize; },
+            ebpf::JSGT32_REG => if (self.reg[dst] as i32) >  self.reg[src] as i32     { self.pc = (self.pc as isize + insn.off as isize) as usize; },
+            ebpf::JSGE32_IMM => if (self.reg[dst] as i32) >= insn.imm      as i32     { self.pc = (self.pc as isize + insn.off as isize) as usize; },
+            ebpf::JSGE32_REG => if (self.reg[dst] as i32) >= self.reg[src] as i32     { self.pc = (self.pc as isize + insn.off as isize) as usize; },
+            ebpf::JSLT32_IMM => if (self.reg[dst] as i32) <  insn.imm      as i32     { self.pc = (self.pc as isize + insn.off as isize) as usize; },
+            ebpf::JSLT32_REG => if (self.reg[dst] as i32) <  self.reg[src] as i32     { self.pc = (self.pc as isize + insn.off as isize) as usize; },
+            ebpf::JSLE32_IMM => if (self.reg[dst] as i32) <= insn.imm      as i32     { self.pc = (self.pc as isize + insn.off as isize) as usize; },
+            ebpf::JSLE32_REG => if (self.reg[dst] as i32) <= self.reg[src] as i32     { self.pc = (self.pc as isize + insn.off as isize) as usize; },
+            ebpf::GOTOL      =>                                                       { self.pc = (self.pc as isize + insn.imm as isize) as usize; },
+
             _ => return Err(EbpfError::UnsupportedInstruction(pc + ebpf::ELF_INSN_DUMP_OFFSET)),
         }
 
         if config.enable_instruction_meter && self.due_insn_count >= self.remaining_insn_count {
+            self.exceeded_instruction_meter = true;
             // Use `pc + instruction_width` instead of `self.pc` here because jumps and calls don't continue at the end of this instruction
             return Err(EbpfError::ExceededMaxInstructions(pc + instruction_width + ebpf::ELF_INSN_DUMP_OFFSET, self.initial_insn_count));
         }
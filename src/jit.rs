@@ -17,10 +17,10 @@
 extern crate libc;
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeSet, HashMap},
     fmt::{Debug, Error as FormatterError, Formatter},
     mem,
-    ops::{Index, IndexMut},
+    ops::{Index, IndexMut, Range},
     pin::Pin, ptr,
 };
 use rand::{rngs::SmallRng, Rng, SeedableRng};
@@ -28,15 +28,24 @@ use rand::{rngs::SmallRng, Rng, SeedableRng};
 use crate::{
     elf::Executable,
     vm::{Config, ProgramResult, InstructionMeter, Tracer, SYSCALL_CONTEXT_OBJECTS_OFFSET},
-    ebpf::{self, INSN_SIZE, FIRST_SCRATCH_REG, SCRATCH_REGS, FRAME_PTR_REG, MM_STACK_START, STACK_PTR_REG},
+    call_frames::CallFrames,
+    sampler::Sampler,
+    ebpf::{self, SbfVersion, INSN_SIZE, FIRST_SCRATCH_REG, SCRATCH_REGS, FRAME_PTR_REG, MM_STACK_START, STACK_PTR_REG},
     error::{UserDefinedError, EbpfError},
     memory_region::{AccessType, MemoryMapping, MemoryRegion},
+    static_analysis::Analysis,
+    jit_symbols,
     user_error::UserError,
     x86::*,
 };
+#[cfg(feature = "gdb-jit-interface")]
+use crate::gdb_jit_interface::{self, GdbJitRegistration};
 
 const MAX_EMPTY_PROGRAM_MACHINE_CODE_LENGTH: usize = 4096;
 const MAX_MACHINE_CODE_LENGTH_PER_INSTRUCTION: usize = 110;
+const MAX_MEMORY_TRACING_MACHINE_CODE_LENGTH_PER_INSTRUCTION: usize = 160;
+const MAX_SAMPLING_MACHINE_CODE_LENGTH_PER_INSTRUCTION: usize = 100;
+const MAX_OPTIMIZED_MACHINE_CODE_LENGTH_PER_INSTRUCTION: usize = 40;
 
 /// Argument for executing a eBPF JIT-compiled program
 pub struct JitProgramArgument<'a> {
@@ -133,6 +142,17 @@ impl JitProgramSections {
         let code_size = round_to_page_size(self.text_section.len(), self.page_size);
         pc_loc_table_size + code_size
     }
+
+    /// Patches the absolute addresses into the text section, which must still be writable
+    fn relocate<E: UserDefinedError, I: InstructionMeter>(&mut self, relocations: &[(usize, Relocation)], executable: &Executable<E, I>) -> Result<(), EbpfError<E>> {
+        for (location, relocation) in relocations {
+            let address = relocation.resolve(executable, self.pc_section.as_ptr())?;
+            unsafe {
+                ptr::write_unaligned(self.text_section.as_mut_ptr().add(*location) as *mut i64, address);
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Drop for JitProgramSections {
@@ -152,8 +172,13 @@ impl Drop for JitProgramSections {
 pub struct JitProgram<E: UserDefinedError, I: InstructionMeter> {
     /// Holds and manages the protected memory
     sections: JitProgramSections,
+    /// Locations of the absolute addresses in the text section
+    relocations: Vec<(usize, Relocation)>,
     /// Call this with JitProgramArgument to execute the compiled code
     pub main: unsafe fn(&ProgramResult<E>, u64, &JitProgramArgument, &mut I) -> i64,
+    /// Keeps the machine code registered with the GDB JIT interface
+    #[cfg(feature = "gdb-jit-interface")]
+    gdb_jit_registration: Option<GdbJitRegistration>,
 }
 
 impl<E: UserDefinedError, I: InstructionMeter> Debug for JitProgram<E, I> {
@@ -170,14 +195,99 @@ impl<E: UserDefinedError, I: InstructionMeter> PartialEq for JitProgram<E, I> {
 
 impl<E: UserDefinedError, I: InstructionMeter> JitProgram<E, I> {
     pub fn new(executable: &Pin<Box<Executable<E, I>>>) -> Result<Self, EbpfError<E>> {
+        Self::new_unpinned(executable)
+    }
+
+    /// Same as `new()` for an executable which is not behind a `Pin`
+    ///
+    /// The machine code embeds the address of the executable, so the caller must guarantee that
+    /// the executable does not move for as long as the returned program exists. This holds for
+    /// the background compilation of the tiering, as the executable owns its tiering state.
+    pub(crate) fn new_unpinned(executable: &Executable<E, I>) -> Result<Self, EbpfError<E>> {
         let program = executable.get_text_bytes().1;
-        let mut jit = JitCompiler::new::<E>(program, executable.get_config())?;
+        let mut jit = JitCompiler::new::<E>(program, executable.get_config(), executable.get_sbf_version())?;
         jit.compile::<E, I>(executable)?;
         let main = unsafe { mem::transmute(jit.result.text_section.as_ptr()) };
-        Ok(Self {
+        let mut compiled_program = Self {
             sections: jit.result,
+            relocations: jit.relocations,
             main,
-        })
+            #[cfg(feature = "gdb-jit-interface")]
+            gdb_jit_registration: None,
+        };
+        compiled_program.announce_symbols(executable);
+        Ok(compiled_program)
+    }
+
+    /// Maps previously emitted machine code and relocates it for the given executable
+    ///
+    /// `pc_section` holds offsets into `text_section` and the absolute addresses in
+    /// `text_section` are expected to be zeroed out.
+    pub(crate) fn from_parts(executable: &Executable<E, I>, pc_section: &[u64], text_section: &[u8], relocations: Vec<(usize, Relocation)>) -> Result<Self, EbpfError<E>> {
+        if pc_section.is_empty() || pc_section.iter().any(|offset| *offset as usize >= text_section.len()) {
+            return Err(EbpfError::InvalidJitCacheEntry("pc section out of bounds".to_string()));
+        }
+        if relocations.iter().any(|(location, _)| location.saturating_add(mem::size_of::<i64>()) > text_section.len()) {
+            return Err(EbpfError::InvalidJitCacheEntry("relocation out of bounds".to_string()));
+        }
+        let mut sections = JitProgramSections::new(pc_section.len(), text_section.len())?;
+        let text_section_base = sections.text_section.as_ptr() as u64;
+        for (host_pc, offset) in sections.pc_section.iter_mut().zip(pc_section.iter()) {
+            *host_pc = text_section_base + *offset;
+        }
+        sections.text_section[0..text_section.len()].copy_from_slice(text_section);
+        sections.relocate(&relocations, executable)?;
+        sections.seal(text_section.len())?;
+        let main = unsafe { mem::transmute(sections.text_section.as_ptr()) };
+        let mut compiled_program = Self {
+            sections,
+            relocations,
+            main,
+            #[cfg(feature = "gdb-jit-interface")]
+            gdb_jit_registration: None,
+        };
+        compiled_program.announce_symbols(executable);
+        Ok(compiled_program)
+    }
+
+    /// Makes the functions of the program visible to native profilers and debuggers, as configured
+    fn announce_symbols(&mut self, executable: &Executable<E, I>) {
+        let config = executable.get_config();
+        let enable_gdb_jit_interface = cfg!(feature = "gdb-jit-interface") && config.enable_gdb_jit_interface;
+        if !config.enable_perf_map && !enable_gdb_jit_interface {
+            return;
+        }
+        let text_section_address = self.sections.text_section.as_ptr() as u64;
+        let symbols = jit_symbols::function_symbols(executable, self);
+        if config.enable_perf_map {
+            // The perf map only serves profiling, failing to write it must not fail the compilation
+            let _ = jit_symbols::write_perf_map(text_section_address, &symbols);
+        }
+        #[cfg(feature = "gdb-jit-interface")]
+        if enable_gdb_jit_interface {
+            let object = gdb_jit_interface::build_debug_object(text_section_address, self.machine_code_length(), &symbols);
+            self.gdb_jit_registration = Some(GdbJitRegistration::new(object));
+        }
+    }
+
+    /// Returns the pc section as offsets into the text section
+    pub(crate) fn pc_section_offsets(&self) -> Vec<u64> {
+        let text_section_base = self.sections.text_section.as_ptr() as u64;
+        self.sections.pc_section.iter().map(|host_pc| *host_pc - text_section_base).collect()
+    }
+
+    /// Returns the machine code with all absolute addresses zeroed out
+    pub(crate) fn position_independent_text_section(&self) -> Vec<u8> {
+        let mut text_section = self.sections.text_section.to_vec();
+        for (location, _relocation) in self.relocations.iter() {
+            text_section[*location..*location + mem::size_of::<i64>()].fill(0);
+        }
+        text_section
+    }
+
+    /// Returns the locations of the absolute addresses in the machine code
+    pub(crate) fn relocations(&self) -> &[(usize, Relocation)] {
+        &self.relocations
     }
 
     pub fn mem_size(&self) -> usize {
@@ -188,10 +298,126 @@ impl<E: UserDefinedError, I: InstructionMeter> JitProgram<E, I> {
     pub fn machine_code_length(&self) -> usize {
         self.sections.text_section.len()
     }
+
+    /// Returns the emitted machine code
+    pub fn machine_code(&self) -> &[u8] {
+        self.sections.text_section
+    }
+
+    /// Returns the range of machine code emitted for each eBPF instruction, indexed by pc
+    ///
+    /// The second slot of `lddw` has an empty range.
+    pub fn machine_code_ranges(&self) -> Vec<Range<usize>> {
+        let offsets = self.pc_section_offsets();
+        let (end_of_program, offsets) = offsets.split_last().unwrap();
+        // Slots which do not start an instruction point to the handler of unsupported instructions,
+        // which lies in front of the code of the program
+        let mut last_start = 0;
+        let starts = offsets.iter().map(|offset| {
+            if *offset >= last_start && *offset <= *end_of_program {
+                last_start = *offset;
+                Some(*offset as usize)
+            } else {
+                None
+            }
+        }).collect::<Vec<_>>();
+        let mut ranges = vec![0..0; starts.len()];
+        let mut end = *end_of_program as usize;
+        for (range, start) in ranges.iter_mut().zip(starts.iter()).rev() {
+            let start = start.unwrap_or(end);
+            *range = start..end;
+            end = start;
+        }
+        ranges
+    }
+}
+
+/// Absolute address embedded in the machine code, which is patched in after emitting and loading
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Relocation {
+    TracerTrace,
+    TracerTraceMemoryAccess,
+    SamplerSample,
+    CaptureCallFrames,
+    InstructionMeterConsume,
+    InstructionMeterGetRemaining,
+    GenerateAccessViolation,
+    ReportUnresolvedSymbol,
+    StopwatchResult,
+    /// The executable the code was compiled or loaded for
+    Executable,
+    /// Address of the pc section plus an offset
+    PcSection(i64),
+    /// Function of the syscall with the given hash
+    Syscall(u32),
+}
+
+impl Relocation {
+    /// Returns the absolute address in this process
+    fn resolve<E: UserDefinedError, I: InstructionMeter>(&self, executable: &Executable<E, I>, pc_section: *const u64) -> Result<i64, EbpfError<E>> {
+        Ok(match self {
+            Relocation::TracerTrace => Tracer::trace as *const u8 as i64,
+            Relocation::TracerTraceMemoryAccess => Tracer::trace_memory_access as *const u8 as i64,
+            Relocation::SamplerSample => Sampler::sample as *const u8 as i64,
+            Relocation::CaptureCallFrames => capture_call_frames as *const u8 as i64,
+            Relocation::InstructionMeterConsume => I::consume as *const u8 as i64,
+            Relocation::InstructionMeterGetRemaining => I::get_remaining as *const u8 as i64,
+            Relocation::GenerateAccessViolation => MemoryMapping::generate_access_violation::<UserError> as *const u8 as i64,
+            Relocation::ReportUnresolvedSymbol => Executable::<E, I>::report_unresolved_symbol as *const u8 as i64,
+            Relocation::StopwatchResult => stopwatch_result as *const u8 as i64,
+            Relocation::Executable => executable as *const _ as i64,
+            Relocation::PcSection(offset) => pc_section as i64 + offset,
+            Relocation::Syscall(hash) => executable.get_syscall_registry().lookup_syscall(*hash)
+                .ok_or_else(|| EbpfError::InvalidJitCacheEntry(format!("syscall {:#x} is not registered", hash)))?
+                .function as i64,
+        })
+    }
+
+    /// Serializes the relocation into a tag and a payload
+    pub(crate) fn encode(&self) -> (u8, u64) {
+        match self {
+            Relocation::TracerTrace => (0, 0),
+            Relocation::TracerTraceMemoryAccess => (1, 0),
+            Relocation::SamplerSample => (2, 0),
+            Relocation::CaptureCallFrames => (3, 0),
+            Relocation::InstructionMeterConsume => (4, 0),
+            Relocation::InstructionMeterGetRemaining => (5, 0),
+            Relocation::GenerateAccessViolation => (6, 0),
+            Relocation::ReportUnresolvedSymbol => (7, 0),
+            Relocation::StopwatchResult => (8, 0),
+            Relocation::Executable => (9, 0),
+            Relocation::PcSection(offset) => (10, *offset as u64),
+            Relocation::Syscall(hash) => (11, *hash as u64),
+        }
+    }
+
+    /// Deserializes a relocation from a tag and a payload
+    pub(crate) fn decode(tag: u8, payload: u64) -> Option<Self> {
+        Some(match tag {
+            0 => Relocation::TracerTrace,
+            1 => Relocation::TracerTraceMemoryAccess,
+            2 => Relocation::SamplerSample,
+            3 => Relocation::CaptureCallFrames,
+            4 => Relocation::InstructionMeterConsume,
+            5 => Relocation::InstructionMeterGetRemaining,
+            6 => Relocation::GenerateAccessViolation,
+            7 => Relocation::ReportUnresolvedSymbol,
+            8 => Relocation::StopwatchResult,
+            9 => Relocation::Executable,
+            10 => Relocation::PcSection(payload as i64),
+            11 if payload <= u32::MAX as u64 => Relocation::Syscall(payload as u32),
+            _ => return None,
+        })
+    }
 }
 
 // Special values for target_pc in struct Jump
 const TARGET_PC_LOCAL_ANCHOR: usize = std::usize::MAX - 100;
+const TARGET_PC_TRANSLATE_MEMORY_ADDRESS_UNCHECKED: usize = std::usize::MAX - 40;
+const TARGET_PC_TRANSLATE_MEMORY_SPAN: usize = std::usize::MAX - 39;
+const TARGET_PC_TRANSLATE_MEMORY_SPAN_UNMAPPED: usize = std::usize::MAX - 37;
+const TARGET_PC_CAPTURE_CALL_FRAMES: usize = std::usize::MAX - 35;
+const TARGET_PC_SAMPLE: usize = std::usize::MAX - 34;
 const TARGET_PC_DIV_OVERFLOW: usize = std::usize::MAX - 33;
 const TARGET_PC_TRACE: usize = std::usize::MAX - 32;
 const TARGET_PC_SYSCALL: usize = std::usize::MAX - 31;
@@ -393,7 +619,7 @@ enum EnvironmentStackSlot {
     CallDepth = 6,
     /// BPF frame pointer (REGISTER_MAP[FRAME_PTR_REG]).
     BpfFramePtr = 7,
-    /// The BPF stack pointer (r11). Only used when config.dynamic_stack_frames=true.
+    /// The BPF stack pointer (r11). Only used when SbfVersion::dynamic_stack_frames() is true.
     ///
     /// The stack pointer isn't exposed as an actual register. Only sub and add
     /// instructions (typically generated by the LLVM backend) are allowed to
@@ -418,6 +644,11 @@ fn slot_on_environment_stack(jit: &JitCompiler, slot: EnvironmentStackSlot) -> i
     -8 * (slot as i32 + jit.environment_stack_key)
 }
 
+/// Prints the stop watch value
+fn stopwatch_result(numerator: u64, denominator: u64) {
+    println!("Stop watch: {} / {} = {}", numerator, denominator, if denominator == 0 { 0.0 } else { numerator as f64 / denominator as f64 });
+}
+
 #[allow(dead_code)]
 #[inline]
 fn emit_stopwatch<E: UserDefinedError>(jit: &mut JitCompiler, begin: bool) -> Result<(), EbpfError<E>> {
@@ -496,7 +727,25 @@ fn emit_validate_instruction_count<E: UserDefinedError>(jit: &mut JitCompiler, e
     } else {
         X86Instruction::cmp(OperandSize::S64, R11, ARGUMENT_REGISTERS[0], None).emit(jit)?;
     }
-    emit_jcc(jit, if exclusive { 0x82 } else { 0x86 }, TARGET_PC_CALL_EXCEEDED_MAX_INSTRUCTIONS)
+    emit_jcc(jit, if exclusive { 0x82 } else { 0x86 }, TARGET_PC_CALL_EXCEEDED_MAX_INSTRUCTIONS)?;
+    if let Some(pc) = pc {
+        if jit.config.enable_instruction_meter && jit.config.sampling_interval != 0 {
+            emit_sample(jit, pc)?;
+        }
+    }
+    Ok(())
+}
+
+#[inline]
+fn emit_sample<E: UserDefinedError>(jit: &mut JitCompiler, pc: usize) -> Result<(), EbpfError<E>> {
+    let sample_anchor = TARGET_PC_LOCAL_ANCHOR - jit.offset_in_text_section;
+    emit_alu(jit, OperandSize::S64, 0x81, 5, ARGUMENT_REGISTERS[0], pc as i64 + 1, None)?; // instruction_meter -= pc + 1;
+    X86Instruction::cmp(OperandSize::S64, ARGUMENT_REGISTERS[0], R10, Some(X86IndirectAccess::Offset(mem::size_of::<MemoryMapping>() as i32 + 16 + jit.program_argument_key))).emit(jit)?;
+    emit_jcc(jit, 0x86, sample_anchor)?; // Skip while next_sample_threshold <= instruction_meter
+    X86Instruction::store_immediate(OperandSize::S64, R10, X86IndirectAccess::Offset(mem::size_of::<MemoryMapping>() as i32 + 24 + jit.program_argument_key), pc as i64).emit(jit)?; // sampled_pc = pc;
+    emit_call(jit, TARGET_PC_SAMPLE)?;
+    set_anchor(jit, sample_anchor);
+    emit_alu(jit, OperandSize::S64, 0x81, 0, ARGUMENT_REGISTERS[0], pc as i64 + 1, None) // instruction_meter += pc + 1;
 }
 
 #[inline]
@@ -548,12 +797,12 @@ fn emit_profile_instruction_count_finalize<E: UserDefinedError>(jit: &mut JitCom
 }
 
 #[inline]
-fn emit_conditional_branch_reg<E: UserDefinedError>(jit: &mut JitCompiler, op: u8, bitwise: bool, first_operand: u8, second_operand: u8, target_pc: usize) -> Result<(), EbpfError<E>> {
+fn emit_conditional_branch_reg<E: UserDefinedError>(jit: &mut JitCompiler, size: OperandSize, op: u8, bitwise: bool, first_operand: u8, second_operand: u8, target_pc: usize) -> Result<(), EbpfError<E>> {
     emit_validate_and_profile_instruction_count(jit, false, Some(target_pc))?;
     if bitwise { // Logical
-        X86Instruction::test(OperandSize::S64, first_operand, second_operand, None).emit(jit)?;
+        X86Instruction::test(size, first_operand, second_operand, None).emit(jit)?;
     } else { // Arithmetic
-        X86Instruction::cmp(OperandSize::S64, first_operand, second_operand, None).emit(jit)?;
+        X86Instruction::cmp(size, first_operand, second_operand, None).emit(jit)?;
     }
     X86Instruction::load_immediate(OperandSize::S64, R11, target_pc as i64).emit(jit)?;
     emit_jcc(jit, op, target_pc)?;
@@ -561,31 +810,33 @@ fn emit_conditional_branch_reg<E: UserDefinedError>(jit: &mut JitCompiler, op: u
 }
 
 #[inline]
-fn emit_conditional_branch_imm<E: UserDefinedError>(jit: &mut JitCompiler, op: u8, bitwise: bool, immediate: i64, second_operand: u8, target_pc: usize) -> Result<(), EbpfError<E>> {
+fn emit_conditional_branch_imm<E: UserDefinedError>(jit: &mut JitCompiler, size: OperandSize, op: u8, bitwise: bool, immediate: i64, second_operand: u8, target_pc: usize) -> Result<(), EbpfError<E>> {
     emit_validate_and_profile_instruction_count(jit, false, Some(target_pc))?;
     if should_sanitize_constant(jit, immediate) {
         emit_sanitized_load_immediate(jit, OperandSize::S64, R11, immediate)?;
         if bitwise { // Logical
-            X86Instruction::test(OperandSize::S64, R11, second_operand, None).emit(jit)?;
+            X86Instruction::test(size, R11, second_operand, None).emit(jit)?;
         } else { // Arithmetic
-            X86Instruction::cmp(OperandSize::S64, R11, second_operand, None).emit(jit)?;
+            X86Instruction::cmp(size, R11, second_operand, None).emit(jit)?;
         }
     } else if bitwise { // Logical
-        X86Instruction::test_immediate(OperandSize::S64, second_operand, immediate, None).emit(jit)?;
+        X86Instruction::test_immediate(size, second_operand, immediate, None).emit(jit)?;
     } else { // Arithmetic
-        X86Instruction::cmp_immediate(OperandSize::S64, second_operand, immediate, None).emit(jit)?;
+        X86Instruction::cmp_immediate(size, second_operand, immediate, None).emit(jit)?;
     }
     X86Instruction::load_immediate(OperandSize::S64, R11, target_pc as i64).emit(jit)?;
     emit_jcc(jit, op, target_pc)?;
     emit_undo_profile_instruction_count(jit, target_pc)
 }
 
+#[derive(Clone, Copy)]
 enum Value {
     Register(u8),
     RegisterIndirect(u8, i32, bool),
     RegisterPlusConstant32(u8, i32, bool),
     RegisterPlusConstant64(u8, i64, bool),
     Constant64(i64, bool),
+    Relocatable(Relocation),
 }
 
 #[inline]
@@ -667,6 +918,8 @@ impl Argument {
                 debug_assert!(!user_provided);
                 if is_stack_argument {
                     X86Instruction::push(reg, Some(X86IndirectAccess::Offset(offset))).emit(jit)?;
+                } else if reg == RSP {
+                    X86Instruction::load(OperandSize::S64, RSP, dst, X86IndirectAccess::OffsetIndexShift(offset, RSP, 0)).emit(jit)?;
                 } else {
                     X86Instruction::load(OperandSize::S64, reg, dst, X86IndirectAccess::Offset(offset)).emit(jit)?;
                 }
@@ -694,11 +947,23 @@ impl Argument {
                 debug_assert!(!user_provided && !is_stack_argument);
                 X86Instruction::load_immediate(OperandSize::S64, dst, value).emit(jit)?;
             },
+            Value::Relocatable(relocation) => {
+                debug_assert!(!is_stack_argument);
+                emit_relocatable_load(jit, dst, relocation)?;
+            },
         }
         Ok(())
     }
 }
 
+/// Loads an absolute address, which is patched in by JitProgramSections::relocate()
+#[inline]
+fn emit_relocatable_load<E: UserDefinedError>(jit: &mut JitCompiler, destination: u8, relocation: Relocation) -> Result<(), EbpfError<E>> {
+    X86Instruction::load_immediate_64(destination, 0).emit(jit)?;
+    jit.relocations.push((jit.offset_in_text_section - mem::size_of::<i64>(), relocation));
+    Ok(())
+}
+
 #[inline]
 fn emit_rust_call<E: UserDefinedError>(jit: &mut JitCompiler, dst: Value, arguments: &[Argument], result_reg: Option<u8>, check_exception: bool) -> Result<(), EbpfError<E>> {
     let mut saved_registers = CALLER_SAVED_REGISTERS.to_vec();
@@ -733,6 +998,10 @@ fn emit_rust_call<E: UserDefinedError>(jit: &mut JitCompiler, dst: Value, argume
             X86Instruction::load_immediate(OperandSize::S64, RAX, value).emit(jit)?;
             X86Instruction::call_reg(RAX, None).emit(jit)?;
         },
+        Value::Relocatable(relocation) => {
+            emit_relocatable_load(jit, RAX, relocation)?;
+            X86Instruction::call_reg(RAX, None).emit(jit)?;
+        },
         _ => {
             #[cfg(debug_assertions)]
             unreachable!();
@@ -758,8 +1027,24 @@ fn emit_rust_call<E: UserDefinedError>(jit: &mut JitCompiler, dst: Value, argume
     Ok(())
 }
 
+/// Called by the exception handler to expose the frames of the host stack in the CallFrames
+///
+/// Every BPF to BPF call leaves a record of its host return address, the caller's frame pointer
+/// and the scratch registers on the host stack. These records are stacked below the environment,
+/// so the one of the call at depth `i` starts `i` records below `stack_base`.
+unsafe fn capture_call_frames(call_frames: &mut CallFrames, pc_section: *const u64, pc_section_len: usize, stack_base: *const u64, call_depth: usize) {
+    let pc_section = std::slice::from_raw_parts(pc_section, pc_section_len);
+    let return_ptrs = (1..=call_depth).map(|depth| {
+        let host_return_address = *stack_base.sub(depth * (SCRATCH_REGS + 2));
+        // Same as the linear search of TARGET_PC_TRANSLATE_PC
+        let call_pc = pc_section.iter().skip(1).position(|host_pc| *host_pc > host_return_address).unwrap_or(pc_section_len - 1);
+        call_pc + 1
+    }).collect::<Vec<_>>();
+    call_frames.set_return_ptrs(&return_ptrs);
+}
+
 #[inline]
-fn emit_address_translation<E: UserDefinedError>(jit: &mut JitCompiler, host_addr: u8, vm_addr: Value, len: u64, access_type: AccessType) -> Result<(), EbpfError<E>> {
+fn emit_vm_addr<E: UserDefinedError>(jit: &mut JitCompiler, vm_addr: Value) -> Result<(), EbpfError<E>> {
     match vm_addr {
         Value::RegisterPlusConstant64(reg, constant, user_provided) => {
             if user_provided && should_sanitize_constant(jit, constant) {
@@ -781,10 +1066,117 @@ fn emit_address_translation<E: UserDefinedError>(jit: &mut JitCompiler, host_add
             unreachable!();
         },
     }
-    emit_call(jit, TARGET_PC_TRANSLATE_MEMORY_ADDRESS + len.trailing_zeros() as usize + 4 * (access_type as usize))?;
+    Ok(())
+}
+
+#[inline]
+fn emit_address_translation<E: UserDefinedError>(jit: &mut JitCompiler, host_addr: u8, vm_addr: Value, len: u64, access_type: AccessType) -> Result<(), EbpfError<E>> {
+    emit_vm_addr(jit, vm_addr)?;
+    if jit.in_bounds_accesses.contains(&jit.pc) {
+        emit_call(jit, TARGET_PC_TRANSLATE_MEMORY_ADDRESS_UNCHECKED)?;
+    } else {
+        emit_call(jit, TARGET_PC_TRANSLATE_MEMORY_ADDRESS + len.trailing_zeros() as usize + 4 * (access_type as usize))?;
+    }
+    if access_type == AccessType::Load {
+        // Loads are traced before they happen, as they could overwrite the registers of vm_addr
+        emit_memory_access_trace(jit, vm_addr, len, access_type)?;
+    }
     X86Instruction::mov(OperandSize::S64, R11, host_addr).emit(jit)
 }
 
+/// Records the access at the host address in R11, if memory tracing is enabled
+#[inline]
+fn emit_memory_access_trace<E: UserDefinedError>(jit: &mut JitCompiler, vm_addr: Value, len: u64, access_type: AccessType) -> Result<(), EbpfError<E>> {
+    if !jit.config.enable_memory_tracing {
+        return Ok(());
+    }
+    let size = match len {
+        1 => OperandSize::S8,
+        2 => OperandSize::S16,
+        4 => OperandSize::S32,
+        _ => OperandSize::S64,
+    };
+    X86Instruction::push(R11, None).emit(jit)?;
+    emit_vm_addr(jit, vm_addr)?;
+    X86Instruction::push(R11, None).emit(jit)?; // Two pushes keep the stack aligned
+    X86Instruction::load(OperandSize::S64, RSP, R11, X86IndirectAccess::OffsetIndexShift(8, RSP, 0)).emit(jit)?; // R11 = host_addr;
+    X86Instruction::load(size, R11, R11, X86IndirectAccess::Offset(0)).emit(jit)?; // R11 = *host_addr;
+    emit_rust_call(jit, Value::Relocatable(Relocation::TracerTraceMemoryAccess), &[
+        Argument { index: 5, value: Value::Register(R11) }, // value
+        Argument { index: 4, value: Value::Constant64(len as i64, false) },
+        Argument { index: 3, value: Value::RegisterIndirect(RSP, 8 * CALLER_SAVED_REGISTERS.len() as i32, false) }, // vm_addr, pushed before the caller saved registers
+        Argument { index: 2, value: Value::Constant64(access_type as i64, false) },
+        Argument { index: 1, value: Value::Constant64(jit.pc as i64, false) },
+        Argument { index: 0, value: Value::RegisterIndirect(R10, mem::size_of::<MemoryMapping>() as i32 + jit.program_argument_key, false) }, // jit.tracer
+    ], None, false)?;
+    X86Instruction::pop(R11).emit(jit)?;
+    X86Instruction::pop(R11).emit(jit)
+}
+
+/// Performs the BPF_ATOMIC operation encoded in `imm` on the memory at `dst + off`
+fn emit_atomic<E: UserDefinedError>(jit: &mut JitCompiler, size: OperandSize, dst: u8, src: u8, off: i64, imm: i64) -> Result<(), EbpfError<E>> {
+    let operation = ebpf::atomic_operation(imm).ok_or(EbpfError::UnsupportedInstruction(jit.pc + ebpf::ELF_INSN_DUMP_OFFSET))?;
+    let len = if size == OperandSize::S64 { 8 } else { 4 };
+    let vm_addr = Value::RegisterPlusConstant64(dst, off, true);
+    emit_address_translation(jit, R11, vm_addr, len, AccessType::Store)?;
+    let opcode = match operation & !ebpf::BPF_FETCH {
+        ebpf::BPF_ADD => 0x01,
+        ebpf::BPF_OR => 0x09,
+        ebpf::BPF_AND => 0x21,
+        _ => 0x31,
+    };
+    if operation & ebpf::BPF_FETCH == 0 {
+        X86Instruction { lock: true, size, opcode, first_operand: src, second_operand: R11, indirect: Some(X86IndirectAccess::Offset(0)), ..X86Instruction::default() }.emit(jit)?;
+        return emit_memory_access_trace(jit, vm_addr, len, AccessType::Store);
+    }
+
+    // Fetching the previous value is a load as well
+    emit_memory_access_trace(jit, vm_addr, len, AccessType::Load)?;
+
+    // The previous value is collected in RAX, while RCX holds the operand and RDX is scratch.
+    // Four pushes keep the stack aligned, the last slot receives the previous value.
+    X86Instruction::push(RAX, None).emit(jit)?;
+    X86Instruction::push(RCX, None).emit(jit)?;
+    X86Instruction::push(RDX, None).emit(jit)?;
+    X86Instruction::push(RDX, None).emit(jit)?;
+    X86Instruction::mov(OperandSize::S64, src, RCX).emit(jit)?;
+    match operation {
+        ebpf::BPF_XCHG => {
+            X86Instruction::xchg(size, RCX, R11, Some(X86IndirectAccess::Offset(0))).emit(jit)?;
+            X86Instruction::mov(OperandSize::S64, RCX, RAX).emit(jit)?;
+        }
+        ebpf::BPF_CMPXCHG => {
+            // RAX still holds r0, which is the value to compare against
+            X86Instruction::compare_and_exchange(size, RCX, R11, Some(X86IndirectAccess::Offset(0))).emit(jit)?;
+        }
+        _ if opcode == 0x01 => {
+            X86Instruction::exchange_and_add(size, RCX, R11, Some(X86IndirectAccess::Offset(0))).emit(jit)?;
+            X86Instruction::mov(OperandSize::S64, RCX, RAX).emit(jit)?;
+        }
+        _ => {
+            // Retry until no other write happened between the load and the compare and exchange
+            X86Instruction::load(size, R11, RAX, X86IndirectAccess::Offset(0)).emit(jit)?;
+            let retry_anchor = TARGET_PC_LOCAL_ANCHOR - jit.offset_in_text_section;
+            set_anchor(jit, retry_anchor);
+            X86Instruction::mov(OperandSize::S64, RAX, RDX).emit(jit)?;
+            emit_alu(jit, size, opcode, RCX, RDX, 0, None)?;
+            X86Instruction::compare_and_exchange(size, RDX, R11, Some(X86IndirectAccess::Offset(0))).emit(jit)?;
+            emit_jcc(jit, 0x85, retry_anchor)?;
+        }
+    }
+    if size == OperandSize::S32 {
+        X86Instruction::mov(OperandSize::S32, RAX, RAX).emit(jit)?; // Zero extend the previous value
+    }
+    X86Instruction::store(OperandSize::S64, RAX, RSP, X86IndirectAccess::OffsetIndexShift(0, RSP, 0)).emit(jit)?;
+    X86Instruction::load(OperandSize::S64, RSP, RDX, X86IndirectAccess::OffsetIndexShift(8, RSP, 0)).emit(jit)?;
+    X86Instruction::load(OperandSize::S64, RSP, RCX, X86IndirectAccess::OffsetIndexShift(16, RSP, 0)).emit(jit)?;
+    X86Instruction::load(OperandSize::S64, RSP, RAX, X86IndirectAccess::OffsetIndexShift(24, RSP, 0)).emit(jit)?;
+    // Traced before the previous value is written back, as it could overwrite the register of vm_addr
+    emit_memory_access_trace(jit, vm_addr, len, AccessType::Store)?;
+    X86Instruction::pop(if operation == ebpf::BPF_CMPXCHG { RAX } else { src }).emit(jit)?;
+    emit_alu(jit, OperandSize::S64, 0x81, 0, RSP, 8 * 3, None)
+}
+
 fn emit_shift<E: UserDefinedError>(jit: &mut JitCompiler, size: OperandSize, opcode_extension: u8, source: u8, destination: u8, immediate: Option<i64>) -> Result<(), EbpfError<E>> {
     if let Some(immediate) = immediate {
         if should_sanitize_constant(jit, immediate) {
@@ -823,16 +1215,17 @@ fn emit_shift<E: UserDefinedError>(jit: &mut JitCompiler, size: OperandSize, opc
     }
 }
 
-fn emit_muldivmod<E: UserDefinedError>(jit: &mut JitCompiler, opc: u8, src: u8, dst: u8, imm: Option<i64>) -> Result<(), EbpfError<E>> {
+fn emit_muldivmod<E: UserDefinedError>(jit: &mut JitCompiler, opc: u8, signed_modulo: bool, src: u8, dst: u8, imm: Option<i64>) -> Result<(), EbpfError<E>> {
     let mul = (opc & ebpf::BPF_ALU_OP_MASK) == (ebpf::MUL32_IMM & ebpf::BPF_ALU_OP_MASK);
     let div = (opc & ebpf::BPF_ALU_OP_MASK) == (ebpf::DIV32_IMM & ebpf::BPF_ALU_OP_MASK);
     let sdiv = (opc & ebpf::BPF_ALU_OP_MASK) == (ebpf::SDIV32_IMM & ebpf::BPF_ALU_OP_MASK);
     let modrm = (opc & ebpf::BPF_ALU_OP_MASK) == (ebpf::MOD32_IMM & ebpf::BPF_ALU_OP_MASK);
+    let smod = modrm && signed_modulo;
     let size = if (opc & ebpf::BPF_CLS_MASK) == ebpf::BPF_ALU64 { OperandSize::S64 } else { OperandSize::S32 };
 
     // subtracting offset_in_text_section from TARGET_PC_LOCAL_ANCHOR gives us a
     // unique local anchor
-    let sdiv_anchor =  if sdiv { TARGET_PC_LOCAL_ANCHOR - jit.offset_in_text_section } else { 0 };
+    let sdiv_anchor =  if sdiv || smod { TARGET_PC_LOCAL_ANCHOR - jit.offset_in_text_section } else { 0 };
 
     if (div || sdiv || modrm) && imm.is_none() {
         // Save pc
@@ -842,9 +1235,9 @@ fn emit_muldivmod<E: UserDefinedError>(jit: &mut JitCompiler, opc: u8, src: u8,
 
     }
 
-    // sdiv overflows with MIN / -1. If we have an immediate and it's not -1, we
+    // sdiv and smod overflow with MIN / -1. If we have an immediate and it's not -1, we
     // don't need any checks.
-    if sdiv && imm.unwrap_or(-1) == -1 {
+    if (sdiv || smod) && imm.unwrap_or(-1) == -1 {
         if imm.is_none() {
             // if src != -1, we can skip checking dst
             X86Instruction::cmp_immediate(size, src, -1, None).emit(jit)?;
@@ -861,7 +1254,7 @@ fn emit_muldivmod<E: UserDefinedError>(jit: &mut JitCompiler, opc: u8, src: u8,
         emit_jmp(jit, TARGET_PC_DIV_OVERFLOW)?;
     }
 
-    if sdiv {
+    if sdiv || smod {
         set_anchor(jit, sdiv_anchor);
     }
 
@@ -886,10 +1279,10 @@ fn emit_muldivmod<E: UserDefinedError>(jit: &mut JitCompiler, opc: u8, src: u8,
         X86Instruction::mov(OperandSize::S64, dst, RAX).emit(jit)?;
     }
 
-    if div || modrm {
+    if div || (modrm && !smod) {
         // xor %edx,%edx
         emit_alu(jit, size, 0x31, RDX, RDX, 0, None)?;
-    } else if sdiv {
+    } else if sdiv || smod {
         // cdq or cqo depending on operand size
         X86Instruction {
             size,
@@ -899,7 +1292,7 @@ fn emit_muldivmod<E: UserDefinedError>(jit: &mut JitCompiler, opc: u8, src: u8,
         }.emit(jit)?;
     }
 
-    emit_alu(jit, size, 0xf7, if mul { 4 } else if sdiv { 7 } else { 6 }, R11, 0, None)?;
+    emit_alu(jit, size, 0xf7, if mul { 4 } else if sdiv || smod { 7 } else { 6 }, R11, 0, None)?;
 
     if dst != RDX {
         if modrm {
@@ -942,16 +1335,170 @@ impl Jump {
     }
 }
 
+/// Bit mask of r0 to r10
+const ALL_REGISTERS: u16 = (1 << 11) - 1;
+
+/// Control and data flow facts about a program, which the optimizations of Config::optimize_jit rely on
+struct OptimizationHints {
+    /// Instructions which can be reached other than by falling through from their predecessor
+    entry_points: Vec<bool>,
+    /// Bit mask of the registers which may be read after an instruction, before they are written again
+    live_registers: Vec<u16>,
+}
+
+impl OptimizationHints {
+    fn new<E: UserDefinedError, I: InstructionMeter>(program: &[u8], executable: &Executable<E, I>) -> Self {
+        let instruction_count = program.len() / ebpf::INSN_SIZE;
+        let mut entry_points = vec![false; instruction_count];
+        let mut used_registers = vec![0u16; instruction_count];
+        let mut written_registers = vec![0u16; instruction_count];
+        let mut successors = vec![[None; 2]; instruction_count];
+        let mut has_indirect_calls = false;
+        let register = |reg: u8| if reg <= 10 { 1u16 << reg } else { ALL_REGISTERS };
+        let mut pc = 0;
+        while pc < instruction_count {
+            let insn = ebpf::get_insn_unchecked(program, pc);
+            let next_pc = if insn.opc == ebpf::LD_DW_IMM { pc + 2 } else { pc + 1 };
+            let fall_through = if next_pc < instruction_count { Some(next_pc) } else { None };
+            // The long jump encodes its offset in the immediate
+            let offset = if insn.opc == ebpf::GOTOL { insn.imm as isize } else { insn.off as isize };
+            let target_pc = pc as isize + offset + 1;
+            let target_pc = if target_pc >= 0 && (target_pc as usize) < instruction_count { Some(target_pc as usize) } else { None };
+            // Jumps which leave the program throw, so they have to be treated as if they read everything
+            let target_pc_used = if target_pc.is_some() { 0 } else { ALL_REGISTERS };
+            let dst = register(insn.dst);
+            let src = if insn.opc & ebpf::BPF_X != 0 { register(insn.src) } else { 0 };
+            let dst_written = if insn.dst <= 10 { 1u16 << insn.dst } else { 0 };
+            let (used, written, targets) = match insn.opc & 0x07 {
+                ebpf::BPF_LD => match insn.opc {
+                    ebpf::LD_DW_IMM => (0, dst_written, [fall_through, None]),
+                    ebpf::LD_ABS_B | ebpf::LD_ABS_H | ebpf::LD_ABS_W | ebpf::LD_ABS_DW => (0, 1, [fall_through, None]),
+                    ebpf::LD_IND_B | ebpf::LD_IND_H | ebpf::LD_IND_W | ebpf::LD_IND_DW => (register(insn.src), 1, [fall_through, None]),
+                    _ => (ALL_REGISTERS, 0, [fall_through, None]),
+                },
+                ebpf::BPF_LDX => (register(insn.src), dst_written, [fall_through, None]),
+                ebpf::BPF_ST => (dst, 0, [fall_through, None]),
+                // Atomic compare and exchange reads r0, the writes of fetching atomics are left out conservatively
+                ebpf::BPF_STX if matches!(insn.opc, ebpf::ST_W_ATOMIC | ebpf::ST_DW_ATOMIC) => (dst | register(insn.src) | register(0), 0, [fall_through, None]),
+                ebpf::BPF_STX => (dst | register(insn.src), 0, [fall_through, None]),
+                ebpf::BPF_ALU | ebpf::BPF_ALU64 if insn.opc & 0xf0 == ebpf::BPF_MOV => (src, dst_written, [fall_through, None]),
+                ebpf::BPF_ALU | ebpf::BPF_ALU64 => (dst | src, dst_written, [fall_through, None]),
+                ebpf::BPF_JMP => match insn.opc & 0xf0 {
+                    ebpf::BPF_JA => (target_pc_used, 0, [target_pc, None]),
+                    ebpf::BPF_CALL => {
+                        has_indirect_calls |= insn.opc == ebpf::CALL_REG;
+                        (ALL_REGISTERS, 0, [fall_through, None])
+                    },
+                    ebpf::BPF_EXIT => (ALL_REGISTERS, 0, [None, None]),
+                    _ => (dst | src | target_pc_used, 0, [fall_through, target_pc]),
+                },
+                ebpf::BPF_JMP32 if insn.opc == ebpf::GOTOL => (target_pc_used, 0, [target_pc, None]),
+                ebpf::BPF_JMP32 => (dst | src | target_pc_used, 0, [fall_through, target_pc]),
+                _ => (ALL_REGISTERS, 0, [fall_through, None]),
+            };
+            if (insn.opc & 0x07 == ebpf::BPF_JMP && insn.opc & 0xf0 != ebpf::BPF_CALL && insn.opc & 0xf0 != ebpf::BPF_EXIT) || insn.opc & 0x07 == ebpf::BPF_JMP32 {
+                if let Some(target_pc) = target_pc {
+                    entry_points[target_pc] = true;
+                }
+            }
+            used_registers[pc] = used;
+            written_registers[pc] = written;
+            successors[pc] = targets;
+            pc = next_pc;
+        }
+        if has_indirect_calls {
+            entry_points.iter_mut().for_each(|entry_point| *entry_point = true);
+        }
+        for pc in executable.get_entrypoint_instruction_offset().ok().into_iter()
+            .chain(executable.get_bpf_functions().values().map(|(pc, _name)| *pc)) {
+            if let Some(entry_point) = entry_points.get_mut(pc) {
+                *entry_point = true;
+            }
+        }
+        let mut live_registers = vec![0u16; instruction_count];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for pc in (0..instruction_count).rev() {
+                let live = successors[pc].iter().flatten().fold(0, |live, successor| {
+                    live | used_registers[*successor] | (live_registers[*successor] & !written_registers[*successor])
+                });
+                if live != live_registers[pc] {
+                    live_registers[pc] = live;
+                    changed = true;
+                }
+            }
+        }
+        Self { entry_points, live_registers }
+    }
+
+    /// Whether the instruction at pc is only reached by falling through from its predecessor
+    fn is_fall_through_only(&self, pc: usize) -> bool {
+        self.entry_points.get(pc) == Some(&false)
+    }
+}
+
+/// A load or store relative to a base register, which can share the address translation with its neighbours
+#[derive(Clone, Copy)]
+struct MemoryAccess {
+    access_type: AccessType,
+    size: OperandSize,
+    len: u64,
+    base: u8,
+    offset: i64,
+    /// Register which is loaded or stored, None for stores of the immediate
+    register: Option<u8>,
+    immediate: i64,
+}
+
+impl MemoryAccess {
+    fn new(insn: &ebpf::Insn) -> Option<Self> {
+        let (access_type, base, register) = match insn.opc {
+            ebpf::LD_B_REG | ebpf::LD_H_REG | ebpf::LD_W_REG | ebpf::LD_DW_REG => (AccessType::Load, insn.src, Some(insn.dst)),
+            ebpf::ST_B_IMM | ebpf::ST_H_IMM | ebpf::ST_W_IMM | ebpf::ST_DW_IMM => (AccessType::Store, insn.dst, None),
+            ebpf::ST_B_REG | ebpf::ST_H_REG | ebpf::ST_W_REG | ebpf::ST_DW_REG => (AccessType::Store, insn.dst, Some(insn.src)),
+            _ => return None,
+        };
+        if base > 10 || register.unwrap_or(0) > 10 {
+            return None;
+        }
+        let (size, len) = match insn.opc & 0x18 {
+            ebpf::BPF_B => (OperandSize::S8, 1),
+            ebpf::BPF_H => (OperandSize::S16, 2),
+            ebpf::BPF_W => (OperandSize::S32, 4),
+            _ => (OperandSize::S64, 8),
+        };
+        Some(Self { access_type, size, len, base, offset: insn.off as i64, register, immediate: insn.imm })
+    }
+
+    /// Whether the access overwrites its own base register
+    fn clobbers_base(&self) -> bool {
+        self.access_type == AccessType::Load && self.register == Some(self.base)
+    }
+
+    /// Emits the access of the host address in R11
+    fn emit<E: UserDefinedError>(&self, jit: &mut JitCompiler) -> Result<(), EbpfError<E>> {
+        match (self.access_type, self.register) {
+            (AccessType::Load, Some(register)) => X86Instruction::load(self.size, R11, REGISTER_MAP[register as usize], X86IndirectAccess::Offset(0)).emit(jit),
+            (_, Some(register)) => X86Instruction::store(self.size, REGISTER_MAP[register as usize], R11, X86IndirectAccess::Offset(0)).emit(jit),
+            (_, None) => X86Instruction::store_immediate(self.size, R11, X86IndirectAccess::Offset(0), self.immediate).emit(jit),
+        }
+    }
+}
+
 pub struct JitCompiler {
     result: JitProgramSections,
     pc_section_jumps: Vec<Jump>,
     text_section_jumps: Vec<Jump>,
+    relocations: Vec<(usize, Relocation)>,
     offset_in_text_section: usize,
     pc: usize,
     last_instruction_meter_validation_pc: usize,
     program_vm_addr: u64,
     handler_anchors: HashMap<usize, usize>,
+    in_bounds_accesses: BTreeSet<usize>,
     config: Config,
+    sbf_version: SbfVersion,
     diversification_rng: SmallRng,
     stopwatch_is_active: bool,
     environment_stack_key: i32,
@@ -993,7 +1540,7 @@ impl std::fmt::Debug for JitCompiler {
 
 impl JitCompiler {
     // Arguments are unused on windows
-    fn new<E: UserDefinedError>(program: &[u8], config: &Config) -> Result<Self, EbpfError<E>> {
+    fn new<E: UserDefinedError>(program: &[u8], config: &Config, sbf_version: SbfVersion) -> Result<Self, EbpfError<E>> {
         #[cfg(target_os = "windows")]
         {
             let _ = program;
@@ -1019,6 +1566,15 @@ impl JitCompiler {
         }
 
         let mut code_length_estimate = MAX_EMPTY_PROGRAM_MACHINE_CODE_LENGTH + MAX_MACHINE_CODE_LENGTH_PER_INSTRUCTION * pc;
+        if config.enable_memory_tracing {
+            code_length_estimate += MAX_MEMORY_TRACING_MACHINE_CODE_LENGTH_PER_INSTRUCTION * pc;
+        }
+        if config.sampling_interval != 0 {
+            code_length_estimate += MAX_SAMPLING_MACHINE_CODE_LENGTH_PER_INSTRUCTION * pc;
+        }
+        if config.optimize_jit {
+            code_length_estimate += MAX_OPTIMIZED_MACHINE_CODE_LENGTH_PER_INSTRUCTION * pc;
+        }
         code_length_estimate += (code_length_estimate as f64 * config.noop_instruction_ratio) as usize;
         let mut diversification_rng = SmallRng::from_rng(rand::thread_rng()).unwrap();
         let (environment_stack_key, program_argument_key) =
@@ -1033,12 +1589,15 @@ impl JitCompiler {
             result: JitProgramSections::new(pc + 1, code_length_estimate)?,
             pc_section_jumps: vec![],
             text_section_jumps: vec![],
+            relocations: vec![],
             offset_in_text_section: 0,
             pc: 0,
             last_instruction_meter_validation_pc: 0,
             program_vm_addr: 0,
             handler_anchors: HashMap::new(),
+            in_bounds_accesses: BTreeSet::new(),
             config: *config,
+            sbf_version,
             diversification_rng,
             stopwatch_is_active: false,
             environment_stack_key,
@@ -1047,11 +1606,14 @@ impl JitCompiler {
     }
 
     fn compile<E: UserDefinedError, I: InstructionMeter>(&mut self,
-            executable: &Pin<Box<Executable<E, I>>>) -> Result<(), EbpfError<E>> {
+            executable: &Executable<E, I>) -> Result<(), EbpfError<E>> {
         let (program_vm_addr, program) = executable.get_text_bytes();
         self.program_vm_addr = program_vm_addr;
+        if self.config.enable_bounds_check_elimination && !self.config.enable_instruction_tracing && !self.config.enable_memory_tracing {
+            self.in_bounds_accesses = Analysis::from_executable(executable).map(|analysis| analysis.find_in_bounds_memory_accesses()).unwrap_or_default();
+        }
 
-        self.generate_prologue::<E, I>()?;
+        self.generate_prologue::<E>()?;
 
         // Jump to entry point
         let entry = executable.get_entrypoint_instruction_offset().unwrap_or(0);
@@ -1062,9 +1624,15 @@ impl JitCompiler {
         emit_jmp(self, entry)?;
 
         // Have these in front so that the linear search of TARGET_PC_TRANSLATE_PC does not terminate early
-        self.generate_helper_routines::<E, I>()?;
+        self.generate_helper_routines::<E>()?;
         self.generate_exception_handlers::<E>()?;
 
+        let optimization_hints = if self.config.optimize_jit && !self.config.enable_instruction_tracing && !self.config.enable_memory_tracing {
+            Some(OptimizationHints::new(program, executable))
+        } else {
+            None
+        };
+
         while self.pc * ebpf::INSN_SIZE < program.len() {
             let mut insn = ebpf::get_insn_unchecked(program, self.pc);
 
@@ -1081,12 +1649,19 @@ impl JitCompiler {
                 X86Instruction::load_immediate(OperandSize::S64, R11, 0).emit(self)?;
             }
 
+            if let Some(optimization_hints) = &optimization_hints {
+                if self.emit_optimized_instructions(program, optimization_hints)? {
+                    self.pc += 1;
+                    continue;
+                }
+            }
+
             let dst = if insn.dst == STACK_PTR_REG as u8 { u8::MAX } else { REGISTER_MAP[insn.dst as usize] };
             let src = REGISTER_MAP[insn.src as usize];
             let target_pc = (self.pc as isize + insn.off as isize + 1) as usize;
 
             match insn.opc {
-                _ if insn.dst == STACK_PTR_REG as u8 && self.config.dynamic_stack_frames => {
+                _ if insn.dst == STACK_PTR_REG as u8 && self.sbf_version.dynamic_stack_frames() => {
                     let stack_ptr_access = X86IndirectAccess::Offset(slot_on_environment_stack(self, EnvironmentStackSlot::BpfStackPtr));
                     match insn.opc {
                         ebpf::SUB64_IMM => emit_alu(self, OperandSize::S64, 0x81, 5, RBP, insn.imm, Some(stack_ptr_access))?,
@@ -1160,42 +1735,64 @@ impl JitCompiler {
                     emit_address_translation(self, R11, Value::RegisterPlusConstant64(src, insn.off as i64, true), 8, AccessType::Load)?;
                     X86Instruction::load(OperandSize::S64, R11, dst, X86IndirectAccess::Offset(0)).emit(self)?;
                 },
+                ebpf::LD_SB_REG  => {
+                    emit_address_translation(self, R11, Value::RegisterPlusConstant64(src, insn.off as i64, true), 1, AccessType::Load)?;
+                    X86Instruction::sign_extend(OperandSize::S8, R11, dst, Some(X86IndirectAccess::Offset(0))).emit(self)?;
+                },
+                ebpf::LD_SH_REG  => {
+                    emit_address_translation(self, R11, Value::RegisterPlusConstant64(src, insn.off as i64, true), 2, AccessType::Load)?;
+                    X86Instruction::sign_extend(OperandSize::S16, R11, dst, Some(X86IndirectAccess::Offset(0))).emit(self)?;
+                },
+                ebpf::LD_SW_REG  => {
+                    emit_address_translation(self, R11, Value::RegisterPlusConstant64(src, insn.off as i64, true), 4, AccessType::Load)?;
+                    X86Instruction::sign_extend(OperandSize::S32, R11, dst, Some(X86IndirectAccess::Offset(0))).emit(self)?;
+                },
 
                 // BPF_ST class
                 ebpf::ST_B_IMM   => {
                     emit_address_translation(self, R11, Value::RegisterPlusConstant64(dst, insn.off as i64, true), 1, AccessType::Store)?;
                     X86Instruction::store_immediate(OperandSize::S8, R11, X86IndirectAccess::Offset(0), insn.imm as i64).emit(self)?;
+                    emit_memory_access_trace(self, Value::RegisterPlusConstant64(dst, insn.off as i64, true), 1, AccessType::Store)?;
                 },
                 ebpf::ST_H_IMM   => {
                     emit_address_translation(self, R11, Value::RegisterPlusConstant64(dst, insn.off as i64, true), 2, AccessType::Store)?;
                     X86Instruction::store_immediate(OperandSize::S16, R11, X86IndirectAccess::Offset(0), insn.imm as i64).emit(self)?;
+                    emit_memory_access_trace(self, Value::RegisterPlusConstant64(dst, insn.off as i64, true), 2, AccessType::Store)?;
                 },
                 ebpf::ST_W_IMM   => {
                     emit_address_translation(self, R11, Value::RegisterPlusConstant64(dst, insn.off as i64, true), 4, AccessType::Store)?;
                     X86Instruction::store_immediate(OperandSize::S32, R11, X86IndirectAccess::Offset(0), insn.imm as i64).emit(self)?;
+                    emit_memory_access_trace(self, Value::RegisterPlusConstant64(dst, insn.off as i64, true), 4, AccessType::Store)?;
                 },
                 ebpf::ST_DW_IMM  => {
                     emit_address_translation(self, R11, Value::RegisterPlusConstant64(dst, insn.off as i64, true), 8, AccessType::Store)?;
                     X86Instruction::store_immediate(OperandSize::S64, R11, X86IndirectAccess::Offset(0), insn.imm as i64).emit(self)?;
+                    emit_memory_access_trace(self, Value::RegisterPlusConstant64(dst, insn.off as i64, true), 8, AccessType::Store)?;
                 },
 
                 // BPF_STX class
                 ebpf::ST_B_REG  => {
                     emit_address_translation(self, R11, Value::RegisterPlusConstant64(dst, insn.off as i64, true), 1, AccessType::Store)?;
                     X86Instruction::store(OperandSize::S8, src, R11, X86IndirectAccess::Offset(0)).emit(self)?;
+                    emit_memory_access_trace(self, Value::RegisterPlusConstant64(dst, insn.off as i64, true), 1, AccessType::Store)?;
                 },
                 ebpf::ST_H_REG  => {
                     emit_address_translation(self, R11, Value::RegisterPlusConstant64(dst, insn.off as i64, true), 2, AccessType::Store)?;
                     X86Instruction::store(OperandSize::S16, src, R11, X86IndirectAccess::Offset(0)).emit(self)?;
+                    emit_memory_access_trace(self, Value::RegisterPlusConstant64(dst, insn.off as i64, true), 2, AccessType::Store)?;
                 },
                 ebpf::ST_W_REG  => {
                     emit_address_translation(self, R11, Value::RegisterPlusConstant64(dst, insn.off as i64, true), 4, AccessType::Store)?;
                     X86Instruction::store(OperandSize::S32, src, R11, X86IndirectAccess::Offset(0)).emit(self)?;
+                    emit_memory_access_trace(self, Value::RegisterPlusConstant64(dst, insn.off as i64, true), 4, AccessType::Store)?;
                 },
                 ebpf::ST_DW_REG  => {
                     emit_address_translation(self, R11, Value::RegisterPlusConstant64(dst, insn.off as i64, true), 8, AccessType::Store)?;
                     X86Instruction::store(OperandSize::S64, src, R11, X86IndirectAccess::Offset(0)).emit(self)?;
+                    emit_memory_access_trace(self, Value::RegisterPlusConstant64(dst, insn.off as i64, true), 8, AccessType::Store)?;
                 },
+                ebpf::ST_W_ATOMIC  => emit_atomic(self, OperandSize::S32, dst, src, insn.off as i64, insn.imm)?,
+                ebpf::ST_DW_ATOMIC => emit_atomic(self, OperandSize::S64, dst, src, insn.off as i64, insn.imm)?,
 
                 // BPF_ALU class
                 ebpf::ADD32_IMM  => {
@@ -1215,9 +1812,9 @@ impl JitCompiler {
                     X86Instruction::sign_extend_i32_to_i64(dst, dst).emit(self)?;
                 },
                 ebpf::MUL32_IMM | ebpf::DIV32_IMM | ebpf::SDIV32_IMM | ebpf::MOD32_IMM  =>
-                    emit_muldivmod(self, insn.opc, dst, dst, Some(insn.imm))?,
+                    emit_muldivmod(self, insn.opc, self.config.enable_ebpf_v4 && insn.off == 1, dst, dst, Some(insn.imm))?,
                 ebpf::MUL32_REG | ebpf::DIV32_REG | ebpf::SDIV32_REG | ebpf::MOD32_REG  =>
-                    emit_muldivmod(self, insn.opc, src, dst, None)?,
+                    emit_muldivmod(self, insn.opc, self.config.enable_ebpf_v4 && insn.off == 1, src, dst, None)?,
                 ebpf::OR32_IMM   => emit_sanitized_alu(self, OperandSize::S32, 0x09, 1, dst, insn.imm)?,
                 ebpf::OR32_REG   => emit_alu(self, OperandSize::S32, 0x09, src, dst, 0, None)?,
                 ebpf::AND32_IMM  => emit_sanitized_alu(self, OperandSize::S32, 0x21, 4, dst, insn.imm)?,
@@ -1236,6 +1833,14 @@ impl JitCompiler {
                         X86Instruction::load_immediate(OperandSize::S32, dst, insn.imm).emit(self)?;
                     }
                 }
+                ebpf::MOV32_REG  if self.config.enable_ebpf_v4 && insn.off == 8 => {
+                    X86Instruction::sign_extend(OperandSize::S8, src, dst, None).emit(self)?;
+                    X86Instruction::mov(OperandSize::S32, dst, dst).emit(self)?; // Zero the upper 32 bits
+                },
+                ebpf::MOV32_REG  if self.config.enable_ebpf_v4 && insn.off == 16 => {
+                    X86Instruction::sign_extend(OperandSize::S16, src, dst, None).emit(self)?;
+                    X86Instruction::mov(OperandSize::S32, dst, dst).emit(self)?; // Zero the upper 32 bits
+                },
                 ebpf::MOV32_REG  => X86Instruction::mov(OperandSize::S32, src, dst).emit(self)?,
                 ebpf::ARSH32_IMM => emit_shift(self, OperandSize::S32, 7, R11, dst, Some(insn.imm))?,
                 ebpf::ARSH32_REG => emit_shift(self, OperandSize::S32, 7, src, dst, None)?,
@@ -1253,7 +1858,7 @@ impl JitCompiler {
                         }
                     }
                 },
-                ebpf::BE         => {
+                ebpf::BE | ebpf::BSWAP => {
                     match insn.imm {
                         16 => {
                             X86Instruction::bswap(OperandSize::S16, dst).emit(self)?;
@@ -1273,9 +1878,9 @@ impl JitCompiler {
                 ebpf::SUB64_IMM  => emit_sanitized_alu(self, OperandSize::S64, 0x29, 5, dst, insn.imm)?,
                 ebpf::SUB64_REG  => emit_alu(self, OperandSize::S64, 0x29, src, dst, 0, None)?,
                 ebpf::MUL64_IMM | ebpf::DIV64_IMM | ebpf::SDIV64_IMM | ebpf::MOD64_IMM  =>
-                    emit_muldivmod(self, insn.opc, dst, dst, Some(insn.imm))?,
+                    emit_muldivmod(self, insn.opc, self.config.enable_ebpf_v4 && insn.off == 1, dst, dst, Some(insn.imm))?,
                 ebpf::MUL64_REG | ebpf::DIV64_REG | ebpf::SDIV64_REG | ebpf::MOD64_REG  =>
-                    emit_muldivmod(self, insn.opc, src, dst, None)?,
+                    emit_muldivmod(self, insn.opc, self.config.enable_ebpf_v4 && insn.off == 1, src, dst, None)?,
                 ebpf::OR64_IMM   => emit_sanitized_alu(self, OperandSize::S64, 0x09, 1, dst, insn.imm)?,
                 ebpf::OR64_REG   => emit_alu(self, OperandSize::S64, 0x09, src, dst, 0, None)?,
                 ebpf::AND64_IMM  => emit_sanitized_alu(self, OperandSize::S64, 0x21, 4, dst, insn.imm)?,
@@ -1294,6 +1899,9 @@ impl JitCompiler {
                         X86Instruction::load_immediate(OperandSize::S64, dst, insn.imm).emit(self)?;
                     }
                 }
+                ebpf::MOV64_REG  if self.config.enable_ebpf_v4 && insn.off == 8  => X86Instruction::sign_extend(OperandSize::S8, src, dst, None).emit(self)?,
+                ebpf::MOV64_REG  if self.config.enable_ebpf_v4 && insn.off == 16 => X86Instruction::sign_extend(OperandSize::S16, src, dst, None).emit(self)?,
+                ebpf::MOV64_REG  if self.config.enable_ebpf_v4 && insn.off == 32 => X86Instruction::sign_extend(OperandSize::S32, src, dst, None).emit(self)?,
                 ebpf::MOV64_REG  => X86Instruction::mov(OperandSize::S64, src, dst).emit(self)?,
                 ebpf::ARSH64_IMM => emit_shift(self, OperandSize::S64, 7, R11, dst, Some(insn.imm))?,
                 ebpf::ARSH64_REG => emit_shift(self, OperandSize::S64, 7, src, dst, None)?,
@@ -1304,35 +1912,35 @@ impl JitCompiler {
                     X86Instruction::load_immediate(OperandSize::S64, R11, target_pc as i64).emit(self)?;
                     emit_jmp(self, target_pc)?;
                 },
-                ebpf::JEQ_IMM    => emit_conditional_branch_imm(self, 0x84, false, insn.imm, dst, target_pc)?,
-                ebpf::JEQ_REG    => emit_conditional_branch_reg(self, 0x84, false, src, dst, target_pc)?,
-                ebpf::JGT_IMM    => emit_conditional_branch_imm(self, 0x87, false, insn.imm, dst, target_pc)?,
-                ebpf::JGT_REG    => emit_conditional_branch_reg(self, 0x87, false, src, dst, target_pc)?,
-                ebpf::JGE_IMM    => emit_conditional_branch_imm(self, 0x83, false, insn.imm, dst, target_pc)?,
-                ebpf::JGE_REG    => emit_conditional_branch_reg(self, 0x83, false, src, dst, target_pc)?,
-                ebpf::JLT_IMM    => emit_conditional_branch_imm(self, 0x82, false, insn.imm, dst, target_pc)?,
-                ebpf::JLT_REG    => emit_conditional_branch_reg(self, 0x82, false, src, dst, target_pc)?,
-                ebpf::JLE_IMM    => emit_conditional_branch_imm(self, 0x86, false, insn.imm, dst, target_pc)?,
-                ebpf::JLE_REG    => emit_conditional_branch_reg(self, 0x86, false, src, dst, target_pc)?,
-                ebpf::JSET_IMM   => emit_conditional_branch_imm(self, 0x85, true, insn.imm, dst, target_pc)?,
-                ebpf::JSET_REG   => emit_conditional_branch_reg(self, 0x85, true, src, dst, target_pc)?,
-                ebpf::JNE_IMM    => emit_conditional_branch_imm(self, 0x85, false, insn.imm, dst, target_pc)?,
-                ebpf::JNE_REG    => emit_conditional_branch_reg(self, 0x85, false, src, dst, target_pc)?,
-                ebpf::JSGT_IMM   => emit_conditional_branch_imm(self, 0x8f, false, insn.imm, dst, target_pc)?,
-                ebpf::JSGT_REG   => emit_conditional_branch_reg(self, 0x8f, false, src, dst, target_pc)?,
-                ebpf::JSGE_IMM   => emit_conditional_branch_imm(self, 0x8d, false, insn.imm, dst, target_pc)?,
-                ebpf::JSGE_REG   => emit_conditional_branch_reg(self, 0x8d, false, src, dst, target_pc)?,
-                ebpf::JSLT_IMM   => emit_conditional_branch_imm(self, 0x8c, false, insn.imm, dst, target_pc)?,
-                ebpf::JSLT_REG   => emit_conditional_branch_reg(self, 0x8c, false, src, dst, target_pc)?,
-                ebpf::JSLE_IMM   => emit_conditional_branch_imm(self, 0x8e, false, insn.imm, dst, target_pc)?,
-                ebpf::JSLE_REG   => emit_conditional_branch_reg(self, 0x8e, false, src, dst, target_pc)?,
+                ebpf::JEQ_IMM    => emit_conditional_branch_imm(self, OperandSize::S64, 0x84, false, insn.imm, dst, target_pc)?,
+                ebpf::JEQ_REG    => emit_conditional_branch_reg(self, OperandSize::S64, 0x84, false, src, dst, target_pc)?,
+                ebpf::JGT_IMM    => emit_conditional_branch_imm(self, OperandSize::S64, 0x87, false, insn.imm, dst, target_pc)?,
+                ebpf::JGT_REG    => emit_conditional_branch_reg(self, OperandSize::S64, 0x87, false, src, dst, target_pc)?,
+                ebpf::JGE_IMM    => emit_conditional_branch_imm(self, OperandSize::S64, 0x83, false, insn.imm, dst, target_pc)?,
+                ebpf::JGE_REG    => emit_conditional_branch_reg(self, OperandSize::S64, 0x83, false, src, dst, target_pc)?,
+                ebpf::JLT_IMM    => emit_conditional_branch_imm(self, OperandSize::S64, 0x82, false, insn.imm, dst, target_pc)?,
+                ebpf::JLT_REG    => emit_conditional_branch_reg(self, OperandSize::S64, 0x82, false, src, dst, target_pc)?,
+                ebpf::JLE_IMM    => emit_conditional_branch_imm(self, OperandSize::S64, 0x86, false, insn.imm, dst, target_pc)?,
+                ebpf::JLE_REG    => emit_conditional_branch_reg(self, OperandSize::S64, 0x86, false, src, dst, target_pc)?,
+                ebpf::JSET_IMM   => emit_conditional_branch_imm(self, OperandSize::S64, 0x85, true, insn.imm, dst, target_pc)?,
+                ebpf::JSET_REG   => emit_conditional_branch_reg(self, OperandSize::S64, 0x85, true, src, dst, target_pc)?,
+                ebpf::JNE_IMM    => emit_conditional_branch_imm(self, OperandSize::S64, 0x85, false, insn.imm, dst, target_pc)?,
+                ebpf::JNE_REG    => emit_conditional_branch_reg(self, OperandSize::S64, 0x85, false, src, dst, target_pc)?,
+                ebpf::JSGT_IMM   => emit_conditional_branch_imm(self, OperandSize::S64, 0x8f, false, insn.imm, dst, target_pc)?,
+                ebpf::JSGT_REG   => emit_conditional_branch_reg(self, OperandSize::S64, 0x8f, false, src, dst, target_pc)?,
+                ebpf::JSGE_IMM   => emit_conditional_branch_imm(self, OperandSize::S64, 0x8d, false, insn.imm, dst, target_pc)?,
+                ebpf::JSGE_REG   => emit_conditional_branch_reg(self, OperandSize::S64, 0x8d, false, src, dst, target_pc)?,
+                ebpf::JSLT_IMM   => emit_conditional_branch_imm(self, OperandSize::S64, 0x8c, false, insn.imm, dst, target_pc)?,
+                ebpf::JSLT_REG   => emit_conditional_branch_reg(self, OperandSize::S64, 0x8c, false, src, dst, target_pc)?,
+                ebpf::JSLE_IMM   => emit_conditional_branch_imm(self, OperandSize::S64, 0x8e, false, insn.imm, dst, target_pc)?,
+                ebpf::JSLE_REG   => emit_conditional_branch_reg(self, OperandSize::S64, 0x8e, false, src, dst, target_pc)?,
                 ebpf::CALL_IMM   => {
                     // For JIT, syscalls MUST be registered at compile time. They can be
                     // updated later, but not created after compiling (we need the address of the
                     // syscall function in the JIT-compiled program).
 
                     let mut resolved = false;
-                    let (syscalls, calls) = if self.config.static_syscalls {
+                    let (syscalls, calls) = if self.sbf_version.static_syscalls() {
                         (insn.src == 0, insn.src != 0)
                     } else {
                         (true, true)
@@ -1343,7 +1951,7 @@ impl JitCompiler {
                             if self.config.enable_instruction_meter {
                                 emit_validate_and_profile_instruction_count(self, true, Some(0))?;
                             }
-                            X86Instruction::load_immediate(OperandSize::S64, R11, syscall.function as *const u8 as i64).emit(self)?;
+                            emit_relocatable_load(self, R11, Relocation::Syscall(insn.imm as u32))?;
                             X86Instruction::load(OperandSize::S64, R10, RAX, X86IndirectAccess::Offset((SYSCALL_CONTEXT_OBJECTS_OFFSET + syscall.context_object_slot) as i32 * 8 + self.program_argument_key)).emit(self)?;
                             emit_call(self, TARGET_PC_SYSCALL)?;
                             if self.config.enable_instruction_meter {
@@ -1373,9 +1981,9 @@ impl JitCompiler {
                             emit_validate_instruction_count(self, true, Some(self.pc))?;
                             // executable.report_unresolved_symbol(self.pc)?;
                             // Workaround for unresolved symbols in ELF: Report error at runtime instead of compiletime
-                            emit_rust_call(self, Value::Constant64(Executable::<E, I>::report_unresolved_symbol as *const u8 as i64, false), &[
+                            emit_rust_call(self, Value::Relocatable(Relocation::ReportUnresolvedSymbol), &[
                                 Argument { index: 2, value: Value::Constant64(self.pc as i64, false) },
-                                Argument { index: 1, value: Value::Constant64(&*executable.as_ref() as *const _ as i64, false) },
+                                Argument { index: 1, value: Value::Relocatable(Relocation::Executable) },
                                 Argument { index: 0, value: Value::RegisterIndirect(RBP, slot_on_environment_stack(self, EnvironmentStackSlot::OptRetValPtr), false) },
                             ], None, true)?;
                             X86Instruction::load_immediate(OperandSize::S64, R11, self.pc as i64).emit(self)?;
@@ -1407,6 +2015,36 @@ impl JitCompiler {
                     X86Instruction::return_near().emit(self)?;
                 },
 
+                // BPF_JMP32 class
+                ebpf::JEQ32_IMM  => emit_conditional_branch_imm(self, OperandSize::S32, 0x84, false, insn.imm, dst, target_pc)?,
+                ebpf::JEQ32_REG  => emit_conditional_branch_reg(self, OperandSize::S32, 0x84, false, src, dst, target_pc)?,
+                ebpf::JGT32_IMM  => emit_conditional_branch_imm(self, OperandSize::S32, 0x87, false, insn.imm, dst, target_pc)?,
+                ebpf::JGT32_REG  => emit_conditional_branch_reg(self, OperandSize::S32, 0x87, false, src, dst, target_pc)?,
+                ebpf::JGE32_IMM  => emit_conditional_branch_imm(self, OperandSize::S32, 0x83, false, insn.imm, dst, target_pc)?,
+                ebpf::JGE32_REG  => emit_conditional_branch_reg(self, OperandSize::S32, 0x83, false, src, dst, target_pc)?,
+                ebpf::JLT32_IMM  => emit_conditional_branch_imm(self, OperandSize::S32, 0x82, false, insn.imm, dst, target_pc)?,
+                ebpf::JLT32_REG  => emit_conditional_branch_reg(self, OperandSize::S32, 0x82, false, src, dst, target_pc)?,
+                ebpf::JLE32_IMM  => emit_conditional_branch_imm(self, OperandSize::S32, 0x86, false, insn.imm, dst, target_pc)?,
+                ebpf::JLE32_REG  => emit_conditional_branch_reg(self, OperandSize::S32, 0x86, false, src, dst, target_pc)?,
+                ebpf::JSET32_IMM => emit_conditional_branch_imm(self, OperandSize::S32, 0x85, true, insn.imm, dst, target_pc)?,
+                ebpf::JSET32_REG => emit_conditional_branch_reg(self, OperandSize::S32, 0x85, true, src, dst, target_pc)?,
+                ebpf::JNE32_IMM  => emit_conditional_branch_imm(self, OperandSize::S32, 0x85, false, insn.imm, dst, target_pc)?,
+                ebpf::JNE32_REG  => emit_conditional_branch_reg(self, OperandSize::S32, 0x85, false, src, dst, target_pc)?,
+                ebpf::JSGT32_IMM => emit_conditional_branch_imm(self, OperandSize::S32, 0x8f, false, insn.imm, dst, target_pc)?,
+                ebpf::JSGT32_REG => emit_conditional_branch_reg(self, OperandSize::S32, 0x8f, false, src, dst, target_pc)?,
+                ebpf::JSGE32_IMM => emit_conditional_branch_imm(self, OperandSize::S32, 0x8d, false, insn.imm, dst, target_pc)?,
+                ebpf::JSGE32_REG => emit_conditional_branch_reg(self, OperandSize::S32, 0x8d, false, src, dst, target_pc)?,
+                ebpf::JSLT32_IMM => emit_conditional_branch_imm(self, OperandSize::S32, 0x8c, false, insn.imm, dst, target_pc)?,
+                ebpf::JSLT32_REG => emit_conditional_branch_reg(self, OperandSize::S32, 0x8c, false, src, dst, target_pc)?,
+                ebpf::JSLE32_IMM => emit_conditional_branch_imm(self, OperandSize::S32, 0x8e, false, insn.imm, dst, target_pc)?,
+                ebpf::JSLE32_REG => emit_conditional_branch_reg(self, OperandSize::S32, 0x8e, false, src, dst, target_pc)?,
+                ebpf::GOTOL      => {
+                    let target_pc = (self.pc as isize + insn.imm as isize + 1) as usize;
+                    emit_validate_and_profile_instruction_count(self, false, Some(target_pc))?;
+                    X86Instruction::load_immediate(OperandSize::S64, R11, target_pc as i64).emit(self)?;
+                    emit_jmp(self, target_pc)?;
+                },
+
                 _               => return Err(EbpfError::UnsupportedInstruction(self.pc + ebpf::ELF_INSN_DUMP_OFFSET)),
             }
 
@@ -1420,8 +2058,15 @@ impl JitCompiler {
         emit_set_exception_kind::<E>(self, EbpfError::ExecutionOverrun(0))?;
         emit_jmp(self, TARGET_PC_EXCEPTION_AT)?;
 
+        if optimization_hints.is_some() {
+            self.generate_memory_span_routines::<E>()?;
+        }
+        if !self.in_bounds_accesses.is_empty() {
+            self.generate_unchecked_translation_routine::<E>()?;
+        }
         self.generate_epilogue::<E>()?;
         self.resolve_jumps();
+        self.result.relocate(&self.relocations, executable)?;
         self.result.seal(self.offset_in_text_section)?;
 
         // Delete secrets
@@ -1431,7 +2076,199 @@ impl JitCompiler {
         Ok(())
     }
 
-    fn generate_helper_routines<E: UserDefinedError, I: InstructionMeter>(&mut self) -> Result<(), EbpfError<E>> {
+    /// Emits the instruction at self.pc, possibly together with its successors, if an optimization applies to it.
+    /// Returns false if the instruction has to be compiled regularly. Otherwise self.pc is left at the last instruction consumed.
+    fn emit_optimized_instructions<E: UserDefinedError>(&mut self, program: &[u8], hints: &OptimizationHints) -> Result<bool, EbpfError<E>> {
+        let mut insn = ebpf::get_insn_unchecked(program, self.pc);
+        if insn.dst > 10 || insn.src > 10 {
+            return Ok(false);
+        }
+
+        // Moves whose result is never read
+        if matches!(insn.opc, ebpf::MOV32_IMM | ebpf::MOV32_REG | ebpf::MOV64_IMM | ebpf::MOV64_REG)
+            && insn.dst < FRAME_PTR_REG as u8 && hints.live_registers[self.pc] & (1 << insn.dst) == 0 {
+            return Ok(true);
+        }
+
+        // A constant address followed by an access through it
+        if insn.opc == ebpf::LD_DW_IMM {
+            let access_pc = self.pc + 2;
+            if (access_pc + 1) * ebpf::INSN_SIZE > program.len() || !hints.is_fall_through_only(access_pc) {
+                return Ok(false);
+            }
+            ebpf::augment_lddw_unchecked(program, &mut insn);
+            let access = match MemoryAccess::new(&ebpf::get_insn_unchecked(program, access_pc)) {
+                Some(access) if access.base == insn.dst => access,
+                _ => return Ok(false),
+            };
+            let vm_addr = insn.imm.wrapping_add(access.offset);
+            if should_sanitize_constant(self, insn.imm) || should_sanitize_constant(self, vm_addr) {
+                return Ok(false);
+            }
+            emit_validate_and_profile_instruction_count(self, true, Some(self.pc + 2))?;
+            self.pc += 1;
+            self.pc_section_jumps.push(Jump { location: self.pc, target_pc: TARGET_PC_CALL_UNSUPPORTED_INSTRUCTION });
+            X86Instruction::load_immediate(OperandSize::S64, REGISTER_MAP[insn.dst as usize], insn.imm).emit(self)?;
+            if self.last_instruction_meter_validation_pc + self.config.instruction_meter_checkpoint_distance <= access_pc {
+                return Ok(true);
+            }
+            self.pc = access_pc;
+            self.result.pc_section[self.pc] = self.offset_in_text_section as u64;
+            emit_address_translation(self, R11, Value::Constant64(vm_addr, true), access.len, access.access_type)?;
+            access.emit(self)?;
+            return Ok(true);
+        }
+
+        // Pairs of instructions on the same register, the second of which is only reached from the first
+        let next_pc = self.pc + 1;
+        if (next_pc + 1) * ebpf::INSN_SIZE <= program.len() && hints.is_fall_through_only(next_pc)
+            && self.last_instruction_meter_validation_pc + self.config.instruction_meter_checkpoint_distance > next_pc {
+            let next_insn = ebpf::get_insn_unchecked(program, next_pc);
+            if next_insn.dst == insn.dst && next_insn.src <= 10 && self.emit_fused_instructions(&insn, &next_insn)? {
+                self.result.pc_section[next_pc] = self.offset_in_text_section as u64;
+                self.pc = next_pc;
+                return Ok(true);
+            }
+        }
+
+        self.emit_memory_access_run(program, hints)
+    }
+
+    /// Emits two instructions as one, returns false if they can not be fused
+    fn emit_fused_instructions<E: UserDefinedError>(&mut self, insn: &ebpf::Insn, next_insn: &ebpf::Insn) -> Result<bool, EbpfError<E>> {
+        let dst = REGISTER_MAP[insn.dst as usize];
+        let src = REGISTER_MAP[insn.src as usize];
+        // Sign extending moves are not plain copies
+        if insn.opc == ebpf::MOV64_REG && self.config.enable_ebpf_v4 && insn.off != 0 {
+            return Ok(false);
+        }
+        match (insn.opc, next_insn.opc) {
+            // mov + add / sub becomes lea
+            (ebpf::MOV64_REG, ebpf::ADD64_IMM) if !should_sanitize_constant(self, next_insn.imm) => {
+                X86Instruction::lea(OperandSize::S64, src, dst, Some(X86IndirectAccess::OffsetIndexShift(next_insn.imm as i32, RSP, 0))).emit(self)?;
+            },
+            (ebpf::MOV64_REG, ebpf::SUB64_IMM) if next_insn.imm != i32::MIN as i64 && !should_sanitize_constant(self, next_insn.imm) => {
+                X86Instruction::lea(OperandSize::S64, src, dst, Some(X86IndirectAccess::OffsetIndexShift(-next_insn.imm as i32, RSP, 0))).emit(self)?;
+            },
+            (ebpf::MOV64_REG, ebpf::ADD64_REG) => {
+                let index = if next_insn.src == insn.dst { src } else { REGISTER_MAP[next_insn.src as usize] };
+                X86Instruction::lea(OperandSize::S64, src, dst, Some(X86IndirectAccess::OffsetIndexShift(0, index, 0))).emit(self)?;
+            },
+            // mov + alu of constants is folded
+            (ebpf::MOV64_IMM, ebpf::ADD64_IMM | ebpf::SUB64_IMM | ebpf::OR64_IMM | ebpf::AND64_IMM | ebpf::XOR64_IMM) => {
+                let value = match next_insn.opc {
+                    ebpf::ADD64_IMM => insn.imm.wrapping_add(next_insn.imm),
+                    ebpf::SUB64_IMM => insn.imm.wrapping_sub(next_insn.imm),
+                    ebpf::OR64_IMM => insn.imm | next_insn.imm,
+                    ebpf::AND64_IMM => insn.imm & next_insn.imm,
+                    _ => insn.imm ^ next_insn.imm,
+                };
+                if should_sanitize_constant(self, value) {
+                    emit_sanitized_load_immediate(self, OperandSize::S64, dst, value)?;
+                } else {
+                    X86Instruction::load_immediate(OperandSize::S64, dst, value).emit(self)?;
+                }
+            },
+            // alu + comparison with zero reuses the flags of the alu
+            (ebpf::ADD64_IMM | ebpf::ADD64_REG | ebpf::SUB64_IMM | ebpf::SUB64_REG, ebpf::JEQ_IMM | ebpf::JNE_IMM)
+            | (ebpf::OR64_IMM | ebpf::OR64_REG | ebpf::AND64_IMM | ebpf::AND64_REG | ebpf::XOR64_IMM | ebpf::XOR64_REG,
+                ebpf::JEQ_IMM | ebpf::JNE_IMM | ebpf::JGT_IMM | ebpf::JGE_IMM | ebpf::JLT_IMM | ebpf::JLE_IMM
+                | ebpf::JSGT_IMM | ebpf::JSGE_IMM | ebpf::JSLT_IMM | ebpf::JSLE_IMM) if next_insn.imm == 0 => {
+                let target_pc = (self.pc as isize + next_insn.off as isize + 2) as usize;
+                // The meter is validated first, as it would clobber the flags
+                self.pc += 1;
+                emit_validate_and_profile_instruction_count(self, false, Some(target_pc))?;
+                match insn.opc {
+                    ebpf::ADD64_IMM => emit_sanitized_alu(self, OperandSize::S64, 0x01, 0, dst, insn.imm)?,
+                    ebpf::ADD64_REG => emit_alu(self, OperandSize::S64, 0x01, src, dst, 0, None)?,
+                    ebpf::SUB64_IMM => emit_sanitized_alu(self, OperandSize::S64, 0x29, 5, dst, insn.imm)?,
+                    ebpf::SUB64_REG => emit_alu(self, OperandSize::S64, 0x29, src, dst, 0, None)?,
+                    ebpf::OR64_IMM => emit_sanitized_alu(self, OperandSize::S64, 0x09, 1, dst, insn.imm)?,
+                    ebpf::OR64_REG => emit_alu(self, OperandSize::S64, 0x09, src, dst, 0, None)?,
+                    ebpf::AND64_IMM => emit_sanitized_alu(self, OperandSize::S64, 0x21, 4, dst, insn.imm)?,
+                    ebpf::AND64_REG => emit_alu(self, OperandSize::S64, 0x21, src, dst, 0, None)?,
+                    ebpf::XOR64_IMM => emit_sanitized_alu(self, OperandSize::S64, 0x31, 6, dst, insn.imm)?,
+                    _ => emit_alu(self, OperandSize::S64, 0x31, src, dst, 0, None)?,
+                }
+                X86Instruction::load_immediate(OperandSize::S64, R11, target_pc as i64).emit(self)?;
+                let condition = match next_insn.opc {
+                    ebpf::JEQ_IMM => 0x84,
+                    ebpf::JNE_IMM => 0x85,
+                    ebpf::JGT_IMM => 0x87,
+                    ebpf::JGE_IMM => 0x83,
+                    ebpf::JLT_IMM => 0x82,
+                    ebpf::JLE_IMM => 0x86,
+                    ebpf::JSGT_IMM => 0x8f,
+                    ebpf::JSGE_IMM => 0x8d,
+                    ebpf::JSLT_IMM => 0x8c,
+                    _ => 0x8e,
+                };
+                emit_jcc(self, condition, target_pc)?;
+                emit_undo_profile_instruction_count(self, target_pc)?;
+            },
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Emits consecutive accesses relative to the same base register, which share one address translation of the
+    /// range they cover. If that range is not mapped as a whole, every access falls back to its own translation.
+    fn emit_memory_access_run<E: UserDefinedError>(&mut self, program: &[u8], hints: &OptimizationHints) -> Result<bool, EbpfError<E>> {
+        if !self.sbf_version.dynamic_stack_frames() && self.config.enable_stack_frame_gaps {
+            return Ok(false);
+        }
+        let first_access = match MemoryAccess::new(&ebpf::get_insn_unchecked(program, self.pc)) {
+            Some(access) => access,
+            None => return Ok(false),
+        };
+        let mut run = vec![first_access];
+        let mut pc = self.pc + 1;
+        while (pc + 1) * ebpf::INSN_SIZE <= program.len() && hints.is_fall_through_only(pc)
+            && self.last_instruction_meter_validation_pc + self.config.instruction_meter_checkpoint_distance > pc
+            && !run[run.len() - 1].clobbers_base() {
+            match MemoryAccess::new(&ebpf::get_insn_unchecked(program, pc)) {
+                Some(access) if access.base == first_access.base => run.push(access),
+                _ => break,
+            }
+            pc += 1;
+        }
+        if run.len() < 2 {
+            return Ok(false);
+        }
+        let begin = run.iter().map(|access| access.offset).min().unwrap();
+        let end = run.iter().map(|access| access.offset + access.len as i64).max().unwrap();
+        let access_type = if run.iter().any(|access| access.access_type == AccessType::Store) { AccessType::Store } else { AccessType::Load };
+        let base = REGISTER_MAP[first_access.base as usize];
+        emit_vm_addr(self, Value::RegisterPlusConstant64(base, begin, true))?;
+        X86Instruction::push_immediate(OperandSize::S64, (end - begin) as i32).emit(self)?;
+        emit_call(self, TARGET_PC_TRANSLATE_MEMORY_SPAN + access_type as usize)?;
+        emit_alu(self, OperandSize::S64, 0x81, 0, RSP, 8, None)?;
+        X86Instruction::test(OperandSize::S64, R11, R11, None).emit(self)?;
+        let mut previous_offset = begin;
+        for (index, access) in run.iter().enumerate() {
+            if index > 0 {
+                self.pc += 1;
+                self.result.pc_section[self.pc] = self.offset_in_text_section as u64;
+            }
+            // R11 is zero if the range is not mapped, otherwise it is the host address of the previous access
+            let slow_path = TARGET_PC_LOCAL_ANCHOR - self.offset_in_text_section;
+            emit_jcc(self, 0x84, slow_path)?;
+            if access.offset != previous_offset {
+                X86Instruction::lea(OperandSize::S64, R11, R11, Some(X86IndirectAccess::Offset((access.offset - previous_offset) as i32))).emit(self)?;
+            }
+            let fast_path = TARGET_PC_LOCAL_ANCHOR - self.offset_in_text_section;
+            emit_jmp(self, fast_path)?;
+            set_anchor(self, slow_path);
+            emit_address_translation(self, R11, Value::RegisterPlusConstant64(base, access.offset, true), access.len, access.access_type)?;
+            X86Instruction::cmp(OperandSize::S64, R11, R11, None).emit(self)?; // Sets the zero flag, so that the rest of the run stays on the slow path
+            set_anchor(self, fast_path);
+            access.emit(self)?;
+            previous_offset = access.offset;
+        }
+        Ok(true)
+    }
+
+    fn generate_helper_routines<E: UserDefinedError>(&mut self) -> Result<(), EbpfError<E>> {
         // Routine for instruction tracing
         if self.config.enable_instruction_tracing {
             set_anchor(self, TARGET_PC_TRACE);
@@ -1442,7 +2279,7 @@ impl JitCompiler {
             }
             X86Instruction::mov(OperandSize::S64, RSP, REGISTER_MAP[0]).emit(self)?;
             emit_alu(self, OperandSize::S64, 0x81, 0, RSP, - 8 * 3, None)?; // RSP -= 8 * 3;
-            emit_rust_call(self, Value::Constant64(Tracer::trace as *const u8 as i64, false), &[
+            emit_rust_call(self, Value::Relocatable(Relocation::TracerTrace), &[
                 Argument { index: 1, value: Value::Register(REGISTER_MAP[0]) }, // registers
                 Argument { index: 0, value: Value::RegisterIndirect(R10, mem::size_of::<MemoryMapping>() as i32 + self.program_argument_key, false) }, // jit.tracer
             ], None, false)?;
@@ -1454,6 +2291,38 @@ impl JitCompiler {
             X86Instruction::return_near().emit(self)?;
         }
 
+        // Routine for sampling
+        if self.config.enable_instruction_meter && self.config.sampling_interval != 0 {
+            set_anchor(self, TARGET_PC_SAMPLE);
+            X86Instruction::push(R11, None).emit(self)?; // Together with the return address this keeps the stack aligned
+            emit_rust_call(self, Value::Relocatable(Relocation::SamplerSample), &[
+                Argument { index: 3, value: Value::RegisterPlusConstant32(R10, mem::size_of::<MemoryMapping>() as i32 + 16 + self.program_argument_key, false) }, // &mut next_sample_threshold
+                Argument { index: 2, value: Value::Register(ARGUMENT_REGISTERS[0]) }, // remaining instructions
+                Argument { index: 1, value: Value::RegisterIndirect(R10, mem::size_of::<MemoryMapping>() as i32 + 24 + self.program_argument_key, false) }, // sampled_pc
+                Argument { index: 0, value: Value::RegisterIndirect(R10, mem::size_of::<MemoryMapping>() as i32 + 8 + self.program_argument_key, false) }, // jit.sampler
+            ], None, false)?;
+            X86Instruction::pop(R11).emit(self)?;
+            X86Instruction::return_near().emit(self)?;
+        }
+
+        // Routine for capturing the return pointers of all active frames for the backtrace
+        set_anchor(self, TARGET_PC_CAPTURE_CALL_FRAMES);
+        // This is reached from exception handlers at various stack depths, thus align the stack dynamically
+        X86Instruction::push(RAX, None).emit(self)?;
+        X86Instruction::mov(OperandSize::S64, RSP, RAX).emit(self)?;
+        emit_alu(self, OperandSize::S64, 0x81, 4, RSP, -16, None)?; // RSP &= -16;
+        X86Instruction::push(RAX, None).emit(self)?; // Save the unaligned RSP
+        emit_rust_call(self, Value::Relocatable(Relocation::CaptureCallFrames), &[
+            Argument { index: 4, value: Value::RegisterIndirect(RBP, slot_on_environment_stack(self, EnvironmentStackSlot::CallDepth), false) },
+            Argument { index: 3, value: Value::RegisterPlusConstant32(RBP, slot_on_environment_stack(self, EnvironmentStackSlot::SlotCount) + 8, false) }, // RSP after the prologue
+            Argument { index: 2, value: Value::Constant64(self.result.pc_section.len() as i64, false) },
+            Argument { index: 1, value: Value::Relocatable(Relocation::PcSection(0)) },
+            Argument { index: 0, value: Value::RegisterIndirect(R10, mem::size_of::<MemoryMapping>() as i32 + 32 + self.program_argument_key, false) }, // jit.call_frames
+        ], None, false)?;
+        X86Instruction::load(OperandSize::S64, RSP, RSP, X86IndirectAccess::OffsetIndexShift(0, RSP, 0)).emit(self)?; // Restore the unaligned RSP
+        X86Instruction::pop(RAX).emit(self)?;
+        X86Instruction::return_near().emit(self)?;
+
         // Routine for syscall
         set_anchor(self, TARGET_PC_SYSCALL);
         X86Instruction::push(R11, None).emit(self)?; // Padding for stack alignment
@@ -1461,7 +2330,7 @@ impl JitCompiler {
             // RDI = *PrevInsnMeter - RDI;
             emit_alu(self, OperandSize::S64, 0x2B, ARGUMENT_REGISTERS[0], RBP, 0, Some(X86IndirectAccess::Offset(slot_on_environment_stack(self, EnvironmentStackSlot::PrevInsnMeter))))?; // RDI -= *PrevInsnMeter;
             emit_alu(self, OperandSize::S64, 0xf7, 3, ARGUMENT_REGISTERS[0], 0, None)?; // RDI = -RDI;
-            emit_rust_call(self, Value::Constant64(I::consume as *const u8 as i64, false), &[
+            emit_rust_call(self, Value::Relocatable(Relocation::InstructionMeterConsume), &[
                 Argument { index: 1, value: Value::Register(ARGUMENT_REGISTERS[0]) },
                 Argument { index: 0, value: Value::RegisterIndirect(RBP, slot_on_environment_stack(self, EnvironmentStackSlot::InsnMeterPtr), false) },
             ], None, false)?;
@@ -1477,7 +2346,7 @@ impl JitCompiler {
             Argument { index: 0, value: Value::Register(RAX) }, // "&mut self" in the "call" method of the SyscallObject
         ], None, false)?;
         if self.config.enable_instruction_meter {
-            emit_rust_call(self, Value::Constant64(I::get_remaining as *const u8 as i64, false), &[
+            emit_rust_call(self, Value::Relocatable(Relocation::InstructionMeterGetRemaining), &[
                 Argument { index: 0, value: Value::RegisterIndirect(RBP, slot_on_environment_stack(self, EnvironmentStackSlot::InsnMeterPtr), false) },
             ], Some(ARGUMENT_REGISTERS[0]), false)?;
             X86Instruction::store(OperandSize::S64, ARGUMENT_REGISTERS[0], RBP, X86IndirectAccess::Offset(slot_on_environment_stack(self, EnvironmentStackSlot::PrevInsnMeter))).emit(self)?;
@@ -1510,7 +2379,7 @@ impl JitCompiler {
 
         // Setup the frame pointer for the new frame. What we do depends on whether we're using dynamic or fixed frames.
         let frame_ptr_access = X86IndirectAccess::Offset(slot_on_environment_stack(self, EnvironmentStackSlot::BpfFramePtr));
-        if self.config.dynamic_stack_frames {
+        if self.sbf_version.dynamic_stack_frames() {
             // When dynamic frames are on, the next frame starts at the end of the current frame
             let stack_ptr_access = X86IndirectAccess::Offset(slot_on_environment_stack(self, EnvironmentStackSlot::BpfStackPtr));
             X86Instruction::load(OperandSize::S64, RBP, REGISTER_MAP[FRAME_PTR_REG], stack_ptr_access).emit(self)?;
@@ -1549,7 +2418,7 @@ impl JitCompiler {
         X86Instruction::store(OperandSize::S64, R11, RSP, X86IndirectAccess::OffsetIndexShift(-8, RSP, 0)).emit(self)?; // RSP[-8] = R11;
         // Load host target_address from self.result.pc_section
         debug_assert_eq!(INSN_SIZE, 8); // Because the instruction size is also the slot size we do not need to shift the offset
-        X86Instruction::load_immediate(OperandSize::S64, REGISTER_MAP[FRAME_PTR_REG], self.result.pc_section.as_ptr() as i64).emit(self)?;
+        emit_relocatable_load(self, REGISTER_MAP[FRAME_PTR_REG], Relocation::PcSection(0))?;
         emit_alu(self, OperandSize::S64, 0x01, REGISTER_MAP[FRAME_PTR_REG], REGISTER_MAP[0], 0, None)?; // RAX += self.result.pc_section;
         X86Instruction::load(OperandSize::S64, REGISTER_MAP[0], REGISTER_MAP[0], X86IndirectAccess::Offset(0)).emit(self)?; // RAX = self.result.pc_section[RAX / 8];
         // Load the frame pointer again since we've clobbered REGISTER_MAP[FRAME_PTR_REG]
@@ -1559,13 +2428,13 @@ impl JitCompiler {
         // Translates a host pc back to a BPF pc by linear search of the pc_section table
         set_anchor(self, TARGET_PC_TRANSLATE_PC);
         X86Instruction::push(REGISTER_MAP[0], None).emit(self)?; // Save REGISTER_MAP[0]
-        X86Instruction::load_immediate(OperandSize::S64, REGISTER_MAP[0], self.result.pc_section.as_ptr() as i64 - 8).emit(self)?; // Loop index and pointer to look up
+        emit_relocatable_load(self, REGISTER_MAP[0], Relocation::PcSection(-8))?; // Loop index and pointer to look up
         set_anchor(self, TARGET_PC_TRANSLATE_PC_LOOP); // Loop label
         emit_alu(self, OperandSize::S64, 0x81, 0, REGISTER_MAP[0], 8, None)?; // Increase index
         X86Instruction::cmp(OperandSize::S64, R11, REGISTER_MAP[0], Some(X86IndirectAccess::Offset(8))).emit(self)?; // Look up and compare against value at next index
         emit_jcc(self, 0x86, TARGET_PC_TRANSLATE_PC_LOOP)?; // Continue while *REGISTER_MAP[0] <= R11
         X86Instruction::mov(OperandSize::S64, REGISTER_MAP[0], R11).emit(self)?; // R11 = REGISTER_MAP[0];
-        X86Instruction::load_immediate(OperandSize::S64, REGISTER_MAP[0], self.result.pc_section.as_ptr() as i64).emit(self)?; // REGISTER_MAP[0] = self.result.pc_section;
+        emit_relocatable_load(self, REGISTER_MAP[0], Relocation::PcSection(0))?; // REGISTER_MAP[0] = self.result.pc_section;
         emit_alu(self, OperandSize::S64, 0x29, REGISTER_MAP[0], R11, 0, None)?; // R11 -= REGISTER_MAP[0];
         emit_alu(self, OperandSize::S64, 0xc1, 5, R11, 3, None)?; // R11 >>= 3;
         X86Instruction::pop(REGISTER_MAP[0]).emit(self)?; // Restore REGISTER_MAP[0]
@@ -1588,7 +2457,7 @@ impl JitCompiler {
             X86Instruction::push(R11, None).emit(self)?;
             X86Instruction::push(RAX, None).emit(self)?;
             X86Instruction::push(RCX, None).emit(self)?;
-            let stack_offset = if !self.config.dynamic_stack_frames && self.config.enable_stack_frame_gaps {
+            let stack_offset = if !self.sbf_version.dynamic_stack_frames() && self.config.enable_stack_frame_gaps {
                 X86Instruction::push(RDX, None).emit(self)?;
                 24
             } else {
@@ -1609,7 +2478,7 @@ impl JitCompiler {
             X86Instruction::cmp(OperandSize::S64, RCX, R11, None).emit(self)?; // vm_addr < region.vm_addr
             emit_jcc(self, 0x82, TARGET_PC_MEMORY_ACCESS_VIOLATION + target_offset)?;
             emit_alu(self, OperandSize::S64, 0x29, RCX, R11, 0, None)?; // vm_addr -= region.vm_addr
-            if !self.config.dynamic_stack_frames && self.config.enable_stack_frame_gaps {
+            if !self.sbf_version.dynamic_stack_frames() && self.config.enable_stack_frame_gaps {
                 X86Instruction::load(OperandSize::S8, RAX, RCX, X86IndirectAccess::Offset(MemoryRegion::VM_GAP_SHIFT_OFFSET)).emit(self)?; // RCX = region.vm_gap_shift;
                 X86Instruction::mov(OperandSize::S64, R11, RDX).emit(self)?; // RDX = R11;
                 emit_alu(self, OperandSize::S64, 0xd3, 5, RDX, 0, None)?; // RDX = R11 >> region.vm_gap_shift;
@@ -1628,7 +2497,7 @@ impl JitCompiler {
             X86Instruction::cmp(OperandSize::S64, RCX, RAX, Some(X86IndirectAccess::Offset(MemoryRegion::LEN_OFFSET))).emit(self)?; // region.len < R11 + len
             emit_jcc(self, 0x82, TARGET_PC_MEMORY_ACCESS_VIOLATION + target_offset)?;
             emit_alu(self, OperandSize::S64, 0x03, R11, RAX, 0, Some(X86IndirectAccess::Offset(MemoryRegion::HOST_ADDR_OFFSET)))?; // R11 += region.host_addr;
-            if !self.config.dynamic_stack_frames && self.config.enable_stack_frame_gaps {
+            if !self.sbf_version.dynamic_stack_frames() && self.config.enable_stack_frame_gaps {
                 X86Instruction::pop(RDX).emit(self)?;
             }
             X86Instruction::pop(RCX).emit(self)?;
@@ -1639,7 +2508,7 @@ impl JitCompiler {
             set_anchor(self, TARGET_PC_MEMORY_ACCESS_VIOLATION + target_offset);
             emit_alu(self, OperandSize::S64, 0x31, R11, R11, 0, None)?; // R11 = 0;
             X86Instruction::load(OperandSize::S64, RSP, R11, X86IndirectAccess::OffsetIndexShift(stack_offset, R11, 0)).emit(self)?;
-            emit_rust_call(self, Value::Constant64(MemoryMapping::generate_access_violation::<UserError> as *const u8 as i64, false), &[
+            emit_rust_call(self, Value::Relocatable(Relocation::GenerateAccessViolation), &[
                 Argument { index: 3, value: Value::Register(R11) }, // Specify first as the src register could be overwritten by other arguments
                 Argument { index: 4, value: Value::Constant64(*len as i64, false) },
                 Argument { index: 2, value: Value::Constant64(*access_type as i64, false) },
@@ -1649,11 +2518,86 @@ impl JitCompiler {
             emit_alu(self, OperandSize::S64, 0x81, 0, RSP, stack_offset as i64 + 8, None)?; // Drop R11, RAX, RCX, RDX from stack
             X86Instruction::pop(R11).emit(self)?; // Put callers PC in R11
             emit_call(self, TARGET_PC_TRANSLATE_PC)?;
+            if self.config.enable_backtrace {
+                emit_call(self, TARGET_PC_CAPTURE_CALL_FRAMES)?;
+            }
             emit_jmp(self, TARGET_PC_EXCEPTION_AT)?;
         }
         Ok(())
     }
 
+    /// Routines to translate a range of memory which is shared by multiple accesses, without throwing
+    fn generate_memory_span_routines<E: UserDefinedError>(&mut self) -> Result<(), EbpfError<E>> {
+        for access_type in [AccessType::Load, AccessType::Store] {
+            set_anchor(self, TARGET_PC_TRANSLATE_MEMORY_SPAN + access_type as usize);
+            X86Instruction::push(RAX, None).emit(self)?;
+            X86Instruction::push(RCX, None).emit(self)?;
+            X86Instruction::mov(OperandSize::S64, R11, RAX).emit(self)?; // RAX = vm_addr;
+            emit_alu(self, OperandSize::S64, 0xc1, 5, RAX, ebpf::VIRTUAL_ADDRESS_BITS as i64, None)?; // RAX >>= ebpf::VIRTUAL_ADDRESS_BITS;
+            X86Instruction::cmp(OperandSize::S64, RAX, R10, Some(X86IndirectAccess::Offset(self.program_argument_key + 8))).emit(self)?; // region_index >= jit_program_argument.memory_mapping.regions.len()
+            emit_jcc(self, 0x86, TARGET_PC_TRANSLATE_MEMORY_SPAN_UNMAPPED + access_type as usize)?;
+            emit_alu(self, OperandSize::S64, 0xc1, 4, RAX, 5, None)?; // RAX *= mem::size_of::<MemoryRegion>();
+            emit_alu(self, OperandSize::S64, 0x03, RAX, R10, 0, Some(X86IndirectAccess::Offset(self.program_argument_key)))?; // region = &jit_program_argument.memory_mapping.regions[region_index];
+            if access_type == AccessType::Store {
+                X86Instruction::cmp_immediate(OperandSize::S8, RAX, 0, Some(X86IndirectAccess::Offset(MemoryRegion::IS_WRITABLE_OFFSET))).emit(self)?; // region.is_writable == 0
+                emit_jcc(self, 0x84, TARGET_PC_TRANSLATE_MEMORY_SPAN_UNMAPPED + access_type as usize)?;
+            }
+            X86Instruction::load(OperandSize::S64, RAX, RCX, X86IndirectAccess::Offset(MemoryRegion::VM_ADDR_OFFSET)).emit(self)?; // RCX = region.vm_addr
+            X86Instruction::cmp(OperandSize::S64, RCX, R11, None).emit(self)?; // vm_addr < region.vm_addr
+            emit_jcc(self, 0x82, TARGET_PC_TRANSLATE_MEMORY_SPAN_UNMAPPED + access_type as usize)?;
+            emit_alu(self, OperandSize::S64, 0x29, RCX, R11, 0, None)?; // vm_addr -= region.vm_addr
+            X86Instruction::load(OperandSize::S64, RSP, RCX, X86IndirectAccess::OffsetIndexShift(24, RSP, 0)).emit(self)?; // RCX = len;
+            emit_alu(self, OperandSize::S64, 0x01, R11, RCX, 0, None)?; // RCX += vm_addr;
+            X86Instruction::cmp(OperandSize::S64, RCX, RAX, Some(X86IndirectAccess::Offset(MemoryRegion::LEN_OFFSET))).emit(self)?; // region.len < vm_addr + len
+            emit_jcc(self, 0x82, TARGET_PC_TRANSLATE_MEMORY_SPAN_UNMAPPED + access_type as usize)?;
+            emit_alu(self, OperandSize::S64, 0x03, R11, RAX, 0, Some(X86IndirectAccess::Offset(MemoryRegion::HOST_ADDR_OFFSET)))?; // R11 += region.host_addr;
+            X86Instruction::pop(RCX).emit(self)?;
+            X86Instruction::pop(RAX).emit(self)?;
+            X86Instruction::return_near().emit(self)?;
+
+            set_anchor(self, TARGET_PC_TRANSLATE_MEMORY_SPAN_UNMAPPED + access_type as usize);
+            emit_alu(self, OperandSize::S64, 0x31, R11, R11, 0, None)?; // R11 = 0;
+            X86Instruction::pop(RCX).emit(self)?;
+            X86Instruction::pop(RAX).emit(self)?;
+            X86Instruction::return_near().emit(self)?;
+        }
+        Ok(())
+    }
+
+    /// Routine to translate the address of an access which the static analysis proved in bounds
+    fn generate_unchecked_translation_routine<E: UserDefinedError>(&mut self) -> Result<(), EbpfError<E>> {
+        let stack_frame_gaps = !self.sbf_version.dynamic_stack_frames() && self.config.enable_stack_frame_gaps;
+        set_anchor(self, TARGET_PC_TRANSLATE_MEMORY_ADDRESS_UNCHECKED);
+        X86Instruction::push(RAX, None).emit(self)?;
+        if stack_frame_gaps {
+            X86Instruction::push(RCX, None).emit(self)?;
+            X86Instruction::push(RDX, None).emit(self)?;
+        }
+        X86Instruction::mov(OperandSize::S64, R11, RAX).emit(self)?; // RAX = vm_addr;
+        emit_alu(self, OperandSize::S64, 0xc1, 5, RAX, ebpf::VIRTUAL_ADDRESS_BITS as i64, None)?; // RAX >>= ebpf::VIRTUAL_ADDRESS_BITS;
+        emit_alu(self, OperandSize::S64, 0xc1, 4, RAX, 5, None)?; // RAX *= mem::size_of::<MemoryRegion>();
+        emit_alu(self, OperandSize::S64, 0x03, RAX, R10, 0, Some(X86IndirectAccess::Offset(self.program_argument_key)))?; // region = &jit_program_argument.memory_mapping.regions[region_index];
+        emit_alu(self, OperandSize::S64, 0x2b, R11, RAX, 0, Some(X86IndirectAccess::Offset(MemoryRegion::VM_ADDR_OFFSET)))?; // vm_addr -= region.vm_addr
+        if stack_frame_gaps {
+            X86Instruction::load(OperandSize::S8, RAX, RCX, X86IndirectAccess::Offset(MemoryRegion::VM_GAP_SHIFT_OFFSET)).emit(self)?; // RCX = region.vm_gap_shift;
+            X86Instruction::load_immediate(OperandSize::S64, RDX, -1).emit(self)?; // RDX = -1;
+            emit_alu(self, OperandSize::S64, 0xd3, 4, RDX, 0, None)?; // gap_mask = -1 << region.vm_gap_shift;
+            X86Instruction::mov(OperandSize::S64, RDX, RCX).emit(self)?; // RCX = RDX;
+            emit_alu(self, OperandSize::S64, 0xf7, 2, RCX, 0, None)?; // inverse_gap_mask = !gap_mask;
+            emit_alu(self, OperandSize::S64, 0x21, R11, RCX, 0, None)?; // below_gap = R11 & inverse_gap_mask;
+            emit_alu(self, OperandSize::S64, 0x21, RDX, R11, 0, None)?; // above_gap = R11 & gap_mask;
+            emit_alu(self, OperandSize::S64, 0xc1, 5, R11, 1, None)?; // above_gap >>= 1;
+            emit_alu(self, OperandSize::S64, 0x09, RCX, R11, 0, None)?; // gapped_offset = above_gap | below_gap;
+        }
+        emit_alu(self, OperandSize::S64, 0x03, R11, RAX, 0, Some(X86IndirectAccess::Offset(MemoryRegion::HOST_ADDR_OFFSET)))?; // R11 += region.host_addr;
+        if stack_frame_gaps {
+            X86Instruction::pop(RDX).emit(self)?;
+            X86Instruction::pop(RCX).emit(self)?;
+        }
+        X86Instruction::pop(RAX).emit(self)?;
+        X86Instruction::return_near().emit(self)
+    }
+
     fn generate_exception_handlers<E: UserDefinedError>(&mut self) -> Result<(), EbpfError<E>> {
         // Handler for EbpfError::ExceededMaxInstructions
         set_anchor(self, TARGET_PC_CALL_EXCEEDED_MAX_INSTRUCTIONS);
@@ -1664,6 +2608,11 @@ impl JitCompiler {
 
         // Handler for EbpfError::CallDepthExceeded
         set_anchor(self, TARGET_PC_CALL_DEPTH_EXCEEDED);
+        if self.config.enable_backtrace {
+            let call_depth_access = X86IndirectAccess::Offset(slot_on_environment_stack(self, EnvironmentStackSlot::CallDepth));
+            emit_alu(self, OperandSize::S64, 0x81, 5, RBP, 1, Some(call_depth_access))?; // The new frame was never entered, so undo the increment of CallDepth
+            emit_call(self, TARGET_PC_CAPTURE_CALL_FRAMES)?;
+        }
         emit_set_exception_kind::<E>(self, EbpfError::CallDepthExceeded(0, 0))?;
         X86Instruction::store_immediate(OperandSize::S64, R10, X86IndirectAccess::Offset(24), self.config.max_call_depth as i64).emit(self)?; // depth = jit.config.max_call_depth;
         emit_jmp(self, TARGET_PC_EXCEPTION_AT)?;
@@ -1676,6 +2625,9 @@ impl JitCompiler {
 
         // Handler for EbpfError::DivideByZero
         set_anchor(self, TARGET_PC_DIV_BY_ZERO);
+        if self.config.enable_backtrace {
+            emit_call(self, TARGET_PC_CAPTURE_CALL_FRAMES)?;
+        }
         emit_set_exception_kind::<E>(self, EbpfError::DivideByZero(0))?;
         emit_jmp(self, TARGET_PC_EXCEPTION_AT)?;
 
@@ -1713,7 +2665,7 @@ impl JitCompiler {
         emit_jmp(self, TARGET_PC_EPILOGUE)
     }
 
-    fn generate_prologue<E: UserDefinedError, I: InstructionMeter>(&mut self) -> Result<(), EbpfError<E>> {
+    fn generate_prologue<E: UserDefinedError>(&mut self) -> Result<(), EbpfError<E>> {
         // Place the environment on the stack according to EnvironmentStackSlot
 
         // Save registers
@@ -1726,7 +2678,7 @@ impl JitCompiler {
         X86Instruction::push(REGISTER_MAP[FRAME_PTR_REG], None).emit(self)?;
 
         // Initialize the BPF frame and stack pointers (BpfFramePtr and BpfStackPtr)
-        if self.config.dynamic_stack_frames {
+        if self.sbf_version.dynamic_stack_frames() {
             // The stack is fully descending from MM_STACK_START + stack_size to MM_STACK_START
             X86Instruction::load_immediate(OperandSize::S64, REGISTER_MAP[FRAME_PTR_REG], MM_STACK_START as i64 + self.config.stack_size() as i64).emit(self)?;
             // Push BpfFramePtr
@@ -1747,7 +2699,7 @@ impl JitCompiler {
         X86Instruction::push(ARGUMENT_REGISTERS[0], None).emit(self)?;
 
         // Save initial value of instruction_meter.get_remaining()
-        emit_rust_call(self, Value::Constant64(I::get_remaining as *const u8 as i64, false), &[
+        emit_rust_call(self, Value::Relocatable(Relocation::InstructionMeterGetRemaining), &[
             Argument { index: 0, value: Value::Register(ARGUMENT_REGISTERS[3]) },
         ], Some(ARGUMENT_REGISTERS[0]), false)?;
         X86Instruction::push(ARGUMENT_REGISTERS[0], None).emit(self)?;
@@ -1792,11 +2744,8 @@ impl JitCompiler {
         set_anchor(self, TARGET_PC_EPILOGUE);
 
         // Print stop watch value
-        fn stopwatch_result(numerator: u64, denominator: u64) {
-            println!("Stop watch: {} / {} = {}", numerator, denominator, if denominator == 0 { 0.0 } else { numerator as f64 / denominator as f64 });
-        }
         if self.stopwatch_is_active {
-            emit_rust_call(self, Value::Constant64(stopwatch_result as *const u8 as i64, false), &[
+            emit_rust_call(self, Value::Relocatable(Relocation::StopwatchResult), &[
                 Argument { index: 1, value: Value::RegisterIndirect(RBP, slot_on_environment_stack(self, EnvironmentStackSlot::StopwatchDenominator), false) },
                 Argument { index: 0, value: Value::RegisterIndirect(RBP, slot_on_environment_stack(self, EnvironmentStackSlot::StopwatchNumerator), false) },
             ], None, false)?;
@@ -1855,8 +2804,8 @@ impl JitCompiler {
 #[cfg(all(test, target_arch = "x86_64", not(target_os = "windows")))]
 mod tests {
     use super::*;
-    use crate::{syscalls, vm::{SyscallRegistry, SyscallObject, TestInstructionMeter}, elf::register_bpf_function};
-    use std::collections::BTreeMap;
+    use crate::{assembler::assemble, syscalls, vm::{SyscallRegistry, SyscallObject, TestInstructionMeter}, elf::register_bpf_function};
+    use std::{collections::BTreeMap, pin::Pin};
     use byteorder::{LittleEndian, ByteOrder};
 
     fn create_mockup_executable(program: &[u8]) -> Pin<Box<Executable::<UserError, TestInstructionMeter>>> {
@@ -1913,7 +2862,8 @@ mod tests {
                 prog[pc * ebpf::INSN_SIZE + 3] = 0xFF;
                 LittleEndian::write_u32(&mut prog[pc * ebpf::INSN_SIZE + 4..], match opcode {
                     0x8D => 8,
-                    0xD4 | 0xDC => 16,
+                    0xD4 | 0xDC | 0xD7 => 16,
+                    0xC3 | 0xDB => (ebpf::BPF_OR | ebpf::BPF_FETCH) as u32,
                     _ => 0xFFFFFFFF,
                 });
             }
@@ -1929,4 +2879,30 @@ mod tests {
             assert!(machine_code_length_per_instruction <= MAX_MACHINE_CODE_LENGTH_PER_INSTRUCTION);
         }
     }
+
+    #[test]
+    fn test_optimized_machine_code_length() {
+        let source = "
+            mov r2, r1
+            add r2, 8
+            mov r3, 1
+            mov r3, 2
+            mov r4, 3
+            xor r4, 5
+            and r2, r3
+            jne r2, 0, +0
+            add r0, r4
+        ".repeat(64) + "exit";
+        let machine_code_length = |optimize_jit: bool| {
+            let config = Config {
+                noop_instruction_ratio: 0.0,
+                optimize_jit,
+                ..Config::default()
+            };
+            let mut executable = assemble::<UserError, TestInstructionMeter>(&source, None, config, SyscallRegistry::default()).unwrap();
+            Executable::<UserError, TestInstructionMeter>::jit_compile(&mut executable).unwrap();
+            executable.get_compiled_program().unwrap().machine_code_length()
+        };
+        assert!(machine_code_length(true) < machine_code_length(false));
+    }
 }
@@ -0,0 +1,405 @@
+#![allow(clippy::integer_arithmetic)]
+//! Persistent cache of JIT-compiled programs
+//!
+//! The [JitCache] stores the machine code and the pc section emitted by the JIT in a directory,
+//! so that processes which load the same program again can skip the compilation. Entries are
+//! keyed by a hash of everything the emitted code depends on: The text section, the JIT related
+//! [Config](crate::vm::Config) fields, the BPF functions and the context object slots of the
+//! [SyscallRegistry](crate::vm::SyscallRegistry). Absolute addresses, such as syscall functions,
+//! helper functions and the pc section, are not stored but recorded as relocations and patched
+//! in when an entry is loaded. Each entry carries a SHA-256 digest of its contents, which is
+//! verified before the code is mapped executable.
+//!
+//! A cached program keeps the diversification (random no-ops, constant blinding and the keys
+//! of the environment register encryption) of the compilation which produced it. The digest
+//! only detects corrupted entries, so the cache directory must not be writable by untrusted
+//! users.
+
+use crate::{
+    elf::Executable,
+    error::{EbpfError, UserDefinedError},
+    jit::{JitProgram, Relocation},
+    memory_region::{MemoryMapping, MemoryRegion},
+    vm::{InstructionMeter, ProgramResult},
+};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    io::{self, Cursor, Read, Write},
+    mem,
+    path::{Path, PathBuf},
+    pin::Pin,
+};
+
+/// Identifies the file format of cache entries
+const MAGIC: &[u8; 8] = b"RBPFJIT1";
+
+/// Size of a SHA-256 digest in bytes
+const DIGEST_SIZE: usize = 32;
+
+/// Size of a serialized relocation in bytes
+const RELOCATION_SIZE: usize = 17;
+
+/// On-disk cache of JIT-compiled programs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JitCache {
+    directory: PathBuf,
+}
+
+impl JitCache {
+    /// Opens the cache in the given directory, creating the directory if it does not exist
+    pub fn new<P: Into<PathBuf>>(directory: P) -> io::Result<Self> {
+        let directory = directory.into();
+        fs::create_dir_all(&directory)?;
+        Ok(Self { directory })
+    }
+
+    /// Get the directory of the cache
+    pub fn get_directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Get the path of the cache entry of an executable
+    pub fn get_entry_path<E: UserDefinedError, I: InstructionMeter>(
+        &self,
+        executable: &Executable<E, I>,
+    ) -> PathBuf {
+        self.directory
+            .join(format!("{}.jit", to_hex(&compute_key(executable))))
+    }
+
+    /// Loads the compiled program of an executable, returns `None` if there is no entry
+    pub(crate) fn load<E: UserDefinedError, I: InstructionMeter>(
+        &self,
+        executable: &Pin<Box<Executable<E, I>>>,
+    ) -> Result<Option<JitProgram<E, I>>, EbpfError<E>> {
+        let bytes = match fs::read(self.get_entry_path(executable)) {
+            Ok(bytes) => bytes,
+            Err(_) => return Ok(None),
+        };
+        deserialize(executable, &bytes).map(Some)
+    }
+
+    /// Stores the compiled program of an executable, replacing any previous entry
+    pub(crate) fn store<E: UserDefinedError, I: InstructionMeter>(
+        &self,
+        executable: &Executable<E, I>,
+        program: &JitProgram<E, I>,
+    ) -> io::Result<()> {
+        let path = self.get_entry_path(executable);
+        // Write to a temporary file first, so that concurrent readers never see a partial entry
+        let temporary_path = path.with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&temporary_path, serialize(executable, program)?)?;
+        let result = fs::rename(&temporary_path, &path);
+        if result.is_err() {
+            let _ = fs::remove_file(&temporary_path);
+        }
+        result
+    }
+}
+
+/// Hashes everything the machine code emitted for an executable depends on
+fn compute_key<E: UserDefinedError, I: InstructionMeter>(
+    executable: &Executable<E, I>,
+) -> [u8; DIGEST_SIZE] {
+    let mut hasher = Sha256::new();
+    hasher.update(MAGIC);
+    hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+    // The types determine the layouts which are baked into the machine code
+    for type_name in [std::any::type_name::<E>(), std::any::type_name::<I>()] {
+        hasher.update((type_name.len() as u64).to_le_bytes());
+        hasher.update(type_name.as_bytes());
+    }
+    for size in [
+        mem::size_of::<ProgramResult<E>>(),
+        mem::size_of::<MemoryMapping>(),
+        mem::size_of::<MemoryRegion>(),
+    ] {
+        hasher.update((size as u64).to_le_bytes());
+    }
+    let (program_vm_addr, text_bytes) = executable.get_text_bytes();
+    hasher.update(program_vm_addr.to_le_bytes());
+    hasher.update((text_bytes.len() as u64).to_le_bytes());
+    hasher.update(text_bytes);
+    let entrypoint = executable.get_entrypoint_instruction_offset().unwrap_or(0);
+    hasher.update((entrypoint as u64).to_le_bytes());
+    let bpf_functions = executable.get_bpf_functions();
+    hasher.update((bpf_functions.len() as u64).to_le_bytes());
+    for (hash, (pc, _name)) in bpf_functions.iter() {
+        hasher.update(hash.to_le_bytes());
+        hasher.update((*pc as u64).to_le_bytes());
+    }
+    let context_object_slots = executable.get_syscall_registry().get_context_object_slots();
+    hasher.update((context_object_slots.len() as u64).to_le_bytes());
+    for (hash, context_object_slot) in context_object_slots {
+        hasher.update(hash.to_le_bytes());
+        hasher.update((context_object_slot as u64).to_le_bytes());
+    }
+    let config = executable.get_config();
+    for value in [
+        config.max_call_depth as u64,
+        config.stack_frame_size as u64,
+        config.enable_stack_frame_gaps as u64,
+        config.instruction_meter_checkpoint_distance as u64,
+        config.enable_instruction_meter as u64,
+        config.enable_instruction_tracing as u64,
+        config.enable_memory_tracing as u64,
+        config.sampling_interval as u64,
+        config.optimize_jit as u64,
+        config.enable_bounds_check_elimination as u64,
+        config.enable_backtrace as u64,
+        config.disable_unresolved_symbols_at_runtime as u64,
+        config.noop_instruction_ratio.to_bits(),
+        config.sanitize_user_provided_values as u64,
+        config.encrypt_environment_registers as u64,
+        config.enable_ebpf_v4 as u64,
+        executable.get_sbf_version() as u64,
+    ] {
+        hasher.update(value.to_le_bytes());
+    }
+    hasher.finalize().into()
+}
+
+/// Layout of an entry:
+///
+/// ```text
+/// magic | digest of the rest | key | pc section length | text section length |
+/// relocation count | pc section | relocations | text section
+/// ```
+fn serialize<E: UserDefinedError, I: InstructionMeter>(
+    executable: &Executable<E, I>,
+    program: &JitProgram<E, I>,
+) -> io::Result<Vec<u8>> {
+    let pc_section = program.pc_section_offsets();
+    let text_section = program.position_independent_text_section();
+    let relocations = program.relocations();
+    let mut body = Vec::with_capacity(
+        DIGEST_SIZE
+            + 3 * mem::size_of::<u64>()
+            + pc_section.len() * mem::size_of::<u64>()
+            + relocations.len() * RELOCATION_SIZE
+            + text_section.len(),
+    );
+    body.write_all(&compute_key(executable))?;
+    body.write_u64::<LittleEndian>(pc_section.len() as u64)?;
+    body.write_u64::<LittleEndian>(text_section.len() as u64)?;
+    body.write_u64::<LittleEndian>(relocations.len() as u64)?;
+    for offset in pc_section.iter() {
+        body.write_u64::<LittleEndian>(*offset)?;
+    }
+    for (location, relocation) in relocations.iter() {
+        let (tag, payload) = relocation.encode();
+        body.write_u64::<LittleEndian>(*location as u64)?;
+        body.write_u8(tag)?;
+        body.write_u64::<LittleEndian>(payload)?;
+    }
+    body.write_all(&text_section)?;
+    let mut bytes = Vec::with_capacity(MAGIC.len() + DIGEST_SIZE + body.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&Sha256::digest(&body));
+    bytes.extend_from_slice(&body);
+    Ok(bytes)
+}
+
+/// Checks the integrity of an entry before mapping its machine code
+fn deserialize<E: UserDefinedError, I: InstructionMeter>(
+    executable: &Pin<Box<Executable<E, I>>>,
+    bytes: &[u8],
+) -> Result<JitProgram<E, I>, EbpfError<E>> {
+    let invalid = |reason: &str| EbpfError::InvalidJitCacheEntry(reason.to_string());
+    if bytes.len() < MAGIC.len() + DIGEST_SIZE || &bytes[0..MAGIC.len()] != MAGIC {
+        return Err(invalid("unknown format"));
+    }
+    let (digest, body) = bytes[MAGIC.len()..].split_at(DIGEST_SIZE);
+    if Sha256::digest(body).as_slice() != digest {
+        return Err(invalid("digest mismatch"));
+    }
+    let truncated = |_err: io::Error| invalid("truncated");
+    let mut reader = Cursor::new(body);
+    let mut key = [0u8; DIGEST_SIZE];
+    reader.read_exact(&mut key).map_err(truncated)?;
+    if key != compute_key(executable.as_ref().get_ref()) {
+        return Err(invalid("key mismatch"));
+    }
+    let pc_section_len = reader.read_u64::<LittleEndian>().map_err(truncated)? as usize;
+    let text_section_len = reader.read_u64::<LittleEndian>().map_err(truncated)? as usize;
+    let relocation_count = reader.read_u64::<LittleEndian>().map_err(truncated)? as usize;
+    let remaining = body.len() - reader.position() as usize;
+    if pc_section_len
+        .checked_mul(mem::size_of::<u64>())
+        .and_then(|size| size.checked_add(relocation_count.checked_mul(RELOCATION_SIZE)?))
+        .and_then(|size| size.checked_add(text_section_len))
+        != Some(remaining)
+    {
+        return Err(invalid("inconsistent section lengths"));
+    }
+    let pc_section = (0..pc_section_len)
+        .map(|_| reader.read_u64::<LittleEndian>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(truncated)?;
+    let relocations = (0..relocation_count)
+        .map(|_| {
+            let location = reader.read_u64::<LittleEndian>().map_err(truncated)? as usize;
+            let tag = reader.read_u8().map_err(truncated)?;
+            let payload = reader.read_u64::<LittleEndian>().map_err(truncated)?;
+            let relocation =
+                Relocation::decode(tag, payload).ok_or_else(|| invalid("unknown relocation"))?;
+            Ok((location, relocation))
+        })
+        .collect::<Result<Vec<_>, EbpfError<E>>>()?;
+    let text_section = &body[reader.position() as usize..];
+    JitProgram::from_parts(executable, &pc_section, text_section, relocations)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(all(test, target_arch = "x86_64", not(target_os = "windows")))]
+mod tests {
+    use super::*;
+    use crate::{
+        assembler::assemble,
+        syscalls::{BpfSyscallContext, BpfSyscallU64},
+        user_error::UserError,
+        vm::{Config, SyscallObject, SyscallRegistry, TestInstructionMeter},
+    };
+
+    fn create_executable(config: Config) -> Pin<Box<Executable<UserError, TestInstructionMeter>>> {
+        let mut syscall_registry = SyscallRegistry::default();
+        syscall_registry
+            .register_syscall_by_name(
+                b"BpfSyscallU64",
+                BpfSyscallU64::init::<BpfSyscallContext, UserError>,
+                BpfSyscallU64::call,
+            )
+            .unwrap();
+        assemble(
+            "
+            mov64 r1, 1
+            call function_foo
+            syscall BpfSyscallU64
+            exit
+        function_foo:
+            ldxdw r0, [r1]
+            exit",
+            None,
+            config,
+            syscall_registry,
+        )
+        .unwrap()
+    }
+
+    fn temporary_directory(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rbpf_jit_cache_{}_{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_store_and_load() {
+        let cache = JitCache::new(temporary_directory("store_and_load")).unwrap();
+        let mut executable = create_executable(Config::default());
+        assert!(cache.load(&executable).unwrap().is_none());
+        Executable::jit_compile(&mut executable).unwrap();
+        let program = executable.get_compiled_program().unwrap();
+        cache.store(&executable, program).unwrap();
+
+        let other_executable = create_executable(Config::default());
+        let loaded_program = cache.load(&other_executable).unwrap().unwrap();
+        assert_eq!(
+            loaded_program.machine_code_length(),
+            program.machine_code_length()
+        );
+        assert_eq!(
+            loaded_program.pc_section_offsets(),
+            program.pc_section_offsets()
+        );
+        assert_eq!(loaded_program.relocations(), program.relocations());
+        assert_eq!(
+            loaded_program.position_independent_text_section(),
+            program.position_independent_text_section()
+        );
+        fs::remove_dir_all(cache.get_directory()).unwrap();
+    }
+
+    #[test]
+    fn test_key() {
+        let cache = JitCache::new(temporary_directory("key")).unwrap();
+        let executable = create_executable(Config::default());
+        assert_eq!(
+            cache.get_entry_path(&executable),
+            cache.get_entry_path(&create_executable(Config::default()))
+        );
+        for config in [
+            Config {
+                enable_instruction_tracing: true,
+                ..Config::default()
+            },
+            Config {
+                max_call_depth: 10,
+                ..Config::default()
+            },
+            Config {
+                enable_ebpf_v4: !Config::default().enable_ebpf_v4,
+                ..Config::default()
+            },
+        ] {
+            assert_ne!(
+                cache.get_entry_path(&executable),
+                cache.get_entry_path(&create_executable(config))
+            );
+        }
+        // Fields which only matter to the interpreter or the loader do not invalidate entries
+        assert_eq!(
+            cache.get_entry_path(&executable),
+            cache.get_entry_path(&create_executable(Config {
+                enable_store_journal: true,
+                ..Config::default()
+            }))
+        );
+        fs::remove_dir_all(cache.get_directory()).unwrap();
+    }
+
+    #[test]
+    fn test_integrity() {
+        let cache = JitCache::new(temporary_directory("integrity")).unwrap();
+        let mut executable = create_executable(Config::default());
+        Executable::jit_compile(&mut executable).unwrap();
+        cache
+            .store(&executable, executable.get_compiled_program().unwrap())
+            .unwrap();
+        let path = cache.get_entry_path(&executable);
+        let bytes = fs::read(&path).unwrap();
+
+        let mut corrupted = bytes.clone();
+        *corrupted.last_mut().unwrap() ^= 0xFF;
+        fs::write(&path, &corrupted).unwrap();
+        assert!(matches!(
+            cache.load(&executable),
+            Err(EbpfError::InvalidJitCacheEntry(reason)) if reason == "digest mismatch"
+        ));
+
+        fs::write(&path, &bytes[0..bytes.len() / 2]).unwrap();
+        assert!(matches!(
+            cache.load(&executable),
+            Err(EbpfError::InvalidJitCacheEntry(reason)) if reason == "digest mismatch"
+        ));
+
+        fs::write(&path, &bytes[0..4]).unwrap();
+        assert!(matches!(
+            cache.load(&executable),
+            Err(EbpfError::InvalidJitCacheEntry(reason)) if reason == "unknown format"
+        ));
+
+        // An intact entry of another executable is rejected as well
+        let other_executable = create_executable(Config {
+            enable_instruction_tracing: true,
+            ..Config::default()
+        });
+        assert!(matches!(
+            deserialize(&other_executable, &bytes),
+            Err(EbpfError::InvalidJitCacheEntry(reason)) if reason == "key mismatch"
+        ));
+        fs::remove_dir_all(cache.get_directory()).unwrap();
+    }
+}
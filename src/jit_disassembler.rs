@@ -0,0 +1,664 @@
+#![allow(clippy::integer_arithmetic)]
+//! Disassembler for the x86-64 machine code emitted by the JIT
+//!
+//! Decodes the subset of x86-64 which the JIT emits (integer instructions with REX, ModRM and SIB
+//! encodings) and prints it in Intel syntax, interleaved with the eBPF instructions it was
+//! compiled from. Relative jumps and calls are resolved to offsets in the text section. Bytes
+//! which are not understood are printed as `.byte`.
+
+use crate::{
+    disassembler::disassemble_instruction, error::UserDefinedError, static_analysis::Analysis,
+    vm::InstructionMeter,
+};
+use std::io::{Error, ErrorKind, Write};
+
+const REGISTER_NAMES_64: [&str; 16] = [
+    "rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15",
+];
+const REGISTER_NAMES_32: [&str; 16] = [
+    "eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi", "r8d", "r9d", "r10d", "r11d", "r12d",
+    "r13d", "r14d", "r15d",
+];
+const REGISTER_NAMES_16: [&str; 16] = [
+    "ax", "cx", "dx", "bx", "sp", "bp", "si", "di", "r8w", "r9w", "r10w", "r11w", "r12w", "r13w",
+    "r14w", "r15w",
+];
+const REGISTER_NAMES_8: [&str; 16] = [
+    "al", "cl", "dl", "bl", "spl", "bpl", "sil", "dil", "r8b", "r9b", "r10b", "r11b", "r12b",
+    "r13b", "r14b", "r15b",
+];
+const REGISTER_NAMES_8_LEGACY: [&str; 8] = ["al", "cl", "dl", "bl", "ah", "ch", "dh", "bh"];
+const ALU_MNEMONICS: [&str; 8] = ["add", "or", "adc", "sbb", "and", "sub", "xor", "cmp"];
+const SHIFT_MNEMONICS: [&str; 8] = ["rol", "ror", "rcl", "rcr", "shl", "shr", "sal", "sar"];
+const UNARY_MNEMONICS: [&str; 8] = ["test", "test", "not", "neg", "mul", "imul", "div", "idiv"];
+const CONDITION_CODES: [&str; 16] = [
+    "o", "no", "b", "ae", "e", "ne", "be", "a", "s", "ns", "p", "np", "l", "ge", "le", "g",
+];
+
+/// Width of an operand in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Width {
+    Byte = 1,
+    Word = 2,
+    Dword = 4,
+    Qword = 8,
+}
+
+impl Width {
+    fn register_name(self, register: u8, has_rex: bool) -> &'static str {
+        match self {
+            Width::Byte if !has_rex && register < 8 => REGISTER_NAMES_8_LEGACY[register as usize],
+            Width::Byte => REGISTER_NAMES_8[register as usize],
+            Width::Word => REGISTER_NAMES_16[register as usize],
+            Width::Dword => REGISTER_NAMES_32[register as usize],
+            Width::Qword => REGISTER_NAMES_64[register as usize],
+        }
+    }
+
+    fn pointer_name(self) -> &'static str {
+        match self {
+            Width::Byte => "byte ptr",
+            Width::Word => "word ptr",
+            Width::Dword => "dword ptr",
+            Width::Qword => "qword ptr",
+        }
+    }
+}
+
+/// Reads the bytes of one instruction
+struct Decoder<'a> {
+    machine_code: &'a [u8],
+    offset: usize,
+    rex: u8,
+    has_rex: bool,
+    operand_size_prefix: bool,
+    lock_prefix: bool,
+}
+
+/// Decoded ModRM byte (and SIB byte and displacement)
+struct ModRm {
+    /// The reg field, extended by REX.R
+    reg: u8,
+    /// The register or memory operand
+    rm: Operand,
+}
+
+enum Operand {
+    Register(u8),
+    Memory(String),
+}
+
+impl<'a> Decoder<'a> {
+    fn byte(&mut self) -> Option<u8> {
+        let byte = *self.machine_code.get(self.offset)?;
+        self.offset += 1;
+        Some(byte)
+    }
+
+    fn immediate(&mut self, width: Width) -> Option<i64> {
+        let bytes = self
+            .machine_code
+            .get(self.offset..self.offset + width as usize)?;
+        self.offset += width as usize;
+        let mut value = [0u8; 8];
+        value[..bytes.len()].copy_from_slice(bytes);
+        let value = u64::from_le_bytes(value);
+        let shift = 64 - 8 * width as u32;
+        Some(((value << shift) as i64) >> shift)
+    }
+
+    fn rex_w(&self) -> bool {
+        self.rex & 0b1000 != 0
+    }
+
+    fn operand_width(&self) -> Width {
+        if self.rex_w() {
+            Width::Qword
+        } else if self.operand_size_prefix {
+            Width::Word
+        } else {
+            Width::Dword
+        }
+    }
+
+    fn modrm(&mut self) -> Option<ModRm> {
+        let modrm = self.byte()?;
+        let mode = modrm >> 6;
+        let reg = (modrm >> 3) & 0b111 | (self.rex & 0b100) << 1;
+        let rm = modrm & 0b111;
+        if mode == 3 {
+            return Some(ModRm {
+                reg,
+                rm: Operand::Register(rm | (self.rex & 0b1) << 3),
+            });
+        }
+        let mut address = String::new();
+        let mut rip_relative = false;
+        if rm == 0b100 {
+            let sib = self.byte()?;
+            let base = sib & 0b111;
+            let index = (sib >> 3) & 0b111 | (self.rex & 0b10) << 2;
+            if !(base == 0b101 && mode == 0) {
+                address += REGISTER_NAMES_64[(base | (self.rex & 0b1) << 3) as usize];
+            }
+            if index != 0b100 {
+                if !address.is_empty() {
+                    address += "+";
+                }
+                address += REGISTER_NAMES_64[index as usize];
+                if sib >> 6 != 0 {
+                    address += &format!("*{}", 1 << (sib >> 6));
+                }
+            }
+            if base == 0b101 && mode == 0 {
+                address += &format_displacement(self.immediate(Width::Dword)?, address.is_empty());
+            }
+        } else if rm == 0b101 && mode == 0 {
+            rip_relative = true;
+        } else {
+            address += REGISTER_NAMES_64[(rm | (self.rex & 0b1) << 3) as usize];
+        }
+        match mode {
+            0 if rip_relative => {
+                address += "rip";
+                address += &format_displacement(self.immediate(Width::Dword)?, false);
+            }
+            1 => address += &format_displacement(self.immediate(Width::Byte)?, address.is_empty()),
+            2 => address += &format_displacement(self.immediate(Width::Dword)?, address.is_empty()),
+            _ => {}
+        }
+        Some(ModRm {
+            reg,
+            rm: Operand::Memory(format!("[{}]", address)),
+        })
+    }
+
+    fn register(&self, register: u8, width: Width) -> String {
+        width.register_name(register, self.has_rex).to_string()
+    }
+
+    fn operand(&self, operand: &Operand, width: Width) -> String {
+        match operand {
+            Operand::Register(register) => self.register(*register, width),
+            Operand::Memory(address) => format!("{} {}", width.pointer_name(), address),
+        }
+    }
+
+    fn relative_target(&mut self, width: Width) -> Option<String> {
+        let displacement = self.immediate(width)?;
+        Some(format!(
+            "{:#x}",
+            (self.offset as i64).wrapping_add(displacement)
+        ))
+    }
+
+    fn instruction(&mut self) -> Option<String> {
+        loop {
+            match self.machine_code.get(self.offset)? {
+                0x66 => self.operand_size_prefix = true,
+                0xf0 => self.lock_prefix = true,
+                0x40..=0x4f => {
+                    self.rex = self.machine_code[self.offset] & 0b1111;
+                    self.has_rex = true;
+                    self.offset += 1;
+                    break;
+                }
+                _ => break,
+            }
+            self.offset += 1;
+        }
+        let opcode = self.byte()?;
+        let width = self.operand_width();
+        Some(match opcode {
+            0x0f => return self.escaped_instruction(),
+            0x00..=0x3f if opcode & 0b111 < 6 => {
+                let mnemonic = ALU_MNEMONICS[(opcode >> 3) as usize];
+                let width = if opcode & 1 == 0 { Width::Byte } else { width };
+                match opcode & 0b110 {
+                    0b000 => {
+                        let modrm = self.modrm()?;
+                        format!(
+                            "{} {}, {}",
+                            mnemonic,
+                            self.operand(&modrm.rm, width),
+                            self.register(modrm.reg, width)
+                        )
+                    }
+                    0b010 => {
+                        let modrm = self.modrm()?;
+                        format!(
+                            "{} {}, {}",
+                            mnemonic,
+                            self.register(modrm.reg, width),
+                            self.operand(&modrm.rm, width)
+                        )
+                    }
+                    _ => {
+                        let immediate = self.immediate(width.min(Width::Dword))?;
+                        format!(
+                            "{} {}, {}",
+                            mnemonic,
+                            self.register(0, width),
+                            format_immediate(immediate)
+                        )
+                    }
+                }
+            }
+            0x50..=0x57 => format!(
+                "push {}",
+                REGISTER_NAMES_64[(opcode & 0b111 | (self.rex & 0b1) << 3) as usize]
+            ),
+            0x58..=0x5f => format!(
+                "pop {}",
+                REGISTER_NAMES_64[(opcode & 0b111 | (self.rex & 0b1) << 3) as usize]
+            ),
+            0x63 => {
+                let modrm = self.modrm()?;
+                format!(
+                    "movsxd {}, {}",
+                    self.register(modrm.reg, width),
+                    self.operand(&modrm.rm, Width::Dword)
+                )
+            }
+            0x68 => format!("push {}", format_immediate(self.immediate(Width::Dword)?)),
+            0x6a => format!("push {}", format_immediate(self.immediate(Width::Byte)?)),
+            0x69 | 0x6b => {
+                let modrm = self.modrm()?;
+                let immediate = self.immediate(if opcode == 0x69 {
+                    width.min(Width::Dword)
+                } else {
+                    Width::Byte
+                })?;
+                format!(
+                    "imul {}, {}, {}",
+                    self.register(modrm.reg, width),
+                    self.operand(&modrm.rm, width),
+                    format_immediate(immediate)
+                )
+            }
+            0x70..=0x7f => format!(
+                "j{} {}",
+                CONDITION_CODES[(opcode & 0xf) as usize],
+                self.relative_target(Width::Byte)?
+            ),
+            0x80 | 0x81 | 0x83 => {
+                let modrm = self.modrm()?;
+                let width = if opcode == 0x80 { Width::Byte } else { width };
+                let immediate = self.immediate(if opcode == 0x81 {
+                    width.min(Width::Dword)
+                } else {
+                    Width::Byte
+                })?;
+                format!(
+                    "{} {}, {}",
+                    ALU_MNEMONICS[(modrm.reg & 0b111) as usize],
+                    self.operand(&modrm.rm, width),
+                    format_immediate(immediate)
+                )
+            }
+            0x84..=0x89 => {
+                let modrm = self.modrm()?;
+                let width = if opcode & 1 == 0 { Width::Byte } else { width };
+                let mnemonic = ["test", "xchg", "mov"][(opcode as usize - 0x84) / 2];
+                format!(
+                    "{} {}, {}",
+                    mnemonic,
+                    self.operand(&modrm.rm, width),
+                    self.register(modrm.reg, width)
+                )
+            }
+            0x8a | 0x8b => {
+                let modrm = self.modrm()?;
+                let width = if opcode == 0x8a { Width::Byte } else { width };
+                format!(
+                    "mov {}, {}",
+                    self.register(modrm.reg, width),
+                    self.operand(&modrm.rm, width)
+                )
+            }
+            0x8d => match self.modrm()? {
+                ModRm {
+                    reg,
+                    rm: Operand::Memory(address),
+                } => format!("lea {}, {}", self.register(reg, width), address),
+                _ => return None,
+            },
+            0x90 if self.rex & 0b1 == 0 => "nop".to_string(),
+            0x98 => if self.rex_w() { "cdqe" } else { "cwde" }.to_string(),
+            0x99 => if self.rex_w() { "cqo" } else { "cdq" }.to_string(),
+            0xb8..=0xbf => {
+                let register = opcode & 0b111 | (self.rex & 0b1) << 3;
+                let immediate = self.immediate(width)?;
+                if width == Width::Qword {
+                    // Mostly host addresses, so they are shown unsigned
+                    format!(
+                        "movabs {}, {:#x}",
+                        self.register(register, width),
+                        immediate
+                    )
+                } else {
+                    format!(
+                        "mov {}, {}",
+                        self.register(register, width),
+                        format_immediate(immediate)
+                    )
+                }
+            }
+            0xc0 | 0xc1 | 0xd0 | 0xd1 | 0xd2 | 0xd3 => {
+                let modrm = self.modrm()?;
+                let width = if opcode & 1 == 0 { Width::Byte } else { width };
+                let count = match opcode {
+                    0xc0 | 0xc1 => format!("{:#x}", self.immediate(Width::Byte)?),
+                    0xd0 | 0xd1 => "1".to_string(),
+                    _ => "cl".to_string(),
+                };
+                format!(
+                    "{} {}, {}",
+                    SHIFT_MNEMONICS[(modrm.reg & 0b111) as usize],
+                    self.operand(&modrm.rm, width),
+                    count
+                )
+            }
+            0xc3 => "ret".to_string(),
+            0xc6 | 0xc7 => {
+                let modrm = self.modrm()?;
+                let width = if opcode == 0xc6 { Width::Byte } else { width };
+                let immediate = self.immediate(width.min(Width::Dword))?;
+                format!(
+                    "mov {}, {}",
+                    self.operand(&modrm.rm, width),
+                    format_immediate(immediate)
+                )
+            }
+            0xcc => "int3".to_string(),
+            0xcd => format!("int {:#x}", self.immediate(Width::Byte)? as u8),
+            0xe8 => format!("call {}", self.relative_target(Width::Dword)?),
+            0xe9 => format!("jmp {}", self.relative_target(Width::Dword)?),
+            0xeb => format!("jmp {}", self.relative_target(Width::Byte)?),
+            0xf6 | 0xf7 => {
+                let modrm = self.modrm()?;
+                let width = if opcode == 0xf6 { Width::Byte } else { width };
+                let operand = self.operand(&modrm.rm, width);
+                if modrm.reg & 0b110 == 0 {
+                    let immediate = self.immediate(width.min(Width::Dword))?;
+                    format!("test {}, {}", operand, format_immediate(immediate))
+                } else {
+                    format!(
+                        "{} {}",
+                        UNARY_MNEMONICS[(modrm.reg & 0b111) as usize],
+                        operand
+                    )
+                }
+            }
+            0xff => {
+                let modrm = self.modrm()?;
+                match modrm.reg & 0b111 {
+                    0 => format!("inc {}", self.operand(&modrm.rm, width)),
+                    1 => format!("dec {}", self.operand(&modrm.rm, width)),
+                    2 => format!("call {}", self.operand(&modrm.rm, Width::Qword)),
+                    4 => format!("jmp {}", self.operand(&modrm.rm, Width::Qword)),
+                    6 => format!("push {}", self.operand(&modrm.rm, Width::Qword)),
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        })
+    }
+
+    /// Instructions with the 0x0f escape byte
+    fn escaped_instruction(&mut self) -> Option<String> {
+        let opcode = self.byte()?;
+        let width = self.operand_width();
+        Some(match opcode {
+            0x0b => "ud2".to_string(),
+            0x1f => {
+                let modrm = self.modrm()?;
+                format!("nop {}", self.operand(&modrm.rm, width))
+            }
+            0x31 => "rdtsc".to_string(),
+            0x40..=0x4f => {
+                let modrm = self.modrm()?;
+                format!(
+                    "cmov{} {}, {}",
+                    CONDITION_CODES[(opcode & 0xf) as usize],
+                    self.register(modrm.reg, width),
+                    self.operand(&modrm.rm, width)
+                )
+            }
+            0x80..=0x8f => format!(
+                "j{} {}",
+                CONDITION_CODES[(opcode & 0xf) as usize],
+                self.relative_target(Width::Dword)?
+            ),
+            0xae => match self.byte()? {
+                0xe8 => "lfence".to_string(),
+                0xf0 => "mfence".to_string(),
+                0xf8 => "sfence".to_string(),
+                _ => return None,
+            },
+            0xaf => {
+                let modrm = self.modrm()?;
+                format!(
+                    "imul {}, {}",
+                    self.register(modrm.reg, width),
+                    self.operand(&modrm.rm, width)
+                )
+            }
+            0xb1 | 0xc1 => {
+                let modrm = self.modrm()?;
+                format!(
+                    "{} {}, {}",
+                    if opcode == 0xb1 { "cmpxchg" } else { "xadd" },
+                    self.operand(&modrm.rm, width),
+                    self.register(modrm.reg, width)
+                )
+            }
+            0xb6 | 0xb7 | 0xbe | 0xbf => {
+                let modrm = self.modrm()?;
+                format!(
+                    "{} {}, {}",
+                    if opcode < 0xb8 { "movzx" } else { "movsx" },
+                    self.register(modrm.reg, width),
+                    self.operand(
+                        &modrm.rm,
+                        if opcode & 1 == 0 {
+                            Width::Byte
+                        } else {
+                            Width::Word
+                        }
+                    )
+                )
+            }
+            0xc8..=0xcf => format!(
+                "bswap {}",
+                self.register(opcode & 0b111 | (self.rex & 0b1) << 3, width)
+            ),
+            _ => return None,
+        })
+    }
+}
+
+fn format_displacement(displacement: i64, is_absolute: bool) -> String {
+    if is_absolute {
+        format!("{:#x}", displacement as u32)
+    } else if displacement < 0 {
+        format!("-{:#x}", displacement.unsigned_abs())
+    } else {
+        format!("+{:#x}", displacement)
+    }
+}
+
+fn format_immediate(immediate: i64) -> String {
+    if immediate < 0 {
+        format!("-{:#x}", immediate.unsigned_abs())
+    } else {
+        format!("{:#x}", immediate)
+    }
+}
+
+/// Disassembles the instruction at `offset` in `machine_code`
+///
+/// Returns the length of the instruction in bytes and its Intel syntax.
+/// Targets of relative jumps and calls are given as offsets into `machine_code`.
+pub fn disassemble_x86_instruction(machine_code: &[u8], offset: usize) -> (usize, String) {
+    let mut decoder = Decoder {
+        machine_code,
+        offset,
+        rex: 0,
+        has_rex: false,
+        operand_size_prefix: false,
+        lock_prefix: false,
+    };
+    match decoder.instruction() {
+        Some(text) if decoder.lock_prefix => (decoder.offset - offset, format!("lock {}", text)),
+        Some(text) => (decoder.offset - offset, text),
+        None => (1, format!(".byte {:#04x}", machine_code[offset])),
+    }
+}
+
+/// Writes the machine code of each eBPF instruction, annotated with its eBPF disassembly
+///
+/// Fails if the executable of the analysis was not JIT compiled.
+pub fn disassemble_jit_program<E: UserDefinedError, I: InstructionMeter, W: Write>(
+    analysis: &Analysis<E, I>,
+    output: &mut W,
+) -> std::io::Result<()> {
+    let compiled_program = analysis.executable.get_compiled_program().ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            "the executable was not JIT compiled",
+        )
+    })?;
+    let machine_code = compiled_program.machine_code();
+    let machine_code_ranges = compiled_program.machine_code_ranges();
+    let mut last_basic_block = usize::MAX;
+    for insn in analysis.instructions.iter() {
+        analysis.disassemble_label(
+            output,
+            Some(insn) == analysis.instructions.first(),
+            insn.ptr,
+            &mut last_basic_block,
+        )?;
+        writeln!(output, "    {}", disassemble_instruction(insn, analysis))?;
+        let range = &machine_code_ranges[insn.ptr];
+        let mut offset = range.start;
+        while offset < range.end {
+            let (length, text) = disassemble_x86_instruction(machine_code, offset);
+            let length = length.min(range.end - offset);
+            let bytes = machine_code[offset..offset + length]
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<Vec<_>>()
+                .join(" ");
+            writeln!(output, "        {:08x}:  {:<32} {}", offset, bytes, text)?;
+            offset += length;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disassemble(machine_code: &[u8]) -> String {
+        let (length, text) = disassemble_x86_instruction(machine_code, 0);
+        assert_eq!(length, machine_code.len(), "{}", text);
+        text
+    }
+
+    #[test]
+    fn test_disassemble_x86_instruction() {
+        assert_eq!(disassemble(&[0x48, 0x89, 0xc7]), "mov rdi, rax");
+        assert_eq!(disassemble(&[0x4d, 0x89, 0xd8]), "mov r8, r11");
+        assert_eq!(
+            disassemble(&[0x4c, 0x8b, 0x5d, 0xf8]),
+            "mov r11, qword ptr [rbp-0x8]"
+        );
+        assert_eq!(
+            disassemble(&[0x4a, 0x8b, 0x84, 0xd9, 0x10, 0x00, 0x00, 0x00]),
+            "mov rax, qword ptr [rcx+r11*8+0x10]"
+        );
+        assert_eq!(
+            disassemble(&[0x0f, 0xb6, 0x44, 0x24, 0x08]),
+            "movzx eax, byte ptr [rsp+0x8]"
+        );
+        assert_eq!(disassemble(&[0x41, 0x88, 0x03]), "mov byte ptr [r11], al");
+        assert_eq!(
+            disassemble(&[0x66, 0x41, 0x89, 0x03]),
+            "mov word ptr [r11], ax"
+        );
+        assert_eq!(
+            disassemble(&[0x48, 0xc7, 0x45, 0x10, 0xff, 0xff, 0xff, 0xff]),
+            "mov qword ptr [rbp+0x10], -0x1"
+        );
+        assert_eq!(
+            disassemble(&[0x49, 0xbb, 0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11]),
+            "movabs r11, 0x1122334455667788"
+        );
+        assert_eq!(
+            disassemble(&[0x48, 0xb8, 0xf0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+            "movabs rax, 0xfffffffffffffff0"
+        );
+        assert_eq!(
+            disassemble(&[0x48, 0x81, 0xc4, 0x08, 0x00, 0x00, 0x00]),
+            "add rsp, 0x8"
+        );
+        assert_eq!(disassemble(&[0x49, 0xc1, 0xeb, 0x20]), "shr r11, 0x20");
+        assert_eq!(disassemble(&[0x48, 0xd3, 0xe0]), "shl rax, cl");
+        assert_eq!(disassemble(&[0x48, 0xf7, 0xd8]), "neg rax");
+        assert_eq!(disassemble(&[0x48, 0x99]), "cqo");
+        assert_eq!(disassemble(&[0x41, 0x53]), "push r11");
+        assert_eq!(disassemble(&[0x5d]), "pop rbp");
+        assert_eq!(disassemble(&[0xc3]), "ret");
+        assert_eq!(disassemble(&[0x90]), "nop");
+        assert_eq!(disassemble(&[0x0f, 0xc8]), "bswap eax");
+        assert_eq!(disassemble(&[0x0f, 0xae, 0xe8]), "lfence");
+        assert_eq!(
+            disassemble(&[0xf0, 0x49, 0x0f, 0xc1, 0x0b]),
+            "lock xadd qword ptr [r11], rcx"
+        );
+        assert_eq!(
+            disassemble(&[0xf0, 0x41, 0x0f, 0xb1, 0x13]),
+            "lock cmpxchg dword ptr [r11], edx"
+        );
+        assert_eq!(
+            disassemble(&[0xf0, 0x41, 0x09, 0x33]),
+            "lock or dword ptr [r11], esi"
+        );
+        assert_eq!(
+            disassemble(&[0x4c, 0x3b, 0x5f, 0x08]),
+            "cmp r11, qword ptr [rdi+0x8]"
+        );
+        assert_eq!(disassemble(&[0x41, 0xff, 0xd3]), "call r11");
+    }
+
+    #[test]
+    fn test_disassemble_relative_targets() {
+        let machine_code = [
+            0x90, 0xe8, 0xfa, 0xff, 0xff, 0xff, 0x0f, 0x84, 0x00, 0x01, 0x00, 0x00,
+        ];
+        assert_eq!(
+            disassemble_x86_instruction(&machine_code, 1),
+            (5, "call 0x0".to_string())
+        );
+        assert_eq!(
+            disassemble_x86_instruction(&machine_code, 6),
+            (6, "je 0x10c".to_string())
+        );
+    }
+
+    #[test]
+    fn test_disassemble_unknown_bytes() {
+        assert_eq!(
+            disassemble_x86_instruction(&[0x0f, 0x0f], 0),
+            (1, ".byte 0x0f".to_string())
+        );
+        // Truncated instruction
+        assert_eq!(
+            disassemble_x86_instruction(&[0xe8, 0x00], 0),
+            (1, ".byte 0xe8".to_string())
+        );
+    }
+}
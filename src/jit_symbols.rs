@@ -0,0 +1,160 @@
+#![allow(clippy::integer_arithmetic)]
+//! Symbols of JIT-compiled programs for native profilers and debuggers
+//!
+//! The machine code emitted by the JIT is invisible to tools which only know about the mapped
+//! object files of a process. Two mechanisms can announce it:
+//!
+//! - With [Config::enable_perf_map](crate::vm::Config::enable_perf_map) the host address range
+//!   of every BPF function is appended to [perf_map_path], which `perf report` picks up.
+//! - With [Config::enable_gdb_jit_interface](crate::vm::Config::enable_gdb_jit_interface) an
+//!   in-memory ELF object, which describes the functions of the program as symbols of a `.text`
+//!   section at the address of the machine code, is registered with the GDB JIT interface. It is
+//!   unregistered again when the compiled program is dropped. This requires the
+//!   `gdb-jit-interface` feature, which exports the symbols of the interface.
+//!
+//! The code in front of and behind the BPF functions (entry, exception handlers and helper
+//! routines) is announced as [RUNTIME_SYMBOL].
+
+use crate::{elf::Executable, error::UserDefinedError, jit::JitProgram, vm::InstructionMeter};
+use rustc_demangle::demangle;
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    ops::Range,
+    path::PathBuf,
+};
+
+/// Name of the machine code which does not belong to any BPF function
+pub const RUNTIME_SYMBOL: &str = "solana_rbpf_jit_runtime";
+
+/// Returns the path of the perf map of the current process
+pub fn perf_map_path() -> PathBuf {
+    PathBuf::from(format!("/tmp/perf-{}.map", std::process::id()))
+}
+
+/// Returns the symbol names and their ranges in the machine code of a compiled program
+pub(crate) fn function_symbols<E: UserDefinedError, I: InstructionMeter>(
+    executable: &Executable<E, I>,
+    compiled_program: &JitProgram<E, I>,
+) -> Vec<(String, Range<usize>)> {
+    let machine_code_ranges = compiled_program.machine_code_ranges();
+    let begin_of_program = machine_code_ranges.first().map_or(0, |range| range.start);
+    let end_of_program = machine_code_ranges
+        .last()
+        .map_or(begin_of_program, |range| range.end);
+    let mut functions = executable
+        .get_function_symbols()
+        .into_iter()
+        .filter(|(pc, _)| *pc < machine_code_ranges.len())
+        .map(|(pc, (_hash, name))| (pc, demangle(&name).to_string()))
+        .collect::<Vec<_>>();
+    if functions.first().map(|(pc, _name)| *pc) != Some(0) {
+        functions.insert(0, (0, String::new()));
+    }
+    // Without symbol labels the functions are unnamed
+    for (pc, name) in functions.iter_mut() {
+        if name.is_empty() {
+            *name = format!("function_{}", pc);
+        }
+    }
+    let mut symbols = vec![(RUNTIME_SYMBOL.to_string(), 0..begin_of_program)];
+    for (index, (pc, name)) in functions.iter().enumerate() {
+        let start = machine_code_ranges[*pc].start;
+        let end = functions
+            .get(index + 1)
+            .map_or(end_of_program, |(next_pc, _name)| {
+                machine_code_ranges[*next_pc].start
+            });
+        symbols.push((name.clone(), start..end));
+    }
+    symbols.push((
+        RUNTIME_SYMBOL.to_string(),
+        end_of_program..compiled_program.machine_code_length(),
+    ));
+    symbols.retain(|(_name, range)| !range.is_empty());
+    symbols
+}
+
+/// Appends the symbols of the machine code at `text_section_address` to the perf map
+pub(crate) fn write_perf_map(
+    text_section_address: u64,
+    symbols: &[(String, Range<usize>)],
+) -> io::Result<()> {
+    let mut lines = String::new();
+    for (name, range) in symbols {
+        lines += &format!(
+            "{:x} {:x} {}\n",
+            text_section_address + range.start as u64,
+            range.len(),
+            name
+        );
+    }
+    // A single write, so that concurrent compilations do not interleave their lines
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(perf_map_path())?
+        .write_all(lines.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(all(target_arch = "x86_64", not(target_os = "windows")))]
+    fn test_compiled_program_symbols() {
+        use crate::{
+            assembler::assemble,
+            user_error::UserError,
+            vm::{Config, SyscallRegistry, TestInstructionMeter},
+        };
+        let config = Config {
+            enable_perf_map: true,
+            enable_symbol_and_section_labels: true,
+            ..Config::default()
+        };
+        let mut executable = assemble::<UserError, TestInstructionMeter>(
+            "
+            mov64 r1, 1
+            call function_foo
+            exit
+        function_foo:
+            mov64 r0, r1
+            exit",
+            None,
+            config,
+            SyscallRegistry::default(),
+        )
+        .unwrap();
+        Executable::jit_compile(&mut executable).unwrap();
+        let compiled_program = executable.get_compiled_program().unwrap();
+        let text_section_address = compiled_program.machine_code().as_ptr() as u64;
+        let symbols = function_symbols(&executable, compiled_program);
+        assert_eq!(
+            symbols
+                .iter()
+                .map(|(name, _range)| name.as_str())
+                .collect::<Vec<_>>(),
+            vec![RUNTIME_SYMBOL, "entrypoint", "function_foo", RUNTIME_SYMBOL]
+        );
+        // The symbols cover the machine code without gaps
+        assert_eq!(symbols.first().unwrap().1.start, 0);
+        assert_eq!(
+            symbols.last().unwrap().1.end,
+            compiled_program.machine_code_length()
+        );
+        for pair in symbols.windows(2) {
+            assert_eq!(pair[0].1.end, pair[1].1.start);
+        }
+
+        let perf_map = std::fs::read_to_string(perf_map_path()).unwrap();
+        let (name, range) = &symbols[2];
+        assert!(perf_map.contains(&format!(
+            "{:x} {:x} {}\n",
+            text_section_address + range.start as u64,
+            range.len(),
+            name
+        )));
+    }
+}
@@ -23,21 +23,40 @@ extern crate log;
 extern crate rand;
 extern crate thiserror;
 
+pub mod abstract_verifier;
 pub mod aligned_memory;
 mod asm_parser;
 pub mod assembler;
+pub mod backtrace;
 pub mod call_frames;
+pub mod coverage;
+pub mod debug_info;
+pub mod debugger;
 pub mod disassembler;
 pub mod ebpf;
 pub mod elf;
 pub mod error;
 pub mod fuzz;
+#[cfg(feature = "gdb-jit-interface")]
+mod gdb_jit_interface;
+pub mod gdb_stub;
 pub mod insn_builder;
+#[macro_use]
 pub mod interpreter;
 mod jit;
+pub mod jit_cache;
+pub mod jit_disassembler;
+pub mod jit_symbols;
 pub mod memory_region;
+pub mod profiler;
+pub mod replay;
+pub mod sampler;
+pub mod snapshot;
 pub mod static_analysis;
 pub mod syscalls;
+pub mod threaded_interpreter;
+pub mod tiering;
+pub mod trace_format;
 pub mod user_error;
 pub mod verifier;
 pub mod vm;
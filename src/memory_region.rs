@@ -1,7 +1,7 @@
 //! This module defines memory regions
 
 use crate::{
-    ebpf,
+    ebpf::{self, SbfVersion},
     error::{EbpfError, UserDefinedError},
     vm::Config,
 };
@@ -162,12 +162,15 @@ pub struct MemoryMapping<'a> {
     regions: Box<[MemoryRegion]>,
     /// VM configuration
     config: &'a Config,
+    /// SBF version of the executable
+    sbf_version: SbfVersion,
 }
 impl<'a> MemoryMapping<'a> {
     /// Creates a new MemoryMapping structure from the given regions
     pub fn new<E: UserDefinedError>(
         mut regions: Vec<MemoryRegion>,
         config: &'a Config,
+        sbf_version: SbfVersion,
     ) -> Result<Self, EbpfError<E>> {
         regions.sort();
         for (index, region) in regions.iter().enumerate() {
@@ -183,6 +186,7 @@ impl<'a> MemoryMapping<'a> {
         Ok(Self {
             regions: regions.into_boxed_slice(),
             config,
+            sbf_version,
         })
     }
 
@@ -218,7 +222,7 @@ impl<'a> MemoryMapping<'a> {
             .saturating_sub(ebpf::MM_STACK_START as i64)
             .checked_div(self.config.stack_frame_size as i64)
             .unwrap_or(0);
-        if !self.config.dynamic_stack_frames
+        if !self.sbf_version.dynamic_stack_frames()
             && (-1..(self.config.max_call_depth as i64).saturating_add(1)).contains(&stack_frame)
         {
             Err(EbpfError::StackAccessViolation(
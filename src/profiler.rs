@@ -0,0 +1,215 @@
+#![allow(clippy::integer_arithmetic)]
+//! Per-function profile of a recorded trace
+//!
+//! The call tree is reconstructed from the instruction log of a [Tracer]: A call
+//! instruction is followed by the entry of the callee and an exit returns to the
+//! caller. Instructions executed inside syscalls are not traced and thus not
+//! attributed to any function.
+
+use crate::{
+    ebpf,
+    error::UserDefinedError,
+    static_analysis::Analysis,
+    trace_format::write_varint,
+    vm::{InstructionMeter, Tracer},
+};
+use std::{collections::BTreeMap, io::Write};
+
+/// Statistics of a single function
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FunctionProfile {
+    /// Symbol name
+    pub name: String,
+    /// How often the function was entered
+    pub call_count: u64,
+    /// Instructions executed in the function itself
+    pub exclusive_insn_count: u64,
+    /// Instructions executed in the function and all of its callees
+    pub inclusive_insn_count: u64,
+}
+
+/// Call-tree profile accumulated from a trace
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Profile {
+    /// Statistics indexed by the instruction index of the function entry
+    pub functions: BTreeMap<usize, FunctionProfile>,
+    /// Exclusive instruction counts indexed by the call stack (from the root to the leaf)
+    pub stacks: BTreeMap<Vec<usize>, u64>,
+    /// Maximal call depth reached, the root function has depth 1
+    pub max_depth: usize,
+    /// Number of traced instructions
+    pub total_insn_count: u64,
+    /// Virtual address of the first instruction of the text section
+    pub program_vm_addr: u64,
+}
+
+impl Profile {
+    /// Accumulates a trace
+    pub fn new<E: UserDefinedError, I: InstructionMeter>(
+        tracer: &Tracer,
+        analysis: &Analysis<E, I>,
+    ) -> Self {
+        let (program_vm_addr, program) = analysis.executable.get_text_bytes();
+        let static_syscalls = analysis.executable.get_sbf_version().static_syscalls();
+        let syscall_registry = analysis.executable.get_syscall_registry();
+        let mut result = Self {
+            program_vm_addr,
+            ..Self::default()
+        };
+        let mut call_stack: Vec<usize> = Vec::new();
+        for (index, traced_instruction) in tracer.log.iter().enumerate() {
+            let pc = traced_instruction[11] as usize;
+            if call_stack.is_empty() {
+                let function = analysis
+                    .functions
+                    .range(..=pc)
+                    .next_back()
+                    .map(|(function, _)| *function)
+                    .unwrap_or(pc);
+                result.enter(analysis, &mut call_stack, function);
+            }
+            result.total_insn_count += 1;
+            *result.stacks.entry(call_stack.clone()).or_insert(0) += 1;
+            for (depth, function) in call_stack.iter().enumerate() {
+                if let Some(function_profile) = result.functions.get_mut(function) {
+                    if depth == call_stack.len() - 1 {
+                        function_profile.exclusive_insn_count += 1;
+                    }
+                    // Count recursive functions only once
+                    if !call_stack[..depth].contains(function) {
+                        function_profile.inclusive_insn_count += 1;
+                    }
+                }
+            }
+            if (pc + 1) * ebpf::INSN_SIZE > program.len() {
+                continue;
+            }
+            let insn = ebpf::get_insn(program, pc);
+            match insn.opc {
+                ebpf::CALL_IMM
+                    if (!static_syscalls || insn.src == 0)
+                        && syscall_registry.lookup_syscall(insn.imm as u32).is_some() => {}
+                ebpf::CALL_IMM | ebpf::CALL_REG => {
+                    if let Some(next_instruction) = tracer.log.get(index + 1) {
+                        result.enter(analysis, &mut call_stack, next_instruction[11] as usize);
+                    }
+                }
+                ebpf::EXIT => {
+                    call_stack.pop();
+                }
+                _ => {}
+            }
+        }
+        result
+    }
+
+    fn enter<E: UserDefinedError, I: InstructionMeter>(
+        &mut self,
+        analysis: &Analysis<E, I>,
+        call_stack: &mut Vec<usize>,
+        function: usize,
+    ) {
+        call_stack.push(function);
+        self.max_depth = self.max_depth.max(call_stack.len());
+        let function_profile = self.functions.entry(function).or_insert_with(|| {
+            let name = match analysis.functions.get(&function) {
+                Some((_hash, name)) => name.clone(),
+                None => format!("function_{}", function),
+            };
+            FunctionProfile {
+                name,
+                ..FunctionProfile::default()
+            }
+        });
+        function_profile.call_count += 1;
+    }
+
+    fn function_name(&self, function: &usize) -> &str {
+        self.functions
+            .get(function)
+            .map(|function_profile| function_profile.name.as_str())
+            .unwrap_or("[unknown]")
+    }
+
+    /// Writes the exclusive instruction counts in the collapsed stack format of flamegraph tools
+    pub fn write_collapsed_stacks<W: Write>(&self, output: &mut W) -> std::io::Result<()> {
+        for (stack, insn_count) in self.stacks.iter() {
+            let names = stack
+                .iter()
+                .map(|function| self.function_name(function))
+                .collect::<Vec<_>>();
+            writeln!(output, "{} {}", names.join(";"), insn_count)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the profile as uncompressed pprof protobuf
+    ///
+    /// Each function becomes one location, the samples carry the exclusive instruction counts.
+    pub fn write_pprof<W: Write>(&self, output: &mut W) -> std::io::Result<()> {
+        let mut string_table = vec!["", "instructions", "count"];
+        let mut profile = Vec::new();
+        // sample_type
+        let mut value_type = Vec::new();
+        write_protobuf_varint_field(&mut value_type, 1, 1);
+        write_protobuf_varint_field(&mut value_type, 2, 2);
+        write_protobuf_bytes_field(&mut profile, 1, &value_type);
+        // sample
+        let location_ids = self
+            .functions
+            .keys()
+            .enumerate()
+            .map(|(index, function)| (*function, index as u64 + 1))
+            .collect::<BTreeMap<usize, u64>>();
+        for (stack, insn_count) in self.stacks.iter() {
+            let mut packed_location_ids = Vec::new();
+            for function in stack.iter().rev() {
+                write_varint(&mut packed_location_ids, location_ids[function]);
+            }
+            let mut packed_values = Vec::new();
+            write_varint(&mut packed_values, *insn_count);
+            let mut sample = Vec::new();
+            write_protobuf_bytes_field(&mut sample, 1, &packed_location_ids);
+            write_protobuf_bytes_field(&mut sample, 2, &packed_values);
+            write_protobuf_bytes_field(&mut profile, 2, &sample);
+        }
+        // location and function
+        for (function, function_profile) in self.functions.iter() {
+            let id = location_ids[function];
+            let mut line = Vec::new();
+            write_protobuf_varint_field(&mut line, 1, id);
+            let mut location = Vec::new();
+            write_protobuf_varint_field(&mut location, 1, id);
+            write_protobuf_varint_field(
+                &mut location,
+                3,
+                self.program_vm_addr + (*function * ebpf::INSN_SIZE) as u64,
+            );
+            write_protobuf_bytes_field(&mut location, 4, &line);
+            write_protobuf_bytes_field(&mut profile, 4, &location);
+            let name_index = string_table.len() as u64;
+            string_table.push(&function_profile.name);
+            let mut function_message = Vec::new();
+            write_protobuf_varint_field(&mut function_message, 1, id);
+            write_protobuf_varint_field(&mut function_message, 2, name_index);
+            write_protobuf_varint_field(&mut function_message, 3, name_index);
+            write_protobuf_bytes_field(&mut profile, 5, &function_message);
+        }
+        // string_table
+        for string in string_table {
+            write_protobuf_bytes_field(&mut profile, 6, string.as_bytes());
+        }
+        output.write_all(&profile)
+    }
+}
+
+fn write_protobuf_varint_field(output: &mut Vec<u8>, field: u64, value: u64) {
+    write_varint(output, field << 3);
+    write_varint(output, value);
+}
+
+fn write_protobuf_bytes_field(output: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    write_varint(output, (field << 3) | 2);
+    write_varint(output, bytes.len() as u64);
+    output.extend_from_slice(bytes);
+}
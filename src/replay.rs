@@ -0,0 +1,119 @@
+#![allow(clippy::integer_arithmetic)]
+//! Reverse execution by replaying the store journal of a traced run
+//!
+//! After a program ran in the interpreter with `enable_store_journal`, its
+//! memory can be moved back and forth to the state before any executed
+//! instruction by undoing or redoing the journaled stores. The registers are
+//! only available if `enable_instruction_tracing` was set as well. Stores
+//! performed by syscalls are not journaled.
+
+use crate::{
+    error::{EbpfError, UserDefinedError},
+    memory_region::AccessType,
+    vm::{EbpfVm, InstructionMeter, StoreRecord},
+};
+
+/// Moves the memory of a journaled VM backward and forward in time
+pub struct Replay<'a, 'b, E: UserDefinedError, I: InstructionMeter> {
+    vm: &'a mut EbpfVm<'b, E, I>,
+    position: usize,
+    applied_stores: usize,
+}
+
+impl<'a, 'b, E: UserDefinedError, I: InstructionMeter> Replay<'a, 'b, E, I> {
+    /// Creates a replay positioned at the end of the run
+    pub fn new(vm: &'a mut EbpfVm<'b, E, I>) -> Self {
+        let position = vm.tracer.insn_count;
+        let applied_stores = vm.tracer.stores.len();
+        Self {
+            vm,
+            position,
+            applied_stores,
+        }
+    }
+
+    /// Returns the number of executed instructions
+    pub fn get_instruction_count(&self) -> usize {
+        self.vm.tracer.insn_count
+    }
+
+    /// Returns the index of the next executed instruction, equals the instruction count at the end
+    pub fn get_position(&self) -> usize {
+        self.position
+    }
+
+    /// Returns the registers r0 to r10 and the pc before the instruction at the current position,
+    /// if instruction tracing was enabled
+    pub fn get_state(&self) -> Option<&[u64; 12]> {
+        self.vm.tracer.log.get(self.position)
+    }
+
+    /// Moves to the state before the executed instruction `index` (clamped to the end of the run)
+    pub fn seek(&mut self, index: usize) -> Result<(), EbpfError<E>> {
+        let index = index.min(self.get_instruction_count());
+        while self.applied_stores > 0 {
+            let record = self.vm.tracer.stores[self.applied_stores - 1];
+            if record.index < index {
+                break;
+            }
+            self.write(&record, record.old_value)?;
+            self.applied_stores -= 1;
+        }
+        while let Some(record) = self.vm.tracer.stores.get(self.applied_stores).copied() {
+            if record.index >= index {
+                break;
+            }
+            self.write(&record, record.new_value)?;
+            self.applied_stores += 1;
+        }
+        self.position = index;
+        Ok(())
+    }
+
+    /// Moves one instruction backward
+    pub fn step_back(&mut self) -> Result<(), EbpfError<E>> {
+        self.seek(self.position.saturating_sub(1))
+    }
+
+    /// Moves one instruction forward
+    pub fn step_forward(&mut self) -> Result<(), EbpfError<E>> {
+        self.seek(self.position.saturating_add(1))
+    }
+
+    /// Returns the most recent store before the current position which wrote the given byte
+    pub fn find_last_store(&self, vm_addr: u64) -> Option<&StoreRecord> {
+        self.vm.tracer.stores[..self.applied_stores]
+            .iter()
+            .rev()
+            .find(|record| {
+                record.vm_addr <= vm_addr
+                    && vm_addr < record.vm_addr.saturating_add(record.len as u64)
+            })
+    }
+
+    /// Reads VM memory at the current position
+    pub fn read_memory(&self, vm_addr: u64, len: u64) -> Result<Vec<u8>, EbpfError<E>> {
+        let host_addr = self
+            .vm
+            .memory_mapping
+            .map::<E>(AccessType::Load, vm_addr, len)?;
+        Ok(unsafe { std::slice::from_raw_parts(host_addr as *const u8, len as usize) }.to_vec())
+    }
+
+    fn write(&mut self, record: &StoreRecord, value: u64) -> Result<(), EbpfError<E>> {
+        let host_addr = self.vm.memory_mapping.map::<E>(
+            AccessType::Store,
+            record.vm_addr,
+            record.len as u64,
+        )?;
+        let bytes = value.to_le_bytes();
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                bytes.as_ptr(),
+                host_addr as *mut u8,
+                record.len as usize,
+            );
+        }
+        Ok(())
+    }
+}
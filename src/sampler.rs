@@ -0,0 +1,109 @@
+#![allow(clippy::integer_arithmetic)]
+//! Statistical profiling of JIT-compiled programs
+//!
+//! Instead of tracing every instruction, the JIT records the current pc at its instruction meter
+//! checkpoints whenever at least [Config::sampling_interval](crate::vm::Config) instructions
+//! have been executed since the last sample. Samples are kept in a ring buffer of fixed capacity,
+//! so only the most recent [SAMPLE_BUFFER_CAPACITY] samples are retained.
+
+use crate::{elf::Executable, error::UserDefinedError, vm::InstructionMeter};
+use std::collections::BTreeMap;
+
+/// Maximum number of samples which are retained
+pub const SAMPLE_BUFFER_CAPACITY: usize = 4096;
+
+/// Samples attributed to a single function
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FunctionSamples {
+    /// Symbol name
+    pub name: String,
+    /// Number of samples which hit the function
+    pub sample_count: u64,
+}
+
+/// Ring buffer of sampled pcs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sampler {
+    interval: u64,
+    samples: Vec<u64>,
+    sample_count: u64,
+}
+
+impl Sampler {
+    /// Creates an empty sampler which takes a sample every `interval` instructions
+    pub fn new(interval: u64) -> Self {
+        Self {
+            interval,
+            samples: Vec::with_capacity(SAMPLE_BUFFER_CAPACITY),
+            sample_count: 0,
+        }
+    }
+
+    /// Returns the distance between two samples in instructions
+    pub fn get_interval(&self) -> u64 {
+        self.interval
+    }
+
+    /// Returns the number of remaining instructions at which the first sample is taken
+    pub(crate) fn get_first_sample_threshold(&self, remaining: u64) -> u64 {
+        remaining.saturating_sub(self.interval)
+    }
+
+    /// Called by the JIT once the remaining instructions drop below the next sample threshold
+    pub(crate) fn sample(&mut self, pc: u64, remaining: u64, next_sample_threshold: &mut u64) {
+        if self.samples.len() < SAMPLE_BUFFER_CAPACITY {
+            self.samples.push(pc);
+        } else {
+            self.samples[(self.sample_count % SAMPLE_BUFFER_CAPACITY as u64) as usize] = pc;
+        }
+        self.sample_count += 1;
+        *next_sample_threshold = remaining.saturating_sub(self.interval);
+    }
+
+    /// Returns the number of samples taken, including the ones which were overwritten
+    pub fn get_sample_count(&self) -> u64 {
+        self.sample_count
+    }
+
+    /// Returns the retained samples in chronological order
+    pub fn get_samples(&self) -> Vec<u64> {
+        let mut samples = self.samples.clone();
+        if samples.len() == SAMPLE_BUFFER_CAPACITY {
+            samples.rotate_left((self.sample_count % SAMPLE_BUFFER_CAPACITY as u64) as usize);
+        }
+        samples
+    }
+
+    /// Discards all samples
+    pub fn clear(&mut self) {
+        self.samples.clear();
+        self.sample_count = 0;
+    }
+
+    /// Attributes the retained samples to the functions of the executable
+    ///
+    /// The result is indexed by the instruction index of the function entry.
+    /// Samples in front of the first known function are attributed to "[unknown]".
+    pub fn get_function_samples<E: UserDefinedError, I: InstructionMeter>(
+        &self,
+        executable: &Executable<E, I>,
+    ) -> BTreeMap<usize, FunctionSamples> {
+        let function_symbols = executable.get_function_symbols();
+        let mut result = BTreeMap::<usize, FunctionSamples>::new();
+        for pc in self.samples.iter() {
+            let pc = *pc as usize;
+            let (function, name) = match function_symbols.range(..=pc).next_back() {
+                Some((function, (_hash, name))) => (*function, name.as_str()),
+                None => (0, "[unknown]"),
+            };
+            result
+                .entry(function)
+                .or_insert_with(|| FunctionSamples {
+                    name: name.to_string(),
+                    sample_count: 0,
+                })
+                .sample_count += 1;
+        }
+        result
+    }
+}
@@ -0,0 +1,329 @@
+#![allow(clippy::integer_arithmetic)]
+//! Snapshot and restore of the execution state of a VM
+//!
+//! A snapshot captures everything an interpreter needs to continue: the
+//! registers, the pc, the call frames, the contents of all writable memory
+//! regions (including the stack) and the remaining instruction budget. It can
+//! be serialized into a versioned byte format and restored into a fresh VM
+//! created from the same executable.
+
+use crate::{
+    ebpf::{self, SCRATCH_REGS},
+    error::UserDefinedError,
+    interpreter::Interpreter,
+    vm::InstructionMeter,
+};
+use byteorder::{ByteOrder, LittleEndian};
+use sha2::{Digest, Sha256};
+
+/// Magic bytes at the start of a serialized snapshot
+const SNAPSHOT_MAGIC: &[u8; 8] = b"SBFSNAP\0";
+/// Version of the serialized format
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+/// Error definitions
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// Not a serialized snapshot
+    #[error("invalid snapshot magic")]
+    InvalidMagic,
+    /// Serialized by an incompatible version
+    #[error("unsupported snapshot version {0}")]
+    UnsupportedVersion(u32),
+    /// Serialized data ended early
+    #[error("snapshot data is truncated")]
+    Truncated,
+    /// Serialized data continues after the end of the snapshot
+    #[error("snapshot data has trailing bytes")]
+    TrailingData,
+    /// Snapshot was taken from a different program
+    #[error("snapshot was taken from a different executable")]
+    ExecutableMismatch,
+    /// Writable memory regions differ
+    #[error("writable memory region at {0:#x} does not match the snapshot")]
+    MemoryRegionMismatch(u64),
+    /// More call frames than the VM supports
+    #[error("snapshot has {0} call frames, exceeding the max call depth")]
+    CallDepthExceeded(usize),
+    /// Frame pointers, stack pointer or return pointers do not fit the stack and program
+    #[error("snapshot has invalid call frames")]
+    InvalidCallFrames,
+    /// The instruction meter has less budget left than the snapshot
+    #[error("instruction meter has less than the {0} remaining instructions of the snapshot")]
+    InsufficientInstructionBudget(u64),
+}
+
+/// Contents of a writable memory region
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemoryRegionSnapshot {
+    /// Start virtual address
+    pub vm_addr: u64,
+    /// Bytes of the region (host layout, without gaps)
+    pub data: Vec<u8>,
+}
+
+/// Execution state of a VM
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    /// SHA-256 of the text section the snapshot was taken from
+    pub text_hash: [u8; 32],
+    /// Instruction index of the next instruction
+    pub pc: usize,
+    /// Registers r0 to r10
+    pub reg: [u64; 11],
+    /// Remaining instruction budget (0 if the instruction meter is disabled)
+    pub remaining_insn_count: u64,
+    /// Frame pointer, saved registers and return pointer of each active call frame
+    pub frames: Vec<(u64, [u64; SCRATCH_REGS], usize)>,
+    /// Stack pointer
+    pub stack_ptr: u64,
+    /// Contents of all writable memory regions, ordered by virtual address
+    pub regions: Vec<MemoryRegionSnapshot>,
+}
+
+impl Snapshot {
+    /// Captures the state of a paused interpreter
+    pub fn capture<E: UserDefinedError, I: InstructionMeter>(
+        interpreter: &Interpreter<E, I>,
+    ) -> Self {
+        let vm = &interpreter.vm;
+        let remaining_insn_count = if vm.executable.get_config().enable_instruction_meter {
+            interpreter
+                .instruction_meter
+                .get_remaining()
+                .saturating_sub(interpreter.due_insn_count)
+        } else {
+            0
+        };
+        let regions = vm
+            .memory_mapping
+            .get_regions()
+            .iter()
+            .filter(|region| region.is_writable)
+            .map(|region| MemoryRegionSnapshot {
+                vm_addr: region.vm_addr,
+                data: unsafe {
+                    std::slice::from_raw_parts(region.host_addr as *const u8, region.len as usize)
+                }
+                .to_vec(),
+            })
+            .collect();
+        Self {
+            text_hash: hash_text(vm.program),
+            pc: interpreter.pc,
+            reg: interpreter.reg,
+            remaining_insn_count,
+            frames: vm.stack.get_frames(),
+            stack_ptr: vm.stack.get_stack_ptr(),
+            regions,
+        }
+    }
+
+    /// Restores the state into an interpreter of a fresh VM created from the same executable
+    ///
+    /// The instruction meter is not part of the VM, so the interpreter has to be handed a
+    /// meter with at least `remaining_insn_count` left. Any surplus is consumed.
+    pub fn restore<E: UserDefinedError, I: InstructionMeter>(
+        &self,
+        interpreter: &mut Interpreter<E, I>,
+    ) -> Result<(), SnapshotError> {
+        let enable_instruction_meter = interpreter
+            .vm
+            .executable
+            .get_config()
+            .enable_instruction_meter;
+        let available_insn_count = interpreter
+            .remaining_insn_count
+            .saturating_sub(interpreter.due_insn_count);
+        if enable_instruction_meter && available_insn_count < self.remaining_insn_count {
+            return Err(SnapshotError::InsufficientInstructionBudget(
+                self.remaining_insn_count,
+            ));
+        }
+        let vm = &mut interpreter.vm;
+        let insn_count = vm.program.len() / ebpf::INSN_SIZE;
+        if hash_text(vm.program) != self.text_hash || self.pc >= insn_count {
+            return Err(SnapshotError::ExecutableMismatch);
+        }
+        let writable_regions = vm
+            .memory_mapping
+            .get_regions()
+            .iter()
+            .filter(|region| region.is_writable)
+            .collect::<Vec<_>>();
+        for index in 0..writable_regions.len().max(self.regions.len()) {
+            match (writable_regions.get(index), self.regions.get(index)) {
+                (Some(region), Some(saved))
+                    if region.vm_addr == saved.vm_addr && region.len == saved.data.len() as u64 => {
+                }
+                (Some(region), _) => {
+                    return Err(SnapshotError::MemoryRegionMismatch(region.vm_addr))
+                }
+                (None, Some(saved)) => {
+                    return Err(SnapshotError::MemoryRegionMismatch(saved.vm_addr))
+                }
+                (None, None) => unreachable!(),
+            }
+        }
+        if self.frames.len() > vm.executable.get_config().max_call_depth {
+            return Err(SnapshotError::CallDepthExceeded(self.frames.len()));
+        }
+        // The innermost frame has not called anything yet
+        let calling_frames = &self.frames[0..self.frames.len().saturating_sub(1)];
+        if calling_frames
+            .iter()
+            .any(|(_frame_ptr, _saved_reg, return_ptr)| *return_ptr > insn_count)
+            || !vm.stack.set_frames(&self.frames, self.stack_ptr)
+        {
+            return Err(SnapshotError::InvalidCallFrames);
+        }
+        for (region, saved) in writable_regions.iter().zip(self.regions.iter()) {
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    saved.data.as_ptr(),
+                    region.host_addr as *mut u8,
+                    saved.data.len(),
+                );
+            }
+        }
+        if enable_instruction_meter {
+            interpreter.instruction_meter.consume(
+                interpreter.due_insn_count + available_insn_count - self.remaining_insn_count,
+            );
+            interpreter.due_insn_count = 0;
+            interpreter.remaining_insn_count = interpreter.instruction_meter.get_remaining();
+        }
+        interpreter.reg = self.reg;
+        interpreter.pc = self.pc;
+        Ok(())
+    }
+
+    /// Serializes the snapshot
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::default();
+        writer.bytes.extend_from_slice(SNAPSHOT_MAGIC);
+        writer.write_u32(SNAPSHOT_VERSION);
+        writer.bytes.extend_from_slice(&self.text_hash);
+        writer.write_u64(self.pc as u64);
+        for value in self.reg.iter() {
+            writer.write_u64(*value);
+        }
+        writer.write_u64(self.remaining_insn_count);
+        writer.write_u64(self.stack_ptr);
+        writer.write_u64(self.frames.len() as u64);
+        for (frame_ptr, saved_reg, return_ptr) in self.frames.iter() {
+            writer.write_u64(*frame_ptr);
+            for value in saved_reg.iter() {
+                writer.write_u64(*value);
+            }
+            writer.write_u64(*return_ptr as u64);
+        }
+        writer.write_u64(self.regions.len() as u64);
+        for region in self.regions.iter() {
+            writer.write_u64(region.vm_addr);
+            writer.write_u64(region.data.len() as u64);
+            writer.bytes.extend_from_slice(&region.data);
+        }
+        writer.bytes
+    }
+
+    /// Deserializes a snapshot
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let mut reader = Reader { bytes };
+        if reader.read_bytes(SNAPSHOT_MAGIC.len())? != SNAPSHOT_MAGIC {
+            return Err(SnapshotError::InvalidMagic);
+        }
+        let version = reader.read_u32()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let mut text_hash = [0u8; 32];
+        text_hash.copy_from_slice(reader.read_bytes(32)?);
+        let pc = reader.read_u64()? as usize;
+        let mut reg = [0u64; 11];
+        for value in reg.iter_mut() {
+            *value = reader.read_u64()?;
+        }
+        let remaining_insn_count = reader.read_u64()?;
+        let stack_ptr = reader.read_u64()?;
+        let number_of_frames = reader.read_u64()? as usize;
+        let mut frames = Vec::new();
+        for _ in 0..number_of_frames {
+            let frame_ptr = reader.read_u64()?;
+            let mut saved_reg = [0u64; SCRATCH_REGS];
+            for value in saved_reg.iter_mut() {
+                *value = reader.read_u64()?;
+            }
+            let return_ptr = reader.read_u64()? as usize;
+            frames.push((frame_ptr, saved_reg, return_ptr));
+        }
+        let number_of_regions = reader.read_u64()? as usize;
+        let mut regions = Vec::new();
+        for _ in 0..number_of_regions {
+            let vm_addr = reader.read_u64()?;
+            let len = reader.read_u64()? as usize;
+            regions.push(MemoryRegionSnapshot {
+                vm_addr,
+                data: reader.read_bytes(len)?.to_vec(),
+            });
+        }
+        if !reader.bytes.is_empty() {
+            return Err(SnapshotError::TrailingData);
+        }
+        Ok(Self {
+            text_hash,
+            pc,
+            reg,
+            remaining_insn_count,
+            frames,
+            stack_ptr,
+            regions,
+        })
+    }
+}
+
+fn hash_text(program: &[u8]) -> [u8; 32] {
+    Sha256::digest(program).into()
+}
+
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn write_u32(&mut self, value: u32) {
+        let mut buffer = [0u8; 4];
+        LittleEndian::write_u32(&mut buffer, value);
+        self.bytes.extend_from_slice(&buffer);
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        let mut buffer = [0u8; 8];
+        LittleEndian::write_u64(&mut buffer, value);
+        self.bytes.extend_from_slice(&buffer);
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.bytes.len() < len {
+            return Err(SnapshotError::Truncated);
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, SnapshotError> {
+        Ok(LittleEndian::read_u32(self.read_bytes(4)?))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, SnapshotError> {
+        Ok(LittleEndian::read_u64(self.read_bytes(8)?))
+    }
+}
@@ -210,7 +210,13 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> Analysis<'a, E, I> {
         }
         let mut cfg_edges = BTreeMap::new();
         for insn in self.instructions.iter() {
-            let target_pc = (insn.ptr as isize + insn.off as isize + 1) as usize;
+            // The long jump encodes its offset in the immediate
+            let offset = if insn.opc == ebpf::GOTOL {
+                insn.imm as isize
+            } else {
+                insn.off as isize
+            };
+            let target_pc = (insn.ptr as isize + offset + 1) as usize;
             match insn.opc {
                 ebpf::CALL_IMM => {
                     if let Some(syscall_name) = self
@@ -259,7 +265,7 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> Analysis<'a, E, I> {
                         .or_insert_with(CfgNode::default);
                     cfg_edges.insert(insn.ptr, (insn.opc, Vec::new()));
                 }
-                ebpf::JA => {
+                ebpf::JA | ebpf::GOTOL => {
                     self.cfg_nodes
                         .entry(insn.ptr + 1)
                         .or_insert_with(CfgNode::default);
@@ -279,6 +285,17 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> Analysis<'a, E, I> {
                 | ebpf::JSGE_IMM
                 | ebpf::JSLT_IMM
                 | ebpf::JSLE_IMM
+                | ebpf::JEQ32_IMM
+                | ebpf::JGT32_IMM
+                | ebpf::JGE32_IMM
+                | ebpf::JLT32_IMM
+                | ebpf::JLE32_IMM
+                | ebpf::JSET32_IMM
+                | ebpf::JNE32_IMM
+                | ebpf::JSGT32_IMM
+                | ebpf::JSGE32_IMM
+                | ebpf::JSLT32_IMM
+                | ebpf::JSLE32_IMM
                 | ebpf::JEQ_REG
                 | ebpf::JGT_REG
                 | ebpf::JGE_REG
@@ -289,7 +306,18 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> Analysis<'a, E, I> {
                 | ebpf::JSGT_REG
                 | ebpf::JSGE_REG
                 | ebpf::JSLT_REG
-                | ebpf::JSLE_REG => {
+                | ebpf::JSLE_REG
+                | ebpf::JEQ32_REG
+                | ebpf::JGT32_REG
+                | ebpf::JGE32_REG
+                | ebpf::JLT32_REG
+                | ebpf::JLE32_REG
+                | ebpf::JSET32_REG
+                | ebpf::JNE32_REG
+                | ebpf::JSGT32_REG
+                | ebpf::JSGE32_REG
+                | ebpf::JSLT32_REG
+                | ebpf::JSLE32_REG => {
                     self.cfg_nodes
                         .entry(insn.ptr + 1)
                         .or_insert_with(CfgNode::default);
@@ -334,7 +362,7 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> Analysis<'a, E, I> {
             let mut instruction_index = 0;
             let mut cfg_node_iter = self.cfg_nodes.iter_mut().peekable();
             let mut cfg_edge_iter = cfg_edges.iter_mut().peekable();
-            while let Some((cfg_node_start, cfg_node)) = cfg_node_iter.next() {
+            while let Some((_cfg_node_start, cfg_node)) = cfg_node_iter.next() {
                 let cfg_node_end = if let Some(next_cfg_node) = cfg_node_iter.peek() {
                     *next_cfg_node.0 - 1
                 } else {
@@ -357,7 +385,7 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> Analysis<'a, E, I> {
                     }
                 }
                 if let Some(next_cfg_node) = cfg_node_iter.peek() {
-                    if !self.functions.contains_key(cfg_node_start) {
+                    if !self.functions.contains_key(next_cfg_node.0) {
                         cfg_node.destinations.push(*next_cfg_node.0);
                     }
                 }
@@ -426,7 +454,7 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> Analysis<'a, E, I> {
                 writeln!(output, "{}:", cfg_node.label)?;
             }
             let last_insn = &self.instructions[cfg_node.instructions.end - 1];
-            *last_basic_block = if last_insn.opc == ebpf::JA {
+            *last_basic_block = if matches!(last_insn.opc, ebpf::JA | ebpf::GOTOL) {
                 usize::MAX
             } else {
                 pc
@@ -916,7 +944,13 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> Analysis<'a, E, I> {
                         ebpf::LD_DW_IMM => {
                             bind(&mut state, insn, true, DataResource::Register(insn.dst));
                         }
-                        ebpf::LD_B_REG | ebpf::LD_H_REG | ebpf::LD_W_REG | ebpf::LD_DW_REG => {
+                        ebpf::LD_B_REG
+                        | ebpf::LD_H_REG
+                        | ebpf::LD_W_REG
+                        | ebpf::LD_DW_REG
+                        | ebpf::LD_SB_REG
+                        | ebpf::LD_SH_REG
+                        | ebpf::LD_SW_REG => {
                             bind(&mut state, insn, false, DataResource::Memory);
                             bind(&mut state, insn, false, DataResource::Register(insn.src));
                             bind(&mut state, insn, true, DataResource::Register(insn.dst));
@@ -930,6 +964,19 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> Analysis<'a, E, I> {
                             bind(&mut state, insn, false, DataResource::Register(insn.dst));
                             bind(&mut state, insn, true, DataResource::Memory);
                         }
+                        ebpf::ST_W_ATOMIC | ebpf::ST_DW_ATOMIC => {
+                            let operation = ebpf::atomic_operation(insn.imm).unwrap_or(0);
+                            bind(&mut state, insn, false, DataResource::Memory);
+                            bind(&mut state, insn, false, DataResource::Register(insn.src));
+                            bind(&mut state, insn, false, DataResource::Register(insn.dst));
+                            if operation == ebpf::BPF_CMPXCHG {
+                                bind(&mut state, insn, false, DataResource::Register(0));
+                                bind(&mut state, insn, true, DataResource::Register(0));
+                            } else if operation & ebpf::BPF_FETCH != 0 {
+                                bind(&mut state, insn, true, DataResource::Register(insn.src));
+                            }
+                            bind(&mut state, insn, true, DataResource::Memory);
+                        }
                         ebpf::ADD32_IMM
                         | ebpf::SUB32_IMM
                         | ebpf::MUL32_IMM
@@ -957,7 +1004,8 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> Analysis<'a, E, I> {
                         | ebpf::NEG32
                         | ebpf::NEG64
                         | ebpf::LE
-                        | ebpf::BE => {
+                        | ebpf::BE
+                        | ebpf::BSWAP => {
                             bind(&mut state, insn, false, DataResource::Register(insn.dst));
                             bind(&mut state, insn, true, DataResource::Register(insn.dst));
                         }
@@ -1006,7 +1054,18 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> Analysis<'a, E, I> {
                         | ebpf::JSGT_IMM
                         | ebpf::JSGE_IMM
                         | ebpf::JSLT_IMM
-                        | ebpf::JSLE_IMM => {
+                        | ebpf::JSLE_IMM
+                        | ebpf::JEQ32_IMM
+                        | ebpf::JGT32_IMM
+                        | ebpf::JGE32_IMM
+                        | ebpf::JLT32_IMM
+                        | ebpf::JLE32_IMM
+                        | ebpf::JSET32_IMM
+                        | ebpf::JNE32_IMM
+                        | ebpf::JSGT32_IMM
+                        | ebpf::JSGE32_IMM
+                        | ebpf::JSLT32_IMM
+                        | ebpf::JSLE32_IMM => {
                             bind(&mut state, insn, false, DataResource::Register(insn.dst));
                         }
                         ebpf::JEQ_REG
@@ -1019,7 +1078,18 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> Analysis<'a, E, I> {
                         | ebpf::JSGT_REG
                         | ebpf::JSGE_REG
                         | ebpf::JSLT_REG
-                        | ebpf::JSLE_REG => {
+                        | ebpf::JSLE_REG
+                        | ebpf::JEQ32_REG
+                        | ebpf::JGT32_REG
+                        | ebpf::JGE32_REG
+                        | ebpf::JLT32_REG
+                        | ebpf::JLE32_REG
+                        | ebpf::JSET32_REG
+                        | ebpf::JNE32_REG
+                        | ebpf::JSGT32_REG
+                        | ebpf::JSGE32_REG
+                        | ebpf::JSLT32_REG
+                        | ebpf::JSLE32_REG => {
                             bind(&mut state, insn, false, DataResource::Register(insn.src));
                             bind(&mut state, insn, false, DataResource::Register(insn.dst));
                         }
@@ -1137,4 +1207,122 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> Analysis<'a, E, I> {
             }
         }
     }
+
+    /// Finds the loads and stores which can not access memory out of bounds
+    ///
+    /// The analysis is conservative, it only reports accesses which either:
+    /// - Are relative to the frame pointer and stay within the stack frame of the current function.
+    ///   This requires fixed size stack frames and a program which never writes to the frame pointer.
+    /// - Are contained in the range of an earlier access in the same basic block, relative to the
+    ///   same value of the same base register. This requires a program without indirect calls,
+    ///   as these could enter the basic block in the middle.
+    ///
+    /// Returns the pcs of these accesses.
+    pub fn find_in_bounds_memory_accesses(&self) -> BTreeSet<usize> {
+        let config = self.executable.get_config();
+        let dynamic_stack_frames = self.executable.get_sbf_version().dynamic_stack_frames();
+        let stack_frame_gaps = !dynamic_stack_frames && config.enable_stack_frame_gaps;
+        let writes_to = |pc: usize, resource: DataResource| {
+            self.dfg_reverse_edges
+                .get(&DfgNode::InstructionNode(pc))
+                .map(|edges| {
+                    edges
+                        .iter()
+                        .any(|edge| edge.kind == DfgEdgeKind::Empty && edge.resource == resource)
+                })
+                .unwrap_or(false)
+        };
+        let frame_pointer_is_fixed = !dynamic_stack_frames
+            && (!stack_frame_gaps || config.stack_frame_size.is_power_of_two())
+            && self.instructions.iter().all(|insn| {
+                matches!(insn.opc, ebpf::CALL_IMM | ebpf::CALL_REG)
+                    || !writes_to(insn.ptr, DataResource::Register(ebpf::FRAME_PTR_REG as u8))
+            });
+        let has_indirect_calls = self
+            .instructions
+            .iter()
+            .any(|insn| insn.opc == ebpf::CALL_REG);
+        let mut in_bounds_accesses = BTreeSet::new();
+        for cfg_node in self.cfg_nodes.values() {
+            // Counts the writes to each register, to tell apart the values it takes in this basic block
+            let mut register_generations = [0usize; 11];
+            // Ranges which are known to be mapped: (base register, generation, begin, end, is_writable)
+            let mut validated_ranges: Vec<(u8, usize, i64, i64, bool)> = Vec::new();
+            for insn in self.instructions[cfg_node.instructions.clone()].iter() {
+                let access = match insn.opc {
+                    ebpf::LD_B_REG
+                    | ebpf::LD_H_REG
+                    | ebpf::LD_W_REG
+                    | ebpf::LD_DW_REG
+                    | ebpf::LD_SB_REG
+                    | ebpf::LD_SH_REG
+                    | ebpf::LD_SW_REG => Some((insn.src, false)),
+                    ebpf::ST_B_IMM | ebpf::ST_H_IMM | ebpf::ST_W_IMM | ebpf::ST_DW_IMM => {
+                        Some((insn.dst, true))
+                    }
+                    ebpf::ST_B_REG | ebpf::ST_H_REG | ebpf::ST_W_REG | ebpf::ST_DW_REG => {
+                        Some((insn.dst, true))
+                    }
+                    ebpf::ST_W_ATOMIC | ebpf::ST_DW_ATOMIC => Some((insn.dst, true)),
+                    _ => None,
+                };
+                if let Some((base, is_store)) = access.filter(|(base, _)| *base <= 10) {
+                    let len = match insn.opc & 0x18 {
+                        ebpf::BPF_B => 1,
+                        ebpf::BPF_H => 2,
+                        ebpf::BPF_W => 4,
+                        _ => 8,
+                    };
+                    let begin = insn.off as i64;
+                    let end = begin + len;
+                    let generation = register_generations[base as usize];
+                    let in_stack_frame = frame_pointer_is_fixed
+                        && base as usize == ebpf::FRAME_PTR_REG
+                        && begin >= -(config.stack_frame_size as i64)
+                        && end <= 0;
+                    let in_validated_range = !has_indirect_calls
+                        && validated_ranges.iter().any(
+                            |(
+                                validated_base,
+                                validated_generation,
+                                validated_begin,
+                                validated_end,
+                                is_writable,
+                            )| {
+                                *validated_base == base
+                                    && *validated_generation == generation
+                                    && (is_writable | !is_store)
+                                    // Only the beginning of an access is checked against the gaps
+                                    && if stack_frame_gaps {
+                                        begin == *validated_begin
+                                    } else {
+                                        begin >= *validated_begin
+                                    }
+                                    && end <= *validated_end
+                            },
+                        );
+                    if in_stack_frame || in_validated_range {
+                        in_bounds_accesses.insert(insn.ptr);
+                    }
+                    validated_ranges.push((
+                        base,
+                        generation,
+                        begin,
+                        end,
+                        is_store || in_stack_frame,
+                    ));
+                }
+                // Syscalls can replace the memory regions and calls can reach syscalls
+                if matches!(insn.opc, ebpf::CALL_IMM | ebpf::CALL_REG) {
+                    validated_ranges.clear();
+                }
+                for (reg, generation) in register_generations.iter_mut().enumerate() {
+                    if writes_to(insn.ptr, DataResource::Register(reg as u8)) {
+                        *generation += 1;
+                    }
+                }
+            }
+        }
+        in_bounds_accesses
+    }
 }
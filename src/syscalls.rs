@@ -51,13 +51,14 @@ pub const BPF_TRACE_PRINTK_IDX: u32 = 6;
 ///
 /// ```
 /// use solana_rbpf::syscalls::{BpfTracePrintf, Result};
+/// use solana_rbpf::ebpf::SbfVersion;
 /// use solana_rbpf::memory_region::{MemoryRegion, MemoryMapping};
 /// use solana_rbpf::vm::{Config, SyscallObject};
 /// use solana_rbpf::user_error::UserError;
 ///
 /// let mut result: Result = Ok(0);
 /// let config = Config::default();
-/// let mut memory_mapping = MemoryMapping::new::<UserError>(vec![], &config).unwrap();
+/// let mut memory_mapping = MemoryMapping::new::<UserError>(vec![], &config, SbfVersion::V2).unwrap();
 /// BpfTracePrintf::call(&mut BpfTracePrintf {}, 0, 0, 1, 15, 32, &mut memory_mapping, &mut result);
 /// assert_eq!(result.unwrap() as usize, "BpfTracePrintf: 0x1, 0xf, 0x20\n".len());
 /// ```
@@ -126,13 +127,14 @@ impl SyscallObject<UserError> for BpfTracePrintf {
 ///
 /// ```
 /// use solana_rbpf::syscalls::{BpfGatherBytes, Result};
+/// use solana_rbpf::ebpf::SbfVersion;
 /// use solana_rbpf::memory_region::{MemoryRegion, MemoryMapping};
 /// use solana_rbpf::vm::{Config, SyscallObject};
 /// use solana_rbpf::user_error::UserError;
 ///
 /// let mut result: Result = Ok(0);
 /// let config = Config::default();
-/// let mut memory_mapping = MemoryMapping::new::<UserError>(vec![], &config).unwrap();
+/// let mut memory_mapping = MemoryMapping::new::<UserError>(vec![], &config, SbfVersion::V2).unwrap();
 /// BpfGatherBytes::call(&mut BpfGatherBytes {}, 0x11, 0x22, 0x33, 0x44, 0x55, &mut memory_mapping, &mut result);
 /// assert_eq!(result.unwrap(), 0x1122334455);
 /// ```
@@ -172,6 +174,7 @@ impl SyscallObject<UserError> for BpfGatherBytes {
 ///
 /// ```
 /// use solana_rbpf::syscalls::{BpfMemFrob, Result};
+/// use solana_rbpf::ebpf::SbfVersion;
 /// use solana_rbpf::memory_region::{MemoryRegion, MemoryMapping};
 /// use solana_rbpf::vm::{Config, SyscallObject};
 /// use solana_rbpf::user_error::UserError;
@@ -181,7 +184,7 @@ impl SyscallObject<UserError> for BpfGatherBytes {
 ///
 /// let mut result: Result = Ok(0);
 /// let config = Config::default();
-/// let mut memory_mapping = MemoryMapping::new::<UserError>(vec![MemoryRegion::default(), MemoryRegion::new_writable(val, val_va)], &config).unwrap();
+/// let mut memory_mapping = MemoryMapping::new::<UserError>(vec![MemoryRegion::default(), MemoryRegion::new_writable(val, val_va)], &config, SbfVersion::V2).unwrap();
 /// BpfMemFrob::call(&mut BpfMemFrob {}, val_va, 8, 0, 0, 0, &mut memory_mapping, &mut result);
 /// assert_eq!(val, &[0x2a, 0x2a, 0x2a, 0x2a, 0x2a, 0x3b, 0x08, 0x19]);
 /// BpfMemFrob::call(&mut BpfMemFrob {}, val_va, 8, 0, 0, 0, &mut memory_mapping, &mut result);
@@ -222,6 +225,7 @@ impl SyscallObject<UserError> for BpfMemFrob {
 ///
 /// ```
 /// use solana_rbpf::syscalls::{BpfStrCmp, Result};
+/// use solana_rbpf::ebpf::SbfVersion;
 /// use solana_rbpf::memory_region::{MemoryRegion, MemoryMapping};
 /// use solana_rbpf::vm::{Config, SyscallObject};
 ///
@@ -233,11 +237,11 @@ impl SyscallObject<UserError> for BpfMemFrob {
 ///
 /// let mut result: Result = Ok(0);
 /// let config = Config::default();
-/// let mut memory_mapping = MemoryMapping::new::<UserError>(vec![MemoryRegion::default(), MemoryRegion::new_readonly(foo.as_bytes(), va_foo)], &config).unwrap();
+/// let mut memory_mapping = MemoryMapping::new::<UserError>(vec![MemoryRegion::default(), MemoryRegion::new_readonly(foo.as_bytes(), va_foo)], &config, SbfVersion::V2).unwrap();
 /// BpfStrCmp::call(&mut BpfStrCmp {}, va_foo, va_foo, 0, 0, 0, &mut memory_mapping, &mut result);
 /// assert!(result.unwrap() == 0);
 /// let mut result: Result = Ok(0);
-/// let mut memory_mapping = MemoryMapping::new::<UserError>(vec![MemoryRegion::default(), MemoryRegion::new_readonly(foo.as_bytes(), va_foo), MemoryRegion::new_readonly(bar.as_bytes(), va_bar)], &config).unwrap();
+/// let mut memory_mapping = MemoryMapping::new::<UserError>(vec![MemoryRegion::default(), MemoryRegion::new_readonly(foo.as_bytes(), va_foo), MemoryRegion::new_readonly(bar.as_bytes(), va_bar)], &config, SbfVersion::V2).unwrap();
 /// BpfStrCmp::call(&mut BpfStrCmp {}, va_foo, va_bar, 0, 0, 0, &mut memory_mapping, &mut result);
 /// assert!(result.unwrap() != 0);
 /// ```
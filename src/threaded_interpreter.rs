@@ -0,0 +1,850 @@
+#![allow(clippy::integer_arithmetic)]
+//! Threaded-code interpreter for eBPF programs
+//!
+//! Instead of decoding every instruction each time it is executed, the text section is
+//! pre-decoded once into an array with one entry per instruction slot. Each entry holds a pointer
+//! to the handler of its opcode together with pre-computed operands: resolved jump and call
+//! targets, merged lddw immediates and pre-resolved syscall function pointers. Execution then
+//! dispatches from one handler to the next, operating on the state of an [Interpreter] so that
+//! both backends produce identical results.
+
+use crate::{
+    ebpf::{self, STACK_PTR_REG},
+    elf::Executable,
+    error::{EbpfError, UserDefinedError},
+    interpreter::Interpreter,
+    memory_region::AccessType,
+    user_error::UserError,
+    vm::{InstructionMeter, ProgramResult, SyscallFunction, SYSCALL_CONTEXT_OBJECTS_OFFSET},
+};
+use std::{fmt, mem};
+
+/// Executes a pre-decoded instruction, returns the result of the program if it exited
+type Handler<E, I> = fn(
+    &mut Interpreter<'_, '_, E, I>,
+    &ThreadedInstruction<E, I>,
+    usize,
+) -> Result<Option<u64>, EbpfError<E>>;
+
+/// An instruction slot decoded into its handler and operands
+struct ThreadedInstruction<E: UserDefinedError, I: InstructionMeter> {
+    handler: Handler<E, I>,
+    /// Immediate, the address of ld_abs / ld_ind or the function of a syscall
+    imm: i64,
+    /// Memory offset or the context object slot of a syscall
+    off: i64,
+    /// Resolved target of a jump or a BPF to BPF call
+    target_pc: usize,
+    dst: u8,
+    src: u8,
+    /// Number of slots the instruction occupies
+    width: u8,
+}
+
+/// Pre-decoded program of the threaded-code interpreter
+pub struct ThreadedProgram<E: UserDefinedError, I: InstructionMeter> {
+    instructions: Vec<ThreadedInstruction<E, I>>,
+}
+
+impl<E: UserDefinedError, I: InstructionMeter> fmt::Debug for ThreadedProgram<E, I> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.write_fmt(format_args!(
+            "ThreadedProgram {:?}",
+            self.instructions.as_ptr()
+        ))
+    }
+}
+
+impl<E: UserDefinedError, I: InstructionMeter> PartialEq for ThreadedProgram<E, I> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.instructions.as_ptr(), other.instructions.as_ptr())
+    }
+}
+
+impl<E: UserDefinedError, I: InstructionMeter> ThreadedProgram<E, I> {
+    /// Pre-decodes the text section of an executable
+    pub fn new(executable: &Executable<E, I>) -> Self {
+        let (_program_vm_addr, program) = executable.get_text_bytes();
+        let instruction_count = program.len() / ebpf::INSN_SIZE;
+        let instructions = (0..instruction_count)
+            .map(|pc| decode(executable, program, pc))
+            .collect();
+        Self { instructions }
+    }
+
+    /// Returns the size of the pre-decoded program in bytes
+    pub fn mem_size(&self) -> usize {
+        mem::size_of::<Self>()
+            + self.instructions.capacity() * mem::size_of::<ThreadedInstruction<E, I>>()
+    }
+
+    /// Runs the program until it exits or fails
+    pub(crate) fn execute(&self, interpreter: &mut Interpreter<E, I>) -> ProgramResult<E> {
+        let config = interpreter.vm.executable.get_config();
+        loop {
+            let pc = interpreter.pc;
+            interpreter.due_insn_count += 1;
+            let instruction = match self.instructions.get(pc) {
+                Some(instruction) => instruction,
+                None => return Err(EbpfError::ExecutionOverrun(pc + ebpf::ELF_INSN_DUMP_OFFSET)),
+            };
+            interpreter.pc = pc + instruction.width as usize;
+
+            if config.enable_instruction_tracing {
+                let mut state = [0u64; 12];
+                state[0..11].copy_from_slice(&interpreter.reg);
+                state[11] = pc as u64;
+                interpreter.vm.tracer.trace(state);
+            } else if config.enable_store_journal {
+                interpreter.vm.tracer.count_instruction();
+            }
+
+            if let Some(result) = (instruction.handler)(interpreter, instruction, pc)? {
+                return Ok(result);
+            }
+
+            if config.enable_instruction_meter
+                && interpreter.due_insn_count >= interpreter.remaining_insn_count
+            {
+                return Err(EbpfError::ExceededMaxInstructions(
+                    pc + instruction.width as usize + ebpf::ELF_INSN_DUMP_OFFSET,
+                    interpreter.initial_insn_count,
+                ));
+            }
+        }
+    }
+}
+
+/// Selects the handler of an instruction and pre-computes its operands
+#[rustfmt::skip]
+fn decode<E: UserDefinedError, I: InstructionMeter>(
+    executable: &Executable<E, I>,
+    program: &[u8],
+    pc: usize,
+) -> ThreadedInstruction<E, I> {
+    let config = executable.get_config();
+    let sbf_version = executable.get_sbf_version();
+    let mut insn = ebpf::get_insn_unchecked(program, pc);
+    let mut instruction = ThreadedInstruction {
+        handler: unsupported_instruction::<E, I>,
+        imm: insn.imm,
+        off: insn.off as i64,
+        target_pc: (pc as isize + 1 + insn.off as isize) as usize,
+        dst: insn.dst,
+        src: insn.src,
+        width: 1,
+    };
+    if insn.dst as usize == STACK_PTR_REG && sbf_version.dynamic_stack_frames() {
+        instruction.handler = match insn.opc {
+            ebpf::SUB64_IMM => {
+                instruction.imm = -insn.imm;
+                resize_stack
+            }
+            ebpf::ADD64_IMM => resize_stack,
+            _ => unexpected_stack_ptr_instruction,
+        };
+        return instruction;
+    }
+    instruction.handler = match insn.opc {
+        // BPF_LD class
+        ebpf::LD_ABS_B | ebpf::LD_ABS_H | ebpf::LD_ABS_W | ebpf::LD_ABS_DW |
+        ebpf::LD_IND_B | ebpf::LD_IND_H | ebpf::LD_IND_W | ebpf::LD_IND_DW => {
+            instruction.imm = ebpf::MM_INPUT_START.wrapping_add(insn.imm as u32 as u64) as i64;
+            match insn.opc {
+                ebpf::LD_ABS_B  => ld_abs_b,
+                ebpf::LD_ABS_H  => ld_abs_h,
+                ebpf::LD_ABS_W  => ld_abs_w,
+                ebpf::LD_ABS_DW => ld_abs_dw,
+                ebpf::LD_IND_B  => ld_ind_b,
+                ebpf::LD_IND_H  => ld_ind_h,
+                ebpf::LD_IND_W  => ld_ind_w,
+                _               => ld_ind_dw,
+            }
+        }
+        ebpf::LD_DW_IMM  => {
+            instruction.width = 2;
+            // The interpreter would run off the end of the text section when loading the second half
+            if (pc + 2) * ebpf::INSN_SIZE > program.len() {
+                incomplete_lddw
+            } else {
+                ebpf::augment_lddw_unchecked(program, &mut insn);
+                instruction.imm = insn.imm;
+                mov64_imm
+            }
+        }
+
+        // BPF_LDX class
+        ebpf::LD_B_REG   => ld_b_reg,
+        ebpf::LD_H_REG   => ld_h_reg,
+        ebpf::LD_W_REG   => ld_w_reg,
+        ebpf::LD_DW_REG  => ld_dw_reg,
+        ebpf::LD_SB_REG  => ld_sb_reg,
+        ebpf::LD_SH_REG  => ld_sh_reg,
+        ebpf::LD_SW_REG  => ld_sw_reg,
+
+        // BPF_ST class
+        ebpf::ST_B_IMM   => st_b_imm,
+        ebpf::ST_H_IMM   => st_h_imm,
+        ebpf::ST_W_IMM   => st_w_imm,
+        ebpf::ST_DW_IMM  => st_dw_imm,
+
+        // BPF_STX class
+        ebpf::ST_B_REG   => st_b_reg,
+        ebpf::ST_H_REG   => st_h_reg,
+        ebpf::ST_W_REG   => st_w_reg,
+        ebpf::ST_DW_REG  => st_dw_reg,
+        ebpf::ST_W_ATOMIC  => st_w_atomic,
+        ebpf::ST_DW_ATOMIC => st_dw_atomic,
+
+        // BPF_ALU class
+        ebpf::ADD32_IMM  => add32_imm,
+        ebpf::ADD32_REG  => add32_reg,
+        ebpf::SUB32_IMM  => sub32_imm,
+        ebpf::SUB32_REG  => sub32_reg,
+        ebpf::MUL32_IMM  => mul32_imm,
+        ebpf::MUL32_REG  => mul32_reg,
+        ebpf::DIV32_IMM  => div32_imm,
+        ebpf::DIV32_REG  => div32_reg,
+        ebpf::SDIV32_IMM => sdiv32_imm,
+        ebpf::SDIV32_REG => sdiv32_reg,
+        ebpf::OR32_IMM   => or32_imm,
+        ebpf::OR32_REG   => or32_reg,
+        ebpf::AND32_IMM  => and32_imm,
+        ebpf::AND32_REG  => and32_reg,
+        ebpf::LSH32_IMM  => lsh32_imm,
+        ebpf::LSH32_REG  => lsh32_reg,
+        ebpf::RSH32_IMM  => rsh32_imm,
+        ebpf::RSH32_REG  => rsh32_reg,
+        ebpf::NEG32      => neg32,
+        ebpf::MOD32_IMM  if config.enable_ebpf_v4 && insn.off == 1 => smod32_imm,
+        ebpf::MOD32_REG  if config.enable_ebpf_v4 && insn.off == 1 => smod32_reg,
+        ebpf::MOD32_IMM  => mod32_imm,
+        ebpf::MOD32_REG  => mod32_reg,
+        ebpf::XOR32_IMM  => xor32_imm,
+        ebpf::XOR32_REG  => xor32_reg,
+        ebpf::MOV32_IMM  => mov32_imm,
+        ebpf::MOV32_REG  if config.enable_ebpf_v4 && insn.off == 8  => mov32sx8,
+        ebpf::MOV32_REG  if config.enable_ebpf_v4 && insn.off == 16 => mov32sx16,
+        ebpf::MOV32_REG  => mov32_reg,
+        ebpf::ARSH32_IMM => arsh32_imm,
+        ebpf::ARSH32_REG => arsh32_reg,
+        ebpf::LE         => match insn.imm {
+            16 => le16,
+            32 => le32,
+            64 => le64,
+            _  => invalid_instruction,
+        },
+        ebpf::BE         => match insn.imm {
+            16 => be16,
+            32 => be32,
+            64 => be64,
+            _  => invalid_instruction,
+        },
+        ebpf::BSWAP      => match insn.imm {
+            16 => bswap16,
+            32 => bswap32,
+            64 => bswap64,
+            _  => invalid_instruction,
+        },
+
+        // BPF_ALU64 class
+        ebpf::ADD64_IMM  => add64_imm,
+        ebpf::ADD64_REG  => add64_reg,
+        ebpf::SUB64_IMM  => sub64_imm,
+        ebpf::SUB64_REG  => sub64_reg,
+        ebpf::MUL64_IMM  => mul64_imm,
+        ebpf::MUL64_REG  => mul64_reg,
+        ebpf::DIV64_IMM  => div64_imm,
+        ebpf::DIV64_REG  => div64_reg,
+        ebpf::SDIV64_IMM => sdiv64_imm,
+        ebpf::SDIV64_REG => sdiv64_reg,
+        ebpf::OR64_IMM   => or64_imm,
+        ebpf::OR64_REG   => or64_reg,
+        ebpf::AND64_IMM  => and64_imm,
+        ebpf::AND64_REG  => and64_reg,
+        ebpf::LSH64_IMM  => lsh64_imm,
+        ebpf::LSH64_REG  => lsh64_reg,
+        ebpf::RSH64_IMM  => rsh64_imm,
+        ebpf::RSH64_REG  => rsh64_reg,
+        ebpf::NEG64      => neg64,
+        ebpf::MOD64_IMM  if config.enable_ebpf_v4 && insn.off == 1 => smod64_imm,
+        ebpf::MOD64_REG  if config.enable_ebpf_v4 && insn.off == 1 => smod64_reg,
+        ebpf::MOD64_IMM  => mod64_imm,
+        ebpf::MOD64_REG  => mod64_reg,
+        ebpf::XOR64_IMM  => xor64_imm,
+        ebpf::XOR64_REG  => xor64_reg,
+        ebpf::MOV64_IMM  => mov64_imm,
+        ebpf::MOV64_REG  if config.enable_ebpf_v4 && insn.off == 8  => movsx8,
+        ebpf::MOV64_REG  if config.enable_ebpf_v4 && insn.off == 16 => movsx16,
+        ebpf::MOV64_REG  if config.enable_ebpf_v4 && insn.off == 32 => movsx32,
+        ebpf::MOV64_REG  => mov64_reg,
+        ebpf::ARSH64_IMM => arsh64_imm,
+        ebpf::ARSH64_REG => arsh64_reg,
+
+        // BPF_JMP class
+        ebpf::JA         => ja,
+        ebpf::JEQ_IMM    => jeq_imm,
+        ebpf::JEQ_REG    => jeq_reg,
+        ebpf::JGT_IMM    => jgt_imm,
+        ebpf::JGT_REG    => jgt_reg,
+        ebpf::JGE_IMM    => jge_imm,
+        ebpf::JGE_REG    => jge_reg,
+        ebpf::JLT_IMM    => jlt_imm,
+        ebpf::JLT_REG    => jlt_reg,
+        ebpf::JLE_IMM    => jle_imm,
+        ebpf::JLE_REG    => jle_reg,
+        ebpf::JSET_IMM   => jset_imm,
+        ebpf::JSET_REG   => jset_reg,
+        ebpf::JNE_IMM    => jne_imm,
+        ebpf::JNE_REG    => jne_reg,
+        ebpf::JSGT_IMM   => jsgt_imm,
+        ebpf::JSGT_REG   => jsgt_reg,
+        ebpf::JSGE_IMM   => jsge_imm,
+        ebpf::JSGE_REG   => jsge_reg,
+        ebpf::JSLT_IMM   => jslt_imm,
+        ebpf::JSLT_REG   => jslt_reg,
+        ebpf::JSLE_IMM   => jsle_imm,
+        ebpf::JSLE_REG   => jsle_reg,
+
+        ebpf::CALL_REG   => call_reg,
+        ebpf::CALL_IMM   => {
+            let (syscalls, calls) = if sbf_version.static_syscalls() {
+                (insn.src == 0, insn.src != 0)
+            } else {
+                (true, true)
+            };
+            let syscall = if syscalls {
+                executable.get_syscall_registry().lookup_syscall(insn.imm as u32)
+            } else {
+                None
+            };
+            let target_pc = if calls {
+                executable.lookup_bpf_function(insn.imm as u32)
+            } else {
+                None
+            };
+            if let Some(syscall) = syscall {
+                instruction.imm = syscall.function as i64;
+                instruction.off = syscall.context_object_slot as i64;
+            }
+            if let Some(target_pc) = target_pc {
+                instruction.target_pc = target_pc;
+            }
+            match (syscall, target_pc) {
+                (Some(_), Some(_)) => call_syscall_and_function,
+                (Some(_), None)    => call_syscall,
+                (None, Some(_))    => call_function,
+                (None, None)       => call_unresolved,
+            }
+        }
+        ebpf::EXIT       => exit,
+
+        // BPF_JMP32 class
+        ebpf::JEQ32_IMM  => jeq32_imm,
+        ebpf::JEQ32_REG  => jeq32_reg,
+        ebpf::JGT32_IMM  => jgt32_imm,
+        ebpf::JGT32_REG  => jgt32_reg,
+        ebpf::JGE32_IMM  => jge32_imm,
+        ebpf::JGE32_REG  => jge32_reg,
+        ebpf::JLT32_IMM  => jlt32_imm,
+        ebpf::JLT32_REG  => jlt32_reg,
+        ebpf::JLE32_IMM  => jle32_imm,
+        ebpf::JLE32_REG  => jle32_reg,
+        ebpf::JSET32_IMM => jset32_imm,
+        ebpf::JSET32_REG => jset32_reg,
+        ebpf::JNE32_IMM  => jne32_imm,
+        ebpf::JNE32_REG  => jne32_reg,
+        ebpf::JSGT32_IMM => jsgt32_imm,
+        ebpf::JSGT32_REG => jsgt32_reg,
+        ebpf::JSGE32_IMM => jsge32_imm,
+        ebpf::JSGE32_REG => jsge32_reg,
+        ebpf::JSLT32_IMM => jslt32_imm,
+        ebpf::JSLT32_REG => jslt32_reg,
+        ebpf::JSLE32_IMM => jsle32_imm,
+        ebpf::JSLE32_REG => jsle32_reg,
+        ebpf::GOTOL      => {
+            instruction.target_pc = (pc as isize + 1 + insn.imm as isize) as usize;
+            ja
+        }
+
+        _                => unsupported_instruction,
+    };
+    instruction
+}
+
+/// Defines handlers which load from memory into a register
+macro_rules! load {
+    ($($name:ident($T:ty, |$interpreter:ident, $insn:ident| ($vm_addr:expr, $dst:expr));)*) => {$(
+        fn $name<E: UserDefinedError, I: InstructionMeter>(
+            $interpreter: &mut Interpreter<E, I>,
+            $insn: &ThreadedInstruction<E, I>,
+            pc: usize,
+        ) -> Result<Option<u64>, EbpfError<E>> {
+            let config = $interpreter.vm.executable.get_config();
+            let vm_addr = $vm_addr;
+            let dst = $dst;
+            let host_ptr = translate_memory_access!($interpreter, vm_addr, AccessType::Load, pc, $T);
+            $interpreter.reg[dst] = unsafe { *host_ptr as u64 };
+            trace_memory_access!($interpreter, config, pc, AccessType::Load, vm_addr, $interpreter.reg[dst], $T);
+            Ok(None)
+        }
+    )*};
+}
+
+/// Defines handlers which store a value to the memory addressed by `dst + off`
+macro_rules! store {
+    ($($name:ident($T:ty, |$interpreter:ident, $insn:ident| $value:expr);)*) => {$(
+        fn $name<E: UserDefinedError, I: InstructionMeter>(
+            $interpreter: &mut Interpreter<E, I>,
+            $insn: &ThreadedInstruction<E, I>,
+            pc: usize,
+        ) -> Result<Option<u64>, EbpfError<E>> {
+            let config = $interpreter.vm.executable.get_config();
+            let vm_addr = ($interpreter.reg[$insn.dst as usize] as i64).wrapping_add($insn.off) as u64;
+            let host_ptr = translate_memory_access!($interpreter, vm_addr, AccessType::Store, pc, $T);
+            journal_store!($interpreter, config, vm_addr, host_ptr, $value, $T);
+            unsafe { *host_ptr = $value as $T };
+            trace_memory_access!($interpreter, config, pc, AccessType::Store, vm_addr, $value, $T);
+            Ok(None)
+        }
+    )*};
+}
+
+/// Defines handlers which atomically modify the memory addressed by `dst + off`
+macro_rules! atomic {
+    ($($name:ident($T:ty);)*) => {$(
+        fn $name<E: UserDefinedError, I: InstructionMeter>(
+            interpreter: &mut Interpreter<E, I>,
+            insn: &ThreadedInstruction<E, I>,
+            pc: usize,
+        ) -> Result<Option<u64>, EbpfError<E>> {
+            let config = interpreter.vm.executable.get_config();
+            atomic_memory_access!(interpreter, config, pc, insn.dst as usize, insn.src as usize, insn.off, insn.imm, $T);
+            Ok(None)
+        }
+    )*};
+}
+
+/// Defines handlers which replace `dst` by a function of `dst` and the immediate
+macro_rules! alu_imm {
+    ($($name:ident(|$dst:ident, $imm:ident| $value:expr);)*) => {$(
+        fn $name<E: UserDefinedError, I: InstructionMeter>(
+            interpreter: &mut Interpreter<E, I>,
+            insn: &ThreadedInstruction<E, I>,
+            _pc: usize,
+        ) -> Result<Option<u64>, EbpfError<E>> {
+            let $dst = interpreter.reg[insn.dst as usize];
+            let $imm = insn.imm;
+            interpreter.reg[insn.dst as usize] = $value;
+            Ok(None)
+        }
+    )*};
+}
+
+/// Defines handlers which replace `dst` by a function of `dst` and `src`
+macro_rules! alu_reg {
+    ($($name:ident(|$dst:ident, $src:ident| $value:expr);)*) => {$(
+        fn $name<E: UserDefinedError, I: InstructionMeter>(
+            interpreter: &mut Interpreter<E, I>,
+            insn: &ThreadedInstruction<E, I>,
+            _pc: usize,
+        ) -> Result<Option<u64>, EbpfError<E>> {
+            let $dst = interpreter.reg[insn.dst as usize];
+            let $src = interpreter.reg[insn.src as usize];
+            interpreter.reg[insn.dst as usize] = $value;
+            Ok(None)
+        }
+    )*};
+}
+
+/// Defines handlers which fail if the divisor is zero or the division overflows
+macro_rules! alu_checked {
+    ($($name:ident(|$dst:ident, $operand:ident: $operand_value:ident| $value:expr, zero: $zero:expr, overflow: $overflow:expr);)*) => {$(
+        fn $name<E: UserDefinedError, I: InstructionMeter>(
+            interpreter: &mut Interpreter<E, I>,
+            insn: &ThreadedInstruction<E, I>,
+            pc: usize,
+        ) -> Result<Option<u64>, EbpfError<E>> {
+            let $dst = interpreter.reg[insn.dst as usize];
+            let $operand = operand!(interpreter, insn, $operand_value);
+            if $zero {
+                return Err(EbpfError::DivideByZero(pc + ebpf::ELF_INSN_DUMP_OFFSET));
+            }
+            if $overflow {
+                return Err(EbpfError::DivideOverflow(pc + ebpf::ELF_INSN_DUMP_OFFSET));
+            }
+            interpreter.reg[insn.dst as usize] = $value;
+            Ok(None)
+        }
+    )*};
+}
+
+/// Selects the second operand of an instruction
+macro_rules! operand {
+    ($interpreter:ident, $insn:ident, imm) => {
+        $insn.imm
+    };
+    ($interpreter:ident, $insn:ident, src) => {
+        $interpreter.reg[$insn.src as usize]
+    };
+}
+
+/// Defines handlers which jump to the target if the condition holds
+macro_rules! jump {
+    ($($name:ident(|$dst:ident, $operand:ident: $operand_value:ident| $condition:expr);)*) => {$(
+        fn $name<E: UserDefinedError, I: InstructionMeter>(
+            interpreter: &mut Interpreter<E, I>,
+            insn: &ThreadedInstruction<E, I>,
+            _pc: usize,
+        ) -> Result<Option<u64>, EbpfError<E>> {
+            let $dst = interpreter.reg[insn.dst as usize];
+            let $operand = operand!(interpreter, insn, $operand_value);
+            if $condition {
+                interpreter.pc = insn.target_pc;
+            }
+            Ok(None)
+        }
+    )*};
+}
+
+// BPF_LD class
+load! {
+    ld_abs_b(u8, |interpreter, insn| (insn.imm as u64, 0));
+    ld_abs_h(u16, |interpreter, insn| (insn.imm as u64, 0));
+    ld_abs_w(u32, |interpreter, insn| (insn.imm as u64, 0));
+    ld_abs_dw(u64, |interpreter, insn| (insn.imm as u64, 0));
+    ld_ind_b(u8, |interpreter, insn| ((insn.imm as u64).wrapping_add(interpreter.reg[insn.src as usize]), 0));
+    ld_ind_h(u16, |interpreter, insn| ((insn.imm as u64).wrapping_add(interpreter.reg[insn.src as usize]), 0));
+    ld_ind_w(u32, |interpreter, insn| ((insn.imm as u64).wrapping_add(interpreter.reg[insn.src as usize]), 0));
+    ld_ind_dw(u64, |interpreter, insn| ((insn.imm as u64).wrapping_add(interpreter.reg[insn.src as usize]), 0));
+}
+
+// BPF_LDX class
+load! {
+    ld_b_reg(u8, |interpreter, insn| ((interpreter.reg[insn.src as usize] as i64).wrapping_add(insn.off) as u64, insn.dst as usize));
+    ld_h_reg(u16, |interpreter, insn| ((interpreter.reg[insn.src as usize] as i64).wrapping_add(insn.off) as u64, insn.dst as usize));
+    ld_w_reg(u32, |interpreter, insn| ((interpreter.reg[insn.src as usize] as i64).wrapping_add(insn.off) as u64, insn.dst as usize));
+    ld_dw_reg(u64, |interpreter, insn| ((interpreter.reg[insn.src as usize] as i64).wrapping_add(insn.off) as u64, insn.dst as usize));
+    ld_sb_reg(i8, |interpreter, insn| ((interpreter.reg[insn.src as usize] as i64).wrapping_add(insn.off) as u64, insn.dst as usize));
+    ld_sh_reg(i16, |interpreter, insn| ((interpreter.reg[insn.src as usize] as i64).wrapping_add(insn.off) as u64, insn.dst as usize));
+    ld_sw_reg(i32, |interpreter, insn| ((interpreter.reg[insn.src as usize] as i64).wrapping_add(insn.off) as u64, insn.dst as usize));
+}
+
+// BPF_ST and BPF_STX class
+store! {
+    st_b_imm(u8, |interpreter, insn| insn.imm);
+    st_h_imm(u16, |interpreter, insn| insn.imm);
+    st_w_imm(u32, |interpreter, insn| insn.imm);
+    st_dw_imm(u64, |interpreter, insn| insn.imm);
+    st_b_reg(u8, |interpreter, insn| interpreter.reg[insn.src as usize]);
+    st_h_reg(u16, |interpreter, insn| interpreter.reg[insn.src as usize]);
+    st_w_reg(u32, |interpreter, insn| interpreter.reg[insn.src as usize]);
+    st_dw_reg(u64, |interpreter, insn| interpreter.reg[insn.src as usize]);
+}
+atomic! {
+    st_w_atomic(u32);
+    st_dw_atomic(u64);
+}
+
+// BPF_ALU class
+alu_imm! {
+    add32_imm(|dst, imm| (dst as i32).wrapping_add(imm as i32) as u64);
+    sub32_imm(|dst, imm| (dst as i32).wrapping_sub(imm as i32) as u64);
+    mul32_imm(|dst, imm| (dst as i32).wrapping_mul(imm as i32) as u64);
+    div32_imm(|dst, imm| (dst as u32 / imm as u32) as u64);
+    or32_imm(|dst, imm| (dst as u32 | imm as u32) as u64);
+    and32_imm(|dst, imm| (dst as u32 & imm as u32) as u64);
+    lsh32_imm(|dst, imm| (dst as u32).wrapping_shl(imm as u32) as u64);
+    rsh32_imm(|dst, imm| (dst as u32).wrapping_shr(imm as u32) as u64);
+    neg32(|dst, _imm| (dst as i32).wrapping_neg() as u64 & (u32::MAX as u64));
+    mod32_imm(|dst, imm| (dst as u32 % imm as u32) as u64);
+    xor32_imm(|dst, imm| (dst as u32 ^ imm as u32) as u64);
+    mov32_imm(|_dst, imm| imm as u32 as u64);
+    arsh32_imm(|dst, imm| (dst as i32).wrapping_shr(imm as u32) as u64 & (u32::MAX as u64));
+    le16(|dst, _imm| (dst as u16).to_le() as u64);
+    le32(|dst, _imm| (dst as u32).to_le() as u64);
+    le64(|dst, _imm| dst.to_le());
+    be16(|dst, _imm| (dst as u16).to_be() as u64);
+    be32(|dst, _imm| (dst as u32).to_be() as u64);
+    be64(|dst, _imm| dst.to_be());
+    bswap16(|dst, _imm| (dst as u16).swap_bytes() as u64);
+    bswap32(|dst, _imm| (dst as u32).swap_bytes() as u64);
+    bswap64(|dst, _imm| dst.swap_bytes());
+}
+alu_reg! {
+    add32_reg(|dst, src| (dst as i32).wrapping_add(src as i32) as u64);
+    sub32_reg(|dst, src| (dst as i32).wrapping_sub(src as i32) as u64);
+    mul32_reg(|dst, src| (dst as i32).wrapping_mul(src as i32) as u64);
+    or32_reg(|dst, src| (dst as u32 | src as u32) as u64);
+    and32_reg(|dst, src| (dst as u32 & src as u32) as u64);
+    lsh32_reg(|dst, src| (dst as u32).wrapping_shl(src as u32) as u64);
+    rsh32_reg(|dst, src| (dst as u32).wrapping_shr(src as u32) as u64);
+    xor32_reg(|dst, src| (dst as u32 ^ src as u32) as u64);
+    mov32_reg(|_dst, src| src as u32 as u64);
+    mov32sx8(|_dst, src| src as i8 as i32 as u32 as u64);
+    mov32sx16(|_dst, src| src as i16 as i32 as u32 as u64);
+    arsh32_reg(|dst, src| (dst as i32).wrapping_shr(src as u32) as u64 & (u32::MAX as u64));
+}
+alu_checked! {
+    div32_reg(|dst, src: src| (dst as u32 / src as u32) as u64, zero: src as u32 == 0, overflow: false);
+    sdiv32_imm(|dst, imm: imm| (dst as i32 / imm as i32) as u64, zero: false, overflow: dst as i32 == i32::MIN && imm == -1);
+    sdiv32_reg(|dst, src: src| (dst as i32 / src as i32) as u64, zero: src as i32 == 0, overflow: dst as i32 == i32::MIN && src as i32 == -1);
+    mod32_reg(|dst, src: src| (dst as u32 % src as u32) as u64, zero: src as u32 == 0, overflow: false);
+    smod32_imm(|dst, imm: imm| (dst as i32 % imm as i32) as u32 as u64, zero: false, overflow: dst as i32 == i32::MIN && imm == -1);
+    smod32_reg(|dst, src: src| (dst as i32 % src as i32) as u32 as u64, zero: src as i32 == 0, overflow: dst as i32 == i32::MIN && src as i32 == -1);
+}
+
+// BPF_ALU64 class
+alu_imm! {
+    add64_imm(|dst, imm| dst.wrapping_add(imm as u64));
+    sub64_imm(|dst, imm| dst.wrapping_sub(imm as u64));
+    mul64_imm(|dst, imm| dst.wrapping_mul(imm as u64));
+    div64_imm(|dst, imm| dst / imm as u64);
+    or64_imm(|dst, imm| dst | imm as u64);
+    and64_imm(|dst, imm| dst & imm as u64);
+    lsh64_imm(|dst, imm| dst.wrapping_shl(imm as u32));
+    rsh64_imm(|dst, imm| dst.wrapping_shr(imm as u32));
+    neg64(|dst, _imm| (dst as i64).wrapping_neg() as u64);
+    mod64_imm(|dst, imm| dst % imm as u64);
+    xor64_imm(|dst, imm| dst ^ imm as u64);
+    mov64_imm(|_dst, imm| imm as u64);
+    arsh64_imm(|dst, imm| (dst as i64).wrapping_shr(imm as u32) as u64);
+}
+alu_reg! {
+    add64_reg(|dst, src| dst.wrapping_add(src));
+    sub64_reg(|dst, src| dst.wrapping_sub(src));
+    mul64_reg(|dst, src| dst.wrapping_mul(src));
+    or64_reg(|dst, src| dst | src);
+    and64_reg(|dst, src| dst & src);
+    lsh64_reg(|dst, src| dst.wrapping_shl(src as u32));
+    rsh64_reg(|dst, src| dst.wrapping_shr(src as u32));
+    xor64_reg(|dst, src| dst ^ src);
+    mov64_reg(|_dst, src| src);
+    movsx8(|_dst, src| src as i8 as i64 as u64);
+    movsx16(|_dst, src| src as i16 as i64 as u64);
+    movsx32(|_dst, src| src as i32 as i64 as u64);
+    arsh64_reg(|dst, src| (dst as i64).wrapping_shr(src as u32) as u64);
+}
+alu_checked! {
+    div64_reg(|dst, src: src| dst / src, zero: src == 0, overflow: false);
+    sdiv64_imm(|dst, imm: imm| (dst as i64 / imm) as u64, zero: false, overflow: dst as i64 == i64::MIN && imm == -1);
+    sdiv64_reg(|dst, src: src| (dst as i64 / src as i64) as u64, zero: src == 0, overflow: dst as i64 == i64::MIN && src as i64 == -1);
+    mod64_reg(|dst, src: src| dst % src, zero: src == 0, overflow: false);
+    smod64_imm(|dst, imm: imm| (dst as i64 % imm) as u64, zero: false, overflow: dst as i64 == i64::MIN && imm == -1);
+    smod64_reg(|dst, src: src| (dst as i64 % src as i64) as u64, zero: src == 0, overflow: dst as i64 == i64::MIN && src as i64 == -1);
+}
+
+// BPF_JMP class
+jump! {
+    ja(|_dst, _imm: imm| true);
+    jeq_imm(|dst, imm: imm| dst == imm as u64);
+    jeq_reg(|dst, src: src| dst == src);
+    jgt_imm(|dst, imm: imm| dst > imm as u64);
+    jgt_reg(|dst, src: src| dst > src);
+    jge_imm(|dst, imm: imm| dst >= imm as u64);
+    jge_reg(|dst, src: src| dst >= src);
+    jlt_imm(|dst, imm: imm| dst < imm as u64);
+    jlt_reg(|dst, src: src| dst < src);
+    jle_imm(|dst, imm: imm| dst <= imm as u64);
+    jle_reg(|dst, src: src| dst <= src);
+    jset_imm(|dst, imm: imm| dst & imm as u64 != 0);
+    jset_reg(|dst, src: src| dst & src != 0);
+    jne_imm(|dst, imm: imm| dst != imm as u64);
+    jne_reg(|dst, src: src| dst != src);
+    jsgt_imm(|dst, imm: imm| dst as i64 > imm);
+    jsgt_reg(|dst, src: src| dst as i64 > src as i64);
+    jsge_imm(|dst, imm: imm| dst as i64 >= imm);
+    jsge_reg(|dst, src: src| dst as i64 >= src as i64);
+    jslt_imm(|dst, imm: imm| (dst as i64) < imm);
+    jslt_reg(|dst, src: src| (dst as i64) < src as i64);
+    jsle_imm(|dst, imm: imm| dst as i64 <= imm);
+    jsle_reg(|dst, src: src| dst as i64 <= src as i64);
+}
+
+// BPF_JMP32 class
+jump! {
+    jeq32_imm(|dst, imm: imm| dst as u32 == imm as u32);
+    jeq32_reg(|dst, src: src| dst as u32 == src as u32);
+    jgt32_imm(|dst, imm: imm| dst as u32 > imm as u32);
+    jgt32_reg(|dst, src: src| dst as u32 > src as u32);
+    jge32_imm(|dst, imm: imm| dst as u32 >= imm as u32);
+    jge32_reg(|dst, src: src| dst as u32 >= src as u32);
+    jlt32_imm(|dst, imm: imm| (dst as u32) < imm as u32);
+    jlt32_reg(|dst, src: src| (dst as u32) < src as u32);
+    jle32_imm(|dst, imm: imm| dst as u32 <= imm as u32);
+    jle32_reg(|dst, src: src| dst as u32 <= src as u32);
+    jset32_imm(|dst, imm: imm| dst as u32 & imm as u32 != 0);
+    jset32_reg(|dst, src: src| dst as u32 & src as u32 != 0);
+    jne32_imm(|dst, imm: imm| dst as u32 != imm as u32);
+    jne32_reg(|dst, src: src| dst as u32 != src as u32);
+    jsgt32_imm(|dst, imm: imm| dst as i32 > imm as i32);
+    jsgt32_reg(|dst, src: src| dst as i32 > src as i32);
+    jsge32_imm(|dst, imm: imm| dst as i32 >= imm as i32);
+    jsge32_reg(|dst, src: src| dst as i32 >= src as i32);
+    jslt32_imm(|dst, imm: imm| (dst as i32) < imm as i32);
+    jslt32_reg(|dst, src: src| (dst as i32) < src as i32);
+    jsle32_imm(|dst, imm: imm| dst as i32 <= imm as i32);
+    jsle32_reg(|dst, src: src| dst as i32 <= src as i32);
+}
+
+fn resize_stack<E: UserDefinedError, I: InstructionMeter>(
+    interpreter: &mut Interpreter<E, I>,
+    insn: &ThreadedInstruction<E, I>,
+    _pc: usize,
+) -> Result<Option<u64>, EbpfError<E>> {
+    interpreter.vm.stack.resize_stack(insn.imm);
+    Ok(None)
+}
+
+fn unexpected_stack_ptr_instruction<E: UserDefinedError, I: InstructionMeter>(
+    _interpreter: &mut Interpreter<E, I>,
+    _insn: &ThreadedInstruction<E, I>,
+    _pc: usize,
+) -> Result<Option<u64>, EbpfError<E>> {
+    #[cfg(debug_assertions)]
+    unreachable!("unexpected insn on r11");
+    #[cfg(not(debug_assertions))]
+    Ok(None)
+}
+
+fn incomplete_lddw<E: UserDefinedError, I: InstructionMeter>(
+    _interpreter: &mut Interpreter<E, I>,
+    _insn: &ThreadedInstruction<E, I>,
+    pc: usize,
+) -> Result<Option<u64>, EbpfError<E>> {
+    Err(EbpfError::ExecutionOverrun(
+        pc + 1 + ebpf::ELF_INSN_DUMP_OFFSET,
+    ))
+}
+
+fn call_reg<E: UserDefinedError, I: InstructionMeter>(
+    interpreter: &mut Interpreter<E, I>,
+    insn: &ThreadedInstruction<E, I>,
+    pc: usize,
+) -> Result<Option<u64>, EbpfError<E>> {
+    let target_address = interpreter.reg[insn.imm as usize];
+    interpreter.reg[ebpf::FRAME_PTR_REG] = interpreter.vm.stack.push(
+        &interpreter.reg[ebpf::FIRST_SCRATCH_REG..ebpf::FIRST_SCRATCH_REG + ebpf::SCRATCH_REGS],
+        interpreter.pc,
+    )?;
+    if target_address < interpreter.vm.program_vm_addr {
+        return Err(EbpfError::CallOutsideTextSegment(
+            pc + ebpf::ELF_INSN_DUMP_OFFSET,
+            target_address / ebpf::INSN_SIZE as u64 * ebpf::INSN_SIZE as u64,
+        ));
+    }
+    interpreter.pc = interpreter.check_pc(
+        pc,
+        (target_address - interpreter.vm.program_vm_addr) as usize / ebpf::INSN_SIZE,
+    )?;
+    Ok(None)
+}
+
+fn call_syscall<E: UserDefinedError, I: InstructionMeter>(
+    interpreter: &mut Interpreter<E, I>,
+    insn: &ThreadedInstruction<E, I>,
+    _pc: usize,
+) -> Result<Option<u64>, EbpfError<E>> {
+    let config = interpreter.vm.executable.get_config();
+    if config.enable_instruction_meter {
+        interpreter
+            .instruction_meter
+            .consume(interpreter.due_insn_count);
+    }
+    interpreter.due_insn_count = 0;
+    let mut result: ProgramResult<E> = Ok(0);
+    (unsafe { std::mem::transmute::<u64, SyscallFunction<E, *mut u8>>(insn.imm as u64) })(
+        interpreter.vm.syscall_context_objects[SYSCALL_CONTEXT_OBJECTS_OFFSET + insn.off as usize],
+        interpreter.reg[1],
+        interpreter.reg[2],
+        interpreter.reg[3],
+        interpreter.reg[4],
+        interpreter.reg[5],
+        &mut interpreter.vm.memory_mapping,
+        &mut result,
+    );
+    interpreter.reg[0] = result?;
+    if config.enable_instruction_meter {
+        interpreter.remaining_insn_count = interpreter.instruction_meter.get_remaining();
+    }
+    Ok(None)
+}
+
+fn call_function<E: UserDefinedError, I: InstructionMeter>(
+    interpreter: &mut Interpreter<E, I>,
+    insn: &ThreadedInstruction<E, I>,
+    pc: usize,
+) -> Result<Option<u64>, EbpfError<E>> {
+    interpreter.reg[ebpf::FRAME_PTR_REG] = interpreter.vm.stack.push(
+        &interpreter.reg[ebpf::FIRST_SCRATCH_REG..ebpf::FIRST_SCRATCH_REG + ebpf::SCRATCH_REGS],
+        interpreter.pc,
+    )?;
+    interpreter.pc = interpreter.check_pc(pc, insn.target_pc)?;
+    Ok(None)
+}
+
+fn call_syscall_and_function<E: UserDefinedError, I: InstructionMeter>(
+    interpreter: &mut Interpreter<E, I>,
+    insn: &ThreadedInstruction<E, I>,
+    pc: usize,
+) -> Result<Option<u64>, EbpfError<E>> {
+    call_syscall(interpreter, insn, pc)?;
+    call_function(interpreter, insn, pc)
+}
+
+fn call_unresolved<E: UserDefinedError, I: InstructionMeter>(
+    interpreter: &mut Interpreter<E, I>,
+    _insn: &ThreadedInstruction<E, I>,
+    pc: usize,
+) -> Result<Option<u64>, EbpfError<E>> {
+    if interpreter
+        .vm
+        .executable
+        .get_config()
+        .disable_unresolved_symbols_at_runtime
+    {
+        return Err(EbpfError::UnsupportedInstruction(
+            pc + ebpf::ELF_INSN_DUMP_OFFSET,
+        ));
+    }
+    interpreter.vm.executable.report_unresolved_symbol(pc)?;
+    Ok(None)
+}
+
+fn exit<E: UserDefinedError, I: InstructionMeter>(
+    interpreter: &mut Interpreter<E, I>,
+    _insn: &ThreadedInstruction<E, I>,
+    pc: usize,
+) -> Result<Option<u64>, EbpfError<E>> {
+    match interpreter.vm.stack.pop::<E>() {
+        Ok((saved_reg, frame_ptr, ptr)) => {
+            // Return from BPF to BPF call
+            interpreter.reg[ebpf::FIRST_SCRATCH_REG..ebpf::FIRST_SCRATCH_REG + ebpf::SCRATCH_REGS]
+                .copy_from_slice(&saved_reg);
+            interpreter.reg[ebpf::FRAME_PTR_REG] = frame_ptr;
+            interpreter.pc = interpreter.check_pc(pc, ptr)?;
+            Ok(None)
+        }
+        _ => Ok(Some(interpreter.reg[0])),
+    }
+}
+
+fn invalid_instruction<E: UserDefinedError, I: InstructionMeter>(
+    _interpreter: &mut Interpreter<E, I>,
+    _insn: &ThreadedInstruction<E, I>,
+    pc: usize,
+) -> Result<Option<u64>, EbpfError<E>> {
+    Err(EbpfError::InvalidInstruction(
+        pc + ebpf::ELF_INSN_DUMP_OFFSET,
+    ))
+}
+
+fn unsupported_instruction<E: UserDefinedError, I: InstructionMeter>(
+    _interpreter: &mut Interpreter<E, I>,
+    _insn: &ThreadedInstruction<E, I>,
+    pc: usize,
+) -> Result<Option<u64>, EbpfError<E>> {
+    Err(EbpfError::UnsupportedInstruction(
+        pc + ebpf::ELF_INSN_DUMP_OFFSET,
+    ))
+}
@@ -0,0 +1,123 @@
+#![allow(clippy::integer_arithmetic)]
+//! Tiered execution
+//!
+//! [EbpfVm::execute_program](crate::vm::EbpfVm::execute_program) starts a program in the
+//! interpreter and counts its invocations per [Executable]. Once the count reaches
+//! [Config::jit_promotion_threshold](crate::vm::Config) the program is JIT-compiled on a
+//! background thread. Invocations do not wait for the compilation, they stay in the interpreter
+//! until the compiled program is installed in the executable. If the JIT fails to compile the
+//! program it keeps being interpreted.
+//!
+//! A program which was compiled explicitly, using [Executable::jit_compile], runs in the JIT
+//! from the first invocation on.
+
+use crate::{elf::Executable, error::UserDefinedError, jit::JitProgram, vm::InstructionMeter};
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, OnceLock,
+    },
+    thread::{self, JoinHandle},
+};
+
+/// The backend a program is executed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutionTier {
+    /// The interpreter (or the threaded-code interpreter if the program was pre-decoded)
+    Interpreter,
+    /// The JIT-compiled machine code
+    Jit,
+}
+
+/// Invocation counter and background compilation of an executable
+pub(crate) struct Tiering<E: UserDefinedError, I: InstructionMeter> {
+    invocation_count: AtomicUsize,
+    background_compilation: Mutex<Option<JoinHandle<()>>>,
+    /// `None` if the background compilation failed
+    compiled_program: OnceLock<Option<JitProgram<E, I>>>,
+}
+
+impl<E: UserDefinedError, I: InstructionMeter> fmt::Debug for Tiering<E, I> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("Tiering")
+            .field("invocation_count", &self.get_invocation_count())
+            .field("compiled_program", &self.get_compiled_program())
+            .finish()
+    }
+}
+
+impl<E: UserDefinedError, I: InstructionMeter> PartialEq for Tiering<E, I> {
+    fn eq(&self, other: &Self) -> bool {
+        self.get_compiled_program() == other.get_compiled_program()
+    }
+}
+
+/// Lets the background thread access the executable which started it
+struct ExecutablePointer<E: UserDefinedError, I: InstructionMeter>(*const Executable<E, I>);
+
+// The executable is pinned and joins the background thread before it is dropped or mutated
+unsafe impl<E: UserDefinedError, I: InstructionMeter> Send for ExecutablePointer<E, I> {}
+
+impl<E: UserDefinedError, I: InstructionMeter> Tiering<E, I> {
+    pub(crate) fn new() -> Self {
+        Self {
+            invocation_count: AtomicUsize::new(0),
+            background_compilation: Mutex::new(None),
+            compiled_program: OnceLock::new(),
+        }
+    }
+
+    pub(crate) fn get_invocation_count(&self) -> usize {
+        self.invocation_count.load(Ordering::Relaxed)
+    }
+
+    /// Counts an invocation, returns the number of invocations including this one
+    pub(crate) fn record_invocation(&self) -> usize {
+        self.invocation_count
+            .fetch_add(1, Ordering::Relaxed)
+            .saturating_add(1)
+    }
+
+    /// Get the program compiled in the background, if it is finished
+    pub(crate) fn get_compiled_program(&self) -> Option<&JitProgram<E, I>> {
+        self.compiled_program.get().and_then(Option::as_ref)
+    }
+
+    /// Starts compiling the executable on a background thread, unless that already happened
+    pub(crate) fn start_background_compilation(&self, executable: &Executable<E, I>)
+    where
+        I: 'static,
+    {
+        let mut background_compilation = self.background_compilation.lock().unwrap();
+        if background_compilation.is_some() || self.compiled_program.get().is_some() {
+            return;
+        }
+        let executable = ExecutablePointer(executable as *const Executable<E, I>);
+        let spawn_result = thread::Builder::new()
+            .name("rbpf-jit".to_string())
+            .spawn(move || {
+                let executable = unsafe { &*executable.0 };
+                let compiled_program = JitProgram::new_unpinned(executable).ok();
+                let _ = executable
+                    .get_tiering()
+                    .compiled_program
+                    .set(compiled_program);
+            });
+        match spawn_result {
+            Ok(join_handle) => *background_compilation = Some(join_handle),
+            Err(_) => {
+                let _ = self.compiled_program.set(None);
+            }
+        }
+    }
+
+    /// Blocks until the background compilation (if any) is finished
+    pub(crate) fn wait_for_background_compilation(&self) {
+        // Holding the lock keeps others from starting another compilation in the meantime
+        let mut background_compilation = self.background_compilation.lock().unwrap();
+        if let Some(join_handle) = background_compilation.take() {
+            let _ = join_handle.join();
+        }
+    }
+}
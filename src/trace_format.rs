@@ -0,0 +1,378 @@
+#![allow(clippy::integer_arithmetic)]
+//! Structured export and import of instruction traces
+//!
+//! Two formats are supported, both covering the instruction log of a [Tracer]
+//! (the store journal is not exported):
+//!
+//! - JSON Lines: One object per instruction with the keys `index`, `pc`
+//!   (instruction index, not offset by `ELF_INSN_DUMP_OFFSET`), `regs`
+//!   (r0 to r10 as hex strings, as JSON numbers can not hold 64 bits exactly),
+//!   `insn` (disassembly) and `function` (enclosing function symbol or `null`).
+//! - Binary: A header (`TRACE_MAGIC`, `TRACE_VERSION` and the number of entries)
+//!   followed by one record per instruction. Each record starts with a 16 bit mask
+//!   of the values (r0 to r10 and pc) which changed relative to the previous
+//!   instruction, followed by the zigzag LEB128 encoded difference of each of them.
+
+use crate::{
+    disassembler::disassemble_instruction,
+    error::UserDefinedError,
+    static_analysis::Analysis,
+    vm::{InstructionMeter, Tracer},
+};
+use byteorder::{ByteOrder, LittleEndian};
+use std::io::{BufRead, Read, Write};
+
+/// Magic bytes at the start of a binary trace
+pub const TRACE_MAGIC: &[u8; 8] = b"SBFTRACE";
+/// Version of the binary format
+pub const TRACE_VERSION: u32 = 1;
+
+/// Error definitions
+#[derive(Debug, thiserror::Error)]
+pub enum TraceFormatError {
+    /// Reading the input failed
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    /// Binary trace does not start with `TRACE_MAGIC`
+    #[error("invalid trace magic")]
+    InvalidMagic,
+    /// Binary trace written by an incompatible version
+    #[error("unsupported trace version {0}")]
+    UnsupportedVersion(u32),
+    /// Binary trace ended early
+    #[error("trace data is truncated")]
+    Truncated,
+    /// An entry could not be decoded
+    #[error("malformed trace entry {0}")]
+    MalformedEntry(usize),
+}
+
+/// Writes the log of a tracer in the JSON Lines format
+pub fn write_json_lines<W: Write, E: UserDefinedError, I: InstructionMeter>(
+    tracer: &Tracer,
+    output: &mut W,
+    analysis: &Analysis<E, I>,
+) -> Result<(), std::io::Error> {
+    let pc_to_insn_index = pc_to_insn_index(analysis);
+    for (index, entry) in tracer.log.iter().enumerate() {
+        let pc = entry[11] as usize;
+        let insn = match pc_to_insn_index.get(pc) {
+            Some(insn_index) => &analysis.instructions[*insn_index],
+            None => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("pc {} of trace entry {} is outside of the text", pc, index),
+                ))
+            }
+        };
+        write!(output, "{{\"index\":{},\"pc\":{},\"regs\":[", index, pc)?;
+        for (reg, value) in entry[0..11].iter().enumerate() {
+            if reg > 0 {
+                write!(output, ",")?;
+            }
+            write!(output, "\"{:#018x}\"", value)?;
+        }
+        write!(output, "],\"insn\":")?;
+        write_json_string(output, &disassemble_instruction(insn, analysis))?;
+        write!(output, ",\"function\":")?;
+        match analysis.functions.range(..=pc).next_back() {
+            Some((_, (_, name))) => write_json_string(output, name)?,
+            None => write!(output, "null")?,
+        }
+        writeln!(output, "}}")?;
+    }
+    Ok(())
+}
+
+/// Writes the log of a tracer in the binary format
+pub fn write_binary<W: Write>(tracer: &Tracer, output: &mut W) -> Result<(), std::io::Error> {
+    let mut header = [0u8; 20];
+    header[0..8].copy_from_slice(TRACE_MAGIC);
+    LittleEndian::write_u32(&mut header[8..12], TRACE_VERSION);
+    LittleEndian::write_u64(&mut header[12..20], tracer.log.len() as u64);
+    output.write_all(&header)?;
+    let mut previous = [0u64; 12];
+    let mut record = Vec::new();
+    for entry in tracer.log.iter() {
+        record.clear();
+        record.extend_from_slice(&[0, 0]);
+        let mut mask = 0u16;
+        for (index, (value, previous)) in entry.iter().zip(previous.iter()).enumerate() {
+            if value != previous {
+                mask |= 1 << index;
+                let delta = value.wrapping_sub(*previous) as i64;
+                write_varint(&mut record, ((delta << 1) ^ (delta >> 63)) as u64);
+            }
+        }
+        LittleEndian::write_u16(&mut record[0..2], mask);
+        output.write_all(&record)?;
+        previous = *entry;
+    }
+    Ok(())
+}
+
+/// Reads a trace in either format back into a tracer
+///
+/// The format is detected by the presence of `TRACE_MAGIC`.
+pub fn read<R: BufRead>(mut input: R) -> Result<Tracer, TraceFormatError> {
+    if input.fill_buf()?.starts_with(TRACE_MAGIC) {
+        read_binary(input)
+    } else {
+        read_json_lines(input)
+    }
+}
+
+/// Reads a trace in the JSON Lines format
+pub fn read_json_lines<R: BufRead>(input: R) -> Result<Tracer, TraceFormatError> {
+    let mut tracer = Tracer::default();
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let index = tracer.log.len();
+        let entry = parse_json_entry(&line).ok_or(TraceFormatError::MalformedEntry(index))?;
+        tracer.trace(entry);
+    }
+    Ok(tracer)
+}
+
+/// Reads a trace in the binary format
+pub fn read_binary<R: Read>(mut input: R) -> Result<Tracer, TraceFormatError> {
+    let mut header = [0u8; 20];
+    read_exact(&mut input, &mut header)?;
+    if &header[0..8] != TRACE_MAGIC {
+        return Err(TraceFormatError::InvalidMagic);
+    }
+    let version = LittleEndian::read_u32(&header[8..12]);
+    if version != TRACE_VERSION {
+        return Err(TraceFormatError::UnsupportedVersion(version));
+    }
+    let number_of_entries = LittleEndian::read_u64(&header[12..20]) as usize;
+    let mut tracer = Tracer::default();
+    let mut entry = [0u64; 12];
+    for index in 0..number_of_entries {
+        let mut mask = [0u8; 2];
+        read_exact(&mut input, &mut mask)?;
+        let mask = LittleEndian::read_u16(&mask);
+        if mask >> entry.len() != 0 {
+            return Err(TraceFormatError::MalformedEntry(index));
+        }
+        for (bit, value) in entry.iter_mut().enumerate() {
+            if mask & (1 << bit) != 0 {
+                let zigzag = read_varint(&mut input, index)?;
+                let delta = ((zigzag >> 1) as i64) ^ -((zigzag & 1) as i64);
+                *value = value.wrapping_add(delta as u64);
+            }
+        }
+        tracer.trace(entry);
+    }
+    Ok(tracer)
+}
+
+fn pc_to_insn_index<E: UserDefinedError, I: InstructionMeter>(
+    analysis: &Analysis<E, I>,
+) -> Vec<usize> {
+    let mut pc_to_insn_index = vec![
+        0usize;
+        analysis
+            .instructions
+            .last()
+            .map(|insn| insn.ptr + 2)
+            .unwrap_or(0)
+    ];
+    for (index, insn) in analysis.instructions.iter().enumerate() {
+        pc_to_insn_index[insn.ptr] = index;
+        pc_to_insn_index[insn.ptr + 1] = index;
+    }
+    pc_to_insn_index
+}
+
+fn write_json_string<W: Write>(output: &mut W, string: &str) -> Result<(), std::io::Error> {
+    write!(output, "\"")?;
+    for c in string.chars() {
+        match c {
+            '"' => write!(output, "\\\"")?,
+            '\\' => write!(output, "\\\\")?,
+            '\n' => write!(output, "\\n")?,
+            c if (c as u32) < 0x20 => write!(output, "\\u{:04x}", c as u32)?,
+            c => write!(output, "{}", c)?,
+        }
+    }
+    write!(output, "\"")
+}
+
+/// Appends an unsigned LEB128 varint, as also used by protobuf
+pub(crate) fn write_varint(output: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        output.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    output.push(value as u8);
+}
+
+fn read_varint<R: Read>(input: &mut R, index: usize) -> Result<u64, TraceFormatError> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0u8; 1];
+        read_exact(input, &mut byte)?;
+        value |= ((byte[0] & 0x7f) as u64) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(TraceFormatError::MalformedEntry(index))
+}
+
+fn read_exact<R: Read>(input: &mut R, buffer: &mut [u8]) -> Result<(), TraceFormatError> {
+    input.read_exact(buffer).map_err(|err| {
+        if err.kind() == std::io::ErrorKind::UnexpectedEof {
+            TraceFormatError::Truncated
+        } else {
+            TraceFormatError::Io(err)
+        }
+    })
+}
+
+/// Subset of JSON produced by `write_json_lines`
+enum JsonValue {
+    Null,
+    Number(u64),
+    String(String),
+    Array(Vec<JsonValue>),
+}
+
+/// Extracts the registers and pc of a single JSON Lines entry
+fn parse_json_entry(line: &str) -> Option<[u64; 12]> {
+    let mut parser = JsonParser {
+        chars: line.chars().peekable(),
+    };
+    let mut entry = [0u64; 12];
+    let mut has_pc = false;
+    let mut has_regs = false;
+    parser.expect('{')?;
+    loop {
+        let key = match parser.parse_value()? {
+            JsonValue::String(key) => key,
+            _ => return None,
+        };
+        parser.expect(':')?;
+        let value = parser.parse_value()?;
+        match (key.as_str(), value) {
+            ("pc", JsonValue::Number(pc)) => {
+                entry[11] = pc;
+                has_pc = true;
+            }
+            ("regs", JsonValue::Array(regs)) if regs.len() == 11 => {
+                for (reg, value) in entry.iter_mut().zip(regs.iter()) {
+                    *reg = match value {
+                        JsonValue::String(hex) => {
+                            u64::from_str_radix(hex.trim_start_matches("0x"), 16).ok()?
+                        }
+                        _ => return None,
+                    };
+                }
+                has_regs = true;
+            }
+            ("pc", _) | ("regs", _) => return None,
+            _ => {}
+        }
+        parser.skip_whitespace();
+        match parser.chars.next()? {
+            ',' => {}
+            '}' => break,
+            _ => return None,
+        }
+    }
+    parser.skip_whitespace();
+    if parser.chars.next().is_some() || !has_pc || !has_regs {
+        return None;
+    }
+    Some(entry)
+}
+
+struct JsonParser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl<'a> JsonParser<'a> {
+    fn skip_whitespace(&mut self) {
+        while matches!(self.chars.peek(), Some(c) if c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Option<()> {
+        self.skip_whitespace();
+        if self.chars.next()? == expected {
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn parse_value(&mut self) -> Option<JsonValue> {
+        self.skip_whitespace();
+        match *self.chars.peek()? {
+            'n' => {
+                for expected in "null".chars() {
+                    if self.chars.next()? != expected {
+                        return None;
+                    }
+                }
+                Some(JsonValue::Null)
+            }
+            '0'..='9' => {
+                let mut value = 0u64;
+                while let Some(digit) = self.chars.peek().and_then(|c| c.to_digit(10)) {
+                    value = value.checked_mul(10)?.checked_add(digit as u64)?;
+                    self.chars.next();
+                }
+                Some(JsonValue::Number(value))
+            }
+            '"' => {
+                self.chars.next();
+                let mut string = String::new();
+                loop {
+                    match self.chars.next()? {
+                        '"' => break,
+                        '\\' => match self.chars.next()? {
+                            'n' => string.push('\n'),
+                            't' => string.push('\t'),
+                            'r' => string.push('\r'),
+                            'u' => {
+                                let hex = (0..4)
+                                    .map(|_| self.chars.next())
+                                    .collect::<Option<String>>()?;
+                                string.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+                            }
+                            c => string.push(c),
+                        },
+                        c => string.push(c),
+                    }
+                }
+                Some(JsonValue::String(string))
+            }
+            '[' => {
+                self.chars.next();
+                let mut elements = Vec::new();
+                self.skip_whitespace();
+                if self.chars.peek() == Some(&']') {
+                    self.chars.next();
+                    return Some(JsonValue::Array(elements));
+                }
+                loop {
+                    elements.push(self.parse_value()?);
+                    self.skip_whitespace();
+                    match self.chars.next()? {
+                        ',' => {}
+                        ']' => break,
+                        _ => return None,
+                    }
+                }
+                Some(JsonValue::Array(elements))
+            }
+            _ => None,
+        }
+    }
+}
@@ -22,8 +22,16 @@
 //! license, so we cannot copy it).
 //!
 //! Contrary to the verifier of the Linux kernel, this one does not modify the bytecode at all.
+//!
+//! [check] stops at the first error and is meant for loading programs, while [check_all] collects
+//! every error and some warnings about suspicious code, which is meant for tooling.
 
-use crate::{ebpf, vm::Config};
+use crate::{
+    ebpf::{self, SbfVersion},
+    elf::Executable,
+    error::UserDefinedError,
+    vm::{Config, InstructionMeter},
+};
 use thiserror::Error;
 
 /// Error definitions
@@ -77,6 +85,54 @@ pub enum VerifierError {
     /// Invalid register specified
     #[error("Invalid register specified at instruction {0}")]
     InvalidRegister(usize),
+    /// UnknownAtomicOperation
+    #[error("unknown atomic operation {0:#x} (insn #{1:?})")]
+    UnknownAtomicOperation(i64, usize),
+    /// UnsupportedOffset
+    #[error("unsupported offset {0} (insn #{1:?})")]
+    UnsupportedOffset(i16, usize),
+}
+
+/// Severity of a [VerifierDiagnostic]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Severity {
+    /// The program violates the verifier's rules and would be rejected
+    Error,
+    /// The program is legal but contains a suspicious pattern
+    Warning,
+}
+
+/// A problem found by [check_all]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VerifierDiagnostic {
+    /// Index of the instruction in the program (not adjusted by `ebpf::ELF_INSN_DUMP_OFFSET`)
+    pub insn_ptr: usize,
+    /// Opcode of the instruction
+    pub opc: u8,
+    /// Severity
+    pub severity: Severity,
+    /// Human readable description
+    pub message: String,
+}
+
+impl VerifierDiagnostic {
+    fn error(prog: &[u8], insn_ptr: usize, error: VerifierError) -> Self {
+        Self {
+            insn_ptr,
+            opc: prog.get(insn_ptr * ebpf::INSN_SIZE).copied().unwrap_or(0),
+            severity: Severity::Error,
+            message: error.to_string(),
+        }
+    }
+
+    fn warning(prog: &[u8], insn_ptr: usize, message: &str) -> Self {
+        Self {
+            insn_ptr,
+            opc: ebpf::get_insn(prog, insn_ptr).opc,
+            severity: Severity::Warning,
+            message: message.to_string(),
+        }
+    }
 }
 
 fn adj_insn_ptr(insn_ptr: usize) -> usize {
@@ -109,6 +165,39 @@ fn check_imm_endian(insn: &ebpf::Insn, insn_ptr: usize) -> Result<(), VerifierEr
     }
 }
 
+fn check_atomic(insn: &ebpf::Insn, insn_ptr: usize) -> Result<(), VerifierError> {
+    match ebpf::atomic_operation(insn.imm) {
+        None => Err(VerifierError::UnknownAtomicOperation(
+            insn.imm,
+            adj_insn_ptr(insn_ptr),
+        )),
+        // All fetching operations except cmpxchg write the previous value into src
+        Some(operation)
+            if operation & ebpf::BPF_FETCH != 0
+                && operation != ebpf::BPF_CMPXCHG
+                && insn.src == ebpf::FRAME_PTR_REG as u8 =>
+        {
+            Err(VerifierError::CannotWriteR10(adj_insn_ptr(insn_ptr)))
+        }
+        Some(_) => Ok(()),
+    }
+}
+
+/// Check that the offset selects one of the variants of the instruction
+fn check_offset_variant(
+    insn: &ebpf::Insn,
+    insn_ptr: usize,
+    variants: &[i16],
+) -> Result<(), VerifierError> {
+    if !variants.contains(&insn.off) {
+        return Err(VerifierError::UnsupportedOffset(
+            insn.off,
+            adj_insn_ptr(insn_ptr),
+        ));
+    }
+    Ok(())
+}
+
 fn check_load_dw(prog: &[u8], insn_ptr: usize) -> Result<(), VerifierError> {
     if (insn_ptr + 1) * ebpf::INSN_SIZE >= prog.len() {
         // Last instruction cannot be LD_DW because there would be no 2nd DW
@@ -121,10 +210,20 @@ fn check_load_dw(prog: &[u8], insn_ptr: usize) -> Result<(), VerifierError> {
     Ok(())
 }
 
+/// Destination of a jump, which might lie outside of the program
+fn jump_target(insn: &ebpf::Insn, insn_ptr: usize) -> isize {
+    // The long jump encodes its offset in the immediate
+    let offset = if insn.opc == ebpf::GOTOL {
+        insn.imm as isize
+    } else {
+        insn.off as isize
+    };
+    insn_ptr as isize + 1 + offset
+}
+
 fn check_jmp_offset(prog: &[u8], insn_ptr: usize) -> Result<(), VerifierError> {
     let insn = ebpf::get_insn(prog, insn_ptr);
-
-    let dst_insn_ptr = insn_ptr as isize + 1 + insn.off as isize;
+    let dst_insn_ptr = jump_target(&insn, insn_ptr);
     if dst_insn_ptr < 0 || dst_insn_ptr as usize * ebpf::INSN_SIZE >= prog.len() {
         return Err(VerifierError::JumpOutOfCode(
             dst_insn_ptr as usize,
@@ -190,163 +289,458 @@ fn check_imm_register(
     Ok(())
 }
 
+/// Check a single instruction against the verifier's rules
+#[rustfmt::skip]
+fn check_insn(prog: &[u8], insn_ptr: usize, config: &Config, sbf_version: SbfVersion) -> Result<(), VerifierError> {
+    let insn = ebpf::get_insn(prog, insn_ptr);
+    let mut store = false;
+
+    match insn.opc {
+        ebpf::LD_ABS_B
+        | ebpf::LD_ABS_H
+        | ebpf::LD_ABS_W
+        | ebpf::LD_ABS_DW
+        | ebpf::LD_IND_B
+        | ebpf::LD_IND_H
+        | ebpf::LD_IND_W
+        | ebpf::LD_IND_DW if config.disable_deprecated_load_instructions => {
+            return Err(VerifierError::UnknownOpCode(insn.opc, adj_insn_ptr(insn_ptr)));
+        },
+
+        // BPF_LD class
+        ebpf::LD_ABS_B   => {},
+        ebpf::LD_ABS_H   => {},
+        ebpf::LD_ABS_W   => {},
+        ebpf::LD_ABS_DW  => {},
+        ebpf::LD_IND_B   => {},
+        ebpf::LD_IND_H   => {},
+        ebpf::LD_IND_W   => {},
+        ebpf::LD_IND_DW  => {},
+
+        ebpf::LD_DW_IMM  => { check_load_dw(prog, insn_ptr)?; },
+
+        // BPF_LDX class
+        ebpf::LD_B_REG   => {},
+        ebpf::LD_H_REG   => {},
+        ebpf::LD_W_REG   => {},
+        ebpf::LD_DW_REG  => {},
+        ebpf::LD_SB_REG  if config.enable_ebpf_v4 => {},
+        ebpf::LD_SH_REG  if config.enable_ebpf_v4 => {},
+        ebpf::LD_SW_REG  if config.enable_ebpf_v4 => {},
+
+        // BPF_ST class
+        ebpf::ST_B_IMM   => store = true,
+        ebpf::ST_H_IMM   => store = true,
+        ebpf::ST_W_IMM   => store = true,
+        ebpf::ST_DW_IMM  => store = true,
+
+        // BPF_STX class
+        ebpf::ST_B_REG   => store = true,
+        ebpf::ST_H_REG   => store = true,
+        ebpf::ST_W_REG   => store = true,
+        ebpf::ST_DW_REG  => store = true,
+        ebpf::ST_W_ATOMIC  if config.enable_atomics => { check_atomic(&insn, insn_ptr)?; store = true; },
+        ebpf::ST_DW_ATOMIC if config.enable_atomics => { check_atomic(&insn, insn_ptr)?; store = true; },
+
+        // BPF_ALU class
+        ebpf::ADD32_IMM  => {},
+        ebpf::ADD32_REG  => {},
+        ebpf::SUB32_IMM  => {},
+        ebpf::SUB32_REG  => {},
+        ebpf::MUL32_IMM  => {},
+        ebpf::MUL32_REG  => {},
+        ebpf::DIV32_IMM  => { check_imm_nonzero(&insn, insn_ptr)?; },
+        ebpf::DIV32_REG  => {},
+        ebpf::SDIV32_IMM if config.enable_sdiv => { check_imm_nonzero(&insn, insn_ptr)?; },
+        ebpf::SDIV32_REG if config.enable_sdiv => {},
+        ebpf::OR32_IMM   => {},
+        ebpf::OR32_REG   => {},
+        ebpf::AND32_IMM  => {},
+        ebpf::AND32_REG  => {},
+        ebpf::LSH32_IMM  => { check_imm_shift(&insn, insn_ptr, 32)?; },
+        ebpf::LSH32_REG  => {},
+        ebpf::RSH32_IMM  => { check_imm_shift(&insn, insn_ptr, 32)?; },
+        ebpf::RSH32_REG  => {},
+        ebpf::NEG32      => {},
+        ebpf::MOD32_IMM  if config.enable_ebpf_v4 => { check_offset_variant(&insn, insn_ptr, &[0, 1])?; check_imm_nonzero(&insn, insn_ptr)?; },
+        ebpf::MOD32_REG  if config.enable_ebpf_v4 => { check_offset_variant(&insn, insn_ptr, &[0, 1])?; },
+        ebpf::MOD32_IMM  => { check_imm_nonzero(&insn, insn_ptr)?; },
+        ebpf::MOD32_REG  => {},
+        ebpf::XOR32_IMM  => {},
+        ebpf::XOR32_REG  => {},
+        ebpf::MOV32_IMM  => {},
+        ebpf::MOV32_REG  if config.enable_ebpf_v4 => { check_offset_variant(&insn, insn_ptr, &[0, 8, 16])?; },
+        ebpf::MOV32_REG  => {},
+        ebpf::ARSH32_IMM => { check_imm_shift(&insn, insn_ptr, 32)?; },
+        ebpf::ARSH32_REG => {},
+        ebpf::LE         => { check_imm_endian(&insn, insn_ptr)?; },
+        ebpf::BE         => { check_imm_endian(&insn, insn_ptr)?; },
+        ebpf::BSWAP      if config.enable_ebpf_v4 => { check_imm_endian(&insn, insn_ptr)?; },
+
+        // BPF_ALU64 class
+        ebpf::ADD64_IMM  => {},
+        ebpf::ADD64_REG  => {},
+        ebpf::SUB64_IMM  => {},
+        ebpf::SUB64_REG  => {},
+        ebpf::MUL64_IMM  => {},
+        ebpf::MUL64_REG  => {},
+        ebpf::DIV64_IMM  => { check_imm_nonzero(&insn, insn_ptr)?; },
+        ebpf::DIV64_REG  => {},
+        ebpf::SDIV64_IMM if config.enable_sdiv => { check_imm_nonzero(&insn, insn_ptr)?; },
+        ebpf::SDIV64_REG if config.enable_sdiv => {},
+        ebpf::OR64_IMM   => {},
+        ebpf::OR64_REG   => {},
+        ebpf::AND64_IMM  => {},
+        ebpf::AND64_REG  => {},
+        ebpf::LSH64_IMM  => { check_imm_shift(&insn, insn_ptr, 64)?; },
+        ebpf::LSH64_REG  => {},
+        ebpf::RSH64_IMM  => { check_imm_shift(&insn, insn_ptr, 64)?; },
+        ebpf::RSH64_REG  => {},
+        ebpf::NEG64      => {},
+        ebpf::MOD64_IMM  if config.enable_ebpf_v4 => { check_offset_variant(&insn, insn_ptr, &[0, 1])?; check_imm_nonzero(&insn, insn_ptr)?; },
+        ebpf::MOD64_REG  if config.enable_ebpf_v4 => { check_offset_variant(&insn, insn_ptr, &[0, 1])?; },
+        ebpf::MOD64_IMM  => { check_imm_nonzero(&insn, insn_ptr)?; },
+        ebpf::MOD64_REG  => {},
+        ebpf::XOR64_IMM  => {},
+        ebpf::XOR64_REG  => {},
+        ebpf::MOV64_IMM  => {},
+        ebpf::MOV64_REG  if config.enable_ebpf_v4 => { check_offset_variant(&insn, insn_ptr, &[0, 8, 16, 32])?; },
+        ebpf::MOV64_REG  => {},
+        ebpf::ARSH64_IMM => { check_imm_shift(&insn, insn_ptr, 64)?; },
+        ebpf::ARSH64_REG => {},
+
+        // BPF_JMP class
+        ebpf::JA         => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JEQ_IMM    => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JEQ_REG    => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JGT_IMM    => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JGT_REG    => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JGE_IMM    => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JGE_REG    => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JLT_IMM    => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JLT_REG    => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JLE_IMM    => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JLE_REG    => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JSET_IMM   => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JSET_REG   => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JNE_IMM    => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JNE_REG    => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JSGT_IMM   => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JSGT_REG   => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JSGE_IMM   => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JSGE_REG   => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JSLT_IMM   => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JSLT_REG   => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JSLE_IMM   => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JSLE_REG   => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::CALL_IMM   => {},
+        ebpf::CALL_REG   => { check_imm_register(&insn, insn_ptr, config)?; },
+        ebpf::EXIT       => {},
+
+        // BPF_JMP32 class
+        ebpf::JEQ32_IMM  if config.enable_jmp32 => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JEQ32_REG  if config.enable_jmp32 => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JGT32_IMM  if config.enable_jmp32 => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JGT32_REG  if config.enable_jmp32 => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JGE32_IMM  if config.enable_jmp32 => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JGE32_REG  if config.enable_jmp32 => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JLT32_IMM  if config.enable_jmp32 => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JLT32_REG  if config.enable_jmp32 => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JLE32_IMM  if config.enable_jmp32 => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JLE32_REG  if config.enable_jmp32 => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JSET32_IMM if config.enable_jmp32 => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JSET32_REG if config.enable_jmp32 => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JNE32_IMM  if config.enable_jmp32 => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JNE32_REG  if config.enable_jmp32 => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JSGT32_IMM if config.enable_jmp32 => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JSGT32_REG if config.enable_jmp32 => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JSGE32_IMM if config.enable_jmp32 => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JSGE32_REG if config.enable_jmp32 => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JSLT32_IMM if config.enable_jmp32 => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JSLT32_REG if config.enable_jmp32 => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JSLE32_IMM if config.enable_jmp32 => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::JSLE32_REG if config.enable_jmp32 => { check_jmp_offset(prog, insn_ptr)?; },
+        ebpf::GOTOL      if config.enable_ebpf_v4 => { check_jmp_offset(prog, insn_ptr)?; },
+
+        _                => {
+            return Err(VerifierError::UnknownOpCode(insn.opc, adj_insn_ptr(insn_ptr)));
+        }
+    }
+
+    check_registers(&insn, store, insn_ptr, sbf_version.dynamic_stack_frames())
+}
+
 /// Check the program against the verifier's rules
 #[rustfmt::skip]
-pub fn check(prog: &[u8], config: &Config) -> Result<(), VerifierError> {
+pub fn check(prog: &[u8], config: &Config, sbf_version: SbfVersion) -> Result<(), VerifierError> {
     check_prog_len(prog)?;
 
     let mut insn_ptr: usize = 0;
     while (insn_ptr + 1) * ebpf::INSN_SIZE <= prog.len() {
+        check_insn(prog, insn_ptr, config, sbf_version)?;
+        insn_ptr += insn_len(prog, insn_ptr);
+    }
+
+    // insn_ptr should now be equal to number of instructions.
+    if insn_ptr != prog.len() / ebpf::INSN_SIZE {
+        return Err(VerifierError::JumpOutOfCode(adj_insn_ptr(insn_ptr), adj_insn_ptr(insn_ptr)));
+    }
+
+    Ok(())
+}
+
+/// Check the whole program and collect all diagnostics instead of stopping at the first error
+///
+/// Reports at most one error per instruction, plus warnings for legal but suspicious code:
+/// unreachable instructions, writes to r0 which a syscall overwrites before they are read and
+/// stores to the stack which are never read. The diagnostics are sorted by instruction.
+pub fn check_all<E: UserDefinedError, I: InstructionMeter>(
+    executable: &Executable<E, I>,
+) -> Vec<VerifierDiagnostic> {
+    let (_program_vm_addr, prog) = executable.get_text_bytes();
+    let config = executable.get_config();
+    let sbf_version = executable.get_sbf_version();
+    let mut diagnostics = Vec::new();
+    if let Err(error) = check_prog_len(prog) {
+        diagnostics.push(VerifierDiagnostic::error(prog, 0, error));
+    }
+    // Trailing bytes which do not form a complete instruction are ignored
+    let prog = &prog[0..prog.len() - prog.len() % ebpf::INSN_SIZE];
+    if prog.is_empty() {
+        return diagnostics;
+    }
+
+    let mut insn_ptr = 0;
+    while (insn_ptr + 1) * ebpf::INSN_SIZE <= prog.len() {
+        if let Err(error) = check_insn(prog, insn_ptr, config, sbf_version) {
+            diagnostics.push(VerifierDiagnostic::error(prog, insn_ptr, error));
+        }
+        insn_ptr += insn_len(prog, insn_ptr);
+    }
+
+    let function_starts = executable
+        .get_bpf_functions()
+        .values()
+        .map(|(pc, _name)| *pc)
+        .collect::<Vec<_>>();
+    let is_syscall = |insn: &ebpf::Insn| {
+        insn.opc == ebpf::CALL_IMM
+            && if sbf_version.static_syscalls() {
+                insn.src == 0
+            } else {
+                executable
+                    .get_syscall_registry()
+                    .lookup_syscall(insn.imm as u32)
+                    .is_some()
+            }
+    };
+    find_unreachable_code(prog, &function_starts, &mut diagnostics);
+    find_dead_writes(prog, &function_starts, is_syscall, &mut diagnostics);
+    diagnostics.sort_by_key(|diagnostic| diagnostic.insn_ptr);
+    diagnostics
+}
+
+/// Number of slots the instruction occupies
+fn insn_len(prog: &[u8], insn_ptr: usize) -> usize {
+    if ebpf::get_insn(prog, insn_ptr).opc == ebpf::LD_DW_IMM {
+        2
+    } else {
+        1
+    }
+}
+
+/// Conditional and unconditional jumps, but not calls and exit
+fn is_jump(insn: &ebpf::Insn) -> bool {
+    matches!(
+        insn.opc & ebpf::BPF_CLS_MASK,
+        ebpf::BPF_JMP | ebpf::BPF_JMP32
+    ) && !matches!(insn.opc, ebpf::CALL_IMM | ebpf::CALL_REG | ebpf::EXIT)
+}
+
+fn find_unreachable_code(
+    prog: &[u8],
+    function_starts: &[usize],
+    diagnostics: &mut Vec<VerifierDiagnostic>,
+) {
+    let insn_count = prog.len() / ebpf::INSN_SIZE;
+    let mut reachable = vec![false; insn_count];
+    let mut worklist = function_starts.to_vec();
+    while let Some(insn_ptr) = worklist.pop() {
+        if insn_ptr >= insn_count || reachable[insn_ptr] {
+            continue;
+        }
+        reachable[insn_ptr] = true;
         let insn = ebpf::get_insn(prog, insn_ptr);
-        let mut store = false;
-
-        match insn.opc {
-            ebpf::LD_ABS_B
-            | ebpf::LD_ABS_H
-            | ebpf::LD_ABS_W
-            | ebpf::LD_ABS_DW
-            | ebpf::LD_IND_B
-            | ebpf::LD_IND_H
-            | ebpf::LD_IND_W
-            | ebpf::LD_IND_DW if config.disable_deprecated_load_instructions => {
-                return Err(VerifierError::UnknownOpCode(insn.opc, adj_insn_ptr(insn_ptr)));
-            },
-
-            // BPF_LD class
-            ebpf::LD_ABS_B   => {},
-            ebpf::LD_ABS_H   => {},
-            ebpf::LD_ABS_W   => {},
-            ebpf::LD_ABS_DW  => {},
-            ebpf::LD_IND_B   => {},
-            ebpf::LD_IND_H   => {},
-            ebpf::LD_IND_W   => {},
-            ebpf::LD_IND_DW  => {},
-
-            ebpf::LD_DW_IMM  => {
-                check_load_dw(prog, insn_ptr)?;
-                insn_ptr += 1;
-            },
-
-            // BPF_LDX class
-            ebpf::LD_B_REG   => {},
-            ebpf::LD_H_REG   => {},
-            ebpf::LD_W_REG   => {},
-            ebpf::LD_DW_REG  => {},
-
-            // BPF_ST class
-            ebpf::ST_B_IMM   => store = true,
-            ebpf::ST_H_IMM   => store = true,
-            ebpf::ST_W_IMM   => store = true,
-            ebpf::ST_DW_IMM  => store = true,
-
-            // BPF_STX class
-            ebpf::ST_B_REG   => store = true,
-            ebpf::ST_H_REG   => store = true,
-            ebpf::ST_W_REG   => store = true,
-            ebpf::ST_DW_REG  => store = true,
-
-            // BPF_ALU class
-            ebpf::ADD32_IMM  => {},
-            ebpf::ADD32_REG  => {},
-            ebpf::SUB32_IMM  => {},
-            ebpf::SUB32_REG  => {},
-            ebpf::MUL32_IMM  => {},
-            ebpf::MUL32_REG  => {},
-            ebpf::DIV32_IMM  => { check_imm_nonzero(&insn, insn_ptr)?; },
-            ebpf::DIV32_REG  => {},
-            ebpf::SDIV32_IMM if config.enable_sdiv => { check_imm_nonzero(&insn, insn_ptr)?; },
-            ebpf::SDIV32_REG if config.enable_sdiv => {},
-            ebpf::OR32_IMM   => {},
-            ebpf::OR32_REG   => {},
-            ebpf::AND32_IMM  => {},
-            ebpf::AND32_REG  => {},
-            ebpf::LSH32_IMM  => { check_imm_shift(&insn, insn_ptr, 32)?; },
-            ebpf::LSH32_REG  => {},
-            ebpf::RSH32_IMM  => { check_imm_shift(&insn, insn_ptr, 32)?; },
-            ebpf::RSH32_REG  => {},
-            ebpf::NEG32      => {},
-            ebpf::MOD32_IMM  => { check_imm_nonzero(&insn, insn_ptr)?; },
-            ebpf::MOD32_REG  => {},
-            ebpf::XOR32_IMM  => {},
-            ebpf::XOR32_REG  => {},
-            ebpf::MOV32_IMM  => {},
-            ebpf::MOV32_REG  => {},
-            ebpf::ARSH32_IMM => { check_imm_shift(&insn, insn_ptr, 32)?; },
-            ebpf::ARSH32_REG => {},
-            ebpf::LE         => { check_imm_endian(&insn, insn_ptr)?; },
-            ebpf::BE         => { check_imm_endian(&insn, insn_ptr)?; },
-
-            // BPF_ALU64 class
-            ebpf::ADD64_IMM  => {},
-            ebpf::ADD64_REG  => {},
-            ebpf::SUB64_IMM  => {},
-            ebpf::SUB64_REG  => {},
-            ebpf::MUL64_IMM  => {},
-            ebpf::MUL64_REG  => {},
-            ebpf::DIV64_IMM  => { check_imm_nonzero(&insn, insn_ptr)?; },
-            ebpf::DIV64_REG  => {},
-            ebpf::SDIV64_IMM if config.enable_sdiv => { check_imm_nonzero(&insn, insn_ptr)?; },
-            ebpf::SDIV64_REG if config.enable_sdiv => {},
-            ebpf::OR64_IMM   => {},
-            ebpf::OR64_REG   => {},
-            ebpf::AND64_IMM  => {},
-            ebpf::AND64_REG  => {},
-            ebpf::LSH64_IMM  => { check_imm_shift(&insn, insn_ptr, 64)?; },
-            ebpf::LSH64_REG  => {},
-            ebpf::RSH64_IMM  => { check_imm_shift(&insn, insn_ptr, 64)?; },
-            ebpf::RSH64_REG  => {},
-            ebpf::NEG64      => {},
-            ebpf::MOD64_IMM  => { check_imm_nonzero(&insn, insn_ptr)?; },
-            ebpf::MOD64_REG  => {},
-            ebpf::XOR64_IMM  => {},
-            ebpf::XOR64_REG  => {},
-            ebpf::MOV64_IMM  => {},
-            ebpf::MOV64_REG  => {},
-            ebpf::ARSH64_IMM => { check_imm_shift(&insn, insn_ptr, 64)?; },
-            ebpf::ARSH64_REG => {},
-
-            // BPF_JMP class
-            ebpf::JA         => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JEQ_IMM    => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JEQ_REG    => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JGT_IMM    => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JGT_REG    => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JGE_IMM    => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JGE_REG    => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JLT_IMM    => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JLT_REG    => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JLE_IMM    => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JLE_REG    => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JSET_IMM   => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JSET_REG   => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JNE_IMM    => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JNE_REG    => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JSGT_IMM   => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JSGT_REG   => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JSGE_IMM   => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JSGE_REG   => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JSLT_IMM   => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JSLT_REG   => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JSLE_IMM   => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::JSLE_REG   => { check_jmp_offset(prog, insn_ptr)?; },
-            ebpf::CALL_IMM   => {},
-            ebpf::CALL_REG   => { check_imm_register(&insn, insn_ptr, config)?; },
-            ebpf::EXIT       => {},
-
-            _                => {
-                return Err(VerifierError::UnknownOpCode(insn.opc, adj_insn_ptr(insn_ptr)));
+        if is_jump(&insn) {
+            let target = jump_target(&insn, insn_ptr);
+            if target >= 0 {
+                worklist.push(target as usize);
             }
+            if insn.opc == ebpf::JA || insn.opc == ebpf::GOTOL {
+                continue;
+            }
+        } else if insn.opc == ebpf::EXIT {
+            continue;
         }
+        worklist.push(insn_ptr + insn_len(prog, insn_ptr));
+    }
 
-        check_registers(&insn, store, insn_ptr, config.dynamic_stack_frames)?;
+    // Report each sequence of unreachable instructions once, at its beginning
+    let mut previous_reachable = true;
+    let mut insn_ptr = 0;
+    while insn_ptr < insn_count {
+        if !reachable[insn_ptr] && previous_reachable {
+            diagnostics.push(VerifierDiagnostic::warning(
+                prog,
+                insn_ptr,
+                "unreachable code",
+            ));
+        }
+        previous_reachable = reachable[insn_ptr];
+        insn_ptr += insn_len(prog, insn_ptr);
+    }
+}
 
-        insn_ptr += 1;
+/// Returns whether the instruction reads r0 and whether it writes r0
+///
+/// Only meant for instructions which do not transfer control.
+fn r0_usage(insn: &ebpf::Insn) -> (bool, bool) {
+    match insn.opc & ebpf::BPF_CLS_MASK {
+        ebpf::BPF_LD => match insn.opc & 0xe0 {
+            ebpf::BPF_ABS => (false, true),
+            ebpf::BPF_IND => (insn.src == 0, true),
+            _ => (false, insn.dst == 0),
+        },
+        ebpf::BPF_LDX => (insn.src == 0, insn.dst == 0),
+        ebpf::BPF_ST => (insn.dst == 0, false),
+        // Atomic operations can compare against r0, assume they read it
+        ebpf::BPF_STX if insn.opc & 0xe0 == ebpf::BPF_ATOMIC => (true, false),
+        ebpf::BPF_STX => (insn.src == 0 || insn.dst == 0, false),
+        _ => {
+            let operation = insn.opc & ebpf::BPF_ALU_OP_MASK;
+            let reads_src =
+                insn.opc & ebpf::BPF_X != 0 && operation != ebpf::BPF_END && insn.src == 0;
+            let reads_dst = operation != ebpf::BPF_MOV && insn.dst == 0;
+            (reads_src || reads_dst, insn.dst == 0)
+        }
     }
+}
 
-    // insn_ptr should now be equal to number of instructions.
-    if insn_ptr != prog.len() / ebpf::INSN_SIZE {
-        return Err(VerifierError::JumpOutOfCode(adj_insn_ptr(insn_ptr), adj_insn_ptr(insn_ptr)));
+/// Byte range relative to r10 a load or store accesses, if r10 is its base register
+fn stack_access(insn: &ebpf::Insn, base: u8) -> Option<std::ops::Range<i64>> {
+    if base != ebpf::FRAME_PTR_REG as u8 {
+        return None;
     }
+    let size = match insn.opc & 0x18 {
+        ebpf::BPF_B => 1,
+        ebpf::BPF_H => 2,
+        ebpf::BPF_W => 4,
+        _ => 8,
+    };
+    Some(insn.off as i64..insn.off as i64 + size)
+}
 
-    Ok(())
+/// Finds writes to r0 and to the stack whose value is discarded without being read
+///
+/// Only follows straight-line code: The tracking is reset at function starts, jump targets,
+/// jumps and calls, so that no read on another path can be missed.
+fn find_dead_writes<F: Fn(&ebpf::Insn) -> bool>(
+    prog: &[u8],
+    function_starts: &[usize],
+    is_syscall: F,
+    diagnostics: &mut Vec<VerifierDiagnostic>,
+) {
+    let insn_count = prog.len() / ebpf::INSN_SIZE;
+    let mut block_starts = vec![false; insn_count];
+    let mut insn_ptr = 0;
+    while insn_ptr < insn_count {
+        let insn = ebpf::get_insn(prog, insn_ptr);
+        if is_jump(&insn) {
+            let target = jump_target(&insn, insn_ptr);
+            if target >= 0 && (target as usize) < insn_count {
+                block_starts[target as usize] = true;
+            }
+        }
+        insn_ptr += insn_len(prog, insn_ptr);
+    }
+    for function_start in function_starts {
+        if *function_start < insn_count {
+            block_starts[*function_start] = true;
+        }
+    }
+
+    let mut pending_r0_write: Option<usize> = None;
+    let mut pending_stack_stores: Vec<(usize, std::ops::Range<i64>)> = Vec::new();
+    let mut insn_ptr = 0;
+    while insn_ptr < insn_count {
+        let insn = ebpf::get_insn(prog, insn_ptr);
+        if block_starts[insn_ptr] {
+            pending_r0_write = None;
+            pending_stack_stores.clear();
+        }
+        match insn.opc & ebpf::BPF_CLS_MASK {
+            ebpf::BPF_JMP | ebpf::BPF_JMP32 => {
+                if let Some(write_insn_ptr) = pending_r0_write {
+                    if is_syscall(&insn) {
+                        diagnostics.push(VerifierDiagnostic::warning(
+                            prog,
+                            write_insn_ptr,
+                            "r0 is overwritten by a syscall before being read",
+                        ));
+                    }
+                }
+                if insn.opc == ebpf::EXIT {
+                    // The frame of the function ends, only the caller's stack stays accessible
+                    for (store_insn_ptr, range) in pending_stack_stores.iter() {
+                        if range.end <= 0 {
+                            diagnostics.push(VerifierDiagnostic::warning(
+                                prog,
+                                *store_insn_ptr,
+                                "store to the stack is never read before returning",
+                            ));
+                        }
+                    }
+                }
+                pending_r0_write = None;
+                pending_stack_stores.clear();
+            }
+            class => {
+                let (reads_r0, writes_r0) = r0_usage(&insn);
+                if reads_r0 {
+                    pending_r0_write = None;
+                }
+                if writes_r0 {
+                    pending_r0_write = Some(insn_ptr);
+                }
+                if class == ebpf::BPF_LDX {
+                    // Any other base register could point into the stack as well
+                    match stack_access(&insn, insn.src) {
+                        Some(load) => pending_stack_stores.retain(|(_, store)| {
+                            store.end <= load.start || load.end <= store.start
+                        }),
+                        None => pending_stack_stores.clear(),
+                    }
+                } else if class == ebpf::BPF_STX && insn.opc & 0xe0 == ebpf::BPF_ATOMIC {
+                    pending_stack_stores.clear();
+                } else if class == ebpf::BPF_ST || class == ebpf::BPF_STX {
+                    if let Some(store) = stack_access(&insn, insn.dst) {
+                        pending_stack_stores.retain(|(store_insn_ptr, overwritten)| {
+                            if store.start <= overwritten.start && overwritten.end <= store.end {
+                                diagnostics.push(VerifierDiagnostic::warning(
+                                    prog,
+                                    *store_insn_ptr,
+                                    "store to the stack is overwritten before being read",
+                                ));
+                                false
+                            } else {
+                                true
+                            }
+                        });
+                        pending_stack_stores.push((insn_ptr, store));
+                    }
+                }
+            }
+        }
+        insn_ptr += insn_len(prog, insn_ptr);
+    }
 }
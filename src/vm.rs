@@ -13,14 +13,16 @@
 //! Virtual machine for eBPF programs.
 
 use crate::{
+    backtrace::Backtrace,
     call_frames::CallFrames,
     disassembler::disassemble_instruction,
-    ebpf,
+    ebpf::{self, SbfVersion},
     elf::Executable,
     error::{EbpfError, UserDefinedError},
     interpreter::Interpreter,
     jit::JitProgramArgument,
-    memory_region::{MemoryMapping, MemoryRegion},
+    memory_region::{AccessType, MemoryMapping, MemoryRegion},
+    sampler::Sampler,
     static_analysis::Analysis,
     verifier::VerifierError,
 };
@@ -40,7 +42,8 @@ use std::{
 ///   - Unknown instructions.
 ///   - Bad formed instruction.
 ///   - Unknown eBPF syscall index.
-pub type Verifier = fn(prog: &[u8], config: &Config) -> Result<(), VerifierError>;
+pub type Verifier =
+    fn(prog: &[u8], config: &Config, sbf_version: SbfVersion) -> Result<(), VerifierError>;
 
 /// Return value of programs and syscalls
 pub type ProgramResult<E> = Result<u64, EbpfError<E>>;
@@ -95,6 +98,7 @@ pub struct Syscall {
 }
 
 /// A virtual method table for dyn trait objects
+#[repr(C)]
 pub struct DynTraitVtable {
     /// Drops the dyn trait object
     pub drop: fn(*const u8),
@@ -109,6 +113,7 @@ pub struct DynTraitVtable {
 // Could be replaced by https://doc.rust-lang.org/std/raw/struct.TraitObject.html
 /// A dyn trait fat pointer for SyscallObject
 #[derive(Clone, Copy)]
+#[repr(C)]
 pub struct DynTraitFatPointer {
     /// Pointer to the actual object
     pub data: *mut u8,
@@ -173,6 +178,17 @@ impl SyscallRegistry {
         self.entries.get(&hash)
     }
 
+    /// Get the hash and context object slot of every syscall, ordered by hash
+    pub(crate) fn get_context_object_slots(&self) -> Vec<(u32, usize)> {
+        let mut slots = self
+            .entries
+            .iter()
+            .map(|(hash, syscall)| (*hash, syscall.context_object_slot))
+            .collect::<Vec<_>>();
+        slots.sort_unstable();
+        slots
+    }
+
     /// Get a function pointer's and context object slot
     pub fn lookup_context_object_slot(&self, function_pointer: u64) -> Option<usize> {
         self.context_object_slots.get(&function_pointer).copied()
@@ -206,8 +222,28 @@ pub struct Config {
     pub enable_instruction_meter: bool,
     /// Enable instruction tracing
     pub enable_instruction_tracing: bool,
+    /// Record the previous and new value of every store in the tracer (interpreter only)
+    pub enable_store_journal: bool,
+    /// Record every load and store in the tracer
+    pub enable_memory_tracing: bool,
+    /// Sample the pc at the instruction meter checkpoints of the JIT every this many instructions (0 = OFF)
+    pub sampling_interval: usize,
+    /// Number of `EbpfVm::execute_program()` calls after which the JIT compiles the program in the background (0 = OFF)
+    pub jit_promotion_threshold: usize,
+    /// Fuse instruction sequences, share address translations and drop dead moves in the JIT (ignored while tracing)
+    pub optimize_jit: bool,
+    /// Skip the address translation checks of loads and stores which the static analysis proves in bounds (JIT only, ignored while tracing)
+    pub enable_bounds_check_elimination: bool,
+    /// Append the host address ranges of the functions of JIT compiled programs to the perf map of the process
+    pub enable_perf_map: bool,
+    /// Register JIT compiled programs with the GDB JIT interface, so that debuggers see their functions (requires the `gdb-jit-interface` feature)
+    pub enable_gdb_jit_interface: bool,
     /// Enable dynamic string allocation for labels
     pub enable_symbol_and_section_labels: bool,
+    /// Parse DWARF debug info (.debug_line, .debug_info and .debug_frame) when loading an ELF
+    pub enable_debug_info: bool,
+    /// Wrap access violations, divisions by zero and exceeded call depths in `EbpfError::WithBacktrace`
+    pub enable_backtrace: bool,
     /// Disable reporting of unresolved symbols at runtime
     pub disable_unresolved_symbols_at_runtime: bool,
     /// Reject ELF files containing issues that the verifier did not catch before (up to v0.2.21)
@@ -224,17 +260,21 @@ pub struct Config {
     pub syscall_bpf_function_hash_collision: bool,
     /// Have the verifier reject "callx r10"
     pub reject_callx_r10: bool,
-    /// Use dynamic stack frame sizes
-    pub dynamic_stack_frames: bool,
     /// Enable native signed division
     pub enable_sdiv: bool,
+    /// Enable atomic memory operations (BPF_ATOMIC)
+    pub enable_atomics: bool,
+    /// Enable conditional jumps comparing the lower 32 bits of the operands (BPF_JMP32)
+    pub enable_jmp32: bool,
+    /// Enable the eBPF v4 instructions: sign extending loads and moves, signed modulo, bswap and gotol
+    pub enable_ebpf_v4: bool,
     /// Avoid copying read only sections when possible
     pub optimize_rodata: bool,
-    /// Support syscalls via pseudo calls (insn.src = 0)
-    pub static_syscalls: bool,
-    /// Allow sh_addr != sh_offset in elf sections. Used in SBFv2 to align
-    /// section vaddrs to MM_PROGRAM_START.
-    pub enable_elf_vaddr: bool,
+    /// Lowest SBF version which is accepted when loading an ELF
+    pub min_sbf_version: SbfVersion,
+    /// Highest SBF version which is accepted when loading an ELF, executables created from text
+    /// bytes (including the assembler) target this version
+    pub max_sbf_version: SbfVersion,
 }
 
 impl Config {
@@ -253,7 +293,17 @@ impl Default for Config {
             instruction_meter_checkpoint_distance: 10000,
             enable_instruction_meter: true,
             enable_instruction_tracing: false,
+            enable_store_journal: false,
+            enable_memory_tracing: false,
+            sampling_interval: 0,
+            jit_promotion_threshold: 16,
+            optimize_jit: false,
+            enable_bounds_check_elimination: false,
+            enable_perf_map: false,
+            enable_gdb_jit_interface: false,
             enable_symbol_and_section_labels: false,
+            enable_debug_info: false,
+            enable_backtrace: false,
             disable_unresolved_symbols_at_runtime: true,
             reject_broken_elfs: false,
             noop_instruction_ratio: 1.0 / 256.0,
@@ -262,17 +312,22 @@ impl Default for Config {
             disable_deprecated_load_instructions: true,
             syscall_bpf_function_hash_collision: true,
             reject_callx_r10: true,
-            dynamic_stack_frames: true,
             enable_sdiv: true,
+            enable_atomics: false,
+            enable_jmp32: false,
+            enable_ebpf_v4: false,
             optimize_rodata: true,
-            static_syscalls: true,
-            enable_elf_vaddr: true,
+            min_sbf_version: SbfVersion::V1,
+            max_sbf_version: SbfVersion::V2,
         }
     }
 }
 
 /// The syscall_context_objects field stores some metadata in the front, thus the entries are shifted
-pub const SYSCALL_CONTEXT_OBJECTS_OFFSET: usize = 4;
+///
+/// The metadata consists of the MemoryMapping (4 words) followed by pointers to the Tracer and
+/// the Sampler, the next sample threshold, the sampled pc and a pointer to the CallFrames.
+pub const SYSCALL_CONTEXT_OBJECTS_OFFSET: usize = 9;
 
 /// Static constructors for Executable
 impl<E: UserDefinedError, I: 'static + InstructionMeter> Executable<E, I> {
@@ -285,7 +340,11 @@ impl<E: UserDefinedError, I: 'static + InstructionMeter> Executable<E, I> {
     ) -> Result<Pin<Box<Self>>, EbpfError<E>> {
         let executable = Executable::load(config, elf_bytes, syscall_registry)?;
         if let Some(verifier) = verifier {
-            verifier(executable.get_text_bytes().1, executable.get_config())?;
+            verifier(
+                executable.get_text_bytes().1,
+                executable.get_config(),
+                executable.get_sbf_version(),
+            )?;
         }
         Ok(Pin::new(Box::new(executable)))
     }
@@ -298,7 +357,8 @@ impl<E: UserDefinedError, I: 'static + InstructionMeter> Executable<E, I> {
         bpf_functions: BTreeMap<u32, (usize, String)>,
     ) -> Result<Pin<Box<Self>>, EbpfError<E>> {
         if let Some(verifier) = verifier {
-            verifier(text_bytes, &config).map_err(EbpfError::VerifierError)?;
+            verifier(text_bytes, &config, config.max_sbf_version)
+                .map_err(EbpfError::VerifierError)?;
         }
         Ok(Pin::new(Box::new(Executable::new_from_text_bytes(
             config,
@@ -372,17 +432,92 @@ impl DynamicAnalysis {
     }
 }
 
+/// A store recorded by the store journal
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StoreRecord {
+    /// Index of the storing instruction in order of execution (in `Tracer::log` if it is traced)
+    pub index: usize,
+    /// Virtual address written to
+    pub vm_addr: u64,
+    /// Number of bytes written
+    pub len: u8,
+    /// Value in memory before the store
+    pub old_value: u64,
+    /// Value in memory after the store
+    pub new_value: u64,
+}
+
+/// A load or store recorded by memory tracing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryAccess {
+    /// Instruction index of the accessing instruction
+    pub pc: usize,
+    /// Load or store
+    pub access_type: AccessType,
+    /// Virtual address accessed
+    pub vm_addr: u64,
+    /// Number of bytes accessed
+    pub len: u8,
+    /// Value loaded or stored
+    pub value: u64,
+}
+
 /// Used for instruction tracing
 #[derive(Default, Clone)]
 pub struct Tracer {
     /// Contains the state at every instruction in order of execution
     pub log: Vec<[u64; 12]>,
+    /// Contains every store in order of execution, if the store journal is enabled
+    pub stores: Vec<StoreRecord>,
+    /// Contains every load and store in order of execution, if memory tracing is enabled
+    pub memory_accesses: Vec<MemoryAccess>,
+    /// Number of executed instructions, also counted if they are not traced
+    pub(crate) insn_count: usize,
 }
 
 impl Tracer {
     /// Logs the state of a single instruction
     pub fn trace(&mut self, state: [u64; 12]) {
         self.log.push(state);
+        self.insn_count += 1;
+    }
+
+    /// Counts an instruction which is not traced, so that journaled stores can refer to it
+    pub(crate) fn count_instruction(&mut self) {
+        self.insn_count += 1;
+    }
+
+    /// Logs a store of the most recently executed instruction
+    pub fn journal_store(&mut self, vm_addr: u64, len: u8, old_value: u64, new_value: u64) {
+        self.stores.push(StoreRecord {
+            index: self.insn_count.saturating_sub(1),
+            vm_addr,
+            len,
+            old_value,
+            new_value,
+        });
+    }
+
+    /// Logs a load or store
+    pub fn trace_memory_access(
+        &mut self,
+        pc: u64,
+        access_type: u64,
+        vm_addr: u64,
+        len: u64,
+        value: u64,
+    ) {
+        self.memory_accesses.push(MemoryAccess {
+            pc: pc as usize,
+            access_type: if access_type == AccessType::Store as u64 {
+                AccessType::Store
+            } else {
+                AccessType::Load
+            },
+            vm_addr,
+            len: len as u8,
+            value,
+        });
     }
 
     /// Use this method to print the log of this tracer
@@ -407,7 +542,7 @@ impl Tracer {
             let entry = &self.log[index];
             let pc = entry[11] as usize;
             let insn = &analysis.instructions[pc_to_insn_index[pc]];
-            writeln!(
+            write!(
                 output,
                 "{:5?} {:016X?} {:5?}: {}",
                 index,
@@ -415,6 +550,14 @@ impl Tracer {
                 pc + ebpf::ELF_INSN_DUMP_OFFSET,
                 disassemble_instruction(insn, analysis),
             )?;
+            match analysis
+                .executable
+                .get_debug_info()
+                .and_then(|debug_info| debug_info.get_location(pc))
+            {
+                Some(location) => writeln!(output, " ; {}", location)?,
+                None => writeln!(output)?,
+            }
         }
         Ok(())
     }
@@ -432,6 +575,39 @@ impl Tracer {
     }
 }
 
+/// Execution state of a program which ran out of instruction budget
+///
+/// The contents of the memory regions are not part of the continuation,
+/// so it must be resumed on the same VM it was suspended on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Continuation {
+    reg: [u64; 11],
+    pc: usize,
+    frames: Vec<(u64, [u64; ebpf::SCRATCH_REGS], usize)>,
+    stack_ptr: u64,
+}
+
+impl Continuation {
+    /// Returns the instruction index at which execution continues
+    pub fn get_pc(&self) -> usize {
+        self.pc
+    }
+
+    /// Returns the registers r0 to r10
+    pub fn get_registers(&self) -> &[u64; 11] {
+        &self.reg
+    }
+}
+
+/// Outcome of a resumable execution
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExecutionStatus {
+    /// The program exited with the given return value
+    Exited(u64),
+    /// The instruction budget was exhausted, execution can be resumed later
+    Suspended(Continuation),
+}
+
 /// A virtual machine to run eBPF program.
 ///
 /// # Examples
@@ -465,10 +641,11 @@ pub struct EbpfVm<'a, E: UserDefinedError, I: InstructionMeter> {
     pub(crate) program_vm_addr: u64,
     pub(crate) memory_mapping: MemoryMapping<'a>,
     pub(crate) tracer: Tracer,
+    pub(crate) sampler: Sampler,
     pub(crate) syscall_context_objects: Vec<*mut u8>,
     syscall_context_object_pool: Vec<Box<dyn SyscallObject<E> + 'a>>,
     pub(crate) stack: CallFrames<'a>,
-    total_insn_count: u64,
+    pub(crate) total_insn_count: u64,
 }
 
 impl<'a, E: UserDefinedError, I: InstructionMeter> EbpfVm<'a, E, I> {
@@ -498,7 +675,8 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> EbpfVm<'a, E, I> {
         additional_regions: Vec<MemoryRegion>,
     ) -> Result<EbpfVm<'a, E, I>, EbpfError<E>> {
         let config = executable.get_config();
-        let mut stack = CallFrames::new(config);
+        let sbf_version = executable.get_sbf_version();
+        let mut stack = CallFrames::new(config, sbf_version);
         let regions: Vec<MemoryRegion> = vec![
             MemoryRegion::new_readonly(&[], 0),
             executable.get_ro_region(),
@@ -514,8 +692,9 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> EbpfVm<'a, E, I> {
             executable,
             program,
             program_vm_addr,
-            memory_mapping: MemoryMapping::new(regions, config)?,
+            memory_mapping: MemoryMapping::new(regions, config, sbf_version)?,
             tracer: Tracer::default(),
+            sampler: Sampler::new(config.sampling_interval as u64),
             syscall_context_objects: vec![
                 std::ptr::null_mut();
                 SYSCALL_CONTEXT_OBJECTS_OFFSET + number_of_syscalls
@@ -549,6 +728,11 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> EbpfVm<'a, E, I> {
         &self.tracer
     }
 
+    /// Returns the sampler
+    pub fn get_sampler(&self) -> &Sampler {
+        &self.sampler
+    }
+
     /// Initializes and binds the context object instances for all previously registered syscalls
     ///
     /// # Examples
@@ -624,6 +808,57 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> EbpfVm<'a, E, I> {
             .map(|slot| self.syscall_context_objects[SYSCALL_CONTEXT_OBJECTS_OFFSET + slot])
     }
 
+    /// Execute the program with the backend chosen by the tiered execution.
+    ///
+    /// The program starts in the interpreter (or the threaded-code interpreter if it was
+    /// pre-decoded) and is JIT-compiled on a background thread once this was called
+    /// `Config::jit_promotion_threshold` times for the executable. All following calls
+    /// use the JIT-compiled program as soon as it is available.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use solana_rbpf::{assembler::assemble, tiering::ExecutionTier, user_error::UserError, vm::{Config, EbpfVm, SyscallRegistry, TestInstructionMeter}};
+    ///
+    /// let executable = assemble::<UserError, TestInstructionMeter>(
+    ///     "
+    ///     mov r0, 1
+    ///     add r0, 2
+    ///     exit",
+    ///     None,
+    ///     Config { jit_promotion_threshold: 2, ..Config::default() },
+    ///     SyscallRegistry::default(),
+    /// ).unwrap();
+    /// let mut vm = EbpfVm::<UserError, TestInstructionMeter>::new(&executable, &mut [], Vec::new()).unwrap();
+    /// let res = vm.execute_program(&mut TestInstructionMeter { remaining: 3 }).unwrap();
+    /// assert_eq!(res, 3);
+    /// assert_eq!(executable.get_invocation_count(), 1);
+    /// assert_eq!(executable.get_execution_tier(), ExecutionTier::Interpreter);
+    /// ```
+    pub fn execute_program(&mut self, instruction_meter: &mut I) -> ProgramResult<E>
+    where
+        I: 'static,
+    {
+        let executable = self.executable;
+        let invocation_count = executable.get_tiering().record_invocation();
+        if cfg!(all(not(windows), target_arch = "x86_64")) {
+            if executable.get_compiled_program().is_some() {
+                return self.execute_program_jit(instruction_meter);
+            }
+            let jit_promotion_threshold = executable.get_config().jit_promotion_threshold;
+            if jit_promotion_threshold != 0 && invocation_count >= jit_promotion_threshold {
+                executable
+                    .get_tiering()
+                    .start_background_compilation(executable);
+            }
+        }
+        if executable.get_threaded_program().is_some() {
+            self.execute_program_threaded(instruction_meter)
+        } else {
+            self.execute_program_interpreted(instruction_meter)
+        }
+    }
+
     /// Execute the program loaded, with the given packet data.
     ///
     /// Warning: The program is executed without limiting the number of
@@ -663,6 +898,7 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> EbpfVm<'a, E, I> {
             }
             (interpreter.initial_insn_count, interpreter.due_insn_count)
         };
+        let result = self.attach_backtrace(result);
         if self.executable.get_config().enable_instruction_meter {
             instruction_meter.consume(due_insn_count);
             self.total_insn_count = initial_insn_count - instruction_meter.get_remaining();
@@ -670,6 +906,134 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> EbpfVm<'a, E, I> {
         Ok(result?.unwrap_or(0))
     }
 
+    /// Execute the program loaded in the interpreter, suspending it instead of failing
+    /// once the instruction meter runs out.
+    ///
+    /// Only the interpreter supports suspension, a suspended program is continued
+    /// with `resume_program_interpreted()`. Errors of syscalls, including
+    /// `ExceededMaxInstructions`, are returned instead of suspending.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use solana_rbpf::{assembler::assemble, user_error::UserError, vm::{Config, EbpfVm, ExecutionStatus, SyscallRegistry, TestInstructionMeter}};
+    ///
+    /// let executable = assemble::<UserError, TestInstructionMeter>(
+    ///     "
+    ///     mov r0, 1
+    ///     add r0, 2
+    ///     exit",
+    ///     None,
+    ///     Config::default(),
+    ///     SyscallRegistry::default(),
+    /// ).unwrap();
+    /// let mut vm = EbpfVm::<UserError, TestInstructionMeter>::new(&executable, &mut [], Vec::new()).unwrap();
+    /// let continuation = match vm.execute_program_interpreted_resumable(&mut TestInstructionMeter { remaining: 2 }).unwrap() {
+    ///     ExecutionStatus::Suspended(continuation) => continuation,
+    ///     ExecutionStatus::Exited(_) => unreachable!(),
+    /// };
+    /// assert_eq!(continuation.get_pc(), 2);
+    /// let status = vm.resume_program_interpreted(&mut TestInstructionMeter { remaining: 1 }, continuation).unwrap();
+    /// assert_eq!(status, ExecutionStatus::Exited(3));
+    /// ```
+    pub fn execute_program_interpreted_resumable(
+        &mut self,
+        instruction_meter: &mut I,
+    ) -> Result<ExecutionStatus, EbpfError<E>> {
+        self.execute_program_interpreted_until_suspended(instruction_meter, None)
+    }
+
+    /// Continue a suspended program with a new instruction budget
+    pub fn resume_program_interpreted(
+        &mut self,
+        instruction_meter: &mut I,
+        continuation: Continuation,
+    ) -> Result<ExecutionStatus, EbpfError<E>> {
+        self.execute_program_interpreted_until_suspended(instruction_meter, Some(continuation))
+    }
+
+    fn execute_program_interpreted_until_suspended(
+        &mut self,
+        instruction_meter: &mut I,
+        continuation: Option<Continuation>,
+    ) -> Result<ExecutionStatus, EbpfError<E>> {
+        let mut result = Ok(None);
+        let (initial_insn_count, due_insn_count, continuation) = {
+            let mut interpreter = Interpreter::new(self, instruction_meter)?;
+            if let Some(continuation) = continuation {
+                if !interpreter
+                    .vm
+                    .stack
+                    .set_frames_unchecked(&continuation.frames, continuation.stack_ptr)
+                {
+                    return Err(EbpfError::CallDepthExceeded(
+                        continuation.pc + ebpf::ELF_INSN_DUMP_OFFSET,
+                        continuation.frames.len(),
+                    ));
+                }
+                interpreter.reg = continuation.reg;
+                interpreter.pc = continuation.pc;
+            }
+            while let Ok(None) = result {
+                result = interpreter.step();
+            }
+            // Errors of syscalls are passed through, as their call would be skipped when resumed
+            let continuation = match result {
+                Err(EbpfError::ExceededMaxInstructions(_, _))
+                    if interpreter.exceeded_instruction_meter =>
+                {
+                    Some(Continuation {
+                        reg: interpreter.reg,
+                        pc: interpreter.pc,
+                        frames: interpreter.vm.stack.get_frames(),
+                        stack_ptr: interpreter.vm.stack.get_stack_ptr(),
+                    })
+                }
+                _ => None,
+            };
+            (
+                interpreter.initial_insn_count,
+                interpreter.due_insn_count,
+                continuation,
+            )
+        };
+        let result = self.attach_backtrace(result);
+        if self.executable.get_config().enable_instruction_meter {
+            instruction_meter.consume(due_insn_count);
+            self.total_insn_count = initial_insn_count - instruction_meter.get_remaining();
+        }
+        if let Some(continuation) = continuation {
+            return Ok(ExecutionStatus::Suspended(continuation));
+        }
+        Ok(ExecutionStatus::Exited(result?.unwrap_or(0)))
+    }
+
+    /// Execute the previously pre-decoded program with the threaded-code interpreter,
+    /// which behaves exactly like `execute_program_interpreted()`.
+    ///
+    /// The program has to be pre-decoded with `Executable::threaded_compile()` first.
+    pub fn execute_program_threaded(&mut self, instruction_meter: &mut I) -> ProgramResult<E> {
+        let executable = self.executable;
+        let threaded_program = executable
+            .get_threaded_program()
+            .ok_or(EbpfError::ThreadedProgramNotCompiled)?;
+        let (result, initial_insn_count, due_insn_count) = {
+            let mut interpreter = Interpreter::new(self, instruction_meter)?;
+            let result = threaded_program.execute(&mut interpreter);
+            (
+                result,
+                interpreter.initial_insn_count,
+                interpreter.due_insn_count,
+            )
+        };
+        let result = self.attach_backtrace(result);
+        if self.executable.get_config().enable_instruction_meter {
+            instruction_meter.consume(due_insn_count);
+            self.total_insn_count = initial_insn_count - instruction_meter.get_remaining();
+        }
+        result
+    }
+
     /// Execute the previously JIT-compiled program, with the given packet data in a manner
     /// very similar to `execute_program_interpreted()`.
     ///
@@ -679,6 +1043,8 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> EbpfVm<'a, E, I> {
     /// the program works with the interpreter before running the JIT-compiled version of it.
     ///
     pub fn execute_program_jit(&mut self, instruction_meter: &mut I) -> ProgramResult<E> {
+        // The JIT only writes the frames for backtraces, so drop those of a previous run
+        self.stack.reset();
         let initial_insn_count = if self.executable.get_config().enable_instruction_meter {
             instruction_meter.get_remaining()
         } else {
@@ -690,8 +1056,14 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> EbpfVm<'a, E, I> {
             .get_compiled_program()
             .ok_or(EbpfError::JitNotCompiled)?;
         let instruction_meter_final = unsafe {
-            self.syscall_context_objects[SYSCALL_CONTEXT_OBJECTS_OFFSET - 1] =
+            self.syscall_context_objects[SYSCALL_CONTEXT_OBJECTS_OFFSET - 5] =
                 &mut self.tracer as *mut _ as *mut u8;
+            self.syscall_context_objects[SYSCALL_CONTEXT_OBJECTS_OFFSET - 4] =
+                &mut self.sampler as *mut _ as *mut u8;
+            self.syscall_context_objects[SYSCALL_CONTEXT_OBJECTS_OFFSET - 3] =
+                self.sampler.get_first_sample_threshold(initial_insn_count) as *mut u8;
+            self.syscall_context_objects[SYSCALL_CONTEXT_OBJECTS_OFFSET - 1] =
+                &mut self.stack as *mut _ as *mut u8;
             (compiled_program.main)(
                 &result,
                 ebpf::MM_INPUT_START,
@@ -708,11 +1080,35 @@ impl<'a, E: UserDefinedError, I: InstructionMeter> EbpfVm<'a, E, I> {
             // Same as:
             // self.total_insn_count = initial_insn_count - instruction_meter.get_remaining();
         }
-        match result {
+        match self.attach_backtrace(result) {
             Err(EbpfError::ExceededMaxInstructions(pc, _)) => {
                 Err(EbpfError::ExceededMaxInstructions(pc, initial_insn_count))
             }
             x => x,
         }
     }
+
+    /// Symbolizes the active call frames if enabled and the program failed at a specific instruction
+    fn attach_backtrace<T>(&self, result: Result<T, EbpfError<E>>) -> Result<T, EbpfError<E>> {
+        if !self.executable.get_config().enable_backtrace {
+            return result;
+        }
+        match result {
+            Err(EbpfError::AccessViolation(pc, _, _, _, _))
+            | Err(EbpfError::StackAccessViolation(pc, _, _, _, _))
+            | Err(EbpfError::DivideByZero(pc))
+            | Err(EbpfError::CallDepthExceeded(pc, _)) => {
+                let backtrace = Backtrace::new(
+                    self.executable,
+                    &self.stack,
+                    pc - ebpf::ELF_INSN_DUMP_OFFSET,
+                );
+                Err(EbpfError::WithBacktrace(
+                    Box::new(result.err().unwrap()),
+                    Box::new(backtrace),
+                ))
+            }
+            result => result,
+        }
+    }
 }
@@ -67,6 +67,7 @@ pub enum FenceType {
 
 #[derive(PartialEq, Eq, Copy, Clone)]
 pub struct X86Instruction {
+    pub lock: bool,
     pub size: OperandSize,
     pub opcode_escape_sequence: u8,
     pub opcode: u8,
@@ -81,6 +82,7 @@ pub struct X86Instruction {
 impl Default for X86Instruction {
     fn default() -> Self {
         Self {
+            lock: false,
             size: OperandSize::S64,
             opcode_escape_sequence: 0,
             opcode: 0,
@@ -145,6 +147,9 @@ impl X86Instruction {
             }
         }
         jit.emit_random_noop()?;
+        if self.lock {
+            emit::<u8, E>(jit, 0xf0)?;
+        }
         if self.size == OperandSize::S16 {
             emit::<u8, E>(jit, 0x66)?;
         }
@@ -162,9 +167,8 @@ impl X86Instruction {
         emit::<u8, E>(jit, self.opcode)?;
         if self.modrm {
             emit::<u8, E>(jit, (modrm.mode << 6) | (modrm.r << 3) | modrm.m)?;
-            let sib = (sib.scale << 6) | (sib.index << 3) | sib.base;
-            if sib != 0 {
-                emit::<u8, E>(jit, sib)?;
+            if modrm.mode != 3 && modrm.m == RSP {
+                emit::<u8, E>(jit, (sib.scale << 6) | (sib.index << 3) | sib.base)?;
             }
             emit_variable_length(jit, displacement_size, displacement as u64)?;
         }
@@ -182,6 +186,46 @@ impl X86Instruction {
         }
     }
 
+    /// Atomically add source to destination and load the previous value of destination into source
+    pub fn exchange_and_add(
+        size: OperandSize,
+        source: u8,
+        destination: u8,
+        indirect: Option<X86IndirectAccess>,
+    ) -> Self {
+        debug_assert!(size == OperandSize::S32 || size == OperandSize::S64);
+        Self {
+            lock: true,
+            size,
+            opcode_escape_sequence: 1,
+            opcode: 0xc1,
+            first_operand: source,
+            second_operand: destination,
+            indirect,
+            ..Self::default()
+        }
+    }
+
+    /// Atomically store source in destination if destination equals RAX, load the previous value of destination into RAX
+    pub fn compare_and_exchange(
+        size: OperandSize,
+        source: u8,
+        destination: u8,
+        indirect: Option<X86IndirectAccess>,
+    ) -> Self {
+        debug_assert!(size == OperandSize::S32 || size == OperandSize::S64);
+        Self {
+            lock: true,
+            size,
+            opcode_escape_sequence: 1,
+            opcode: 0xb1,
+            first_operand: source,
+            second_operand: destination,
+            indirect,
+            ..Self::default()
+        }
+    }
+
     /// Swap source and destination
     pub fn xchg(
         size: OperandSize,
@@ -189,7 +233,7 @@ impl X86Instruction {
         destination: u8,
         indirect: Option<X86IndirectAccess>,
     ) -> Self {
-        debug_assert_eq!(size, OperandSize::S64);
+        debug_assert!(size == OperandSize::S32 || size == OperandSize::S64);
         Self {
             size,
             opcode: 0x87,
@@ -235,6 +279,28 @@ impl X86Instruction {
         }
     }
 
+    /// Sign extend the lower bits of source, or of [source + offset], to destination i64
+    pub fn sign_extend(
+        size: OperandSize,
+        source: u8,
+        destination: u8,
+        indirect: Option<X86IndirectAccess>,
+    ) -> Self {
+        debug_assert!(size != OperandSize::S0 && size != OperandSize::S64);
+        Self {
+            opcode_escape_sequence: if size == OperandSize::S32 { 0 } else { 1 },
+            opcode: match size {
+                OperandSize::S8 => 0xbe,
+                OperandSize::S16 => 0xbf,
+                _ => 0x63,
+            },
+            first_operand: destination,
+            second_operand: source,
+            indirect,
+            ..Self::default()
+        }
+    }
+
     /// Test source and destination
     pub fn test(
         size: OperandSize,
@@ -422,6 +488,21 @@ impl X86Instruction {
         }
     }
 
+    /// Load destination from 64 bit immediate, always using the full width encoding
+    ///
+    /// The immediate is the last 8 bytes of the instruction, so that it can be patched later.
+    pub fn load_immediate_64(destination: u8, immediate: i64) -> Self {
+        Self {
+            size: OperandSize::S64,
+            opcode: 0xb8 | (destination & 0b111),
+            modrm: false,
+            second_operand: destination,
+            immediate_size: OperandSize::S64,
+            immediate,
+            ..Self::default()
+        }
+    }
+
     /// Store sign-extended immediate in destination
     pub fn store_immediate(
         size: OperandSize,
@@ -449,7 +530,6 @@ impl X86Instruction {
     }
 
     /// Push source onto the stack
-    #[allow(dead_code)]
     pub fn push_immediate(size: OperandSize, immediate: i32) -> Self {
         debug_assert_ne!(size, OperandSize::S0);
         debug_assert_ne!(size, OperandSize::S16);
@@ -11,6 +11,36 @@
 extern crate libc;
 extern crate solana_rbpf;
 
+use solana_rbpf::{
+    assembler::assemble,
+    ebpf::SbfVersion,
+    elf::Executable,
+    user_error::UserError,
+    vm::{Config, SyscallRegistry, TestInstructionMeter},
+};
+use std::pin::Pin;
+
+// Helpers for the tests of the static analysis passes.
+
+/// Assembles a program without running the verifier on it
+pub fn assemble_unverified(
+    source: &str,
+    config: Config,
+) -> Pin<Box<Executable<UserError, TestInstructionMeter>>> {
+    assemble::<UserError, TestInstructionMeter>(source, None, config, SyscallRegistry::default())
+        .unwrap()
+}
+
+/// SBFv1 config, which has stack frames of a fixed size
+pub fn config_with_fixed_stack_frames(enable_stack_frame_gaps: bool) -> Config {
+    Config {
+        min_sbf_version: SbfVersion::V1,
+        max_sbf_version: SbfVersion::V1,
+        enable_stack_frame_gaps,
+        ..Config::default()
+    }
+}
+
 // Assembly code and data for tcp_sack testcases.
 
 pub const PROG_TCP_PORT_80: &str = "
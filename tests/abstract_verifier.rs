@@ -0,0 +1,275 @@
+#![allow(clippy::integer_arithmetic)]
+// Copyright 2022 Solana Maintainers <maintainers@solana.com>
+//
+// Licensed under the Apache License, Version 2.0 <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license <http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+extern crate solana_rbpf;
+extern crate test_utils;
+
+use solana_rbpf::{
+    abstract_verifier::{self, DiagnosticKind, RegisterType},
+    static_analysis::Analysis,
+    vm::Config,
+};
+use test_utils::{assemble_unverified, config_with_fixed_stack_frames};
+
+fn check(source: &str, config: Config) -> Vec<(usize, DiagnosticKind)> {
+    let executable = assemble_unverified(source, config);
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    abstract_verifier::check(&analysis)
+        .into_iter()
+        .map(|diagnostic| (diagnostic.pc, diagnostic.kind))
+        .collect()
+}
+
+#[test]
+fn test_valid_program() {
+    let source = "
+        stdw [r10-8], 1
+        ldxdw r2, [r10-8]
+        ldxb r3, [r1+4]
+        lddw r4, 0x100000000
+        ldxb r4, [r4]
+        mov r0, 0
+        add r0, 1
+        jlt r0, 10, -2
+        stxb [r1], r0
+        exit";
+    for config in [
+        config_with_fixed_stack_frames(true),
+        config_with_fixed_stack_frames(false),
+        Config::default(),
+    ] {
+        assert_eq!(check(source, config), vec![]);
+    }
+}
+
+#[test]
+fn test_uninitialized_register() {
+    assert_eq!(
+        check(
+            "
+            mov r3, r2
+            exit",
+            Config::default()
+        ),
+        vec![
+            (0, DiagnosticKind::UninitializedRegister(2)),
+            (1, DiagnosticKind::UninitializedRegister(0)),
+        ]
+    );
+    // Only written on one of the paths
+    assert_eq!(
+        check(
+            "
+            ldxb r2, [r1]
+            jeq r2, 0, +1
+            mov r0, 1
+            exit",
+            Config::default()
+        ),
+        vec![(3, DiagnosticKind::UninitializedRegister(0))]
+    );
+    // The arguments are clobbered by calls
+    assert_eq!(
+        check(
+            "
+            mov r6, r1
+            call function_foo
+            ldxb r0, [r6]
+            ldxb r0, [r1]
+            exit
+            function_foo:
+            exit",
+            Config::default()
+        ),
+        vec![(3, DiagnosticKind::UninitializedRegister(1))]
+    );
+}
+
+#[test]
+fn test_stack_out_of_bounds() {
+    let source = "
+        stb [r10-1], 0
+        stb [r10-0x1001], 0
+        stb [r10], 0
+        stb [r10-0x3001], 0
+        mov r0, 0
+        exit";
+    let out_of_bounds = DiagnosticKind::OutOfBoundsAccess(RegisterType::StackPointer, 1);
+    // The frames of the callers are accessible, but the one of the callee is in a gap
+    assert_eq!(
+        check(source, config_with_fixed_stack_frames(true)),
+        vec![(1, out_of_bounds), (2, out_of_bounds), (3, out_of_bounds)]
+    );
+    assert_eq!(
+        check(source, config_with_fixed_stack_frames(false)),
+        vec![(1, out_of_bounds), (3, out_of_bounds)]
+    );
+    // The frame pointer starts at the end of the stack
+    assert_eq!(check(source, Config::default()), vec![(2, out_of_bounds)]);
+    // Callees do not know their frame, only the whole stack
+    assert_eq!(
+        check(
+            "
+            call function_foo
+            exit
+            function_foo:
+            stb [r10-0x1001], 0
+            stb [r10+0x1000], 0
+            exit",
+            config_with_fixed_stack_frames(false)
+        ),
+        vec![]
+    );
+}
+
+#[test]
+fn test_pointer_out_of_bounds() {
+    assert_eq!(
+        check(
+            "
+            ldxb r0, [r1-1]
+            mov r2, 0
+            ldxdw r0, [r2+8]
+            lddw r3, 0x100000000
+            ldxb r0, [r3+0x1000]
+            exit",
+            Config::default()
+        ),
+        vec![
+            (
+                0,
+                DiagnosticKind::OutOfBoundsAccess(RegisterType::InputPointer, 1)
+            ),
+            (
+                2,
+                DiagnosticKind::OutOfBoundsAccess(RegisterType::Scalar, 8)
+            ),
+            (
+                5,
+                DiagnosticKind::OutOfBoundsAccess(RegisterType::ReadOnlyPointer, 1)
+            ),
+        ]
+    );
+    // Addresses computed from scalars are typed by their region
+    assert_eq!(
+        check(
+            "
+            mov r2, 1
+            lsh r2, 32
+            ldxb r0, [r2-1]
+            ldxb r0, [r2]
+            stb [r2+8], 0
+            exit",
+            Config::default()
+        ),
+        vec![
+            (
+                2,
+                DiagnosticKind::OutOfBoundsAccess(RegisterType::Scalar, 1)
+            ),
+            (4, DiagnosticKind::WriteToReadOnly),
+        ]
+    );
+}
+
+#[test]
+fn test_write_to_read_only() {
+    assert_eq!(
+        check(
+            "
+            lddw r1, 0x100000000
+            ldxb r0, [r1]
+            stb [r1], 0
+            mov r2, r1
+            add r2, 8
+            stxdw [r2], r0
+            exit",
+            Config::default()
+        ),
+        vec![
+            (3, DiagnosticKind::WriteToReadOnly),
+            (6, DiagnosticKind::WriteToReadOnly),
+        ]
+    );
+}
+
+#[test]
+fn test_branch_refinement() {
+    // Only the branch where r2 is at least 8 certainly leaves the input region
+    assert_eq!(
+        check(
+            "
+            ldxb r2, [r1]
+            jgt r2, 7, +4
+            mov r3, r1
+            sub r3, r2
+            ldxb r0, [r3]
+            exit
+            mov r3, r1
+            sub r3, r2
+            ldxb r0, [r3]
+            exit",
+            Config::default()
+        ),
+        vec![(
+            8,
+            DiagnosticKind::OutOfBoundsAccess(RegisterType::InputPointer, 1)
+        )]
+    );
+    // Branches which can not be taken are not followed
+    assert_eq!(
+        check(
+            "
+            mov r2, 0
+            jne r2, 0, +1
+            ja +1
+            ldxb r0, [r2]
+            mov r0, 0
+            exit",
+            Config::default()
+        ),
+        vec![]
+    );
+}
+
+#[test]
+fn test_loop() {
+    // Widening makes the loop converge, the counter is only bounded on exit
+    assert_eq!(
+        check(
+            "
+            mov r0, 0
+            mov r2, r1
+            ldxb r3, [r2]
+            add r2, 1
+            add r0, r3
+            jlt r2, r1, -4
+            mov r3, r10
+            sub r3, r2
+            exit",
+            Config::default()
+        ),
+        vec![]
+    );
+    assert_eq!(
+        check(
+            "
+            mov r0, 0
+            add r0, 1
+            jlt r0, 100, -2
+            mov r2, r1
+            sub r2, r0
+            ldxb r0, [r2]
+            exit",
+            Config::default()
+        ),
+        vec![(
+            5,
+            DiagnosticKind::OutOfBoundsAccess(RegisterType::InputPointer, 1)
+        )]
+    );
+}
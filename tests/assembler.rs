@@ -10,19 +10,19 @@ extern crate test_utils;
 
 use solana_rbpf::{
     assembler::assemble,
-    ebpf,
+    ebpf::{self, SbfVersion},
     user_error::UserError,
     vm::{Config, SyscallRegistry, TestInstructionMeter},
 };
 use test_utils::{TCP_SACK_ASM, TCP_SACK_BIN};
 
 fn asm(src: &str) -> Result<Vec<ebpf::Insn>, String> {
-    let executable = assemble::<UserError, TestInstructionMeter>(
-        src,
-        None,
-        Config::default(),
-        SyscallRegistry::default(),
-    )?;
+    asm_with_config(src, Config::default())
+}
+
+fn asm_with_config(src: &str, config: Config) -> Result<Vec<ebpf::Insn>, String> {
+    let executable =
+        assemble::<UserError, TestInstructionMeter>(src, None, config, SyscallRegistry::default())?;
     let (_program_vm_addr, program) = executable.get_text_bytes();
     Ok((0..program.len() / ebpf::INSN_SIZE)
         .map(|insn_ptr| ebpf::get_insn(program, insn_ptr))
@@ -97,6 +97,22 @@ fn test_stxw() {
     );
 }
 
+// Example for InstructionType::Atomic.
+#[test]
+fn test_stxxaddw() {
+    assert_eq!(
+        asm("stxxaddw [r2+5], r8"),
+        Ok(vec![insn(
+            0,
+            ebpf::ST_W_ATOMIC,
+            2,
+            8,
+            5,
+            ebpf::BPF_ADD as i64
+        )])
+    );
+}
+
 // Example for InstructionType::JumpUnconditional.
 #[test]
 fn test_ja() {
@@ -104,6 +120,16 @@ fn test_ja() {
     assert_eq!(asm("ja -3"), Ok(vec![insn(0, ebpf::JA, 0, 0, -3, 0)]));
 }
 
+// Example for InstructionType::JumpLong.
+#[test]
+fn test_gotol() {
+    assert_eq!(asm("gotol +8"), Ok(vec![insn(0, ebpf::GOTOL, 0, 0, 0, 8)]));
+    assert_eq!(
+        asm("gotol -70000"),
+        Ok(vec![insn(0, ebpf::GOTOL, 0, 0, 0, -70000)])
+    );
+}
+
 // Example for InstructionType::JumpConditional.
 #[test]
 fn test_jeq() {
@@ -204,6 +230,35 @@ fn test_stxh() {
     );
 }
 
+// Test all supported Atomic mnemonics.
+#[test]
+fn test_atomic() {
+    assert_eq!(
+        asm("stxxaddw [r1+2], r3
+             stxxordw [r1+2], r3
+             stxxandw [r1+2], r3
+             stxxxordw [r1+2], r3
+             stxxfetchaddw [r1+2], r3
+             stxxfetchordw [r1+2], r3
+             stxxfetchandw [r1+2], r3
+             stxxfetchxordw [r1+2], r3
+             stxxxchgw [r1+2], r3
+             stxxcmpxchgdw [r1+2], r3"),
+        Ok(vec![
+            insn(0, ebpf::ST_W_ATOMIC, 1, 3, 2, 0x00),
+            insn(1, ebpf::ST_DW_ATOMIC, 1, 3, 2, 0x40),
+            insn(2, ebpf::ST_W_ATOMIC, 1, 3, 2, 0x50),
+            insn(3, ebpf::ST_DW_ATOMIC, 1, 3, 2, 0xa0),
+            insn(4, ebpf::ST_W_ATOMIC, 1, 3, 2, 0x01),
+            insn(5, ebpf::ST_DW_ATOMIC, 1, 3, 2, 0x41),
+            insn(6, ebpf::ST_W_ATOMIC, 1, 3, 2, 0x51),
+            insn(7, ebpf::ST_DW_ATOMIC, 1, 3, 2, 0xa1),
+            insn(8, ebpf::ST_W_ATOMIC, 1, 3, 2, 0xe1),
+            insn(9, ebpf::ST_DW_ATOMIC, 1, 3, 2, 0xf1)
+        ])
+    );
+}
+
 // Test all supported AluBinary mnemonics.
 #[test]
 fn test_alu_binary() {
@@ -382,6 +437,33 @@ fn test_alu_binary() {
     );
 }
 
+// Test all supported AluSigned and MoveSignExtend mnemonics.
+#[test]
+fn test_alu_sign_extend() {
+    assert_eq!(
+        asm("smod r1, r2
+             smod r1, 2
+             smod64 r1, r2
+             smod32 r1, 2
+             movsx8 r1, r2
+             movsx16 r1, r2
+             movsx32 r1, r2
+             mov32sx8 r1, r2
+             mov32sx16 r1, r2"),
+        Ok(vec![
+            insn(0, ebpf::MOD64_REG, 1, 2, 1, 0),
+            insn(1, ebpf::MOD64_IMM, 1, 0, 1, 2),
+            insn(2, ebpf::MOD64_REG, 1, 2, 1, 0),
+            insn(3, ebpf::MOD32_IMM, 1, 0, 1, 2),
+            insn(4, ebpf::MOV64_REG, 1, 2, 8, 0),
+            insn(5, ebpf::MOV64_REG, 1, 2, 16, 0),
+            insn(6, ebpf::MOV64_REG, 1, 2, 32, 0),
+            insn(7, ebpf::MOV32_REG, 1, 2, 8, 0),
+            insn(8, ebpf::MOV32_REG, 1, 2, 16, 0)
+        ])
+    );
+}
+
 // Test all supported AluUnary mnemonics.
 #[test]
 fn test_alu_unary() {
@@ -448,6 +530,21 @@ fn test_load_reg() {
     );
 }
 
+// Test all supported sign extending LoadReg mnemonics.
+#[test]
+fn test_load_reg_sign_extend() {
+    assert_eq!(
+        asm("ldxsw r1, [r2+3]
+             ldxsh r1, [r2+3]
+             ldxsb r1, [r2+3]"),
+        Ok(vec![
+            insn(0, ebpf::LD_SW_REG, 1, 2, 3, 0),
+            insn(1, ebpf::LD_SH_REG, 1, 2, 3, 0),
+            insn(2, ebpf::LD_SB_REG, 1, 2, 3, 0)
+        ])
+    );
+}
+
 // Test all supported StoreImm mnemonics.
 #[test]
 fn test_store_imm() {
@@ -540,6 +637,64 @@ fn test_jump_conditional() {
     );
 }
 
+// Test all supported JumpConditional mnemonics of the JMP32 class.
+#[test]
+fn test_jump_conditional_32() {
+    assert_eq!(
+        asm("jeq32 r1, r2, +3
+             jgt32 r1, r2, +3
+             jge32 r1, r2, +3
+             jlt32 r1, r2, +3
+             jle32 r1, r2, +3
+             jset32 r1, r2, +3
+             jne32 r1, r2, +3
+             jsgt32 r1, r2, +3
+             jsge32 r1, r2, +3
+             jslt32 r1, r2, +3
+             jsle32 r1, r2, +3"),
+        Ok(vec![
+            insn(0, ebpf::JEQ32_REG, 1, 2, 3, 0),
+            insn(1, ebpf::JGT32_REG, 1, 2, 3, 0),
+            insn(2, ebpf::JGE32_REG, 1, 2, 3, 0),
+            insn(3, ebpf::JLT32_REG, 1, 2, 3, 0),
+            insn(4, ebpf::JLE32_REG, 1, 2, 3, 0),
+            insn(5, ebpf::JSET32_REG, 1, 2, 3, 0),
+            insn(6, ebpf::JNE32_REG, 1, 2, 3, 0),
+            insn(7, ebpf::JSGT32_REG, 1, 2, 3, 0),
+            insn(8, ebpf::JSGE32_REG, 1, 2, 3, 0),
+            insn(9, ebpf::JSLT32_REG, 1, 2, 3, 0),
+            insn(10, ebpf::JSLE32_REG, 1, 2, 3, 0)
+        ])
+    );
+
+    assert_eq!(
+        asm("jeq32 r1, 2, +3
+             jgt32 r1, 2, +3
+             jge32 r1, 2, +3
+             jlt32 r1, 2, +3
+             jle32 r1, 2, +3
+             jset32 r1, 2, +3
+             jne32 r1, 2, +3
+             jsgt32 r1, 2, +3
+             jsge32 r1, 2, +3
+             jslt32 r1, 2, +3
+             jsle32 r1, 2, +3"),
+        Ok(vec![
+            insn(0, ebpf::JEQ32_IMM, 1, 0, 3, 2),
+            insn(1, ebpf::JGT32_IMM, 1, 0, 3, 2),
+            insn(2, ebpf::JGE32_IMM, 1, 0, 3, 2),
+            insn(3, ebpf::JLT32_IMM, 1, 0, 3, 2),
+            insn(4, ebpf::JLE32_IMM, 1, 0, 3, 2),
+            insn(5, ebpf::JSET32_IMM, 1, 0, 3, 2),
+            insn(6, ebpf::JNE32_IMM, 1, 0, 3, 2),
+            insn(7, ebpf::JSGT32_IMM, 1, 0, 3, 2),
+            insn(8, ebpf::JSGE32_IMM, 1, 0, 3, 2),
+            insn(9, ebpf::JSLT32_IMM, 1, 0, 3, 2),
+            insn(10, ebpf::JSLE32_IMM, 1, 0, 3, 2)
+        ])
+    );
+}
+
 // Test all supported Endian mnemonics.
 #[test]
 fn test_endian() {
@@ -561,6 +716,21 @@ fn test_endian() {
     );
 }
 
+// Test all supported bswap mnemonics.
+#[test]
+fn test_bswap() {
+    assert_eq!(
+        asm("bswap16 r1
+             bswap32 r1
+             bswap64 r1"),
+        Ok(vec![
+            insn(0, ebpf::BSWAP, 1, 0, 0, 16),
+            insn(1, ebpf::BSWAP, 1, 0, 0, 32),
+            insn(2, ebpf::BSWAP, 1, 0, 0, 64)
+        ])
+    );
+}
+
 #[test]
 fn test_large_immediate() {
     assert_eq!(
@@ -624,3 +794,35 @@ fn test_error_operands_out_of_range() {
         Err("Invalid immediate -2147483649".to_string())
     );
 }
+
+#[test]
+fn test_sbf_version() {
+    let config_v1 = Config {
+        min_sbf_version: SbfVersion::V1,
+        max_sbf_version: SbfVersion::V1,
+        ..Config::default()
+    };
+    assert_eq!(
+        asm("sub r11, 8"),
+        Ok(vec![insn(0, ebpf::SUB64_IMM, 11, 0, 0, 8)])
+    );
+    assert_eq!(
+        asm_with_config("sub r11, 8", config_v1),
+        Err("Resizing the stack frame with sub r11 is not supported in SBFv1".to_string())
+    );
+    assert_eq!(
+        asm("syscall log"),
+        Ok(vec![insn(
+            0,
+            ebpf::CALL_IMM,
+            0,
+            0,
+            0,
+            ebpf::hash_symbol_name(b"log") as i32 as i64
+        )])
+    );
+    assert_eq!(
+        asm_with_config("syscall log", config_v1),
+        Err("Unknown syscall log is not supported in SBFv1".to_string())
+    );
+}
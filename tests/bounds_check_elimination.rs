@@ -0,0 +1,322 @@
+#![allow(clippy::integer_arithmetic)]
+// Copyright 2022 Solana Maintainers <maintainers@solana.com>
+//
+// Licensed under the Apache License, Version 2.0 <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license <http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+extern crate solana_rbpf;
+extern crate test_utils;
+
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use solana_rbpf::{
+    assembler::assemble,
+    ebpf::{self, SbfVersion},
+    error::EbpfError,
+    memory_region::{MemoryMapping, MemoryRegion},
+    static_analysis::Analysis,
+    user_error::UserError,
+    vm::{Config, EbpfVm, ProgramResult, SyscallObject, SyscallRegistry, TestInstructionMeter},
+};
+use test_utils::{assemble_unverified, config_with_fixed_stack_frames};
+
+/// Unmaps the input region, like a syscall which shrinks or removes an account would
+struct SyscallUnmapInput {}
+impl SyscallUnmapInput {
+    fn init<C, E>(_unused: C) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self {})
+    }
+}
+impl SyscallObject<UserError> for SyscallUnmapInput {
+    fn call(
+        &mut self,
+        _arg1: u64,
+        _arg2: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        memory_mapping: &mut MemoryMapping,
+        result: &mut ProgramResult<UserError>,
+    ) {
+        *result = memory_mapping
+            .replace_region(
+                (ebpf::MM_INPUT_START >> ebpf::VIRTUAL_ADDRESS_BITS) as usize,
+                MemoryRegion::new_readonly(&[], ebpf::MM_INPUT_START),
+            )
+            .map(|()| 0);
+    }
+}
+
+fn syscall_registry() -> SyscallRegistry {
+    let mut syscall_registry = SyscallRegistry::default();
+    syscall_registry
+        .register_syscall_by_name(
+            b"unmap_input",
+            SyscallUnmapInput::init::<u64, UserError>,
+            SyscallUnmapInput::call,
+        )
+        .unwrap();
+    syscall_registry
+}
+
+fn find_in_bounds_memory_accesses(source: &str, config: Config) -> Vec<usize> {
+    let executable = assemble_unverified(source, config);
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    analysis
+        .find_in_bounds_memory_accesses()
+        .into_iter()
+        .collect()
+}
+
+#[test]
+fn test_stack_frame_accesses() {
+    let source = "
+        stdw [r10-8], 1
+        ldxdw r0, [r10-8]
+        stxb [r10-4096], r0
+        ldxb r0, [r10-4097]
+        ldxw r0, [r10+2]
+        ldxb r0, [r10]
+        exit";
+    assert_eq!(
+        find_in_bounds_memory_accesses(source, config_with_fixed_stack_frames(true)),
+        vec![0, 1, 2]
+    );
+    assert_eq!(
+        find_in_bounds_memory_accesses(source, config_with_fixed_stack_frames(false)),
+        vec![0, 1, 2]
+    );
+    // Dynamic stack frames have no fixed size, only the repeated access remains
+    assert_eq!(
+        find_in_bounds_memory_accesses(source, Config::default()),
+        vec![1]
+    );
+    // The gaps of the stack can only be compressed if the frame size is a power of two
+    let config = Config {
+        stack_frame_size: 3_000,
+        ..config_with_fixed_stack_frames(true)
+    };
+    assert_eq!(find_in_bounds_memory_accesses(source, config), vec![1]);
+    let config = Config {
+        stack_frame_size: 3_000,
+        ..config_with_fixed_stack_frames(false)
+    };
+    assert_eq!(find_in_bounds_memory_accesses(source, config), vec![0, 1]);
+}
+
+#[test]
+fn test_stack_frame_accesses_in_functions() {
+    assert_eq!(
+        find_in_bounds_memory_accesses(
+            "
+            stdw [r10-8], 1
+            call function_foo
+            ldxdw r0, [r10-8]
+            exit
+            function_foo:
+            stdw [r10-16], 2
+            exit",
+            config_with_fixed_stack_frames(true)
+        ),
+        vec![0, 2, 4]
+    );
+}
+
+#[test]
+fn test_repeated_accesses() {
+    let source = "
+        ldxdw r2, [r1]
+        ldxw r3, [r1+4]
+        ldxw r3, [r1]
+        exit";
+    assert_eq!(
+        find_in_bounds_memory_accesses(source, Config::default()),
+        vec![1, 2]
+    );
+    // Only the beginning of an access is checked against the gaps, so it has to stay the same
+    assert_eq!(
+        find_in_bounds_memory_accesses(source, config_with_fixed_stack_frames(true)),
+        vec![2]
+    );
+}
+
+#[test]
+fn test_store_after_load() {
+    assert!(find_in_bounds_memory_accesses(
+        "
+        ldxdw r2, [r1]
+        stxdw [r1], r2
+        exit",
+        Config::default()
+    )
+    .is_empty());
+    assert_eq!(
+        find_in_bounds_memory_accesses(
+            "
+            stxdw [r1], r2
+            ldxdw r2, [r1]
+            stxw [r1+4], r2
+            exit",
+            Config::default()
+        ),
+        vec![1, 2]
+    );
+}
+
+#[test]
+fn test_redefined_base() {
+    assert_eq!(
+        find_in_bounds_memory_accesses(
+            "
+            ldxdw r2, [r1]
+            add r1, 8
+            ldxdw r2, [r1]
+            ldxdw r1, [r1]
+            ldxdw r2, [r1]
+            exit",
+            Config::default()
+        ),
+        vec![3]
+    );
+}
+
+#[test]
+fn test_accesses_in_different_basic_blocks() {
+    assert!(find_in_bounds_memory_accesses(
+        "
+        ldxdw r2, [r1]
+        jeq r2, 0, +1
+        ldxdw r2, [r1]
+        ldxdw r2, [r1]
+        exit",
+        Config::default()
+    )
+    .is_empty());
+}
+
+#[test]
+fn test_indirect_calls() {
+    // An indirect call could enter a basic block in the middle, but the frame pointer stays valid
+    assert_eq!(
+        find_in_bounds_memory_accesses(
+            "
+            ldxdw r2, [r1]
+            ldxdw r2, [r1]
+            stdw [r10-8], 1
+            callx r3
+            exit",
+            config_with_fixed_stack_frames(false)
+        ),
+        vec![2]
+    );
+}
+
+#[test]
+fn test_syscall_between_accesses() {
+    let source = "
+        mov r6, r1
+        ldxdw r0, [r6+8]
+        syscall unmap_input
+        ldxdw r0, [r6+8]
+        exit";
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        source,
+        None,
+        Config::default(),
+        syscall_registry(),
+    )
+    .unwrap();
+    let in_bounds_accesses = Analysis::from_executable(&executable)
+        .unwrap()
+        .find_in_bounds_memory_accesses();
+    assert!(in_bounds_accesses.is_empty());
+    let mut mem = [0u8; 16];
+    let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+    let mut vm = EbpfVm::new(&executable, &mut [], vec![mem_region]).unwrap();
+    vm.bind_syscall_context_objects(0).unwrap();
+    let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 5 });
+    assert!(matches!(
+        result,
+        Err(EbpfError::AccessViolation(pc, ..)) if pc == 3 + ebpf::ELF_INSN_DUMP_OFFSET
+    ));
+}
+
+#[test]
+fn test_eliminated_checks_are_sound() {
+    const BASES: [u8; 4] = [1, 2, 3, 10];
+    const SIZES: [&str; 4] = ["b", "h", "w", "dw"];
+    let mut rng = SmallRng::seed_from_u64(0);
+    let mut eliminated_checks = 0;
+    let mut access_violations = 0;
+    for _ in 0..2_000 {
+        let sbf_version = if rng.gen_ratio(1, 4) {
+            SbfVersion::V2
+        } else {
+            SbfVersion::V1
+        };
+        let config = Config {
+            enable_stack_frame_gaps: rng.gen(),
+            stack_frame_size: [4_096, 3_000][rng.gen_range(0..2)],
+            min_sbf_version: sbf_version,
+            max_sbf_version: sbf_version,
+            ..Config::default()
+        };
+        let mut source = format!(
+            "
+            mov r2, r1
+            lddw r3, {:#x}
+            ",
+            ebpf::MM_PROGRAM_START
+        );
+        for _ in 0..rng.gen_range(1..24) {
+            let base = BASES[rng.gen_range(0..BASES.len())];
+            let suffix = SIZES[rng.gen_range(0..SIZES.len())];
+            let offset = if base == 10 {
+                rng.gen_range(-4_110..4)
+            } else {
+                rng.gen_range(-8..24)
+            };
+            source += &match rng.gen_range(0..9) {
+                0..=2 => format!("ldx{} r0, [r{}{:+}]\n", suffix, base, offset),
+                3 | 4 => format!("stx{} [r{}{:+}], r0\n", suffix, base, offset),
+                5 => format!("st{} [r{}{:+}], 0\n", suffix, base, offset),
+                6 if base != 10 => format!("add r{}, {}\n", base, offset),
+                7 => "syscall unmap_input\n".to_string(),
+                _ => format!("jeq r0, {}, +1\n", rng.gen_range(0..2)),
+            };
+        }
+        source += "mov r0, 0\nexit";
+        let executable =
+            assemble::<UserError, TestInstructionMeter>(&source, None, config, syscall_registry())
+                .unwrap();
+        let in_bounds_accesses = Analysis::from_executable(&executable)
+            .unwrap()
+            .find_in_bounds_memory_accesses();
+        eliminated_checks += in_bounds_accesses.len();
+        let mut mem = [0u8; 16];
+        let mem_region = if rng.gen() {
+            MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START)
+        } else {
+            MemoryRegion::new_readonly(&mem, ebpf::MM_INPUT_START)
+        };
+        let mut vm = EbpfVm::new(&executable, &mut [], vec![mem_region]).unwrap();
+        vm.bind_syscall_context_objects(0).unwrap();
+        let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 1_000 });
+        match result {
+            Err(EbpfError::AccessViolation(pc, ..))
+            | Err(EbpfError::StackAccessViolation(pc, ..)) => {
+                access_violations += 1;
+                let pc = pc - ebpf::ELF_INSN_DUMP_OFFSET;
+                assert!(
+                    !in_bounds_accesses.contains(&pc),
+                    "the check of the access at pc {} was eliminated but failed:\n{}",
+                    pc,
+                    source
+                );
+            }
+            result => assert!(result.is_ok(), "{:?}", result),
+        }
+    }
+    assert!(eliminated_checks > 1_000);
+    assert!(access_violations > 100);
+}
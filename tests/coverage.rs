@@ -0,0 +1,189 @@
+#![allow(clippy::integer_arithmetic)]
+// Copyright 2022 Solana Maintainers <maintainers@solana.com>
+//
+// Licensed under the Apache License, Version 2.0 <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license <http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+extern crate solana_rbpf;
+
+use solana_rbpf::{
+    assembler::assemble,
+    coverage::{Coverage, LineMapping},
+    ebpf,
+    elf::Executable,
+    memory_region::MemoryRegion,
+    static_analysis::Analysis,
+    user_error::UserError,
+    vm::{Config, EbpfVm, SyscallRegistry, TestInstructionMeter},
+};
+use std::{fs::File, io::Read, pin::Pin};
+
+const PROG: &str = "
+    ldxb r2, [r1]
+    jeq r2, 0, zero
+    mov r0, 1
+    exit
+zero:
+    call function_foo
+    exit
+function_foo:
+    mov r0, 2
+    exit";
+
+fn asm() -> Pin<Box<Executable<UserError, TestInstructionMeter>>> {
+    let config = Config {
+        enable_instruction_tracing: true,
+        enable_symbol_and_section_labels: true,
+        ..Config::default()
+    };
+    assemble(PROG, None, config, SyscallRegistry::default()).unwrap()
+}
+
+fn run(
+    executable: &Pin<Box<Executable<UserError, TestInstructionMeter>>>,
+    analysis: &Analysis<UserError, TestInstructionMeter>,
+    input: u8,
+) -> Coverage {
+    let mut mem = [input];
+    let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+    let mut vm = EbpfVm::new(executable, &mut [], vec![mem_region]).unwrap();
+    vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 100 })
+        .unwrap();
+    let mut coverage = Coverage::default();
+    coverage.add_trace(vm.get_tracer(), analysis);
+    coverage
+}
+
+#[test]
+fn test_coverage() {
+    let executable = asm();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let mut coverage = run(&executable, &analysis, 1);
+    assert_eq!(coverage.run_count, 1);
+    assert_eq!(coverage.get_instruction_coverage(&analysis), (4, 8));
+    assert_eq!(coverage.get_branch_coverage(&analysis), (1, 2));
+    assert_eq!(coverage.edge_hits.get(&(0, 2)), Some(&1));
+
+    coverage.merge(&run(&executable, &analysis, 0));
+    coverage.merge(&run(&executable, &analysis, 0));
+    assert_eq!(coverage.run_count, 3);
+    assert_eq!(coverage.get_instruction_coverage(&analysis), (8, 8));
+    assert_eq!(coverage.get_branch_coverage(&analysis), (2, 2));
+    assert_eq!(coverage.instruction_hits[&0], 3);
+    assert_eq!(coverage.instruction_hits[&6], 2);
+    assert_eq!(coverage.edge_hits.get(&(0, 4)), Some(&2));
+}
+
+#[test]
+fn test_lcov() {
+    let executable = asm();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let coverage = run(&executable, &analysis, 1);
+    let mut output = Vec::new();
+    coverage
+        .write_lcov(
+            &mut output,
+            &analysis,
+            &LineMapping::Instructions("program.so"),
+        )
+        .unwrap();
+    print!("{}", String::from_utf8(output).unwrap());
+}
+
+#[test]
+fn test_cobertura() {
+    let executable = asm();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let coverage = run(&executable, &analysis, 1);
+    let locate = |pc: usize| -> Option<(String, u32)> {
+        if pc < 6 {
+            Some(("src/lib.rs".to_string(), 10 + pc as u32 / 2))
+        } else {
+            Some(("src/foo&bar.rs".to_string(), 3))
+        }
+    };
+    let mut output = Vec::new();
+    coverage
+        .write_cobertura(&mut output, &analysis, &LineMapping::Source(&locate))
+        .unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains(
+        "<coverage line-rate=\"0.5000\" branch-rate=\"0.5000\" lines-covered=\"2\" lines-valid=\"4\" branches-covered=\"1\" branches-valid=\"2\""
+    ));
+    assert!(output.contains("<class name=\"src/foo&amp;bar.rs\" filename=\"src/foo&amp;bar.rs\""));
+    assert!(output.contains(
+        "<line number=\"10\" hits=\"1\" branch=\"true\" condition-coverage=\"50% (1/2)\"/>"
+    ));
+    assert!(output.contains("<line number=\"12\" hits=\"0\" branch=\"false\"/>"));
+    assert!(output.ends_with("</coverage>\n"));
+}
+
+#[test]
+fn test_branch_after_lddw() {
+    let config = Config {
+        enable_instruction_tracing: true,
+        ..Config::default()
+    };
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        lddw r3, 0x100000000
+        ldxb r2, [r1]
+        jeq r2, 0, zero
+        mov r0, 1
+        exit
+    zero:
+        mov r0, 2
+        exit",
+        None,
+        config,
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let coverage = run(&executable, &analysis, 1);
+    assert_eq!(coverage.get_branch_coverage(&analysis), (1, 2));
+    let mut output = Vec::new();
+    coverage
+        .write_lcov(
+            &mut output,
+            &analysis,
+            &LineMapping::Instructions("program.so"),
+        )
+        .unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("BRDA:4,0,0,1\nBRDA:4,0,1,0\n"));
+}
+
+#[test]
+fn test_debug_info_line_mapping() {
+    let config = Config {
+        enable_instruction_tracing: true,
+        enable_debug_info: true,
+        ..Config::default()
+    };
+    let mut file = File::open("tests/elfs/debug_info.so").unwrap();
+    let mut elf = Vec::new();
+    file.read_to_end(&mut elf).unwrap();
+    let executable = Executable::<UserError, TestInstructionMeter>::from_elf(
+        &elf,
+        None,
+        config,
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let coverage = run(&executable, &analysis, 3);
+    let mut output = Vec::new();
+    coverage
+        .write_lcov(
+            &mut output,
+            &analysis,
+            &LineMapping::DebugInfo(executable.get_debug_info().unwrap()),
+        )
+        .unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.starts_with("TN:\nSF:tests/elfs/debug_info.c\n"));
+    assert!(output.contains("DA:9,1\n"));
+    assert!(output.contains("DA:13,1\n"));
+}
@@ -0,0 +1,144 @@
+#![allow(clippy::integer_arithmetic)]
+// Copyright 2022 Solana Maintainers <maintainers@solana.com>
+//
+// Licensed under the Apache License, Version 2.0 <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license <http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+extern crate goblin;
+extern crate solana_rbpf;
+
+use goblin::elf::Elf;
+use solana_rbpf::{
+    debug_info::{FunctionFrame, SourceLocation},
+    elf::Executable,
+    static_analysis::Analysis,
+    user_error::UserError,
+    vm::{Config, EbpfVm, SyscallRegistry, TestInstructionMeter},
+};
+use std::{fs::File, io::Read, pin::Pin};
+
+const SOURCE_FILE: &str = "tests/elfs/debug_info.c";
+
+fn load(config: Config, path: &str) -> Pin<Box<Executable<UserError, TestInstructionMeter>>> {
+    let mut file = File::open(path).unwrap();
+    let mut elf = Vec::new();
+    file.read_to_end(&mut elf).unwrap();
+    Executable::from_elf(&elf, None, config, SyscallRegistry::default()).unwrap()
+}
+
+fn location(line: u32, column: u32) -> SourceLocation {
+    SourceLocation {
+        file: SOURCE_FILE.to_string(),
+        line,
+        column,
+    }
+}
+
+#[test]
+fn test_debug_info() {
+    let config = Config {
+        enable_debug_info: true,
+        ..Config::default()
+    };
+    let executable = load(config, "tests/elfs/debug_info.so");
+    let debug_info = executable.get_debug_info().unwrap();
+    assert_eq!(debug_info.get_location(0), Some(&location(17, 26)));
+    assert_eq!(debug_info.get_location(1), Some(&location(9, 12)));
+    assert_eq!(debug_info.get_location(2), Some(&location(19, 10)));
+    assert_eq!(debug_info.get_location(5), Some(&location(13, 12)));
+    assert_eq!(debug_info.get_location(7), None);
+    assert_eq!(
+        debug_info.get_function_frames(1),
+        vec![
+            FunctionFrame {
+                function: "square".to_string(),
+                location: Some(location(9, 12)),
+            },
+            FunctionFrame {
+                function: "entrypoint".to_string(),
+                location: Some(location(18, 7)),
+            },
+        ]
+    );
+    assert_eq!(
+        debug_info.symbolize(1).unwrap(),
+        format!(
+            "square at {}:9:12 inlined into entrypoint at {}:18:7",
+            SOURCE_FILE, SOURCE_FILE
+        )
+    );
+    assert_eq!(
+        debug_info.symbolize(6).unwrap(),
+        format!("increment at {}:13:3", SOURCE_FILE)
+    );
+    assert_eq!(debug_info.get_frame_range(0), Some(0..4));
+    assert_eq!(debug_info.get_frame_range(5), Some(4..7));
+    assert_eq!(debug_info.get_frame_range(7), None);
+}
+
+#[test]
+fn test_debug_info_absent() {
+    let executable = load(Config::default(), "tests/elfs/debug_info.so");
+    assert!(executable.get_debug_info().is_none());
+    let config = Config {
+        enable_debug_info: true,
+        ..Config::default()
+    };
+    let executable = load(config, "tests/elfs/noop.so");
+    assert!(executable.get_debug_info().is_none());
+}
+
+#[test]
+fn test_malformed_debug_info() {
+    let mut file = File::open("tests/elfs/debug_info.so").unwrap();
+    let mut elf_bytes = Vec::new();
+    file.read_to_end(&mut elf_bytes).unwrap();
+    let elf = Elf::parse(&elf_bytes).unwrap();
+    let section_header = elf
+        .section_headers
+        .iter()
+        .find(|section_header| {
+            elf.shdr_strtab.get_at(section_header.sh_name) == Some(".debug_info")
+        })
+        .unwrap();
+    // Unsupported DWARF version of the first unit
+    let version_offset = section_header.sh_offset as usize + 4;
+    elf_bytes[version_offset..version_offset + 2].copy_from_slice(&[0xff, 0xff]);
+    let config = Config {
+        enable_debug_info: true,
+        ..Config::default()
+    };
+    let executable = Executable::<UserError, TestInstructionMeter>::from_elf(
+        &elf_bytes,
+        None,
+        config,
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    assert!(executable.get_debug_info().is_none());
+}
+
+#[test]
+fn test_symbolized_error_and_trace() {
+    let config = Config {
+        enable_debug_info: true,
+        enable_instruction_tracing: true,
+        enable_symbol_and_section_labels: true,
+        ..Config::default()
+    };
+    let executable = load(config, "tests/elfs/debug_info.so");
+    let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+    let error = vm
+        .execute_program_interpreted(&mut TestInstructionMeter { remaining: 10 })
+        .unwrap_err();
+    assert_eq!(
+        executable.describe_error(&error),
+        format!("{} (entrypoint at {}:17:26)", error, SOURCE_FILE)
+    );
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let mut output = Vec::new();
+    vm.get_tracer().write(&mut output, &analysis).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.ends_with(&format!(" ; {}:17:26\n", SOURCE_FILE)));
+}
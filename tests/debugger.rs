@@ -0,0 +1,176 @@
+#![allow(clippy::integer_arithmetic)]
+// Copyright 2022 Solana Maintainers <maintainers@solana.com>
+//
+// Licensed under the Apache License, Version 2.0 <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license <http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+extern crate solana_rbpf;
+
+use solana_rbpf::{
+    assembler::assemble,
+    debugger::{Debugger, StopReason, WatchpointKind},
+    ebpf,
+    elf::Executable,
+    error::EbpfError,
+    memory_region::{AccessType, MemoryRegion},
+    user_error::UserError,
+    vm::{Config, EbpfVm, SyscallRegistry, TestInstructionMeter},
+};
+use std::pin::Pin;
+
+fn asm(src: &str) -> Pin<Box<Executable<UserError, TestInstructionMeter>>> {
+    let config = Config {
+        enable_symbol_and_section_labels: true,
+        ..Config::default()
+    };
+    assemble(src, None, config, SyscallRegistry::default()).unwrap()
+}
+
+const PROG_CALL: &str = "
+    mov r6, 1
+    call function_foo
+    add r0, r6
+    exit
+function_foo:
+    mov r0, 2
+    add r0, 3
+    exit";
+
+#[test]
+fn test_breakpoint_and_registers() {
+    let executable = asm(PROG_CALL);
+    let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+    let mut instruction_meter = TestInstructionMeter { remaining: 100 };
+    let mut debugger = Debugger::new(&mut vm, &mut instruction_meter).unwrap();
+    assert!(debugger.set_breakpoint(2));
+    assert_eq!(debugger.resume().unwrap(), StopReason::Breakpoint(2));
+    assert_eq!(debugger.get_pc(), 2);
+    assert_eq!(debugger.get_registers()[0], 5);
+    assert!(debugger.set_register(6, 10));
+    assert!(!debugger.set_register(11, 10));
+    assert_eq!(debugger.resume().unwrap(), StopReason::Exited(15));
+    assert!(debugger.is_terminated());
+    assert_eq!(debugger.step(), Err(EbpfError::ExitRootCallFrame));
+    drop(debugger);
+    assert_eq!(vm.get_total_instruction_count(), 7);
+}
+
+#[test]
+fn test_set_pc() {
+    let executable = asm(PROG_CALL);
+    let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+    let mut instruction_meter = TestInstructionMeter { remaining: 100 };
+    let mut debugger = Debugger::new(&mut vm, &mut instruction_meter).unwrap();
+    assert!(!debugger.set_pc(7));
+    assert_eq!(debugger.get_pc(), 0);
+    assert!(debugger.set_pc(2));
+    assert_eq!(debugger.get_pc(), 2);
+    assert_eq!(debugger.resume().unwrap(), StopReason::Exited(0));
+}
+
+#[test]
+fn test_breakpoint_at_symbol() {
+    let executable = asm(PROG_CALL);
+    let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+    let mut instruction_meter = TestInstructionMeter { remaining: 100 };
+    let mut debugger = Debugger::new(&mut vm, &mut instruction_meter).unwrap();
+    assert_eq!(debugger.set_breakpoint_at_symbol("function_bar"), None);
+    assert_eq!(debugger.set_breakpoint_at_symbol("function_foo"), Some(4));
+    assert_eq!(debugger.resume().unwrap(), StopReason::Breakpoint(4));
+    assert_eq!(debugger.get_frame_index(), 1);
+    assert!(debugger.remove_breakpoint(4));
+    assert_eq!(debugger.resume().unwrap(), StopReason::Exited(6));
+}
+
+#[test]
+fn test_step_over_and_step_out() {
+    let executable = asm(PROG_CALL);
+    let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+    let mut instruction_meter = TestInstructionMeter { remaining: 100 };
+    let mut debugger = Debugger::new(&mut vm, &mut instruction_meter).unwrap();
+    assert_eq!(debugger.step_over().unwrap(), StopReason::Step);
+    assert_eq!(debugger.get_pc(), 1);
+    assert_eq!(debugger.step_over().unwrap(), StopReason::Step);
+    assert_eq!(debugger.get_pc(), 2);
+    assert_eq!(debugger.get_frame_index(), 0);
+    assert_eq!(debugger.get_registers()[0], 5);
+
+    let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+    let mut debugger = Debugger::new(&mut vm, &mut instruction_meter).unwrap();
+    debugger.step().unwrap();
+    debugger.step().unwrap();
+    assert_eq!(debugger.get_pc(), 4);
+    assert_eq!(debugger.get_frame_index(), 1);
+    assert_eq!(debugger.step_out().unwrap(), StopReason::Step);
+    assert_eq!(debugger.get_pc(), 2);
+    assert_eq!(debugger.get_frame_index(), 0);
+    assert_eq!(debugger.step_out().unwrap(), StopReason::Exited(6));
+}
+
+#[test]
+fn test_watchpoint() {
+    let executable = asm("
+    mov r1, 0x11
+    stxb [r10-8], r1
+    ldxb r2, [r10-8]
+    stxb [r10-16], r1
+    mov r0, r2
+    exit");
+    let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+    let mut instruction_meter = TestInstructionMeter { remaining: 100 };
+    let mut debugger = Debugger::new(&mut vm, &mut instruction_meter).unwrap();
+    let frame_ptr = debugger.get_registers()[ebpf::FRAME_PTR_REG];
+    debugger.set_watchpoint(frame_ptr - 8, 8, WatchpointKind::Write);
+    assert_eq!(
+        debugger.resume().unwrap(),
+        StopReason::Watchpoint(1, AccessType::Store, frame_ptr - 8, 1)
+    );
+    assert_eq!(debugger.read_memory(frame_ptr - 8, 1).unwrap(), vec![0x11]);
+    debugger.write_memory(frame_ptr - 8, &[0x22]).unwrap();
+    assert!(debugger.remove_watchpoint(frame_ptr - 8));
+    debugger.set_watchpoint(frame_ptr - 8, 1, WatchpointKind::Read);
+    assert_eq!(
+        debugger.resume().unwrap(),
+        StopReason::Watchpoint(2, AccessType::Load, frame_ptr - 8, 1)
+    );
+    assert_eq!(debugger.resume().unwrap(), StopReason::Exited(0x22));
+}
+
+#[test]
+fn test_memory_access() {
+    let executable = asm("
+    exit");
+    let mut mem = [0u8; 4];
+    let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+    let mut vm = EbpfVm::new(&executable, &mut [], vec![mem_region]).unwrap();
+    let mut instruction_meter = TestInstructionMeter { remaining: 100 };
+    let mut debugger = Debugger::new(&mut vm, &mut instruction_meter).unwrap();
+    debugger
+        .write_memory(ebpf::MM_INPUT_START + 1, &[1, 2])
+        .unwrap();
+    assert_eq!(
+        debugger.read_memory(ebpf::MM_INPUT_START, 4).unwrap(),
+        vec![0, 1, 2, 0]
+    );
+    assert!(matches!(
+        debugger.read_memory(ebpf::MM_INPUT_START, 5),
+        Err(EbpfError::AccessViolation(
+            _,
+            AccessType::Load,
+            _,
+            5,
+            "input"
+        ))
+    ));
+    assert!(matches!(
+        debugger.write_memory(ebpf::MM_PROGRAM_START, &[0]),
+        Err(EbpfError::AccessViolation(
+            _,
+            AccessType::Store,
+            ebpf::MM_PROGRAM_START,
+            1,
+            "program"
+        ))
+    ));
+}
@@ -17,11 +17,15 @@ use solana_rbpf::{
 // Using a macro to keep actual line numbers in failure output
 macro_rules! disasm {
     ($src:expr) => {{
-        let src = $src;
         let config = Config {
             enable_symbol_and_section_labels: true,
             ..Config::default()
         };
+        disasm!($src, config);
+    }};
+    ($src:expr, $config:expr) => {{
+        let src = $src;
+        let config = $config;
         let executable = assemble::<UserError, TestInstructionMeter>(
             src,
             None,
@@ -93,6 +97,18 @@ lbb_1:
     );
 }
 
+// Example for InstructionType::JumpLong.
+#[test]
+fn test_gotol() {
+    disasm!(
+        "entrypoint:
+    gotol lbb_1
+lbb_1:
+    exit
+"
+    );
+}
+
 // Example for InstructionType::JumpConditional.
 #[test]
 fn test_jeq() {
@@ -171,6 +187,25 @@ fn test_stxh() {
     disasm!("entrypoint:\n    stxh [r1-0x2], r3\n");
 }
 
+// Test all supported Atomic mnemonics.
+#[test]
+fn test_atomic() {
+    disasm!(
+        "entrypoint:
+    stxxaddw [r1+0x2], r3
+    stxxordw [r1+0x2], r3
+    stxxandw [r1-0x2], r3
+    stxxxordw [r1+0x2], r3
+    stxxfetchaddw [r1+0x2], r3
+    stxxfetchordw [r1+0x2], r3
+    stxxfetchandw [r1+0x2], r3
+    stxxfetchxordw [r1+0x2], r3
+    stxxxchgw [r1+0x2], r3
+    stxxcmpxchgdw [r1+0x2], r3
+"
+    );
+}
+
 // Test all supported AluBinary mnemonics.
 #[test]
 fn test_alu_binary() {
@@ -243,6 +278,30 @@ fn test_alu_binary() {
     );
 }
 
+// Test all supported AluSigned and MoveSignExtend mnemonics.
+#[test]
+fn test_alu_sign_extend() {
+    let config = Config {
+        enable_symbol_and_section_labels: true,
+        enable_ebpf_v4: true,
+        ..Config::default()
+    };
+    disasm!(
+        "entrypoint:
+    smod64 r1, r2
+    smod64 r1, 2
+    smod32 r1, r2
+    smod32 r1, 2
+    movsx8 r1, r2
+    movsx16 r1, r2
+    movsx32 r1, r2
+    mov32sx8 r1, r2
+    mov32sx16 r1, r2
+",
+        config
+    );
+}
+
 // Test all supported AluUnary mnemonics.
 #[test]
 fn test_alu_unary() {
@@ -293,6 +352,18 @@ fn test_load_reg() {
     );
 }
 
+// Test all supported sign extending LoadReg mnemonics.
+#[test]
+fn test_load_reg_sign_extend() {
+    disasm!(
+        r"entrypoint:
+    ldxsw r1, [r2+0x3]
+    ldxsh r1, [r2+0x3]
+    ldxsb r1, [r2+0x3]
+"
+    );
+}
+
 // Test all supported StoreImm mnemonics.
 #[test]
 fn test_store_imm() {
@@ -359,6 +430,46 @@ lbb_11:
     );
 }
 
+// Test all supported JumpConditional mnemonics of the JMP32 class.
+#[test]
+fn test_jump_conditional_32() {
+    disasm!(
+        "entrypoint:
+    jeq32 r1, r2, lbb_11
+    jgt32 r1, r2, lbb_11
+    jge32 r1, r2, lbb_11
+    jlt32 r1, r2, lbb_11
+    jle32 r1, r2, lbb_11
+    jset32 r1, r2, lbb_11
+    jne32 r1, r2, lbb_11
+    jsgt32 r1, r2, lbb_11
+    jsge32 r1, r2, lbb_11
+    jslt32 r1, r2, lbb_11
+    jsle32 r1, r2, lbb_11
+lbb_11:
+    exit
+"
+    );
+
+    disasm!(
+        "entrypoint:
+    jeq32 r1, 2, lbb_11
+    jgt32 r1, 2, lbb_11
+    jge32 r1, 2, lbb_11
+    jlt32 r1, 2, lbb_11
+    jle32 r1, 2, lbb_11
+    jset32 r1, 2, lbb_11
+    jne32 r1, 2, lbb_11
+    jsgt32 r1, 2, lbb_11
+    jsge32 r1, 2, lbb_11
+    jslt32 r1, 2, lbb_11
+    jsle32 r1, 2, lbb_11
+lbb_11:
+    exit
+"
+    );
+}
+
 // Test all supported Endian mnemonics.
 #[test]
 fn test_endian() {
@@ -374,8 +485,42 @@ fn test_endian() {
     );
 }
 
+// Test all supported bswap mnemonics.
+#[test]
+fn test_bswap() {
+    disasm!(
+        "entrypoint:
+    bswap16 r1
+    bswap32 r1
+    bswap64 r1
+"
+    );
+}
+
 #[test]
 fn test_large_immediate() {
     disasm!("entrypoint:\n    add64 r1, -1\n");
     disasm!("entrypoint:\n    add64 r1, -1\n");
 }
+
+#[test]
+fn test_cfg_fall_through() {
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r0, 0
+        add r0, 1
+        jlt r0, 3, -2
+        call function_foo
+        mov r0, 0
+        function_foo:
+        exit",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    // The entry block falls through, but no block falls through into a function
+    assert_eq!(analysis.cfg_nodes[&0].destinations, vec![1]);
+    assert_eq!(analysis.cfg_nodes[&4].destinations, Vec::<usize>::new());
+}
@@ -0,0 +1,20 @@
+/**
+ * @brief test program with DWARF debug info, an inlined and a called function
+ */
+
+typedef unsigned char uint8_t;
+typedef unsigned long int uint64_t;
+
+static inline __attribute__((always_inline)) uint64_t square(uint64_t x) {
+  return x * x;
+}
+
+static uint64_t __attribute__((noinline)) increment(uint64_t x) {
+  return x + 1;
+}
+
+extern uint64_t entrypoint(const uint8_t *input) {
+  uint64_t x = (uint64_t)*input;
+  x = square(x);
+  return increment(x);
+}
@@ -93,4 +93,8 @@ rm syscall_static.o
 
 "$LLVM_DIR"clang -Werror -target sbf -mcpu=sbfv2 -O2 -fno-builtin -fPIC -o program_headers_overflow.o -c rodata.c
 "$LLVM_DIR"ld.lld -z notext -shared --Bdynamic -entry entrypoint --script program_headers_overflow.ld --noinhibit-exec -o program_headers_overflow.so program_headers_overflow.o
-rm program_headers_overflow.o
\ No newline at end of file
+rm program_headers_overflow.o
+
+"$LLVM_DIR"clang -Werror -target bpf -O2 -g -fno-builtin -fPIC -o debug_info.o -c debug_info.c
+"$LLVM_DIR"ld.lld -z notext -shared --Bdynamic -entry entrypoint -o debug_info.so debug_info.o
+rm debug_info.o
@@ -0,0 +1,226 @@
+#![allow(clippy::integer_arithmetic)]
+// Copyright 2022 Solana Maintainers <maintainers@solana.com>
+//
+// Licensed under the Apache License, Version 2.0 <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license <http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+extern crate solana_rbpf;
+
+use solana_rbpf::{
+    assembler::assemble,
+    debugger::Debugger,
+    ebpf,
+    gdb_stub::GdbStub,
+    user_error::UserError,
+    vm::{Config, EbpfVm, SyscallRegistry, TestInstructionMeter},
+};
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+};
+
+const PROG: &str = "
+    mov r1, 0x11
+    stxb [r10-8], r1
+    ldxb r0, [r10-8]
+    add r0, 1
+    exit";
+
+/// Minimal client side of the protocol, as gdb would speak it
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    fn request(&mut self, packet: &str) -> String {
+        let checksum = packet.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(self.stream, "${}#{:02x}", packet, checksum).unwrap();
+        let mut byte = [0u8; 1];
+        self.stream.read_exact(&mut byte).unwrap();
+        assert_eq!(byte[0], b'+');
+        self.stream.read_exact(&mut byte).unwrap();
+        assert_eq!(byte[0], b'$');
+        let mut response = Vec::new();
+        loop {
+            self.stream.read_exact(&mut byte).unwrap();
+            if byte[0] == b'#' {
+                break;
+            }
+            response.push(byte[0]);
+        }
+        let mut checksum = [0u8; 2];
+        self.stream.read_exact(&mut checksum).unwrap();
+        self.stream.write_all(b"+").unwrap();
+        String::from_utf8(response).unwrap()
+    }
+}
+
+fn register_hex(value: u64) -> String {
+    value
+        .to_le_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[test]
+fn test_gdb_stub_loopback() {
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        PROG,
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+    let mut instruction_meter = TestInstructionMeter { remaining: 100 };
+    let debugger = Debugger::new(&mut vm, &mut instruction_meter).unwrap();
+    let frame_ptr = debugger.get_registers()[ebpf::FRAME_PTR_REG];
+    let text_start = debugger.get_program_vm_addr();
+    let mut stub = GdbStub::new(debugger);
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let client = thread::spawn(move || {
+        let stream = TcpStream::connect(address).unwrap();
+        stream.set_nodelay(true).unwrap();
+        let mut client = Client { stream };
+        assert!(client
+            .request("qSupported:multiprocess+")
+            .starts_with("PacketSize="));
+        assert_eq!(client.request("?"), "S05");
+        assert_eq!(client.request("vMustReplyEmpty"), "");
+
+        // Registers r0 to r10 and the pc
+        let registers = client.request("g");
+        assert_eq!(registers.len(), 12 * 16);
+        assert_eq!(&registers[10 * 16..11 * 16], register_hex(frame_ptr));
+        assert_eq!(&registers[11 * 16..], register_hex(text_start));
+        assert_eq!(client.request("pb"), register_hex(text_start));
+
+        // Single step and software breakpoint
+        assert_eq!(client.request("s"), "S05");
+        assert_eq!(client.request("p1"), register_hex(0x11));
+        assert_eq!(
+            client.request(&format!("Z0,{:x},8", text_start + 3 * 8)),
+            "OK"
+        );
+        assert_eq!(client.request("c"), "S05");
+        assert_eq!(client.request("pb"), register_hex(text_start + 3 * 8));
+
+        // Memory packets
+        let vm_addr = frame_ptr - 8;
+        assert_eq!(client.request(&format!("m{:x},1", vm_addr)), "11");
+        assert_eq!(client.request(&format!("M{:x},1:41", vm_addr)), "OK");
+        assert_eq!(client.request(&format!("m{:x},1", vm_addr)), "41");
+        assert_eq!(client.request("m0,8"), "E01");
+        assert_eq!(client.request(&format!("M{:x},1:00", text_start)), "E01");
+
+        // Registers can be written while paused
+        assert_eq!(client.request(&format!("P0={}", register_hex(0x20))), "OK");
+        assert_eq!(
+            client.request(&format!("z0,{:x},8", text_start + 3 * 8)),
+            "OK"
+        );
+        assert_eq!(client.request("c"), "W21");
+        assert_eq!(client.request("D"), "OK");
+    });
+    let (mut stream, _address) = listener.accept().unwrap();
+    stream.set_nodelay(true).unwrap();
+    stub.serve(&mut stream).unwrap();
+    client.join().unwrap();
+    assert!(stub.get_debugger().is_terminated());
+}
+
+#[test]
+fn test_gdb_stub_watchpoint() {
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        PROG,
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+    let mut instruction_meter = TestInstructionMeter { remaining: 100 };
+    let debugger = Debugger::new(&mut vm, &mut instruction_meter).unwrap();
+    let vm_addr = debugger.get_registers()[ebpf::FRAME_PTR_REG] - 8;
+    let mut stub = GdbStub::new(debugger);
+    assert_eq!(
+        stub.handle_packet(&format!("Z2,{:x},1", vm_addr)),
+        Some("OK".to_string())
+    );
+    assert_eq!(
+        stub.handle_packet("c"),
+        Some(format!("T05watch:{:x};", vm_addr))
+    );
+    assert_eq!(stub.handle_packet("c"), Some("W12".to_string()));
+    assert_eq!(stub.handle_packet("k"), None);
+}
+
+#[test]
+fn test_gdb_stub_packets() {
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r0, 1
+        mov r1, 0
+        div r0, r1
+        exit",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+    let mut instruction_meter = TestInstructionMeter { remaining: 100 };
+    let debugger = Debugger::new(&mut vm, &mut instruction_meter).unwrap();
+    let text_start = debugger.get_program_vm_addr();
+    let mut stub = GdbStub::new(debugger);
+
+    // Packets starting with a multi-byte character are unknown, not a crash
+    assert_eq!(stub.handle_packet("\u{fffd}xyz"), Some(String::new()));
+
+    // The target description can be read in chunks
+    let first = stub
+        .handle_packet("qXfer:features:read:target.xml:0,10")
+        .unwrap();
+    assert_eq!(first, "m<?xml version=\"1");
+    let rest = stub
+        .handle_packet("qXfer:features:read:target.xml:10,1000")
+        .unwrap();
+    assert!(rest.starts_with("l.0\"?>"));
+    assert!(
+        rest.ends_with("<reg name=\"pc\" bitsize=\"64\" type=\"code_ptr\"/></feature></target>")
+    );
+    assert_eq!(
+        stub.handle_packet("qXfer:features:read:other.xml:0,10"),
+        Some("E01".to_string())
+    );
+
+    // The pc must point to an instruction of the program
+    let out_of_program = register_hex(text_start + 4 * 8);
+    assert_eq!(
+        stub.handle_packet(&format!("Pb={}", out_of_program)),
+        Some("E01".to_string())
+    );
+    assert_eq!(
+        stub.handle_packet(&format!(
+            "G{}{}",
+            register_hex(7).repeat(11),
+            out_of_program
+        )),
+        Some("E01".to_string())
+    );
+    assert_eq!(stub.get_debugger().get_registers()[0], 0);
+    assert_eq!(stub.get_debugger().get_pc(), 0);
+    assert_eq!(
+        stub.handle_packet(&format!("Pb={}", register_hex(text_start + 8))),
+        Some("OK".to_string())
+    );
+    assert_eq!(stub.get_debugger().get_pc(), 1);
+
+    // Errors are reported with a matching signal
+    assert_eq!(stub.handle_packet("c"), Some("X08".to_string()));
+}
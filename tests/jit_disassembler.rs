@@ -0,0 +1,92 @@
+#![allow(clippy::integer_arithmetic)]
+// Copyright 2022 Solana Maintainers <maintainers@solana.com>
+//
+// Licensed under the Apache License, Version 2.0 <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license <http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+extern crate solana_rbpf;
+
+use solana_rbpf::{
+    assembler::assemble,
+    elf::Executable,
+    jit_disassembler::{disassemble_jit_program, disassemble_x86_instruction},
+    static_analysis::Analysis,
+    user_error::UserError,
+    vm::{Config, SyscallRegistry, TestInstructionMeter},
+};
+use std::pin::Pin;
+
+const SOURCE: &str = "
+    mov r0, 1
+    lddw r1, 0x1122334455667788
+    jeq r1, 0, +1
+    add r0, r1
+    exit";
+
+fn assemble_source(source: &str) -> Pin<Box<Executable<UserError, TestInstructionMeter>>> {
+    let config = Config {
+        noop_instruction_ratio: 0.0,
+        ..Config::default()
+    };
+    assemble::<UserError, TestInstructionMeter>(source, None, config, SyscallRegistry::default())
+        .unwrap()
+}
+
+#[test]
+fn test_machine_code_ranges() {
+    let mut executable = assemble_source(SOURCE);
+    Executable::<UserError, TestInstructionMeter>::jit_compile(&mut executable).unwrap();
+    let compiled_program = executable.get_compiled_program().unwrap();
+    let machine_code = compiled_program.machine_code();
+    assert_eq!(machine_code.len(), compiled_program.machine_code_length());
+    let ranges = compiled_program.machine_code_ranges();
+    assert_eq!(ranges.len(), 6);
+    // The second slot of lddw emits no code
+    assert!(ranges[2].is_empty());
+    for (pc, range) in ranges.iter().enumerate() {
+        assert!(range.end <= machine_code.len());
+        if pc != 2 {
+            assert!(!range.is_empty(), "pc {} has no machine code", pc);
+        }
+        if pc > 0 {
+            assert_eq!(ranges[pc - 1].end, range.start);
+        }
+        // Every range consists of whole x86 instructions
+        let mut offset = range.start;
+        while offset < range.end {
+            offset += disassemble_x86_instruction(machine_code, offset).0;
+        }
+        assert_eq!(offset, range.end);
+    }
+}
+
+#[test]
+fn test_disassemble_jit_program() {
+    let mut executable = assemble_source(SOURCE);
+    let mut output = Vec::new();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    assert!(disassemble_jit_program(&analysis, &mut output).is_err());
+    Executable::<UserError, TestInstructionMeter>::jit_compile(&mut executable).unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    disassemble_jit_program(&analysis, &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let ebpf_lines = output
+        .lines()
+        .filter(|line| line.starts_with("    ") && !line.starts_with("        "))
+        .map(|line| line.trim())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        ebpf_lines,
+        vec![
+            "mov64 r0, 1",
+            "lddw r1, 0x1122334455667788",
+            "jeq r1, 0, lbb_5",
+            "add64 r0, r1",
+            "exit",
+        ]
+    );
+    assert!(output.contains("movabs "));
+    assert!(output.contains(" ret\n"));
+    assert!(!output.contains(".byte"));
+}
@@ -0,0 +1,153 @@
+#![allow(clippy::integer_arithmetic)]
+// Copyright 2022 Solana Maintainers <maintainers@solana.com>
+//
+// Licensed under the Apache License, Version 2.0 <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license <http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+extern crate solana_rbpf;
+
+use solana_rbpf::{
+    assembler::assemble,
+    profiler::{FunctionProfile, Profile},
+    static_analysis::Analysis,
+    syscalls::{BpfSyscallContext, BpfSyscallU64},
+    user_error::UserError,
+    vm::{Config, EbpfVm, SyscallObject, SyscallRegistry, TestInstructionMeter, Tracer},
+};
+
+const PROG: &str = "
+    mov r6, 0
+loop:
+    add r6, 1
+    mov r1, r6
+    call function_outer
+    jlt r6, 3, loop
+    exit
+function_outer:
+    mov r0, r1
+    call function_inner
+    exit
+function_inner:
+    mul r0, r0
+    exit";
+
+fn profile() -> Profile {
+    let config = Config {
+        enable_instruction_tracing: true,
+        enable_symbol_and_section_labels: true,
+        ..Config::default()
+    };
+    let executable =
+        assemble::<UserError, TestInstructionMeter>(PROG, None, config, SyscallRegistry::default())
+            .unwrap();
+    let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+    vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 100 })
+        .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    Profile::new(vm.get_tracer(), &analysis)
+}
+
+#[test]
+fn test_profile() {
+    let profile = profile();
+    assert_eq!(profile.total_insn_count, 1 + 3 * 4 + 1 + 3 * 3 + 3 * 2);
+    assert_eq!(profile.max_depth, 3);
+    assert_eq!(
+        profile.functions.values().cloned().collect::<Vec<_>>(),
+        vec![
+            FunctionProfile {
+                name: "entrypoint".to_string(),
+                call_count: 1,
+                exclusive_insn_count: 14,
+                inclusive_insn_count: 29,
+            },
+            FunctionProfile {
+                name: "function_outer".to_string(),
+                call_count: 3,
+                exclusive_insn_count: 9,
+                inclusive_insn_count: 15,
+            },
+            FunctionProfile {
+                name: "function_inner".to_string(),
+                call_count: 3,
+                exclusive_insn_count: 6,
+                inclusive_insn_count: 6,
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_collapsed_stacks() {
+    let mut output = Vec::new();
+    profile().write_collapsed_stacks(&mut output).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "entrypoint 14\n\
+         entrypoint;function_outer 9\n\
+         entrypoint;function_outer;function_inner 6\n"
+    );
+}
+
+#[test]
+fn test_pprof() {
+    let mut output = Vec::new();
+    profile().write_pprof(&mut output).unwrap();
+    // sample_type { type: 1, unit: 2 }
+    assert_eq!(&output[0..6], &[0x0a, 0x04, 0x08, 0x01, 0x10, 0x02]);
+    // First sample { location_id: [1], value: [14] }
+    assert_eq!(
+        &output[6..14],
+        &[0x12, 0x06, 0x0a, 0x01, 0x01, 0x12, 0x01, 14]
+    );
+    // Last entry of the string table
+    assert!(output.ends_with(b"\x32\x0efunction_inner"));
+}
+
+#[test]
+fn test_syscall_before_function_entry() {
+    let config = Config {
+        enable_instruction_tracing: true,
+        enable_symbol_and_section_labels: true,
+        ..Config::default()
+    };
+    let mut syscall_registry = SyscallRegistry::default();
+    syscall_registry
+        .register_syscall_by_name(
+            b"log_64",
+            BpfSyscallU64::init::<BpfSyscallContext, UserError>,
+            BpfSyscallU64::call,
+        )
+        .unwrap();
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r6, 0
+        syscall log_64
+    function_foo:
+        add r6, 1
+        jgt r6, 1, +1
+        call function_foo
+        exit",
+        None,
+        config,
+        syscall_registry,
+    )
+    .unwrap();
+    let mut tracer = Tracer::default();
+    for pc in [0, 1, 2, 3, 4, 2, 3, 5, 5] {
+        tracer.trace([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, pc]);
+    }
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let profile = Profile::new(&tracer, &analysis);
+    assert_eq!(profile.max_depth, 2);
+    assert_eq!(
+        profile
+            .functions
+            .values()
+            .map(|function_profile| (function_profile.name.as_str(), function_profile.call_count))
+            .collect::<Vec<_>>(),
+        vec![("entrypoint", 1), ("function_foo", 1)]
+    );
+    assert_eq!(profile.program_vm_addr, executable.get_text_bytes().0);
+}
@@ -0,0 +1,236 @@
+#![allow(clippy::integer_arithmetic)]
+// Copyright 2022 Solana Maintainers <maintainers@solana.com>
+//
+// Licensed under the Apache License, Version 2.0 <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license <http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+extern crate solana_rbpf;
+
+use solana_rbpf::{
+    assembler::assemble,
+    ebpf,
+    elf::Executable,
+    error::EbpfError,
+    memory_region::MemoryRegion,
+    replay::Replay,
+    user_error::UserError,
+    vm::{Config, EbpfVm, StoreRecord, SyscallRegistry, TestInstructionMeter},
+};
+use std::pin::Pin;
+
+const PROG: &str = "
+    mov r2, 0x11
+    stxb [r1], r2
+    stxh [r1+2], r2
+    mov r2, 0x2233
+    stxh [r1+1], r2
+    stw [r1+4], 0x44556677
+    ldxdw r0, [r1]
+    stdw [r10-8], 0x12345678
+    exit";
+
+const MEM: [u8; 8] = [0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff, 0x00, 0x11];
+
+fn asm() -> Pin<Box<Executable<UserError, TestInstructionMeter>>> {
+    let config = Config {
+        enable_instruction_tracing: true,
+        enable_store_journal: true,
+        ..Config::default()
+    };
+    assemble(PROG, None, config, SyscallRegistry::default()).unwrap()
+}
+
+/// Memory contents after running the first `instruction_count` instructions
+fn run_prefix(
+    executable: &Pin<Box<Executable<UserError, TestInstructionMeter>>>,
+    instruction_count: u64,
+) -> [u8; 8] {
+    let mut mem = MEM;
+    let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+    let mut vm = EbpfVm::new(executable, &mut [], vec![mem_region]).unwrap();
+    let result = vm.execute_program_interpreted(&mut TestInstructionMeter {
+        remaining: instruction_count,
+    });
+    assert!(
+        instruction_count == 0
+            || result.is_ok()
+            || matches!(result, Err(EbpfError::ExceededMaxInstructions(_, _)))
+    );
+    mem
+}
+
+#[test]
+fn test_store_journal() {
+    let executable = asm();
+    let mut mem = MEM;
+    let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+    let mut vm = EbpfVm::new(&executable, &mut [], vec![mem_region]).unwrap();
+    vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 100 })
+        .unwrap();
+    let stores = &vm.get_tracer().stores;
+    assert_eq!(stores.len(), 5);
+    assert_eq!(
+        stores[0],
+        StoreRecord {
+            index: 1,
+            vm_addr: ebpf::MM_INPUT_START,
+            len: 1,
+            old_value: 0xaa,
+            new_value: 0x11,
+        }
+    );
+    assert_eq!(
+        stores[2],
+        StoreRecord {
+            index: 4,
+            vm_addr: ebpf::MM_INPUT_START + 1,
+            len: 2,
+            old_value: 0x11bb,
+            new_value: 0x2233,
+        }
+    );
+    assert_eq!(stores[4].index, 7);
+    assert_eq!(stores[4].new_value, 0x12345678);
+}
+
+#[test]
+fn test_store_journal_without_tracing() {
+    let config = Config {
+        enable_store_journal: true,
+        ..Config::default()
+    };
+    let executable =
+        assemble::<UserError, TestInstructionMeter>(PROG, None, config, SyscallRegistry::default())
+            .unwrap();
+    let mut mem = MEM;
+    let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+    let mut vm = EbpfVm::new(&executable, &mut [], vec![mem_region]).unwrap();
+    vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 100 })
+        .unwrap();
+    // The stores still refer to the instructions in order of execution
+    assert!(vm.get_tracer().log.is_empty());
+    assert_eq!(
+        vm.get_tracer()
+            .stores
+            .iter()
+            .map(|store| store.index)
+            .collect::<Vec<_>>(),
+        vec![1, 2, 4, 5, 7]
+    );
+}
+
+#[test]
+fn test_replay_seek() {
+    let executable = asm();
+    let expected = (0..=9)
+        .map(|instruction_count| run_prefix(&executable, instruction_count))
+        .collect::<Vec<_>>();
+
+    let mut mem = MEM;
+    let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+    let mut vm = EbpfVm::new(&executable, &mut [], vec![mem_region]).unwrap();
+    vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 100 })
+        .unwrap();
+    let mut replay = Replay::new(&mut vm);
+    assert_eq!(replay.get_instruction_count(), 9);
+    assert_eq!(replay.get_position(), 9);
+    assert_eq!(replay.get_state(), None);
+
+    // Backwards one instruction at a time, then forwards, then jumping around
+    for index in (0..9).rev() {
+        replay.step_back().unwrap();
+        assert_eq!(replay.get_position(), index);
+        assert_eq!(
+            replay.read_memory(ebpf::MM_INPUT_START, 8).unwrap(),
+            expected[index]
+        );
+    }
+    replay.step_back().unwrap();
+    assert_eq!(replay.get_position(), 0);
+    assert_eq!(replay.get_state().unwrap()[11], 0);
+    for index in 1..=9 {
+        replay.step_forward().unwrap();
+        assert_eq!(
+            replay.read_memory(ebpf::MM_INPUT_START, 8).unwrap(),
+            expected[index]
+        );
+    }
+    for index in [3, 7, 1, 9, 0, 5] {
+        replay.seek(index).unwrap();
+        assert_eq!(
+            replay.read_memory(ebpf::MM_INPUT_START, 8).unwrap(),
+            expected[index]
+        );
+        assert_eq!(replay.get_position(), index);
+    }
+    replay.seek(100).unwrap();
+    assert_eq!(replay.get_position(), 9);
+}
+
+#[test]
+fn test_replay_without_tracing() {
+    let config = Config {
+        enable_store_journal: true,
+        ..Config::default()
+    };
+    let executable =
+        assemble::<UserError, TestInstructionMeter>(PROG, None, config, SyscallRegistry::default())
+            .unwrap();
+    let expected = (0..=9)
+        .map(|instruction_count| run_prefix(&executable, instruction_count))
+        .collect::<Vec<_>>();
+
+    let mut mem = MEM;
+    let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+    let mut vm = EbpfVm::new(&executable, &mut [], vec![mem_region]).unwrap();
+    vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 100 })
+        .unwrap();
+    let mut replay = Replay::new(&mut vm);
+    assert_eq!(replay.get_instruction_count(), 9);
+    assert_eq!(replay.get_position(), 9);
+    for index in [4, 0, 9, 2] {
+        replay.seek(index).unwrap();
+        assert_eq!(replay.get_state(), None);
+        assert_eq!(
+            replay.read_memory(ebpf::MM_INPUT_START, 8).unwrap(),
+            expected[index]
+        );
+    }
+}
+
+#[test]
+fn test_replay_find_last_store() {
+    let executable = asm();
+    let mut mem = MEM;
+    let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+    let mut vm = EbpfVm::new(&executable, &mut [], vec![mem_region]).unwrap();
+    vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 100 })
+        .unwrap();
+    let mut replay = Replay::new(&mut vm);
+    assert_eq!(
+        replay
+            .find_last_store(ebpf::MM_INPUT_START + 2)
+            .unwrap()
+            .index,
+        4
+    );
+    assert_eq!(
+        replay
+            .find_last_store(ebpf::MM_INPUT_START + 3)
+            .unwrap()
+            .index,
+        2
+    );
+    assert!(replay.find_last_store(ebpf::MM_STACK_START).is_none());
+    replay.seek(4).unwrap();
+    assert_eq!(
+        replay
+            .find_last_store(ebpf::MM_INPUT_START + 2)
+            .unwrap()
+            .index,
+        2
+    );
+    replay.seek(2).unwrap();
+    assert!(replay.find_last_store(ebpf::MM_INPUT_START + 2).is_none());
+}
@@ -0,0 +1,174 @@
+#![allow(clippy::integer_arithmetic)]
+// Copyright 2022 Solana Maintainers <maintainers@solana.com>
+//
+// Licensed under the Apache License, Version 2.0 <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license <http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+extern crate solana_rbpf;
+
+use solana_rbpf::{
+    assembler::assemble,
+    ebpf,
+    elf::Executable,
+    interpreter::Interpreter,
+    snapshot::{Snapshot, SnapshotError, SNAPSHOT_VERSION},
+    user_error::UserError,
+    vm::{Config, EbpfVm, SyscallRegistry, TestInstructionMeter},
+};
+use std::pin::Pin;
+
+const PROG: &str = "
+    mov r6, 0
+    lddw r7, 0x300000000
+loop:
+    add r6, 1
+    mov r1, r6
+    call function_square
+    stxdw [r7], r0
+    stxdw [r10-8], r6
+    jlt r6, 5, loop
+    ldxdw r0, [r7]
+    exit
+function_square:
+    mov r0, r1
+    mul r0, r1
+    stxdw [r10-8], r0
+    exit";
+
+fn asm(src: &str) -> Pin<Box<Executable<UserError, TestInstructionMeter>>> {
+    assemble(src, None, Config::default(), SyscallRegistry::default()).unwrap()
+}
+
+/// Runs the program for `steps` instructions and serializes a snapshot of its state
+fn capture(
+    executable: &Pin<Box<Executable<UserError, TestInstructionMeter>>>,
+    steps: usize,
+) -> Vec<u8> {
+    let mut heap = [0u8; 16];
+    let mut vm = EbpfVm::new(executable, &mut heap, Vec::new()).unwrap();
+    let mut instruction_meter = TestInstructionMeter { remaining: 1000 };
+    let mut interpreter = Interpreter::new(&mut vm, &mut instruction_meter).unwrap();
+    for _ in 0..steps {
+        assert_eq!(interpreter.step().unwrap(), None);
+    }
+    Snapshot::capture(&interpreter).to_bytes()
+}
+
+#[test]
+fn test_snapshot_roundtrip() {
+    let executable = asm(PROG);
+    let mut expected_heap = [0u8; 16];
+    let expected_result = {
+        let mut vm = EbpfVm::new(&executable, &mut expected_heap, Vec::new()).unwrap();
+        vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 1000 })
+            .unwrap()
+    };
+    assert_eq!(expected_result, 25);
+
+    // Capture inside the called function, in the second iteration
+    let bytes = capture(&executable, 15);
+    let snapshot = Snapshot::from_bytes(&bytes).unwrap();
+    assert_eq!(snapshot.to_bytes(), bytes);
+    assert_eq!(snapshot.pc, 11);
+    assert_eq!(snapshot.frames.len(), 2);
+    assert_eq!(snapshot.reg[6], 2);
+    assert_eq!(snapshot.remaining_insn_count, 1000 - 15);
+
+    // The surplus of the instruction meter is consumed
+    let mut heap = [0u8; 16];
+    let mut vm = EbpfVm::new(&executable, &mut heap, Vec::new()).unwrap();
+    let mut instruction_meter = TestInstructionMeter { remaining: 2000 };
+    let mut interpreter = Interpreter::new(&mut vm, &mut instruction_meter).unwrap();
+    snapshot.restore(&mut interpreter).unwrap();
+    assert_eq!(Snapshot::capture(&interpreter), snapshot);
+    let result = loop {
+        if let Some(result) = interpreter.step().unwrap() {
+            break result;
+        }
+    };
+    assert_eq!(result, expected_result);
+    drop(vm);
+    assert_eq!(heap, expected_heap);
+    // Stepping does not consume from the meter, so it still holds the restored budget
+    assert_eq!(instruction_meter.remaining, snapshot.remaining_insn_count);
+}
+
+#[test]
+fn test_snapshot_malformed() {
+    let executable = asm(PROG);
+    let bytes = capture(&executable, 3);
+    assert_eq!(
+        Snapshot::from_bytes(&bytes[..bytes.len() - 1]),
+        Err(SnapshotError::Truncated)
+    );
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    assert_eq!(
+        Snapshot::from_bytes(&trailing),
+        Err(SnapshotError::TrailingData)
+    );
+    let mut bad_magic = bytes.clone();
+    bad_magic[0] = 0;
+    assert_eq!(
+        Snapshot::from_bytes(&bad_magic),
+        Err(SnapshotError::InvalidMagic)
+    );
+    let mut bad_version = bytes;
+    bad_version[8..12].copy_from_slice(&(SNAPSHOT_VERSION + 1).to_le_bytes());
+    assert_eq!(
+        Snapshot::from_bytes(&bad_version),
+        Err(SnapshotError::UnsupportedVersion(SNAPSHOT_VERSION + 1))
+    );
+}
+
+#[test]
+fn test_snapshot_mismatch() {
+    let executable = asm(PROG);
+    let snapshot = Snapshot::from_bytes(&capture(&executable, 3)).unwrap();
+
+    let other_executable = asm("
+    mov r0, 0
+    exit");
+    let mut heap = [0u8; 16];
+    let mut vm = EbpfVm::new(&other_executable, &mut heap, Vec::new()).unwrap();
+    let mut instruction_meter = TestInstructionMeter { remaining: 1000 };
+    let mut interpreter = Interpreter::new(&mut vm, &mut instruction_meter).unwrap();
+    assert_eq!(
+        snapshot.restore(&mut interpreter),
+        Err(SnapshotError::ExecutableMismatch)
+    );
+
+    let mut heap = [0u8; 8];
+    let mut vm = EbpfVm::new(&executable, &mut heap, Vec::new()).unwrap();
+    let mut interpreter = Interpreter::new(&mut vm, &mut instruction_meter).unwrap();
+    assert_eq!(
+        snapshot.restore(&mut interpreter),
+        Err(SnapshotError::MemoryRegionMismatch(ebpf::MM_HEAP_START))
+    );
+
+    let mut heap = [0u8; 16];
+    let mut vm = EbpfVm::new(&executable, &mut heap, Vec::new()).unwrap();
+    let mut instruction_meter = TestInstructionMeter { remaining: 10 };
+    let mut interpreter = Interpreter::new(&mut vm, &mut instruction_meter).unwrap();
+    assert_eq!(
+        snapshot.restore(&mut interpreter),
+        Err(SnapshotError::InsufficientInstructionBudget(1000 - 3))
+    );
+
+    let mut instruction_meter = TestInstructionMeter { remaining: 1000 };
+    for frames in [
+        vec![(0, [0; 4], 0)],
+        vec![snapshot.frames[0], snapshot.frames[0]],
+    ] {
+        let mut invalid = snapshot.clone();
+        invalid.frames = frames;
+        invalid.frames[0].2 = 100;
+        let mut vm = EbpfVm::new(&executable, &mut heap, Vec::new()).unwrap();
+        let mut interpreter = Interpreter::new(&mut vm, &mut instruction_meter).unwrap();
+        assert_eq!(
+            invalid.restore(&mut interpreter),
+            Err(SnapshotError::InvalidCallFrames)
+        );
+    }
+}
@@ -0,0 +1,109 @@
+#![allow(clippy::integer_arithmetic)]
+// Copyright 2022 Solana Maintainers <maintainers@solana.com>
+//
+// Licensed under the Apache License, Version 2.0 <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license <http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+extern crate json;
+extern crate solana_rbpf;
+
+use solana_rbpf::{
+    assembler::assemble,
+    static_analysis::Analysis,
+    trace_format::{self, TraceFormatError, TRACE_VERSION},
+    user_error::UserError,
+    vm::{Config, EbpfVm, SyscallRegistry, TestInstructionMeter, Tracer},
+};
+
+const PROG: &str = "
+    mov r6, 0
+    lddw r7, 0x123456789abcdef0
+loop:
+    add r6, 1
+    mov r1, r6
+    call function_square
+    jlt r6, 3, loop
+    exit
+function_square:
+    mov r0, r1
+    mul r0, r1
+    exit";
+
+fn trace() -> (Tracer, Vec<u8>) {
+    let config = Config {
+        enable_instruction_tracing: true,
+        enable_symbol_and_section_labels: true,
+        ..Config::default()
+    };
+    let executable =
+        assemble::<UserError, TestInstructionMeter>(PROG, None, config, SyscallRegistry::default())
+            .unwrap();
+    let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+    vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 100 })
+        .unwrap();
+    let tracer = vm.get_tracer().clone();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let mut json_lines = Vec::new();
+    trace_format::write_json_lines(&tracer, &mut json_lines, &analysis).unwrap();
+    (tracer, json_lines)
+}
+
+#[test]
+fn test_json_lines() {
+    let (tracer, json_lines) = trace();
+    let text = String::from_utf8(json_lines.clone()).unwrap();
+    let lines = text.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), tracer.log.len());
+    let entry = json::parse(lines[1]).unwrap();
+    assert_eq!(entry["index"], 1);
+    assert_eq!(entry["pc"], 1);
+    assert_eq!(entry["insn"], "lddw r7, 0x123456789abcdef0");
+    assert_eq!(entry["function"], "entrypoint");
+    assert_eq!(entry["regs"].len(), 11);
+    let entry = json::parse(lines[2]).unwrap();
+    assert_eq!(entry["regs"][7], "0x123456789abcdef0");
+    let entry = json::parse(lines[6]).unwrap();
+    assert_eq!(entry["function"], "function_square");
+
+    let loaded = trace_format::read(json_lines.as_slice()).unwrap();
+    assert_eq!(loaded.log, tracer.log);
+    assert!(matches!(
+        trace_format::read(&b"{\"pc\":1}\n"[..]),
+        Err(TraceFormatError::MalformedEntry(0))
+    ));
+}
+
+#[test]
+fn test_json_lines_pc_outside_of_text() {
+    let config = Config::default();
+    let executable =
+        assemble::<UserError, TestInstructionMeter>(PROG, None, config, SyscallRegistry::default())
+            .unwrap();
+    let analysis = Analysis::from_executable(&executable).unwrap();
+    let mut tracer = Tracer::default();
+    tracer.trace([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1000]);
+    let mut json_lines = Vec::new();
+    let err = trace_format::write_json_lines(&tracer, &mut json_lines, &analysis).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_binary() {
+    let (tracer, json_lines) = trace();
+    let mut binary = Vec::new();
+    trace_format::write_binary(&tracer, &mut binary).unwrap();
+    assert!(binary.len() * 10 < json_lines.len());
+    let loaded = trace_format::read(binary.as_slice()).unwrap();
+    assert_eq!(loaded.log, tracer.log);
+    assert!(matches!(
+        trace_format::read_binary(&binary[..binary.len() - 1]),
+        Err(TraceFormatError::Truncated)
+    ));
+    let mut bad_version = binary;
+    bad_version[8..12].copy_from_slice(&(TRACE_VERSION + 1).to_le_bytes());
+    assert!(matches!(
+        trace_format::read(bad_version.as_slice()),
+        Err(TraceFormatError::UnsupportedVersion(version)) if version == TRACE_VERSION + 1
+    ));
+}
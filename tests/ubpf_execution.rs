@@ -16,13 +16,17 @@ use byteorder::{ByteOrder, LittleEndian};
 use rand::{rngs::SmallRng, RngCore, SeedableRng};
 use solana_rbpf::{
     assembler::assemble,
-    ebpf,
+    ebpf::{self, SbfVersion},
     elf::{register_bpf_function, ElfError, Executable},
     error::EbpfError,
     memory_region::{AccessType, MemoryMapping, MemoryRegion},
     syscalls::{self, BpfSyscallContext, Result},
+    tiering::ExecutionTier,
     user_error::UserError,
-    vm::{Config, EbpfVm, SyscallObject, SyscallRegistry, TestInstructionMeter},
+    vm::{
+        Config, EbpfVm, ExecutionStatus, MemoryAccess, SyscallObject, SyscallRegistry,
+        TestInstructionMeter,
+    },
 };
 use std::{collections::BTreeMap, fs::File, io::Read};
 use test_utils::{PROG_TCP_PORT_80, TCP_SACK_ASM, TCP_SACK_MATCH, TCP_SACK_NOMATCH};
@@ -50,6 +54,38 @@ macro_rules! test_interpreter_and_jit {
             assert!(check_closure(&vm, result));
             (vm.get_total_instruction_count(), vm.get_tracer().clone())
         };
+        {
+            #[allow(unused_mut)]
+            let mut check_closure = $check;
+            Executable::<UserError, TestInstructionMeter>::threaded_compile(&mut $executable)
+                .unwrap();
+            let mut mem = $mem;
+            let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+            let mut vm = EbpfVm::new(&$executable, &mut [], vec![mem_region]).unwrap();
+            test_interpreter_and_jit!(bind, vm, $syscall_context);
+            let result = vm.execute_program_threaded(&mut TestInstructionMeter {
+                remaining: $expected_instruction_count,
+            });
+            let tracer_threaded = vm.get_tracer();
+            if !check_closure(&vm, result)
+                || !solana_rbpf::vm::Tracer::compare(&_tracer_interpreter, tracer_threaded)
+            {
+                let analysis =
+                    solana_rbpf::static_analysis::Analysis::from_executable(&$executable).unwrap();
+                let stdout = std::io::stdout();
+                _tracer_interpreter
+                    .write(&mut stdout.lock(), &analysis)
+                    .unwrap();
+                tracer_threaded
+                    .write(&mut stdout.lock(), &analysis)
+                    .unwrap();
+                panic!();
+            }
+            if $executable.get_config().enable_instruction_meter {
+                let instruction_count_threaded = vm.get_total_instruction_count();
+                assert_eq!(instruction_count_interpreter, instruction_count_threaded);
+            }
+        }
         #[cfg(all(not(windows), target_arch = "x86_64"))]
         {
             #[allow(unused_mut)]
@@ -623,6 +659,51 @@ fn test_be64() {
     );
 }
 
+#[test]
+fn test_bswap16() {
+    test_interpreter_and_jit_asm!(
+        "
+        ldxdw r0, [r1]
+        bswap16 r0
+        exit",
+        [0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0x1122 } },
+        3
+    );
+}
+
+#[test]
+fn test_bswap32() {
+    test_interpreter_and_jit_asm!(
+        "
+        ldxdw r0, [r1]
+        bswap32 r0
+        exit",
+        [0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0x11223344 } },
+        3
+    );
+}
+
+#[test]
+fn test_bswap64() {
+    test_interpreter_and_jit_asm!(
+        "
+        ldxdw r0, [r1]
+        bswap64 r0
+        exit",
+        [0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0x1122334455667788 } },
+        3
+    );
+}
+
 #[test]
 fn test_le16() {
     test_interpreter_and_jit_asm!(
@@ -1174,6 +1255,213 @@ fn test_err_mod_by_zero_reg() {
     );
 }
 
+#[test]
+fn test_smod64_imm() {
+    let config = Config {
+        enable_ebpf_v4: true,
+        ..Config::default()
+    };
+    test_interpreter_and_jit_asm!(
+        "
+        mov r0, -7
+        smod r0, 3
+        exit",
+        config,
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() as i64 == -1 } },
+        3
+    );
+}
+
+#[test]
+fn test_smod64_reg() {
+    let config = Config {
+        enable_ebpf_v4: true,
+        ..Config::default()
+    };
+    test_interpreter_and_jit_asm!(
+        "
+        mov r0, 7
+        mov r1, -3
+        smod r0, r1
+        exit",
+        config,
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 1 } },
+        4
+    );
+}
+
+#[test]
+fn test_smod32() {
+    let config = Config {
+        enable_ebpf_v4: true,
+        ..Config::default()
+    };
+    test_interpreter_and_jit_asm!(
+        "
+        lddw r0, 0x1fffffff9
+        mov r1, 3
+        smod32 r0, r1
+        smod32 r0, 2
+        exit",
+        config,
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0xffffffff } },
+        5
+    );
+}
+
+#[test]
+fn test_mod_with_offset_without_ebpf_v4() {
+    test_interpreter_and_jit_asm!(
+        "
+        mov r0, -7
+        smod r0, 3
+        exit",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == (-7i64 as u64) % 3 } },
+        3
+    );
+}
+
+#[test]
+fn test_err_smod64_by_zero_reg() {
+    let config = Config {
+        enable_ebpf_v4: true,
+        ..Config::default()
+    };
+    test_interpreter_and_jit_asm!(
+        "
+        mov32 r0, 1
+        mov32 r1, 0
+        smod r0, r1
+        exit",
+        config,
+        [],
+        (),
+        0,
+        { |_vm, res: Result| matches!(res.unwrap_err(), EbpfError::DivideByZero(pc) if pc == 31) },
+        3
+    );
+}
+
+#[test]
+fn test_err_smod32_overflow_imm() {
+    let config = Config {
+        enable_ebpf_v4: true,
+        ..Config::default()
+    };
+    test_interpreter_and_jit_asm!(
+        "
+        mov r0, 0x80
+        lsh r0, 24
+        smod32 r0, -1
+        exit",
+        config,
+        [],
+        (),
+        0,
+        {
+            |_vm, res: Result| matches!(res.unwrap_err(), EbpfError::DivideOverflow(pc) if pc == 31)
+        },
+        3
+    );
+}
+
+#[test]
+fn test_err_smod64_overflow_reg() {
+    let config = Config {
+        enable_ebpf_v4: true,
+        ..Config::default()
+    };
+    test_interpreter_and_jit_asm!(
+        "
+        mov r0, 1
+        lsh r0, 63
+        mov r1, -1
+        smod r0, r1
+        exit",
+        config,
+        [],
+        (),
+        0,
+        {
+            |_vm, res: Result| matches!(res.unwrap_err(), EbpfError::DivideOverflow(pc) if pc == 32)
+        },
+        4
+    );
+}
+
+#[test]
+fn test_movsx() {
+    let config = Config {
+        enable_ebpf_v4: true,
+        ..Config::default()
+    };
+    test_interpreter_and_jit_asm!(
+        "
+        lddw r1, 0x180008080
+        movsx8 r0, r1
+        movsx16 r2, r1
+        add r0, r2
+        movsx32 r2, r1
+        add r0, r2
+        exit",
+        config,
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() as i64 == -0x80 - 0x7f80 - 0x7fff7f80 } },
+        7
+    );
+}
+
+#[test]
+fn test_mov32sx() {
+    let config = Config {
+        enable_ebpf_v4: true,
+        ..Config::default()
+    };
+    test_interpreter_and_jit_asm!(
+        "
+        lddw r1, 0x100018080
+        mov32sx8 r0, r1
+        mov32sx16 r2, r1
+        add r0, r2
+        exit",
+        config,
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0xffffff80 + 0xffff8080 } },
+        5
+    );
+}
+
+#[test]
+fn test_mov_with_offset_without_ebpf_v4() {
+    test_interpreter_and_jit_asm!(
+        "
+        lddw r1, 0x180008080
+        movsx8 r0, r1
+        exit",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0x180008080 } },
+        3
+    );
+}
+
 // BPF_LD : Loads
 
 #[test]
@@ -1509,6 +1797,48 @@ fn test_err_ldxdw_oob() {
     );
 }
 
+#[test]
+fn test_ldxsb() {
+    test_interpreter_and_jit_asm!(
+        "
+        ldxsb r0, [r1+2]
+        exit",
+        [0xaa, 0xbb, 0x81, 0xcc, 0xdd],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() as i64 == -0x7f } },
+        2
+    );
+}
+
+#[test]
+fn test_ldxsh() {
+    test_interpreter_and_jit_asm!(
+        "
+        ldxsh r0, [r1+2]
+        exit",
+        [0xaa, 0xbb, 0x11, 0x82, 0xcc, 0xdd],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() as i64 == -0x7def } },
+        2
+    );
+}
+
+#[test]
+fn test_ldxsw() {
+    test_interpreter_and_jit_asm!(
+        "
+        ldxsw r0, [r1+2]
+        exit",
+        [0xaa, 0xbb, 0x11, 0x22, 0x33, 0x84, 0xcc, 0xdd],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0xffffffff84332211 } },
+        2
+    );
+}
+
 #[test]
 fn test_ldxb_all() {
     test_interpreter_and_jit_asm!(
@@ -1963,28 +2293,175 @@ fn test_stxb_chain() {
     );
 }
 
-// BPF_JMP : Branches
-
 #[test]
-fn test_exit_without_value() {
+fn test_stxxadd() {
     test_interpreter_and_jit_asm!(
         "
+        mov r2, 0x10
+        stxxaddw [r1], r2
+        stxxadddw [r1], r2
+        ldxdw r0, [r1]
         exit",
-        [],
+        [
+            0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, //
+        ],
         (),
         0,
-        { |_vm, res: Result| { res.unwrap() == 0x0 } },
-        1
+        { |_vm, res: Result| { res.unwrap() == 0x8877665544332231 } },
+        5
     );
 }
 
 #[test]
-fn test_exit() {
+fn test_stxx_or_and_xor() {
     test_interpreter_and_jit_asm!(
         "
-        mov r0, 0
+        mov r2, 0xf0
+        stxxorw [r1], r2
+        stxxanddw [r1], r2
+        mov r2, 0x0f
+        stxxxorw [r1], r2
+        ldxdw r0, [r1]
         exit",
-        [],
+        [
+            0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, //
+        ],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0xff } },
+        7
+    );
+}
+
+#[test]
+fn test_stxxfetchadd() {
+    test_interpreter_and_jit_asm!(
+        "
+        mov r2, 0x10
+        stxxfetchaddw [r1+4], r2
+        mov r3, 1
+        stxxfetchadddw [r1], r3
+        ldxdw r0, [r1]
+        sub r0, r3
+        add r0, r2
+        exit",
+        [
+            0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, //
+        ],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0x88776656 } },
+        8
+    );
+}
+
+#[test]
+fn test_stxxfetchadd_same_register() {
+    test_interpreter_and_jit_asm!(
+        "
+        mov r0, r1
+        stxxfetchadddw [r0], r0
+        ldxdw r2, [r1]
+        sub r2, r0
+        mov r0, r2
+        exit",
+        [
+            0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, //
+        ],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == ebpf::MM_INPUT_START } },
+        6
+    );
+}
+
+#[test]
+fn test_stxxfetch_or_and_xor() {
+    test_interpreter_and_jit_asm!(
+        "
+        mov r2, 0x0f
+        stxxfetchorw [r1], r2
+        mov r3, -1
+        stxxfetchanddw [r1], r3
+        mov r4, r3
+        stxxfetchxordw [r1], r4
+        ldxdw r0, [r1]
+        add r0, r2
+        sub r4, r3
+        add r0, r4
+        exit",
+        [
+            0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, //
+        ],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0x44332211 } },
+        11
+    );
+}
+
+#[test]
+fn test_stxxxchg() {
+    test_interpreter_and_jit_asm!(
+        "
+        mov r2, 0x99
+        stxxxchgw [r1+4], r2
+        ldxdw r0, [r1]
+        add r0, r2
+        exit",
+        [
+            0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, //
+        ],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0x99ccaa8866 } },
+        5
+    );
+}
+
+#[test]
+fn test_stxxcmpxchg() {
+    test_interpreter_and_jit_asm!(
+        "
+        mov r0, 0x11
+        mov r2, 0x99
+        stxxcmpxchgw [r1], r2
+        stxxcmpxchgw [r1], r2
+        ldxdw r2, [r1]
+        add r0, r2
+        exit",
+        [
+            0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, //
+        ],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0x88776655443322aa } },
+        7
+    );
+}
+
+// BPF_JMP : Branches
+
+#[test]
+fn test_exit_without_value() {
+    test_interpreter_and_jit_asm!(
+        "
+        exit",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0x0 } },
+        1
+    );
+}
+
+#[test]
+fn test_exit() {
+    test_interpreter_and_jit_asm!(
+        "
+        mov r0, 0
+        exit",
+        [],
         (),
         0,
         { |_vm, res: Result| { res.unwrap() == 0x0 } },
@@ -2488,6 +2965,153 @@ fn test_jslt_reg() {
     );
 }
 
+#[test]
+fn test_jeq32() {
+    test_interpreter_and_jit_asm!(
+        "
+        mov32 r0, 0
+        lddw r1, 0x100000001
+        mov32 r2, 1
+        jeq32 r1, 2, +4
+        lddw r3, 0x200000002
+        jeq32 r1, r3, +1
+        jeq32 r1, 1, +1
+        exit
+        jeq32 r1, r2, +1
+        exit
+        mov32 r0, 1
+        exit",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0x1 } },
+        10
+    );
+}
+
+#[test]
+fn test_jne32() {
+    test_interpreter_and_jit_asm!(
+        "
+        mov32 r0, 0
+        lddw r1, 0x100000001
+        mov32 r2, 1
+        jne32 r1, 1, +2
+        jne32 r1, r2, +1
+        jne32 r1, 2, +1
+        exit
+        mov32 r3, 3
+        jne32 r1, r3, +1
+        exit
+        mov32 r0, 1
+        exit",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0x1 } },
+        10
+    );
+}
+
+#[test]
+fn test_jmp32_unsigned() {
+    test_interpreter_and_jit_asm!(
+        "
+        mov32 r0, 0
+        lddw r1, 0x180000000
+        lddw r2, 0x80000001
+        jge32 r1, r2, +3
+        jle32 r1, 0x7fffffff, +2
+        jgt32 r1, -1, +1
+        jlt32 r1, r2, +1
+        exit
+        jgt32 r2, r1, +1
+        exit
+        jle32 r1, -1, +1
+        exit
+        mov32 r0, 1
+        exit",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0x1 } },
+        11
+    );
+}
+
+#[test]
+fn test_jmp32_signed() {
+    test_interpreter_and_jit_asm!(
+        "
+        mov32 r0, 0
+        lddw r1, 0x1fffffffe
+        mov32 r2, 1
+        jsgt32 r1, r2, +4
+        jsge32 r1, -1, +3
+        jslt32 r1, -2, +2
+        jsle32 r2, r1, +1
+        jslt32 r1, r2, +1
+        exit
+        jsle32 r1, -2, +1
+        exit
+        jsge32 r2, r1, +1
+        exit
+        jsgt32 r1, -3, +1
+        exit
+        mov32 r0, 1
+        exit",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0x1 } },
+        13
+    );
+}
+
+#[test]
+fn test_jset32() {
+    test_interpreter_and_jit_asm!(
+        "
+        mov32 r0, 0
+        lddw r1, 0x300000001
+        lddw r2, 0x100000002
+        jset32 r1, r2, +2
+        jset32 r1, 2, +1
+        jset32 r1, 3, +1
+        exit
+        mov32 r2, 5
+        jset32 r1, r2, +1
+        exit
+        mov32 r0, 1
+        exit",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0x1 } },
+        10
+    );
+}
+
+#[test]
+fn test_gotol() {
+    test_interpreter_and_jit_asm!(
+        "
+        mov32 r0, 0
+        gotol forward
+        backward:
+        mov32 r0, 1
+        exit
+        forward:
+        mov32 r0, 2
+        gotol backward",
+        [],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0x1 } },
+        6
+    );
+}
+
 // Call Stack
 
 #[test]
@@ -2587,7 +3211,8 @@ fn test_string_stack() {
 #[test]
 fn test_err_fixed_stack_out_of_bound() {
     let config = Config {
-        dynamic_stack_frames: false,
+        min_sbf_version: SbfVersion::V1,
+        max_sbf_version: SbfVersion::V1,
         max_call_depth: 3,
         ..Config::default()
     };
@@ -2614,7 +3239,8 @@ fn test_err_fixed_stack_out_of_bound() {
 #[test]
 fn test_err_dynamic_stack_out_of_bound() {
     let config = Config {
-        dynamic_stack_frames: true,
+        min_sbf_version: SbfVersion::V2,
+        max_sbf_version: SbfVersion::V2,
         max_call_depth: 3,
         ..Config::default()
     };
@@ -2665,7 +3291,8 @@ fn test_err_dynamic_stack_out_of_bound() {
 #[test]
 fn test_err_dynamic_stack_ptr_overflow() {
     let config = Config {
-        dynamic_stack_frames: true,
+        min_sbf_version: SbfVersion::V2,
+        max_sbf_version: SbfVersion::V2,
         ..Config::default()
     };
 
@@ -2704,7 +3331,8 @@ fn test_err_dynamic_stack_ptr_overflow() {
 #[test]
 fn test_dynamic_stack_frames_empty() {
     let config = Config {
-        dynamic_stack_frames: true,
+        min_sbf_version: SbfVersion::V2,
+        max_sbf_version: SbfVersion::V2,
         ..Config::default()
     };
 
@@ -2728,7 +3356,8 @@ fn test_dynamic_stack_frames_empty() {
 #[test]
 fn test_dynamic_frame_ptr() {
     let config = Config {
-        dynamic_stack_frames: true,
+        min_sbf_version: SbfVersion::V2,
+        max_sbf_version: SbfVersion::V2,
         ..Config::default()
     };
 
@@ -2780,9 +3409,10 @@ fn test_entrypoint_exit() {
     // can't infer anything from the stack size so we track call depth
     // explicitly. Make sure exit still works with both fixed and dynamic
     // frames.
-    for dynamic_stack_frames in [false, true] {
+    for sbf_version in [SbfVersion::V1, SbfVersion::V2] {
         let config = Config {
-            dynamic_stack_frames,
+            min_sbf_version: sbf_version,
+            max_sbf_version: sbf_version,
             ..Config::default()
         };
 
@@ -2809,9 +3439,10 @@ fn test_entrypoint_exit() {
 
 #[test]
 fn test_stack_call_depth_tracking() {
-    for dynamic_stack_frames in [false, true] {
+    for sbf_version in [SbfVersion::V1, SbfVersion::V2] {
         let config = Config {
-            dynamic_stack_frames,
+            min_sbf_version: sbf_version,
+            max_sbf_version: sbf_version,
             max_call_depth: 2,
             ..Config::default()
         };
@@ -3484,7 +4115,8 @@ impl SyscallObject<UserError> for NestedVmSyscall {
 fn test_nested_vm_syscall() {
     let config = Config::default();
     let mut nested_vm_syscall = NestedVmSyscall {};
-    let mut memory_mapping = MemoryMapping::new::<UserError>(vec![], &config).unwrap();
+    let mut memory_mapping =
+        MemoryMapping::new::<UserError>(vec![], &config, SbfVersion::V2).unwrap();
     let mut result = Ok(0);
     nested_vm_syscall.call(1, 0, 0, 0, 0, &mut memory_mapping, &mut result);
     assert!(result.unwrap() == 42);
@@ -3962,6 +4594,633 @@ fn test_err_exit_capped() {
     );
 }
 
+#[test]
+fn test_resumable_execution() {
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r6, 0
+        mov r7, 0
+        loop:
+        add r6, 1
+        mov r1, r6
+        call function_double
+        add r7, r0
+        stxdw [r10-8], r7
+        jlt r6, 10, loop
+        ldxdw r0, [r10-8]
+        exit
+        function_double:
+        mov r0, r1
+        lsh r0, 1
+        exit",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let (expected_result, expected_instruction_count) = {
+        let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+        let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 1000 });
+        (result.unwrap(), vm.get_total_instruction_count())
+    };
+    assert_eq!(expected_result, 110);
+
+    for budget in [1, 4, 7, 1000] {
+        let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+        let mut instruction_count = 0;
+        let mut status = vm
+            .execute_program_interpreted_resumable(&mut TestInstructionMeter { remaining: budget })
+            .unwrap();
+        let result = loop {
+            instruction_count += vm.get_total_instruction_count();
+            match status {
+                ExecutionStatus::Exited(result) => break result,
+                ExecutionStatus::Suspended(continuation) => {
+                    status = vm
+                        .resume_program_interpreted(
+                            &mut TestInstructionMeter { remaining: budget },
+                            continuation,
+                        )
+                        .unwrap();
+                }
+            }
+        };
+        assert_eq!(result, expected_result);
+        assert_eq!(instruction_count, expected_instruction_count);
+    }
+}
+
+#[test]
+fn test_resumable_execution_error() {
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r0, 1
+        mov r1, 0
+        div r0, r1
+        exit",
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+    let continuation = match vm
+        .execute_program_interpreted_resumable(&mut TestInstructionMeter { remaining: 2 })
+        .unwrap()
+    {
+        ExecutionStatus::Suspended(continuation) => continuation,
+        ExecutionStatus::Exited(_) => panic!(),
+    };
+    assert_eq!(continuation.get_pc(), 2);
+    assert_eq!(continuation.get_registers()[0], 1);
+    assert!(matches!(
+        vm.resume_program_interpreted(&mut TestInstructionMeter { remaining: 2 }, continuation),
+        Err(EbpfError::DivideByZero(pc)) if pc == 31
+    ));
+}
+
+pub struct ExhaustingSyscall {}
+impl ExhaustingSyscall {
+    pub fn init<C, E>(_unused: C) -> Box<dyn SyscallObject<UserError>> {
+        Box::new(Self {})
+    }
+}
+impl SyscallObject<UserError> for ExhaustingSyscall {
+    fn call(
+        &mut self,
+        _arg1: u64,
+        _arg2: u64,
+        _arg3: u64,
+        _arg4: u64,
+        _arg5: u64,
+        _memory_mapping: &mut MemoryMapping,
+        result: &mut Result,
+    ) {
+        *result = Err(EbpfError::ExceededMaxInstructions(0, 0));
+    }
+}
+
+#[test]
+fn test_resumable_execution_syscall_error() {
+    let mut syscall_registry = SyscallRegistry::default();
+    syscall_registry
+        .register_syscall_by_name(
+            b"ExhaustingSyscall",
+            ExhaustingSyscall::init::<UserContext, UserError>,
+            ExhaustingSyscall::call,
+        )
+        .unwrap();
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r0, 1
+        syscall ExhaustingSyscall
+        exit",
+        None,
+        Config::default(),
+        syscall_registry,
+    )
+    .unwrap();
+    let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+    vm.bind_syscall_context_objects(0).unwrap();
+    // The syscall did not complete, so it must not be skipped by suspending after it
+    assert!(matches!(
+        vm.execute_program_interpreted_resumable(&mut TestInstructionMeter { remaining: 10 }),
+        Err(EbpfError::ExceededMaxInstructions(0, 0))
+    ));
+}
+
+#[test]
+fn test_memory_tracing() {
+    let config = Config {
+        enable_memory_tracing: true,
+        ..Config::default()
+    };
+    #[allow(unused_mut)]
+    let mut executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        ldxb r2, [r1+1]
+        ldabsh 2
+        stxh [r1+4], r0
+        sth [r1+6], 0x1234
+        ldxh r4, [r1+6]
+        lddw r3, 0x400000005
+        ldxw r3, [r3]
+        exit",
+        None,
+        config,
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let expected = [
+        (0, AccessType::Load, ebpf::MM_INPUT_START + 1, 1, 0x22),
+        (1, AccessType::Load, ebpf::MM_INPUT_START + 2, 2, 0x4433),
+        (2, AccessType::Store, ebpf::MM_INPUT_START + 4, 2, 0x4433),
+        (3, AccessType::Store, ebpf::MM_INPUT_START + 6, 2, 0x1234),
+        (4, AccessType::Load, ebpf::MM_INPUT_START + 6, 2, 0x1234),
+        (7, AccessType::Load, ebpf::MM_INPUT_START + 5, 4, 0x99123444),
+    ]
+    .iter()
+    .map(|(pc, access_type, vm_addr, len, value)| MemoryAccess {
+        pc: *pc,
+        access_type: *access_type,
+        vm_addr: *vm_addr,
+        len: *len,
+        value: *value,
+    })
+    .collect::<Vec<_>>();
+    {
+        let mut mem = [0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99];
+        let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+        let mut vm = EbpfVm::new(&executable, &mut [], vec![mem_region]).unwrap();
+        vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 9 })
+            .unwrap();
+        assert_eq!(vm.get_tracer().memory_accesses, expected);
+    }
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    {
+        Executable::<UserError, TestInstructionMeter>::jit_compile(&mut executable).unwrap();
+        let mut mem = [0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99];
+        let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+        let mut vm = EbpfVm::new(&executable, &mut [], vec![mem_region]).unwrap();
+        vm.execute_program_jit(&mut TestInstructionMeter { remaining: 9 })
+            .unwrap();
+        assert_eq!(vm.get_tracer().memory_accesses, expected);
+    }
+}
+
+#[test]
+fn test_memory_tracing_atomic() {
+    let config = Config {
+        enable_memory_tracing: true,
+        enable_atomics: true,
+        ..Config::default()
+    };
+    #[allow(unused_mut)]
+    let mut executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r2, 0x10
+        stxxaddw [r1], r2
+        stxxfetchaddw [r1], r2
+        stxxxchgw [r1+4], r2
+        mov r0, 0x44332221
+        stxxcmpxchgw [r1+4], r2
+        exit",
+        None,
+        config,
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let expected = [
+        (1, AccessType::Store, ebpf::MM_INPUT_START, 4, 0x44332221),
+        (2, AccessType::Load, ebpf::MM_INPUT_START, 4, 0x44332221),
+        (2, AccessType::Store, ebpf::MM_INPUT_START, 4, 0x44332231),
+        (3, AccessType::Load, ebpf::MM_INPUT_START + 4, 4, 0x8776655),
+        (
+            3,
+            AccessType::Store,
+            ebpf::MM_INPUT_START + 4,
+            4,
+            0x44332221,
+        ),
+        (5, AccessType::Load, ebpf::MM_INPUT_START + 4, 4, 0x44332221),
+        (5, AccessType::Store, ebpf::MM_INPUT_START + 4, 4, 0x8776655),
+    ]
+    .iter()
+    .map(|(pc, access_type, vm_addr, len, value)| MemoryAccess {
+        pc: *pc,
+        access_type: *access_type,
+        vm_addr: *vm_addr,
+        len: *len,
+        value: *value,
+    })
+    .collect::<Vec<_>>();
+    {
+        let mut mem = [0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x08];
+        let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+        let mut vm = EbpfVm::new(&executable, &mut [], vec![mem_region]).unwrap();
+        vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 7 })
+            .unwrap();
+        assert_eq!(vm.get_tracer().memory_accesses, expected);
+    }
+    #[cfg(all(not(windows), target_arch = "x86_64"))]
+    {
+        Executable::<UserError, TestInstructionMeter>::jit_compile(&mut executable).unwrap();
+        let mut mem = [0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x08];
+        let mem_region = MemoryRegion::new_writable(&mut mem, ebpf::MM_INPUT_START);
+        let mut vm = EbpfVm::new(&executable, &mut [], vec![mem_region]).unwrap();
+        vm.execute_program_jit(&mut TestInstructionMeter { remaining: 7 })
+            .unwrap();
+        assert_eq!(vm.get_tracer().memory_accesses, expected);
+    }
+}
+
+#[cfg(all(not(windows), target_arch = "x86_64"))]
+#[test]
+fn test_sampling_profiler() {
+    let config = Config {
+        enable_symbol_and_section_labels: true,
+        sampling_interval: 10,
+        ..Config::default()
+    };
+    let mut executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r6, 0
+    loop:
+        add r6, 1
+        call function_work
+        jlt r6, 100, loop
+        exit
+    function_work:
+        mov r0, r6
+        mul r0, r0
+        add r0, 1
+        mul r0, r0
+        exit",
+        None,
+        config,
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    Executable::<UserError, TestInstructionMeter>::jit_compile(&mut executable).unwrap();
+    let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+    vm.execute_program_jit(&mut TestInstructionMeter { remaining: 1000 })
+        .unwrap();
+    assert_eq!(vm.get_total_instruction_count(), 802);
+    let sampler = vm.get_sampler();
+    let samples = sampler.get_samples();
+    assert_eq!(samples.len() as u64, sampler.get_sample_count());
+    assert!(samples.len() >= 40 && samples.len() <= 81);
+    assert!(samples.iter().all(|pc| *pc < 10));
+    let function_samples = sampler.get_function_samples(&executable);
+    assert_eq!(
+        function_samples
+            .values()
+            .map(|function_samples| function_samples.name.as_str())
+            .collect::<Vec<_>>(),
+        vec!["entrypoint", "function_work"]
+    );
+    assert_eq!(
+        function_samples
+            .values()
+            .map(|function_samples| function_samples.sample_count)
+            .sum::<u64>(),
+        sampler.get_sample_count()
+    );
+}
+
+#[test]
+fn test_tiered_execution_without_promotion() {
+    let config = Config {
+        jit_promotion_threshold: 0,
+        ..Config::default()
+    };
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r0, 1
+        add r0, 2
+        exit",
+        None,
+        config,
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    for invocation_count in 1..=4 {
+        let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+        let result = vm.execute_program(&mut TestInstructionMeter { remaining: 3 });
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(vm.get_total_instruction_count(), 3);
+        executable.wait_for_background_compilation();
+        assert_eq!(executable.get_invocation_count(), invocation_count);
+        assert_eq!(executable.get_execution_tier(), ExecutionTier::Interpreter);
+    }
+}
+
+#[cfg(all(not(windows), target_arch = "x86_64"))]
+#[test]
+fn test_tiered_execution() {
+    let config = Config {
+        enable_instruction_tracing: true,
+        jit_promotion_threshold: 2,
+        ..Config::default()
+    };
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r6, 0
+    loop:
+        add r6, 1
+        call function_square
+        jlt r6, 10, loop
+        exit
+    function_square:
+        mov r0, r6
+        mul r0, r0
+        exit",
+        None,
+        config,
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let mut first_tracer = None;
+    for (invocation_count, expected_tier) in [
+        (1, ExecutionTier::Interpreter),
+        (2, ExecutionTier::Interpreter),
+        (3, ExecutionTier::Jit),
+        (4, ExecutionTier::Jit),
+    ] {
+        // Observe the tier this invocation runs in, not the one it promotes to
+        assert_eq!(executable.get_execution_tier(), expected_tier);
+        let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+        let result = vm.execute_program(&mut TestInstructionMeter { remaining: 100 });
+        assert_eq!(result.unwrap(), 100);
+        assert_eq!(vm.get_total_instruction_count(), 62);
+        let tracer = vm.get_tracer().clone();
+        let first_tracer = first_tracer.get_or_insert(tracer.clone());
+        assert!(solana_rbpf::vm::Tracer::compare(first_tracer, &tracer));
+        executable.wait_for_background_compilation();
+        assert_eq!(executable.get_invocation_count(), invocation_count);
+    }
+}
+
+#[test]
+fn test_jit_optimizations() {
+    for sanitize_user_provided_values in [true, false] {
+        let config = Config {
+            optimize_jit: true,
+            sanitize_user_provided_values,
+            ..Config::default()
+        };
+        test_interpreter_and_jit_asm!(
+            "
+            mov r6, r1
+            add r6, 8
+            stxdw [r1], r6
+            stw [r1+8], 0x11
+            sth [r1+12], 0x22
+            ldxw r2, [r1+8]
+            ldxh r3, [r1+12]
+            mov r4, 5
+            mov r4, 0
+            mov r0, 3
+            sub r0, 1
+            and r2, 0xf0
+            jeq r2, 0, +1
+            add r0, r2
+            sub r3, 0x22
+            jne r3, 0, +1
+            add r0, r3
+            lddw r5, 0x400000000
+            ldxdw r5, [r5]
+            sub r5, r6
+            add r0, r5
+            add r0, r4
+            exit",
+            config,
+            [
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+            ],
+            (),
+            0,
+            { |_vm, res: Result| { res.unwrap() == 0x12 } },
+            23
+        );
+    }
+}
+
+#[test]
+fn test_jit_optimizations_atomic_cmpxchg() {
+    let config = Config {
+        enable_atomics: true,
+        optimize_jit: true,
+        ..Config::default()
+    };
+    test_interpreter_and_jit_asm!(
+        "
+        mov r0, 0x44332211
+        mov r2, 0x99
+        stxxcmpxchgw [r1], r2
+        ldxw r0, [r1]
+        exit",
+        config,
+        [
+            0x11, 0x22, 0x33, 0x44, //
+        ],
+        (),
+        0,
+        { |_vm, res: Result| { res.unwrap() == 0x99 } },
+        5
+    );
+}
+
+#[test]
+fn test_bounds_check_elimination() {
+    for enable_stack_frame_gaps in [true, false] {
+        let config = Config {
+            enable_bounds_check_elimination: true,
+            min_sbf_version: SbfVersion::V1,
+            max_sbf_version: SbfVersion::V1,
+            enable_stack_frame_gaps,
+            ..Config::default()
+        };
+        test_interpreter_and_jit_asm!(
+            "
+            stdw [r10-8], 0x11
+            ldxdw r0, [r10-8]
+            ldxw r2, [r1]
+            ldxw r3, [r1]
+            stxw [r1+4], r3
+            ldxw r4, [r1+4]
+            add r0, r4
+            exit",
+            config,
+            [
+                0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+            ],
+            (),
+            0,
+            { |_vm, res: Result| { res.unwrap() == 0x12 } },
+            8
+        );
+    }
+}
+
+#[cfg(all(not(windows), target_arch = "x86_64"))]
+#[test]
+fn test_backtrace() {
+    for (source, expected_frames) in [
+        (
+            "
+            mov r1, 0
+            call function_outer
+            exit
+        function_outer:
+            call function_inner
+            exit
+        function_inner:
+            mov r0, 1
+            div r0, r1
+            exit",
+            vec![
+                (6, "function_inner"),
+                (3, "function_outer"),
+                (1, "entrypoint"),
+            ],
+        ),
+        (
+            "
+            call function_inner
+            exit
+        function_inner:
+            mov r1, 0
+            ldxdw r0, [r1]
+            exit",
+            vec![(3, "function_inner"), (0, "entrypoint")],
+        ),
+        (
+            "
+            call function_recurse
+            exit
+        function_recurse:
+            call function_recurse
+            exit",
+            std::iter::repeat((2, "function_recurse"))
+                .take(3)
+                .chain(std::iter::once((0, "entrypoint")))
+                .collect(),
+        ),
+    ] {
+        let config = Config {
+            enable_symbol_and_section_labels: true,
+            enable_backtrace: true,
+            max_call_depth: 4,
+            ..Config::default()
+        };
+        let mut executable = assemble::<UserError, TestInstructionMeter>(
+            source,
+            None,
+            config,
+            SyscallRegistry::default(),
+        )
+        .unwrap();
+        Executable::<UserError, TestInstructionMeter>::jit_compile(&mut executable).unwrap();
+        let mut backtraces = Vec::new();
+        for use_jit in [false, true] {
+            let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+            let result = if use_jit {
+                vm.execute_program_jit(&mut TestInstructionMeter { remaining: 100 })
+            } else {
+                vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 100 })
+            };
+            let backtrace = match result {
+                Err(EbpfError::WithBacktrace(_error, backtrace)) => backtrace,
+                result => panic!("{:?}", result),
+            };
+            assert_eq!(
+                backtrace
+                    .frames
+                    .iter()
+                    .map(|frame| (frame.pc, frame.name.as_str()))
+                    .collect::<Vec<_>>(),
+                expected_frames
+            );
+            backtraces.push(backtrace.to_string());
+        }
+        assert_eq!(backtraces[0], backtraces[1]);
+    }
+}
+
+#[test]
+fn test_backtrace_reused_vm() {
+    let config = Config {
+        enable_symbol_and_section_labels: true,
+        enable_backtrace: true,
+        ..Config::default()
+    };
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r1, 0
+        call function_inner
+        exit
+    function_inner:
+        mov r0, 1
+        div r0, r1
+        exit",
+        None,
+        config,
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+    for _ in 0..2 {
+        let result = vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 100 });
+        assert!(matches!(
+            result,
+            Err(EbpfError::WithBacktrace(error, backtrace))
+                if *error == EbpfError::DivideByZero(33) && backtrace.frames.len() == 2
+        ));
+    }
+
+    let config = Config {
+        enable_symbol_and_section_labels: true,
+        ..Config::default()
+    };
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        mov r1, 0
+        div r0, r1
+        exit",
+        None,
+        config,
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    let mut vm = EbpfVm::new(&executable, &mut [], Vec::new()).unwrap();
+    assert_eq!(
+        vm.execute_program_interpreted(&mut TestInstructionMeter { remaining: 100 }),
+        Err(EbpfError::DivideByZero(30))
+    );
+}
+
 // Symbols and Relocation
 
 #[test]
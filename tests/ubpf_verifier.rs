@@ -20,18 +20,20 @@
 // These are unit tests for the eBPF “verifier”.
 
 extern crate solana_rbpf;
+extern crate test_utils;
 extern crate thiserror;
 
 use solana_rbpf::{
     assembler::assemble,
-    ebpf,
+    ebpf::{self, SbfVersion},
     elf::Executable,
     error::UserDefinedError,
     user_error::UserError,
-    verifier::{check, VerifierError},
+    verifier::{check, check_all, Severity, VerifierError},
     vm::{Config, EbpfVm, SyscallRegistry, TestInstructionMeter},
 };
 use std::collections::BTreeMap;
+use test_utils::assemble_unverified;
 use thiserror::Error;
 
 /// Error definitions
@@ -48,7 +50,7 @@ fn test_verifier_success() {
         "
         mov32 r0, 0xBEE
         exit",
-        Some(|_prog: &[u8], _config: &Config| Ok(())),
+        Some(|_prog: &[u8], _config: &Config, _sbf_version: SbfVersion| Ok(())),
         Config::default(),
         SyscallRegistry::default(),
     )
@@ -60,7 +62,11 @@ fn test_verifier_success() {
 #[test]
 #[should_panic(expected = "NoProgram")]
 fn test_verifier_fail() {
-    fn verifier_fail(_prog: &[u8], _config: &Config) -> Result<(), VerifierError> {
+    fn verifier_fail(
+        _prog: &[u8],
+        _config: &Config,
+        _sbf_version: SbfVersion,
+    ) -> Result<(), VerifierError> {
         Err(VerifierError::NoProgram)
     }
     let _executable = assemble::<UserError, TestInstructionMeter>(
@@ -127,9 +133,8 @@ fn test_verifier_err_incomplete_lddw() {
 
 #[test]
 fn test_verifier_err_invalid_reg_dst() {
-    // r11 is disabled when dynamic_stack_frames=false, and only sub and add are
-    // allowed when dynamic_stack_frames=true
-    for dynamic_stack_frames in [false, true] {
+    // r11 is disabled in SBFv1, and only sub and add are allowed in SBFv2
+    for sbf_version in [SbfVersion::V1, SbfVersion::V2] {
         assert_eq!(
             assemble::<UserError, TestInstructionMeter>(
                 "
@@ -137,7 +142,8 @@ fn test_verifier_err_invalid_reg_dst() {
                 exit",
                 Some(check),
                 Config {
-                    dynamic_stack_frames,
+                    min_sbf_version: sbf_version,
+                    max_sbf_version: sbf_version,
                     ..Config::default()
                 },
                 SyscallRegistry::default(),
@@ -150,9 +156,8 @@ fn test_verifier_err_invalid_reg_dst() {
 
 #[test]
 fn test_verifier_err_invalid_reg_src() {
-    // r11 is disabled when dynamic_stack_frames=false, and only sub and add are
-    // allowed when dynamic_stack_frames=true
-    for dynamic_stack_frames in [false, true] {
+    // r11 is disabled in SBFv1, and only sub and add are allowed in SBFv2
+    for sbf_version in [SbfVersion::V1, SbfVersion::V2] {
         assert_eq!(
             assemble::<UserError, TestInstructionMeter>(
                 "
@@ -160,7 +165,8 @@ fn test_verifier_err_invalid_reg_src() {
                 exit",
                 Some(check),
                 Config {
-                    dynamic_stack_frames,
+                    min_sbf_version: sbf_version,
+                    max_sbf_version: sbf_version,
                     ..Config::default()
                 },
                 SyscallRegistry::default(),
@@ -180,7 +186,8 @@ fn test_verifier_resize_stack_ptr_success() {
         exit",
         Some(check),
         Config {
-            dynamic_stack_frames: true,
+            min_sbf_version: SbfVersion::V2,
+            max_sbf_version: SbfVersion::V2,
             enable_stack_frame_gaps: false,
             ..Config::default()
         },
@@ -189,6 +196,24 @@ fn test_verifier_resize_stack_ptr_success() {
     .unwrap();
 }
 
+#[test]
+#[should_panic(expected = "Resizing the stack frame with sub r11 is not supported in SBFv1")]
+fn test_verifier_err_resize_stack_ptr_v1() {
+    let _executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        sub r11, 1
+        exit",
+        Some(check),
+        Config {
+            min_sbf_version: SbfVersion::V1,
+            max_sbf_version: SbfVersion::V1,
+            ..Config::default()
+        },
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+}
+
 #[test]
 #[should_panic(expected = "JumpToMiddleOfLDDW(2, 29)")]
 fn test_verifier_err_jmp_lddw() {
@@ -389,3 +414,351 @@ fn test_sdiv_disabled() {
         }
     }
 }
+
+#[test]
+fn test_jmp32_disabled() {
+    let instructions = [
+        (ebpf::JEQ32_IMM, "jeq32 r1, 2, +0"),
+        (ebpf::JGT32_REG, "jgt32 r1, r2, +0"),
+        (ebpf::JSET32_IMM, "jset32 r1, 2, +0"),
+        (ebpf::JSLE32_REG, "jsle32 r1, r2, +0"),
+    ];
+
+    for (opc, instruction) in instructions {
+        for enable_jmp32 in [true, false] {
+            let assembly = format!("\n{}\nexit", instruction);
+            let result = assemble::<UserError, TestInstructionMeter>(
+                &assembly,
+                Some(check),
+                Config {
+                    enable_jmp32,
+                    ..Config::default()
+                },
+                SyscallRegistry::default(),
+            );
+
+            if enable_jmp32 {
+                assert!(result.is_ok());
+            } else {
+                assert_eq!(
+                    result.unwrap_err(),
+                    format!(
+                        "Executable constructor VerifierError(UnknownOpCode({}, {}))",
+                        opc,
+                        ebpf::ELF_INSN_DUMP_OFFSET
+                    ),
+                );
+            }
+        }
+    }
+}
+
+#[test]
+#[should_panic(expected = "JumpOutOfCode(3, 29)")]
+fn test_verifier_err_jmp32_out() {
+    let _executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        jeq32 r0, 0, +2
+        exit",
+        Some(check),
+        Config {
+            enable_jmp32: true,
+            ..Config::default()
+        },
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_ebpf_v4_disabled() {
+    let instructions = [
+        (ebpf::LD_SB_REG, "ldxsb r1, [r2]"),
+        (ebpf::LD_SW_REG, "ldxsw r1, [r2+4]"),
+        (ebpf::BSWAP, "bswap32 r1"),
+        (ebpf::GOTOL, "gotol +0"),
+    ];
+
+    for (opc, instruction) in instructions {
+        for enable_ebpf_v4 in [true, false] {
+            let assembly = format!("\n{}\nexit", instruction);
+            let result = assemble::<UserError, TestInstructionMeter>(
+                &assembly,
+                Some(check),
+                Config {
+                    enable_ebpf_v4,
+                    ..Config::default()
+                },
+                SyscallRegistry::default(),
+            );
+
+            if enable_ebpf_v4 {
+                assert!(result.is_ok());
+            } else {
+                assert_eq!(
+                    result.unwrap_err(),
+                    format!(
+                        "Executable constructor VerifierError(UnknownOpCode({}, {}))",
+                        opc,
+                        ebpf::ELF_INSN_DUMP_OFFSET
+                    ),
+                );
+            }
+        }
+    }
+}
+
+#[test]
+#[should_panic(expected = "JumpOutOfCode(70001, 29)")]
+fn test_verifier_err_gotol_out() {
+    let _executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        gotol +70000
+        exit",
+        Some(check),
+        Config {
+            enable_ebpf_v4: true,
+            ..Config::default()
+        },
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+}
+
+#[test]
+#[should_panic(expected = "UnsupportedOffset(7, 29)")]
+fn test_verifier_err_unsupported_offset() {
+    // mov64 r0, r1 with an offset which selects no sign extension
+    let prog = &[
+        0xbf, 0x10, 0x07, 0x00, 0x00, 0x00, 0x00, 0x00, //
+        0x95, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+    ];
+    let _ = Executable::<UserError, TestInstructionMeter>::from_text_bytes(
+        prog,
+        Some(check),
+        Config {
+            enable_ebpf_v4: true,
+            ..Config::default()
+        },
+        SyscallRegistry::default(),
+        BTreeMap::default(),
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_atomics_disabled() {
+    let instructions = [
+        (ebpf::ST_W_ATOMIC, "stxxaddw [r1], r2"),
+        (ebpf::ST_DW_ATOMIC, "stxxfetchordw [r1], r2"),
+        (ebpf::ST_W_ATOMIC, "stxxxchgw [r1], r2"),
+        (ebpf::ST_DW_ATOMIC, "stxxcmpxchgdw [r1], r2"),
+    ];
+
+    for (opc, instruction) in instructions {
+        for enable_atomics in [true, false] {
+            let assembly = format!("\n{}\nexit", instruction);
+            let result = assemble::<UserError, TestInstructionMeter>(
+                &assembly,
+                Some(check),
+                Config {
+                    enable_atomics,
+                    ..Config::default()
+                },
+                SyscallRegistry::default(),
+            );
+
+            if enable_atomics {
+                assert!(result.is_ok());
+            } else {
+                assert_eq!(
+                    result.unwrap_err(),
+                    format!(
+                        "Executable constructor VerifierError(UnknownOpCode({}, {}))",
+                        opc,
+                        ebpf::ELF_INSN_DUMP_OFFSET
+                    ),
+                );
+            }
+        }
+    }
+}
+
+#[test]
+#[should_panic(expected = "UnknownAtomicOperation(2, 29)")]
+fn test_verifier_err_unknown_atomic_operation() {
+    let prog = &[
+        0xdb, 0x21, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, //
+        0x95, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+    ];
+    let _ = Executable::<UserError, TestInstructionMeter>::from_text_bytes(
+        prog,
+        Some(check),
+        Config {
+            enable_atomics: true,
+            ..Config::default()
+        },
+        SyscallRegistry::default(),
+        BTreeMap::default(),
+    )
+    .unwrap();
+}
+
+#[test]
+#[should_panic(expected = "CannotWriteR10(29)")]
+fn test_verifier_err_atomic_fetch_r10() {
+    let _executable = assemble::<UserError, TestInstructionMeter>(
+        "
+        stxxfetchadddw [r1], r10
+        exit",
+        Some(check),
+        Config {
+            enable_atomics: true,
+            ..Config::default()
+        },
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+}
+
+fn check_all_diagnostics(source: &str, config: Config) -> Vec<(usize, u8, Severity, String)> {
+    let executable = assemble_unverified(source, config);
+    check_all(&executable)
+        .into_iter()
+        .map(|diagnostic| {
+            (
+                diagnostic.insn_ptr,
+                diagnostic.opc,
+                diagnostic.severity,
+                diagnostic.message,
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_check_all_errors() {
+    let source = "
+        div32 r0, 0
+        mov r10, 1
+        ja +5
+        exit";
+    assert_eq!(
+        check_all_diagnostics(source, Config::default()),
+        vec![
+            (
+                0,
+                ebpf::DIV32_IMM,
+                Severity::Error,
+                "division by 0 (insn #29)".to_string()
+            ),
+            (
+                1,
+                ebpf::MOV64_IMM,
+                Severity::Error,
+                "cannot write into register r10 (insn #30)".to_string()
+            ),
+            (
+                2,
+                ebpf::JA,
+                Severity::Error,
+                "jump out of code to #8 (insn #31)".to_string()
+            ),
+            (
+                3,
+                ebpf::EXIT,
+                Severity::Warning,
+                "unreachable code".to_string()
+            ),
+        ]
+    );
+    // The fail-fast verifier only reports the first error
+    let executable = assemble::<UserError, TestInstructionMeter>(
+        source,
+        None,
+        Config::default(),
+        SyscallRegistry::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        check(
+            executable.get_text_bytes().1,
+            executable.get_config(),
+            executable.get_sbf_version()
+        ),
+        Err(VerifierError::DivisionByZero(29))
+    );
+}
+
+#[test]
+fn test_check_all_warnings() {
+    assert_eq!(
+        check_all_diagnostics(
+            "
+            mov r0, 1
+            stdw [r10-8], 1
+            stdw [r10-8], 2
+            ldxdw r1, [r10-8]
+            syscall bpf_syscall_u64
+            stw [r10-16], 0
+            exit
+            mov r0, 0
+            exit",
+            Config::default()
+        ),
+        vec![
+            (
+                0,
+                ebpf::MOV64_IMM,
+                Severity::Warning,
+                "r0 is overwritten by a syscall before being read".to_string()
+            ),
+            (
+                1,
+                ebpf::ST_DW_IMM,
+                Severity::Warning,
+                "store to the stack is overwritten before being read".to_string()
+            ),
+            (
+                5,
+                ebpf::ST_W_IMM,
+                Severity::Warning,
+                "store to the stack is never read before returning".to_string()
+            ),
+            (
+                7,
+                ebpf::MOV64_IMM,
+                Severity::Warning,
+                "unreachable code".to_string()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_check_all_no_warnings() {
+    // Reads through other registers, calls and other paths keep the writes alive
+    assert_eq!(
+        check_all_diagnostics(
+            "
+            stdw [r10-8], 1
+            mov r1, r10
+            add r1, -8
+            ldxdw r2, [r1]
+            stdw [r10-8], 2
+            syscall bpf_syscall_u64
+            mov r0, 1
+            jeq r1, 0, +1
+            stdw [r10-8], 3
+            call function_foo
+            stdw [r10-8], 4
+            ldxdw r0, [r10-8]
+            exit
+            function_foo:
+            stdw [r10+8], 5
+            exit",
+            Config::default()
+        ),
+        vec![]
+    );
+}